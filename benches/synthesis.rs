@@ -0,0 +1,125 @@
+//! Time spent by `CircomCircuit::synthesize` outside of the prover, compared to building the
+//! linear combinations straight from the nested term vectors of `R1CS`.
+//!
+//! cargo bench --features prover --bench synthesis -- [constraints]
+
+use std::time::{Duration, Instant};
+use bellman_ce::{
+    Circuit,
+    ConstraintSystem,
+    Index,
+    LinearCombination,
+    SynthesisError,
+    Variable,
+    pairing::{
+        bn256::{Bn256, Fr},
+        ff::{Field, PrimeField},
+    },
+};
+use zkutil::circom_circuit::{CircomCircuit, R1CS};
+
+/// Builds and drops every linear combination, like a prover that only synthesizes
+struct BuildingCs {
+    inputs: usize,
+    aux: usize,
+}
+
+impl ConstraintSystem<Bn256> for BuildingCs {
+    type Root = Self;
+
+    fn alloc<F, A, AR>(&mut self, _: A, f: F) -> Result<Variable, SynthesisError>
+        where F: FnOnce() -> Result<Fr, SynthesisError>, A: FnOnce() -> AR, AR: Into<String>
+    {
+        f()?;
+        self.aux += 1;
+        Ok(Variable::new_unchecked(Index::Aux(self.aux - 1)))
+    }
+
+    fn alloc_input<F, A, AR>(&mut self, _: A, f: F) -> Result<Variable, SynthesisError>
+        where F: FnOnce() -> Result<Fr, SynthesisError>, A: FnOnce() -> AR, AR: Into<String>
+    {
+        f()?;
+        self.inputs += 1;
+        Ok(Variable::new_unchecked(Index::Input(self.inputs - 1)))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, _: A, a: LA, b: LB, c: LC)
+        where A: FnOnce() -> AR, AR: Into<String>,
+              LA: FnOnce(LinearCombination<Bn256>) -> LinearCombination<Bn256>,
+              LB: FnOnce(LinearCombination<Bn256>) -> LinearCombination<Bn256>,
+              LC: FnOnce(LinearCombination<Bn256>) -> LinearCombination<Bn256>
+    {
+        a(LinearCombination::zero());
+        b(LinearCombination::zero());
+        c(LinearCombination::zero());
+    }
+
+    fn push_namespace<NR, N>(&mut self, _: N) where NR: Into<String>, N: FnOnce() -> NR {}
+
+    fn pop_namespace(&mut self) {}
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+/// Chain of `num_constraints` constraints with a few terms each, in the shape circom emits
+fn circuit(num_constraints: usize) -> R1CS<Bn256> {
+    let num_inputs = 2;
+    let num_variables = num_constraints + 3;
+    let coeff = Fr::from_str("7").unwrap();
+    let constraints = (0..num_constraints)
+        .map(|i| {
+            let wire = i + num_inputs;
+            (
+                vec![(wire, coeff), (0, Fr::one())],
+                vec![(wire + 1, coeff)],
+                vec![(wire + 2, Fr::one()), (1, coeff), (wire, Fr::one())],
+            )
+        })
+        .collect();
+    R1CS { num_inputs, num_aux: num_variables - num_inputs, num_variables, constraints }
+}
+
+/// Original synthesis path: every combination is built from a clone of its nested term vector
+fn synthesize_nested(r1cs: &R1CS<Bn256>, cs: &mut BuildingCs) {
+    let make_index = |index|
+        if index < r1cs.num_inputs {
+            Index::Input(index)
+        } else {
+            Index::Aux(index - r1cs.num_inputs)
+        };
+    let make_lc = |lc_data: Vec<(usize, Fr)>|
+        lc_data.iter().fold(
+            LinearCombination::<Bn256>::zero(),
+            |lc: LinearCombination<Bn256>, (index, coeff)| lc + (*coeff, Variable::new_unchecked(make_index(*index)))
+        );
+    for (i, constraint) in r1cs.constraints.iter().enumerate() {
+        cs.enforce(|| format!("constraint {}", i),
+            |_| make_lc(constraint.0.clone()),
+            |_| make_lc(constraint.1.clone()),
+            |_| make_lc(constraint.2.clone()),
+        );
+    }
+}
+
+fn time(f: impl FnOnce()) -> Duration {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
+
+fn main() {
+    let num_constraints = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(1_000_000);
+    let r1cs = circuit(num_constraints);
+    let nested = time(|| synthesize_nested(&r1cs, &mut BuildingCs { inputs: 1, aux: 0 }));
+    let circuit = CircomCircuit { r1cs, witness: None, wire_mapping: None };
+    let sparse = time(|| circuit.synthesize(&mut BuildingCs { inputs: 1, aux: 0 }).unwrap());
+    println!("{} constraints", num_constraints);
+    println!("nested terms: {:?}", nested);
+    println!("sparse terms: {:?} (including conversion)", sparse);
+    println!("speedup: {:.2}x", nested.as_secs_f64() / sparse.as_secs_f64());
+}
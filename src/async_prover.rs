@@ -0,0 +1,150 @@
+//! Async API for tokio services.
+//!
+//! Proving and parameter parsing are CPU bound and run on tokio's blocking thread pool, witnesses
+//! and output files are read and written with async I/O, so awaiting a proof doesn't block the
+//! runtime. Every call takes a `CancellationToken` and returns `ErrorKind::Interrupted` as soon as it
+//! is cancelled. Bellman can't stop a running proof, it finishes on its blocking thread and is dropped.
+use std::io::{Cursor, Error, ErrorKind, Result};
+use std::sync::Arc;
+use bellman_ce::{
+    groth16::{Parameters, Proof},
+    pairing::bn256::{Bn256, Fr},
+};
+use tokio_util::sync::CancellationToken;
+use crate::circom_circuit::{create_rng, proof_to_json_with_format, R1CS};
+use crate::circuit::{load_r1cs, read_witness};
+use crate::file_header::FileHeader;
+use crate::keys;
+use crate::proof::{prove_witness, PublicInputs};
+use crate::utils::{atomic_temp_path, NumberFormat};
+
+fn cancelled() -> Error {
+    Error::new(ErrorKind::Interrupted, "cancelled")
+}
+
+/// Runs `f` on the blocking thread pool unless `cancel` fires first
+async fn blocking<T, F>(cancel: &CancellationToken, f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    if cancel.is_cancelled() {
+        return Err(cancelled());
+    }
+    tokio::select! {
+        biased;
+        _ = cancel.cancelled() => Err(cancelled()),
+        result = tokio::task::spawn_blocking(f) => result.map_err(|e| Error::new(ErrorKind::Other, format!("blocking task failed: {}", e)))?,
+    }
+}
+
+/// Runs an async I/O future unless `cancel` fires first
+async fn io<T>(cancel: &CancellationToken, future: impl std::future::Future<Output = Result<T>>) -> Result<T> {
+    tokio::select! {
+        biased;
+        _ = cancel.cancelled() => Err(cancelled()),
+        result = future => result,
+    }
+}
+
+/// Loads params from a params file or a bundle with `keys::load_params`, compressed and encrypted
+/// ones included, and their header. It is parsed while it is read so the whole file is read on the
+/// blocking pool. Insecure dev params are logged as a warning
+pub async fn load_params(filename: &str, cancel: &CancellationToken) -> Result<(Option<FileHeader>, Arc<Parameters<Bn256>>)> {
+    let filename = filename.to_string();
+    blocking(cancel, move || {
+        let (header, params) = keys::load_params(&filename)?;
+        keys::warn_if_insecure(header.as_ref());
+        Ok((header, Arc::new(params)))
+    }).await
+}
+
+/// Loads a circuit R1CS or JSON file for params with `header`, see `keys::circuit_for_params`
+pub async fn load_circuit(filename: &str, header: Option<&FileHeader>, cancel: &CancellationToken) -> Result<Arc<R1CS<Bn256>>> {
+    let filename = filename.to_string();
+    let header = header.cloned();
+    blocking(cancel, move || Ok(Arc::new(keys::circuit_for_params(header.as_ref(), load_r1cs(&filename)?)?))).await
+}
+
+/// Loads params and the circuit they were generated for with `keys::load_prover_pair`
+pub async fn load_prover_inputs(params: &str, circuit: &str, cancel: &CancellationToken) -> Result<(Arc<R1CS<Bn256>>, Arc<Parameters<Bn256>>)> {
+    let (params, circuit) = (params.to_string(), circuit.to_string());
+    blocking(cancel, move || {
+        let (header, params, r1cs) = keys::load_prover_pair(&params, &circuit)?;
+        keys::warn_if_insecure(header.as_ref());
+        Ok((Arc::new(r1cs), Arc::new(params)))
+    }).await
+}
+
+/// Loads a wtns or JSON witness file
+pub async fn load_witness(filename: &str, cancel: &CancellationToken) -> Result<Vec<Fr>> {
+    let bytes = io(cancel, tokio::fs::read(filename)).await?;
+    blocking(cancel, move || read_witness::<Bn256, _>(Cursor::new(bytes))).await
+}
+
+/// Proves a witness like `proof::prove_witness` with a fresh OS rng
+pub async fn prove(
+    r1cs: Arc<R1CS<Bn256>>,
+    params: Arc<Parameters<Bn256>>,
+    witness: Vec<Fr>,
+    cancel: &CancellationToken,
+) -> Result<(Proof<Bn256>, PublicInputs)> {
+    blocking(cancel, move || {
+        prove_witness(&r1cs, &params, witness, create_rng())
+            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("unable to prove: {:?}", e)))
+    }).await
+}
+
+async fn write_file_atomic(filename: &str, content: Vec<u8>) -> Result<()> {
+    let tmp = atomic_temp_path(filename);
+    if let Err(e) = tokio::fs::write(&tmp, content).await {
+        let _ = tokio::fs::remove_file(&tmp).await;
+        return Err(e);
+    }
+    tokio::fs::rename(&tmp, filename).await
+}
+
+/// Writes proof.json and public.json files with field elements in `format`
+pub async fn write_proof_files(
+    proof: &Proof<Bn256>,
+    public: &PublicInputs,
+    format: NumberFormat,
+    proof_file: &str,
+    public_file: &str,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    let proof_json = proof_to_json_with_format(proof, format)?;
+    io(cancel, write_file_atomic(proof_file, proof_json.into_bytes())).await?;
+    io(cancel, write_file_atomic(public_file, public.to_json_with_format(format).into_bytes())).await
+}
+
+/// Proves a witness file and writes the proof and public inputs
+pub async fn prove_witness_file(
+    r1cs: Arc<R1CS<Bn256>>,
+    params: Arc<Parameters<Bn256>>,
+    witness_file: &str,
+    proof_file: &str,
+    public_file: &str,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    let witness = load_witness(witness_file, cancel).await?;
+    let (proof, public) = prove(r1cs, params, witness, cancel).await?;
+    write_proof_files(&proof, &public, NumberFormat::Decimal, proof_file, public_file, cancel).await
+}
+
+#[test]
+fn prove_async() {
+    use crate::circom_circuit::verify;
+    use crate::test_utils::{fr, multiplier_circuit, multiplier_params, multiplier_witness};
+    let (r1cs, params) = (Arc::new(multiplier_circuit()), Arc::new(multiplier_params()));
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    let cancel = CancellationToken::new();
+    let (proof, public) = runtime.block_on(prove(r1cs.clone(), params.clone(), multiplier_witness(3, 4), &cancel)).unwrap();
+    assert_eq!(public.values(), &[fr("12")]);
+    assert!(verify(&params, &proof, public.values()).unwrap());
+
+    cancel.cancel();
+    let error = runtime.block_on(prove(r1cs, params, multiplier_witness(3, 4), &cancel)).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::Interrupted);
+    assert_eq!(runtime.block_on(load_witness("missing.wtns", &cancel)).unwrap_err().kind(), ErrorKind::Interrupted);
+}
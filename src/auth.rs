@@ -0,0 +1,184 @@
+//! Authentication of `serve` requests with API keys and HS256 JWT bearer tokens. Every key and
+//! token lists the circuits it may have proved, so teams sharing a prover on an internal network
+//! can only use their own circuits. Only admin keys and tokens may register and retire circuits.
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use hmac::{Hmac, Mac, NewMac};
+use sha2::{Digest, Sha256};
+use crate::utils::hex_to_bytes;
+
+/// Config file of `serve --auth`
+#[derive(Deserialize)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub keys: Vec<ApiKey>,
+    /// File with the HS256 secret of JWTs, relative to the config file. JWTs are refused without it
+    #[serde(default)]
+    pub jwt_secret_file: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ApiKey {
+    /// Client the key belongs to, rate limits are counted per client
+    pub name: String,
+    /// Hex SHA-256 of the key, so the config doesn't reveal keys
+    pub sha256: String,
+    /// Circuits the key may have proved, `*` for all of them
+    pub circuits: Vec<String>,
+    /// Whether the key may use the admin API of the circuit registry
+    #[serde(default)]
+    pub admin: bool,
+}
+
+/// Claims of JWTs, `exp` is required
+#[derive(Deserialize)]
+struct Claims {
+    sub: String,
+    exp: u64,
+    #[serde(default)]
+    circuits: Vec<String>,
+    #[serde(default)]
+    admin: bool,
+}
+
+/// Why a request was refused, `Unauthorized` asks for another token and `Forbidden` doesn't
+#[derive(Debug, PartialEq)]
+pub enum AuthError {
+    Unauthorized(String),
+    Forbidden(String),
+}
+
+pub struct Auth {
+    /// Keys with their decoded hashes
+    keys: Vec<(ApiKey, Vec<u8>)>,
+    jwt_secret: Option<Vec<u8>>,
+}
+
+impl Auth {
+    pub fn new(config: AuthConfig, jwt_secret: Option<Vec<u8>>) -> Result<Self> {
+        let keys = config.keys.into_iter()
+            .map(|key| match hex_to_bytes(&key.sha256).filter(|hash| hash.len() == 32) {
+                Some(hash) => Ok((key, hash)),
+                None => Err(Error::new(ErrorKind::InvalidData, format!("sha256 of key {} is not 32 hex bytes", key.name))),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Auth { keys, jwt_secret })
+    }
+
+    /// Loads a JSON `AuthConfig` and its JWT secret
+    pub fn load(filename: &str) -> Result<Self> {
+        let config: AuthConfig = serde_json::from_slice(&fs::read(filename)?)?;
+        let jwt_secret = match &config.jwt_secret_file {
+            Some(file) => Some(fs::read(Path::new(filename).parent().unwrap_or_else(|| Path::new("")).join(file))?),
+            None => None,
+        };
+        Auth::new(config, jwt_secret)
+    }
+
+    /// Name of the client of an `Authorization` header value, if it may have `circuit` proved
+    pub fn authorize(&self, authorization: Option<&str>, circuit: &str, now: SystemTime) -> std::result::Result<String, AuthError> {
+        let claims = self.claims(authorization, now)?;
+        if !claims.circuits.iter().any(|allowed| allowed == "*" || allowed == circuit) {
+            return Err(AuthError::Forbidden(format!("{} may not use circuit {}", claims.sub, circuit)));
+        }
+        Ok(claims.sub)
+    }
+
+    /// Name of the client of a valid `Authorization` header value, whatever it may use
+    pub fn authenticate(&self, authorization: Option<&str>, now: SystemTime) -> std::result::Result<String, AuthError> {
+        self.claims(authorization, now).map(|claims| claims.sub)
+    }
+
+    /// Name of the client of an `Authorization` header value, if it may use the admin API
+    pub fn authorize_admin(&self, authorization: Option<&str>, now: SystemTime) -> std::result::Result<String, AuthError> {
+        let claims = self.claims(authorization, now)?;
+        if !claims.admin {
+            return Err(AuthError::Forbidden(format!("{} may not use the admin API", claims.sub)));
+        }
+        Ok(claims.sub)
+    }
+
+    /// Claims of a JWT, or the same for an API key
+    fn claims(&self, authorization: Option<&str>, now: SystemTime) -> std::result::Result<Claims, AuthError> {
+        let unauthorized = |msg: &str| AuthError::Unauthorized(msg.to_string());
+        let token = authorization.ok_or_else(|| unauthorized("a bearer token is required"))?;
+        let token = token.strip_prefix("Bearer ").ok_or_else(|| unauthorized("tokens are sent as Authorization: Bearer <token>"))?.trim();
+        if token.matches('.').count() == 2 {
+            return self.verify_jwt(token, now).map_err(AuthError::Unauthorized);
+        }
+        // keys are compared by their hashes, which doesn't leak how much of a key matches
+        let hash = Sha256::digest(token.as_bytes());
+        match self.keys.iter().find(|(_, key_hash)| key_hash[..] == hash[..]) {
+            Some((key, _)) => Ok(Claims { sub: key.name.clone(), exp: u64::MAX, circuits: key.circuits.clone(), admin: key.admin }),
+            None => Err(unauthorized("unknown API key")),
+        }
+    }
+
+    fn verify_jwt(&self, token: &str, now: SystemTime) -> std::result::Result<Claims, String> {
+        let secret = self.jwt_secret.as_ref().ok_or_else(|| String::from("JWTs are not accepted"))?;
+        let decode = |part: &str| base64::decode_config(part, base64::URL_SAFE_NO_PAD).map_err(|_| String::from("malformed JWT"));
+        let parts = token.split('.').collect::<Vec<_>>();
+        let header: serde_json::Value = serde_json::from_slice(&decode(parts[0])?).map_err(|_| String::from("malformed JWT"))?;
+        // the algorithm is fixed, so neither `none` nor a public key used as an HMAC secret is accepted
+        if header["alg"] != "HS256" {
+            return Err(String::from("JWTs must be signed with HS256"));
+        }
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).map_err(|_| String::from("invalid JWT secret"))?;
+        mac.update(&token.as_bytes()[..parts[0].len() + 1 + parts[1].len()]);
+        mac.verify(&decode(parts[2])?).map_err(|_| String::from("invalid JWT signature"))?;
+        let claims: Claims = serde_json::from_slice(&decode(parts[1])?).map_err(|e| format!("invalid JWT claims: {}", e))?;
+        let now = now.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        if claims.exp <= now {
+            return Err(String::from("JWT has expired"));
+        }
+        Ok(claims)
+    }
+}
+
+#[test]
+fn tokens() {
+    let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    let config = AuthConfig {
+        keys: vec![
+            ApiKey { name: String::from("payments"), sha256: hex(&Sha256::digest(b"key-1")), circuits: vec![String::from("transfer")], admin: false },
+            ApiKey { name: String::from("ops"), sha256: hex(&Sha256::digest(b"key-2")), circuits: vec![], admin: true },
+        ],
+        jwt_secret_file: None,
+    };
+    let secret = b"jwt-secret".to_vec();
+    let auth = Auth::new(config, Some(secret.clone())).unwrap();
+    let now = UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+    let forbidden = |result| matches!(result, Err(AuthError::Forbidden(_)));
+    let unauthorized = |result| matches!(result, Err(AuthError::Unauthorized(_)));
+    assert_eq!(auth.authorize(Some("Bearer key-1"), "transfer", now).unwrap(), "payments");
+    assert!(forbidden(auth.authorize(Some("Bearer key-1"), "withdraw", now)));
+    assert!(unauthorized(auth.authorize(Some("Bearer key-3"), "transfer", now)));
+    assert!(forbidden(auth.authorize(Some("Bearer key-2"), "transfer", now)));
+    assert_eq!(auth.authorize_admin(Some("Bearer key-2"), now).unwrap(), "ops");
+    assert!(forbidden(auth.authorize_admin(Some("Bearer key-1"), now)));
+    assert!(unauthorized(auth.authorize(Some("key-1"), "transfer", now)));
+    assert!(unauthorized(auth.authorize(None, "transfer", now)));
+    assert_eq!(auth.authenticate(Some("Bearer key-2"), now).unwrap(), "ops");
+
+    let encode = |json: &str| base64::encode_config(json, base64::URL_SAFE_NO_PAD);
+    let jwt = |header: &str, claims: &str, secret: &[u8]| {
+        let signed = format!("{}.{}", encode(header), encode(claims));
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+        mac.update(signed.as_bytes());
+        format!("Bearer {}.{}", signed, base64::encode_config(mac.finalize().into_bytes(), base64::URL_SAFE_NO_PAD))
+    };
+    let hs256 = r#"{"alg":"HS256","typ":"JWT"}"#;
+    let claims = r#"{"sub":"risk","exp":1000001,"circuits":["*"]}"#;
+    assert_eq!(auth.authorize(Some(&jwt(hs256, claims, &secret)), "liquidation", now).unwrap(), "risk");
+    let scoped = r#"{"sub":"risk","exp":1000001,"circuits":["liquidation"]}"#;
+    assert!(forbidden(auth.authorize(Some(&jwt(hs256, scoped, &secret)), "transfer", now)));
+    assert!(unauthorized(auth.authorize(Some(&jwt(hs256, claims, b"other")), "liquidation", now)));
+    assert!(unauthorized(auth.authorize(Some(&jwt(r#"{"alg":"none"}"#, claims, &secret)), "liquidation", now)));
+    assert!(forbidden(auth.authorize_admin(Some(&jwt(hs256, claims, &secret)), now)));
+    let admin = r#"{"sub":"deploy","exp":1000001,"admin":true}"#;
+    assert_eq!(auth.authorize_admin(Some(&jwt(hs256, admin, &secret)), now).unwrap(), "deploy");
+    let expired = r#"{"sub":"risk","exp":1000000,"circuits":["*"]}"#;
+    assert!(unauthorized(auth.authorize(Some(&jwt(hs256, expired, &secret)), "liquidation", now)));
+}
@@ -0,0 +1,218 @@
+//! Baby Jubjub curve and EdDSA-Poseidon signatures compatible with circomlib `EdDSAPoseidonVerifier`.
+use num_bigint::BigUint;
+use num_traits::Num;
+use bellman_ce::pairing::{
+    bn256::{Bn256, Fr},
+    ff::{Field, PrimeField},
+};
+use blake_hash::Blake512;
+use sha2::Digest;
+use crate::poseidon::poseidon;
+use crate::utils::{repr_to_big, repr_to_biguint};
+
+const A: &str = "168700";
+const D: &str = "168696";
+const BASE8_X: &str = "5299619240641551281634865583518297030282874472190772894086521144482721001553";
+const BASE8_Y: &str = "16950150798460657717958625567821834550301663161624707787222815936182638968203";
+/// Order of the prime subgroup generated by Base8
+const SUBORDER: &str = "2736030358979909402780800718157159386076813972158567259200215660948447373041";
+
+/// BLAKE-512, the SHA-3 finalist rather than BLAKE2b, which circomlib derives keys with
+fn blake512(data: &[u8]) -> Vec<u8> {
+    Blake512::digest(data).to_vec()
+}
+
+fn fr(s: &str) -> Fr {
+    Fr::from_str(s).unwrap()
+}
+
+pub fn suborder() -> BigUint {
+    BigUint::from_str_radix(SUBORDER, 10).unwrap()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Point {
+    pub x: Fr,
+    pub y: Fr,
+}
+
+impl Point {
+    pub fn identity() -> Self {
+        Point { x: Fr::zero(), y: Fr::one() }
+    }
+
+    pub fn base8() -> Self {
+        Point { x: fr(BASE8_X), y: fr(BASE8_Y) }
+    }
+
+    pub fn is_on_curve(&self) -> bool {
+        // a * x^2 + y^2 = 1 + d * x^2 * y^2
+        let (mut x2, mut y2) = (self.x, self.y);
+        x2.square();
+        y2.square();
+        let mut left = fr(A);
+        left.mul_assign(&x2);
+        left.add_assign(&y2);
+        let mut right = fr(D);
+        right.mul_assign(&x2);
+        right.mul_assign(&y2);
+        right.add_assign(&Fr::one());
+        left == right
+    }
+
+    pub fn add(&self, other: &Point) -> Point {
+        // x3 = (x1*y2 + y1*x2) / (1 + d*x1*x2*y1*y2), y3 = (y1*y2 - a*x1*x2) / (1 - d*x1*x2*y1*y2)
+        let mut x1x2 = self.x;
+        x1x2.mul_assign(&other.x);
+        let mut y1y2 = self.y;
+        y1y2.mul_assign(&other.y);
+        let mut dxy = fr(D);
+        dxy.mul_assign(&x1x2);
+        dxy.mul_assign(&y1y2);
+
+        let mut x_num = self.x;
+        x_num.mul_assign(&other.y);
+        let mut y1x2 = self.y;
+        y1x2.mul_assign(&other.x);
+        x_num.add_assign(&y1x2);
+        let mut x_den = Fr::one();
+        x_den.add_assign(&dxy);
+
+        let mut y_num = y1y2;
+        let mut ax1x2 = fr(A);
+        ax1x2.mul_assign(&x1x2);
+        y_num.sub_assign(&ax1x2);
+        let mut y_den = Fr::one();
+        y_den.sub_assign(&dxy);
+
+        // the addition law is complete on Baby Jubjub, denominators are never zero
+        x_num.mul_assign(&x_den.inverse().unwrap());
+        y_num.mul_assign(&y_den.inverse().unwrap());
+        Point { x: x_num, y: y_num }
+    }
+
+    pub fn mul_scalar(&self, scalar: &BigUint) -> Point {
+        let mut result = Point::identity();
+        let mut base = *self;
+        for byte in scalar.to_bytes_le() {
+            for bit in 0..8 {
+                if (byte >> bit) & 1 == 1 {
+                    result = result.add(&base);
+                }
+                base = base.add(&base);
+            }
+        }
+        result
+    }
+}
+
+pub struct Signature {
+    pub r8: Point,
+    pub s: BigUint,
+}
+
+pub struct PrivateKey {
+    pub key: [u8; 32],
+}
+
+fn fr_to_le_bytes(x: &Fr) -> Vec<u8> {
+    let mut bytes = repr_to_biguint(x.into_repr()).to_bytes_le();
+    bytes.resize(32, 0);
+    bytes
+}
+
+impl PrivateKey {
+    /// Secret scalar derived from the key the same way as circomlib `prv2pub`
+    pub fn scalar(&self) -> BigUint {
+        let h = blake512(&self.key);
+        let mut s = [0u8; 32];
+        s.copy_from_slice(&h[..32]);
+        s[0] &= 0xf8;
+        s[31] &= 0x7f;
+        s[31] |= 0x40;
+        BigUint::from_bytes_le(&s)
+    }
+
+    pub fn public(&self) -> Point {
+        Point::base8().mul_scalar(&(self.scalar() >> 3))
+    }
+
+    /// Signs a single field element with EdDSA over Baby Jubjub, using Poseidon as the message hash
+    pub fn sign_poseidon(&self, msg: &Fr) -> Signature {
+        let h = blake512(&self.key);
+        let s = self.scalar();
+        let a = Point::base8().mul_scalar(&(&s >> 3));
+
+        let mut r_input = h[32..].to_vec();
+        r_input.extend(fr_to_le_bytes(msg));
+        let r = BigUint::from_bytes_le(&blake512(&r_input)) % suborder();
+        let r8 = Point::base8().mul_scalar(&r);
+
+        let hm = poseidon::<Bn256>(&[r8.x, r8.y, a.x, a.y, *msg]);
+        let hm = repr_to_biguint(hm.into_repr());
+        Signature { r8, s: (r + hm * s) % suborder() }
+    }
+}
+
+pub fn verify_poseidon(public: &Point, msg: &Fr, signature: &Signature) -> bool {
+    if !public.is_on_curve() || !signature.r8.is_on_curve() || signature.s >= suborder() {
+        return false;
+    }
+    let hm = poseidon::<Bn256>(&[signature.r8.x, signature.r8.y, public.x, public.y, *msg]);
+    let hm = repr_to_biguint(hm.into_repr());
+    let left = Point::base8().mul_scalar(&signature.s);
+    let right = public.mul_scalar(&(hm * BigUint::from(8u32))).add(&signature.r8);
+    left == right
+}
+
+/// Signature fields named after `EdDSAPoseidonVerifier` input signals, ready to be merged into input.json
+#[derive(Serialize, Deserialize)]
+#[allow(non_snake_case)]
+pub struct SignatureJson {
+    pub Ax: String,
+    pub Ay: String,
+    pub R8x: String,
+    pub R8y: String,
+    pub S: String,
+    pub M: String,
+}
+
+impl SignatureJson {
+    pub fn new(public: &Point, msg: &Fr, signature: &Signature) -> Self {
+        SignatureJson {
+            Ax: repr_to_big(public.x.into_repr()),
+            Ay: repr_to_big(public.y.into_repr()),
+            R8x: repr_to_big(signature.r8.x.into_repr()),
+            R8y: repr_to_big(signature.r8.y.into_repr()),
+            S: signature.s.to_str_radix(10),
+            M: repr_to_big(msg.into_repr()),
+        }
+    }
+}
+
+#[test]
+fn sign_and_verify() {
+    let base8 = Point::base8();
+    assert!(base8.is_on_curve());
+    assert_eq!(base8.mul_scalar(&suborder()), Point::identity());
+
+    // circomlibjs test/eddsa.js "Sign (using Poseidon) a single number"
+    let key = PrivateKey { key: hex!("0001020304050607080900010203040506070809000102030405060708090001") };
+    let public = key.public();
+    assert!(public.is_on_curve());
+    assert_eq!(public, Point {
+        x: fr("13277427435165878497778222415993513565335242147425444199013288855685581939618"),
+        y: fr("13622229784656158136036771217484571176836296686641868549125388198837476602820"),
+    });
+    let msg = fr(&BigUint::from_bytes_le(&hex!("000102030405060708090000")).to_str_radix(10));
+    let signature = key.sign_poseidon(&msg);
+    assert_eq!(signature.r8, Point {
+        x: fr("11384336176656855268977457483345535180380036354188103142384839473266348197733"),
+        y: fr("15383486972088797283337779941324724402501462225528836549661220478783371668959"),
+    });
+    assert_eq!(signature.s.to_str_radix(10), "1672775540645840396591609181675628451599263765380031905495115170613215233181");
+    assert!(verify_poseidon(&public, &msg, &signature));
+    let mut other = msg;
+    other.add_assign(&Fr::one());
+    assert!(!verify_poseidon(&public, &other, &signature));
+}
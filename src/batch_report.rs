@@ -0,0 +1,84 @@
+//! Per-item reports of batch commands like `verify-many` and `prove-manifest`, for loading into
+//! spreadsheets and analytics pipelines.
+//!
+//! Reports are CSV with a header row when the file name ends with `.csv` and a JSON array otherwise.
+use std::io::{Error, Result, Write};
+use crate::utils::write_file_atomic;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemStatus {
+    Ok,
+    /// The proof doesn't verify
+    Invalid,
+    /// The item couldn't be processed, see `error`
+    Failed,
+}
+
+impl ItemStatus {
+    fn name(&self) -> &'static str {
+        match self {
+            ItemStatus::Ok => "ok",
+            ItemStatus::Invalid => "invalid",
+            ItemStatus::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BatchItem {
+    pub file: String,
+    pub status: ItemStatus,
+    pub seconds: f64,
+    /// Size of the proof file in bytes
+    pub proof_size: Option<u64>,
+    pub error: Option<String>,
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+pub fn write_csv<W: Write>(items: &[BatchItem], mut writer: W) -> Result<()> {
+    writeln!(writer, "file,status,seconds,proof_size,error")?;
+    for item in items {
+        writeln!(
+            writer,
+            "{},{},{:.6},{},{}",
+            csv_field(&item.file),
+            item.status.name(),
+            item.seconds,
+            item.proof_size.map(|s| s.to_string()).unwrap_or_default(),
+            csv_field(item.error.as_deref().unwrap_or("")),
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes a CSV report for `.csv` files and a JSON one otherwise
+pub fn write_report_file(items: &[BatchItem], filename: &str) -> Result<()> {
+    if filename.to_lowercase().ends_with(".csv") {
+        write_file_atomic(filename, |w| write_csv(items, w))
+    } else {
+        write_file_atomic(filename, |w| serde_json::to_writer_pretty(w, items).map_err(Error::from))
+    }
+}
+
+#[test]
+fn csv_report() {
+    let items = vec![
+        BatchItem { file: "a.json".to_string(), status: ItemStatus::Ok, seconds: 0.25, proof_size: Some(712), error: None },
+        BatchItem { file: "b,c.json".to_string(), status: ItemStatus::Failed, seconds: 0.0, proof_size: None, error: Some("bad \"point\"".to_string()) },
+    ];
+    let mut out = vec![];
+    write_csv(&items, &mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "file,status,seconds,proof_size,error\na.json,ok,0.250000,712,\n\"b,c.json\",failed,0.000000,,\"bad \"\"point\"\"\"\n",
+    );
+    assert_eq!(serde_json::to_value(&items[1]).unwrap()["status"], "failed");
+}
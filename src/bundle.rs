@@ -0,0 +1,170 @@
+use byteorder::{ReadBytesExt, WriteBytesExt, LittleEndian};
+use std::{collections::HashMap, io::{Cursor, Error, ErrorKind, Read, Result, Seek, SeekFrom, Write}};
+use bellman_ce::{
+    groth16::{Parameters, VerifyingKey},
+    pairing::bn256::Bn256,
+};
+use crate::circom_circuit::{
+    R1CS,
+    read_r1cs_json,
+    r1cs_from_bin,
+    read_params,
+    read_params_header,
+    read_verifying_key_with_header,
+};
+use crate::file_header::FileHeader;
+
+const MAGIC: [u8; 4] = [0x7a, 0x6b, 0x62, 0x6e]; // magic = "zkbn"
+const VERSION: u32 = 1;
+
+const METADATA_TYPE: u32 = 1;
+const CIRCUIT_TYPE: u32 = 2;
+const PARAMS_TYPE: u32 = 3;
+const SYM_TYPE: u32 = 4;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BundleMetadata {
+    pub zkutil_version: String,
+    /// Format of the packed circuit: "json" or "r1cs"
+    pub circuit_format: String,
+    pub num_inputs: usize,
+    pub num_aux: usize,
+    pub num_constraints: usize,
+    /// Unix timestamp of bundle creation
+    pub created: u64,
+}
+
+/// Circuit, trusted setup parameters and optional debug symbols packed together,
+/// so they can't be mixed up with artifacts of a different circuit.
+pub struct Bundle {
+    pub metadata: BundleMetadata,
+    pub circuit: Vec<u8>,
+    pub params: Vec<u8>,
+    pub sym: Option<Vec<u8>>,
+}
+
+impl Bundle {
+    pub fn r1cs(&self) -> Result<R1CS<Bn256>> {
+        match self.metadata.circuit_format.as_str() {
+            "json" => read_r1cs_json(Cursor::new(&self.circuit[..])),
+            "r1cs" => Ok(r1cs_from_bin(Cursor::new(&self.circuit[..]))?.0),
+            f => Err(Error::new(ErrorKind::InvalidData, format!("Unknown circuit format in bundle: {}", f))),
+        }
+    }
+
+    pub fn params(&self) -> Result<Parameters<Bn256>> {
+        read_params(&self.params[..])
+    }
+
+    pub fn params_header(&self) -> Result<Option<FileHeader>> {
+        read_params_header(&self.params[..])
+    }
+
+    pub fn verifying_key(&self) -> Result<VerifyingKey<Bn256>> {
+        Ok(read_verifying_key_with_header(&self.params[..])?.1)
+    }
+}
+
+fn write_section<W: Write>(mut writer: W, sec_type: u32, data: &[u8]) -> Result<()> {
+    writer.write_u32::<LittleEndian>(sec_type)?;
+    writer.write_u64::<LittleEndian>(data.len() as u64)?;
+    writer.write_all(data)
+}
+
+pub fn write<W: Write>(bundle: &Bundle, mut writer: W) -> Result<()> {
+    let metadata = serde_json::to_vec(&bundle.metadata)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    writer.write_all(&MAGIC)?;
+    writer.write_u32::<LittleEndian>(VERSION)?;
+    writer.write_u32::<LittleEndian>(if bundle.sym.is_some() { 4 } else { 3 })?;
+    write_section(&mut writer, METADATA_TYPE, &metadata)?;
+    write_section(&mut writer, CIRCUIT_TYPE, &bundle.circuit)?;
+    write_section(&mut writer, PARAMS_TYPE, &bundle.params)?;
+    if let Some(sym) = &bundle.sym {
+        write_section(&mut writer, SYM_TYPE, sym)?;
+    }
+    Ok(())
+}
+
+pub fn read<R: Read + Seek>(mut reader: R) -> Result<Bundle> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(Error::new(ErrorKind::InvalidData, "Invalid magic number, not a zkutil bundle"))
+    }
+
+    let version = reader.read_u32::<LittleEndian>()?;
+    if version != VERSION {
+        return Err(Error::new(ErrorKind::InvalidData, "Unsupported bundle version"))
+    }
+
+    let num_sections = reader.read_u32::<LittleEndian>()?;
+    let start = reader.stream_position()?;
+    let len = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(start))?;
+
+    // section type -> (file offset, size), sizes are checked before anything is allocated for them
+    let mut sections = HashMap::<u32, (u64, u64)>::new();
+    for _ in 0..num_sections {
+        let sec_type = reader.read_u32::<LittleEndian>()?;
+        let sec_size = reader.read_u64::<LittleEndian>()?;
+        let offset = reader.stream_position()?;
+        if sec_size > len - offset {
+            return Err(Error::new(ErrorKind::UnexpectedEof, format!("Bundle section {} is truncated", sec_type)));
+        }
+        sections.insert(sec_type, (offset, sec_size));
+        reader.seek(SeekFrom::Start(offset + sec_size))?;
+    }
+
+    let mut read_section = |sec_type: u32| -> Result<Option<Vec<u8>>> {
+        match sections.get(&sec_type) {
+            None => Ok(None),
+            Some((offset, size)) => {
+                reader.seek(SeekFrom::Start(*offset))?;
+                let mut data = vec![0u8; *size as usize];
+                reader.read_exact(&mut data)?;
+                Ok(Some(data))
+            }
+        }
+    };
+    let missing = |name: &str| Error::new(ErrorKind::InvalidData, format!("Bundle is missing {} section", name));
+
+    let metadata = read_section(METADATA_TYPE)?.ok_or_else(|| missing("metadata"))?;
+    let metadata: BundleMetadata = serde_json::from_slice(&metadata)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    let circuit = read_section(CIRCUIT_TYPE)?.ok_or_else(|| missing("circuit"))?;
+    let params = read_section(PARAMS_TYPE)?.ok_or_else(|| missing("params"))?;
+    let sym = read_section(SYM_TYPE)?;
+
+    Ok(Bundle { metadata, circuit, params, sym })
+}
+
+#[test]
+fn roundtrip() {
+    let bundle = Bundle {
+        metadata: BundleMetadata {
+            zkutil_version: String::from("0.0.0"),
+            circuit_format: String::from("json"),
+            num_inputs: 2,
+            num_aux: 3,
+            num_constraints: 4,
+            created: 0,
+        },
+        circuit: b"{}".to_vec(),
+        params: vec![1, 2, 3],
+        sym: Some(b"1,1,0,main.in".to_vec()),
+    };
+    let mut data = vec![];
+    write(&bundle, &mut data).unwrap();
+    let bundle2 = read(Cursor::new(&data[..])).unwrap();
+    assert_eq!(bundle2.metadata.circuit_format, "json");
+    assert_eq!(bundle2.metadata.num_constraints, 4);
+    assert_eq!(bundle2.circuit, bundle.circuit);
+    assert_eq!(bundle2.params, bundle.params);
+    assert_eq!(bundle2.sym, bundle.sym);
+
+    assert!(read(Cursor::new(&data[..data.len() - 1])).is_err());
+    // a corrupted size must fail instead of allocating it
+    data[16..24].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert_eq!(read(Cursor::new(&data[..])).err().unwrap().kind(), ErrorKind::UnexpectedEof);
+}
@@ -3,13 +3,15 @@ extern crate bellman_ce;
 extern crate rand;
 
 use std::str;
-use std::fs::{self, OpenOptions, File};
-use std::io::{BufReader, Read, Seek};
+use std::fs::{OpenOptions, File};
+use std::io::{BufReader, Cursor, Error, ErrorKind, Read, Seek, Write};
 use std::collections::BTreeMap;
 use std::iter::repeat;
 use std::sync::Arc;
 use itertools::Itertools;
-use rand::{Rng, OsRng};
+use rand::{ChaChaRng, Rng, SeedableRng, XorShiftRng};
+#[cfg(not(target_os = "wasi"))]
+use rand::OsRng;
 
 use bellman_ce::{
     Circuit,
@@ -22,6 +24,7 @@ use bellman_ce::{
     groth16::{
         Parameters,
         Proof,
+        VerifyingKey,
         generate_random_parameters as generate_random_parameters2,
         prepare_verifying_key,
         create_random_proof,
@@ -31,7 +34,10 @@ use bellman_ce::{
     pairing::{
         Engine,
         CurveAffine,
-        ff::PrimeField,
+        CurveProjective,
+        EncodedPoint,
+        GroupDecodingError,
+        ff::{Field, PrimeField, PrimeFieldRepr},
         ff::ScalarEngine,
         bn256::{
             Bn256,
@@ -43,13 +49,34 @@ use bellman_ce::{
     }
 };
 
+use crate::file_header::{
+    FileHeader,
+    ParamsMetadata,
+    FLAG_ENCRYPTED,
+    FLAG_ZSTD,
+    PARAMS_MAGIC,
+    PARAMS_VERSION,
+    CURVE_BN254,
+    CURVE_NAME,
+    check_curve_name,
+    describe_magic,
+    read_header_after_magic,
+    write_header,
+};
 use crate::utils::{
+    write_file_atomic,
     repr_to_big,
     proof_to_hex,
-    p1_to_vec,
-    p2_to_vec,
-    pairing_to_vec,
+    fr_from_str,
+    p1_to_vec_with_format,
+    p2_to_vec_with_format,
+    pairing_to_vec_with_format,
+    NumberFormat,
 };
+use crate::field_json::read_field_array;
+#[cfg(feature = "encryption")]
+use crate::encryption::{key_from_env, DecryptingReader, EncryptingWriter};
+pub use crate::solidity::{create_verifier_sol, create_verifier_sol_file};
 
 #[derive(Serialize, Deserialize)]
 struct CircuitJson {
@@ -65,6 +92,12 @@ struct CircuitJson {
 #[derive(Serialize, Deserialize)]
 struct ProofJson {
     pub protocol: String,
+    /// Missing in proofs of older zkutil and snarkjs versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub curve: Option<String>,
+    /// Warning for humans, set in dummy proofs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
     pub proof: Option<String>,
     pub pi_a: Vec<String>,
     pub pi_b: Vec<Vec<String>>,
@@ -95,6 +128,8 @@ struct ProvingKeyJson {
     #[serde(rename = "hExps")]
     pub h: Vec<Vec<String>>,
     pub protocol: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub curve: Option<String>,
     #[serde(rename = "nPublic")]
     pub n_public: usize,
     #[serde(rename = "nVars")]
@@ -105,21 +140,25 @@ struct ProvingKeyJson {
     pub domain_size: usize,
 }
 
+/// verification_key.json of `snarkjs zkey export verificationkey`, fields in its order. Files of
+/// websnark and snarkjs 0.1 have no curve and spell alpha `alfa`, they are read as well
 #[derive(Serialize, Deserialize)]
 struct VerifyingKeyJson {
-    #[serde(rename = "IC")]
-    pub ic: Vec<Vec<String>>,
-    pub vk_alfa_1: Vec<String>,
+    pub protocol: String,
+    #[serde(default)]
+    pub curve: String,
+    #[serde(rename = "nPublic")]
+    pub inputs_count: usize,
+    #[serde(alias = "vk_alfa_1")]
     pub vk_alpha_1: Vec<String>,
     pub vk_beta_2: Vec<Vec<String>>,
     pub vk_gamma_2: Vec<Vec<String>>,
     pub vk_delta_2: Vec<Vec<String>>,
-    pub vk_alfabeta_12: Vec<Vec<Vec<String>>>,
+    /// Not needed for verification, so it is optional
+    #[serde(default, alias = "vk_alfabeta_12")]
     pub vk_alphabeta_12: Vec<Vec<Vec<String>>>,
-    pub curve: String,
-    pub protocol: String,
-    #[serde(rename = "nPublic")]
-    pub inputs_count: usize,
+    #[serde(rename = "IC")]
+    pub ic: Vec<Vec<String>>,
 }
 
 pub type Constraint<E> = (
@@ -207,24 +246,17 @@ impl<'a, E: Engine> Circuit<E> for CircomCircuit<E> {
             )?;
         }
 
-        let make_index = |index|
-            if index < self.r1cs.num_inputs {
-                Index::Input(index)
-            } else {
-                Index::Aux(index - self.r1cs.num_inputs)
-            };
-        let make_lc = |lc_data: Vec<(usize, E::Fr)>|
-            lc_data.iter().fold(
-                LinearCombination::<E>::zero(),
-                |lc: LinearCombination<E>, (index, coeff)| lc + (*coeff, Variable::new_unchecked(make_index(*index)))
-            );
-        for (i, constraint) in self.r1cs.constraints.iter().enumerate() {
+        let constraints = SparseConstraints::from_r1cs(self.r1cs)?;
+        let add_terms = |lc: LinearCombination<E>, terms: &[(Variable, E::Fr)]|
+            terms.iter().fold(lc, |lc, (variable, coeff)| lc + (*coeff, *variable));
+        for i in 0..constraints.len() {
+            let [a, b, c] = constraints.constraint(i);
             // 0 * LC = 0 must be ignored
-            if !((constraint.0.is_empty() || constraint.1.is_empty()) && constraint.2.is_empty()) {
+            if !((a.is_empty() || b.is_empty()) && c.is_empty()) {
                 cs.enforce(|| format!("constraint {}", i),
-                    |_| make_lc(constraint.0.clone()),
-                    |_| make_lc(constraint.1.clone()),
-                    |_| make_lc(constraint.2.clone()),
+                    |lc| add_terms(lc, a),
+                    |lc| add_terms(lc, b),
+                    |lc| add_terms(lc, c),
                 );
             }
         }
@@ -232,13 +264,191 @@ impl<'a, E: Engine> Circuit<E> for CircomCircuit<E> {
     }
 }
 
+/// Constraints as one sparse matrix: the terms of every linear combination, sorted by wire and
+/// with their variables resolved, are stored back to back in a single preallocated buffer
+pub struct SparseConstraints<E: Engine> {
+    terms: Vec<(Variable, E::Fr)>,
+    // end of the terms of each linear combination in `terms`, three per constraint
+    ends: Vec<usize>,
+}
+
+impl<E: Engine> SparseConstraints<E> {
+    /// Converts the constraints of `r1cs`, freeing each one once it is copied.
+    /// Fails if a term refers to a wire the circuit doesn't have
+    pub fn from_r1cs(r1cs: R1CS<E>) -> Result<Self, SynthesisError> {
+        let num_terms = r1cs.constraints.iter().map(|c| c.0.len() + c.1.len() + c.2.len()).sum();
+        let mut sparse = SparseConstraints {
+            terms: Vec::with_capacity(num_terms),
+            ends: Vec::with_capacity(r1cs.constraints.len() * 3),
+        };
+        let (num_inputs, num_variables) = (r1cs.num_inputs, r1cs.num_variables);
+        for (i, (a, b, c)) in r1cs.constraints.into_iter().enumerate() {
+            for mut lc in [a, b, c] {
+                lc.sort_unstable_by_key(|term| term.0);
+                if let Some((wire, _)) = lc.last().filter(|(wire, _)| *wire >= num_variables) {
+                    return Err(SynthesisError::IoError(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("constraint {} refers to wire {}, but the circuit has {} wires", i, wire, num_variables),
+                    )));
+                }
+                sparse.terms.extend(lc.into_iter().map(|(wire, coeff)| {
+                    let index = if wire < num_inputs { Index::Input(wire) } else { Index::Aux(wire - num_inputs) };
+                    (Variable::new_unchecked(index), coeff)
+                }));
+                sparse.ends.push(sparse.terms.len());
+            }
+        }
+        Ok(sparse)
+    }
+
+    /// Number of constraints
+    pub fn len(&self) -> usize {
+        self.ends.len() / 3
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    /// Terms of the A, B and C combinations of constraint `i`
+    pub fn constraint(&self, i: usize) -> [&[(Variable, E::Fr)]; 3] {
+        let start = |lc: usize| if lc == 0 { 0 } else { self.ends[lc - 1] };
+        let lc = |lc: usize| &self.terms[start(lc)..self.ends[lc]];
+        [lc(3 * i), lc(3 * i + 1), lc(3 * i + 2)]
+    }
+}
+
+/// What `R1CS::simplify` removed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SimplifyStats {
+    pub constraints: usize,
+    pub terms: usize,
+}
+
+// sorts terms by wire, adds up the coefficients of terms of the same wire and drops zero terms
+fn merge_terms<E: Engine>(lc: &mut Vec<(usize, E::Fr)>) {
+    lc.sort_by_key(|term| term.0);
+    let mut merged: Vec<(usize, E::Fr)> = Vec::with_capacity(lc.len());
+    for (wire, coeff) in lc.drain(..) {
+        match merged.last_mut() {
+            Some(last) if last.0 == wire => last.1.add_assign(&coeff),
+            _ => merged.push((wire, coeff)),
+        }
+    }
+    merged.retain(|term| !term.1.is_zero());
+    *lc = merged;
+}
+
+// value of a merged combination of only the constant wire 0
+fn constant_value<E: Engine>(lc: &[(usize, E::Fr)]) -> Option<E::Fr> {
+    match lc {
+        [] => Some(E::Fr::zero()),
+        [(0, coeff)] => Some(*coeff),
+        _ => None,
+    }
+}
+
+impl<E: Engine> R1CS<E> {
+    /// Merges terms of the same wire in every linear combination and folds constants: `k1 * k2 = C`
+    /// becomes `0 * 0 = C - k1 * k2` and `0 * B = C` becomes `0 * 0 = C`. Constraints that hold for
+    /// every witness afterwards are removed. Wires don't change, so witnesses stay valid, but the
+    /// constraints do: params must be generated for the simplified circuit, see `FLAG_SIMPLIFIED`
+    pub fn simplify(&mut self) -> SimplifyStats {
+        let count_terms = |constraints: &[Constraint<E>]| constraints.iter().map(|c| c.0.len() + c.1.len() + c.2.len()).sum::<usize>();
+        let (constraints, terms) = (self.constraints.len(), count_terms(&self.constraints));
+        for (a, b, c) in self.constraints.iter_mut() {
+            merge_terms::<E>(a);
+            merge_terms::<E>(b);
+            merge_terms::<E>(c);
+            if a.is_empty() || b.is_empty() {
+                a.clear();
+                b.clear();
+            } else if let (Some(mut product), Some(k)) = (constant_value::<E>(a), constant_value::<E>(b)) {
+                product.mul_assign(&k);
+                product.negate();
+                c.push((0, product));
+                merge_terms::<E>(c);
+                a.clear();
+                b.clear();
+            }
+        }
+        self.constraints.retain(|c| !c.2.is_empty() || !(c.0.is_empty() || c.1.is_empty()));
+        SimplifyStats {
+            constraints: constraints - self.constraints.len(),
+            terms: terms - count_terms(&self.constraints),
+        }
+    }
+
+    /// Sorts terms of every linear combination by wire index
+    pub fn sort_terms(&mut self) {
+        for (a, b, c) in self.constraints.iter_mut() {
+            a.sort_by_key(|term| term.0);
+            b.sort_by_key(|term| term.0);
+            c.sort_by_key(|term| term.0);
+        }
+    }
+
+    /// Number of constraints that are actually enforced during synthesis
+    pub fn num_enforced_constraints(&self) -> usize {
+        self.constraints.iter()
+            .filter(|c| !((c.0.is_empty() || c.1.is_empty()) && c.2.is_empty()))
+            .count()
+    }
+
+    /// Index of the first constraint not satisfied by `witness`
+    pub fn unsatisfied_constraint(&self, witness: &[E::Fr]) -> Option<usize> {
+        (0..self.constraints.len()).find(|&i| !self.is_satisfied(i, witness))
+    }
+
+    /// Whether constraint `i` holds for `witness`
+    pub fn is_satisfied(&self, i: usize, witness: &[E::Fr]) -> bool {
+        let eval = |lc: &[(usize, E::Fr)]| lc.iter().fold(E::Fr::zero(), |mut acc, (wire, coeff)| {
+            let mut term = witness[*wire];
+            term.mul_assign(coeff);
+            acc.add_assign(&term);
+            acc
+        });
+        let (a, b, c) = &self.constraints[i];
+        let mut ab = eval(a);
+        ab.mul_assign(&eval(b));
+        ab == eval(c)
+    }
+
+    /// Size of the FFT evaluation domain required for this circuit
+    pub fn domain_size(&self) -> usize {
+        (self.num_enforced_constraints() + self.num_inputs).next_power_of_two()
+    }
+}
+
+/// Checks that trusted setup parameters were generated for a circuit of this shape
+pub fn check_params<E: Engine>(r1cs: &R1CS<E>, params: &Parameters<E>) -> Result<(), String> {
+    check_query_sizes(r1cs, params.vk.ic.len(), params.l.len(), params.h.len())
+}
+
+/// Checks sizes of the IC, L and H queries of parameters against a circuit, see `check_params`
+pub fn check_query_sizes<E: Engine>(r1cs: &R1CS<E>, params_inputs: usize, params_aux: usize, num_h: usize) -> Result<(), String> {
+    let params_domain = num_h + 1;
+    if params_inputs != r1cs.num_inputs || params_aux != r1cs.num_aux || params_domain != r1cs.domain_size() {
+        return Err(format!(
+            "params built for {} inputs / {} private variables / domain of {} constraints, \
+            circuit has {} inputs / {} private variables / {} constraints (domain {}). \
+            Make sure params were generated by `setup` for this circuit",
+            params_inputs, params_aux, params_domain,
+            r1cs.num_inputs, r1cs.num_aux, r1cs.constraints.len(), r1cs.domain_size(),
+        ));
+    }
+    Ok(())
+}
+
 pub fn prove<E: Engine, R: Rng>(circuit: CircomCircuit<E>, params: &Parameters<E>, mut rng: R) -> Result<Proof<E>, SynthesisError> {
+    let _span = tracing::info_span!("prove", constraints = circuit.r1cs.constraints.len()).entered();
     let mut params2 = params.clone();
     filter_params(&mut params2);
     create_random_proof(circuit, &params2, &mut rng)
 }
 
 pub fn generate_random_parameters<E: Engine, R: Rng>(circuit: CircomCircuit<E>, mut rng: R) -> Result<Parameters<E>, SynthesisError> {
+    let _span = tracing::info_span!("setup", constraints = circuit.r1cs.constraints.len()).entered();
     generate_random_parameters2(circuit, &mut rng)
 }
 
@@ -251,69 +461,101 @@ pub fn verify_circuit<E: Engine>(circuit: &CircomCircuit<E>, params: &Parameters
 }
 
 pub fn verify<E: Engine>(params: &Parameters<E>, proof: &Proof<E>, inputs: &[E::Fr]) -> Result<bool, SynthesisError> {
-    verify_proof(&prepare_verifying_key(&params.vk), proof, &inputs)
+    verify_with_key(&params.vk, proof, inputs)
+}
+
+pub fn verify_with_key<E: Engine>(vk: &VerifyingKey<E>, proof: &Proof<E>, inputs: &[E::Fr]) -> Result<bool, SynthesisError> {
+    let _span = tracing::info_span!("verify", inputs = inputs.len()).entered();
+    verify_proof(&prepare_verifying_key(vk), proof, inputs)
 }
 
-pub fn create_verifier_sol(params: &Parameters<Bn256>) -> String {
-    // TODO: use a simple template engine
-    let bytes = include_bytes!("verifier_groth.sol");
-    let template = String::from_utf8_lossy(bytes);
+/// Verifies many proofs at once with a random linear combination of their pairing equations:
+/// `prod e(r_i A_i, B_i) = e(sum r_i alpha, beta) e(sum r_i IC(x_i), gamma) e(sum r_i C_i, delta)`.
+/// Takes n + 3 Miller loops and a single final exponentiation instead of n times 4 pairings.
+/// A false result means at least one proof is invalid.
+pub fn verify_batch<E: Engine, R: Rng>(params: &Parameters<E>, proofs: &[(Proof<E>, Vec<E::Fr>)], rng: &mut R) -> Result<bool, SynthesisError> {
+    verify_batch_with_key(&params.vk, proofs, rng)
+}
 
-    let p1_to_str = |p: &<Bn256 as Engine>::G1Affine| {
-        if p.is_zero() {
-            // todo: throw instead
-            return String::from("<POINT_AT_INFINITY>");
+pub fn verify_batch_with_key<E: Engine, R: Rng>(vk: &VerifyingKey<E>, proofs: &[(Proof<E>, Vec<E::Fr>)], rng: &mut R) -> Result<bool, SynthesisError> {
+    let _span = tracing::info_span!("verify_batch", proofs = proofs.len()).entered();
+    if proofs.is_empty() {
+        return Ok(true);
+    }
+    let mut r_sum = E::Fr::zero();
+    // sum of r_i * x_ij for every public input j
+    let mut input_sums = vec![E::Fr::zero(); vk.ic.len() - 1];
+    let mut c_sum = E::G1::zero();
+    let mut a_prepared = Vec::with_capacity(proofs.len());
+    for (proof, inputs) in proofs {
+        if inputs.len() + 1 != vk.ic.len() {
+            return Err(SynthesisError::MalformedVerifyingKey);
         }
-        let xy = p.into_xy_unchecked();
-        let x = repr_to_big(xy.0.into_repr());
-        let y = repr_to_big(xy.1.into_repr());
-        format!("uint256({}), uint256({})", x, y)
-    };
-    let p2_to_str = |p: &<Bn256 as Engine>::G2Affine| {
-        if p.is_zero() {
-            // todo: throw instead
-            return String::from("<POINT_AT_INFINITY>");
+        let r: E::Fr = rng.gen();
+        r_sum.add_assign(&r);
+        for (sum, input) in input_sums.iter_mut().zip(inputs) {
+            let mut term = *input;
+            term.mul_assign(&r);
+            sum.add_assign(&term);
         }
-        let xy = p.into_xy_unchecked();
-        let x_c0 = repr_to_big(xy.0.c0.into_repr());
-        let x_c1 = repr_to_big(xy.0.c1.into_repr());
-        let y_c0 = repr_to_big(xy.1.c0.into_repr());
-        let y_c1 = repr_to_big(xy.1.c1.into_repr());
-        format!("[uint256({}), uint256({})], [uint256({}), uint256({})]", x_c1, x_c0, y_c1, y_c0)
-    };
+        c_sum.add_assign(&proof.c.mul(r.into_repr()));
+        a_prepared.push((proof.a.mul(r.into_repr()).into_affine().prepare(), proof.b.prepare()));
+    }
 
-    let template = template.replace("<%vk_alfa1%>", &*p1_to_str(&params.vk.alpha_g1));
-    let template = template.replace("<%vk_beta2%>", &*p2_to_str(&params.vk.beta_g2));
-    let template = template.replace("<%vk_gamma2%>", &*p2_to_str(&params.vk.gamma_g2));
-    let template = template.replace("<%vk_delta2%>", &*p2_to_str(&params.vk.delta_g2));
+    let mut ic_sum = vk.ic[0].mul(r_sum.into_repr());
+    for (sum, ic) in input_sums.iter().zip(vk.ic.iter().skip(1)) {
+        ic_sum.add_assign(&ic.mul(sum.into_repr()));
+    }
+    let mut alpha_sum = vk.alpha_g1.mul(r_sum.into_repr());
+    alpha_sum.negate();
+    ic_sum.negate();
+    c_sum.negate();
 
-    let template = template.replace("<%vk_ic_length%>", &*params.vk.ic.len().to_string());
-    let template = template.replace("<%vk_input_length%>", &*(params.vk.ic.len() - 1).to_string());
+    let alpha_prepared = (alpha_sum.into_affine().prepare(), vk.beta_g2.prepare());
+    let ic_prepared = (ic_sum.into_affine().prepare(), vk.gamma_g2.prepare());
+    let c_prepared = (c_sum.into_affine().prepare(), vk.delta_g2.prepare());
+    let terms = a_prepared.iter()
+        .chain(std::iter::once(&alpha_prepared))
+        .chain(std::iter::once(&ic_prepared))
+        .chain(std::iter::once(&c_prepared))
+        .map(|(g1, g2)| (g1, g2))
+        .collect::<Vec<_>>();
+    let result = E::final_exponentiation(&E::miller_loop(terms.iter()));
+    Ok(result == Some(E::Fqk::one()))
+}
 
-    let mut vi = String::from("");
-    for i in 0..params.vk.ic.len() {
-        vi = format!("{}{}vk.IC[{}] = Pairing.G1Point({});\n", vi, if vi.is_empty() { "" } else { "        " }, i, &*p1_to_str(&params.vk.ic[i]));
-    }
-    template.replace("<%vk_ic_pts%>", &*vi)
+pub fn proof_to_json(proof: &Proof<Bn256>) -> Result<String, serde_json::error::Error> {
+    proof_to_json_with_format(proof, NumberFormat::Decimal)
 }
 
-pub fn create_verifier_sol_file(params: &Parameters<Bn256>, filename: &str) -> std::io::Result<()> {
-    fs::write(filename, create_verifier_sol(params).as_bytes())
+/// Same as `proof_to_json`, with the points in `format`, the `proof` hex string is always the same
+pub fn proof_to_json_with_format(proof: &Proof<Bn256>, format: NumberFormat) -> Result<String, serde_json::error::Error> {
+    proof_to_json_with_note(proof, format, None)
 }
 
-pub fn proof_to_json(proof: &Proof<Bn256>) -> Result<String, serde_json::error::Error> {
+/// Same as `proof_to_json_with_format`, with a `note` field next to the protocol
+pub fn proof_to_json_with_note(proof: &Proof<Bn256>, format: NumberFormat, note: Option<&str>) -> Result<String, serde_json::error::Error> {
     serde_json::to_string_pretty(&ProofJson {
         protocol: "groth".to_string(),
+        curve: Some(CURVE_NAME.to_string()),
+        note: note.map(String::from),
         proof: Some(proof_to_hex(&proof)),
-        pi_a: p1_to_vec(&proof.a),
-        pi_b: p2_to_vec(&proof.b),
-        pi_c: p1_to_vec(&proof.c),
+        pi_a: p1_to_vec_with_format(&proof.a, format),
+        pi_b: p2_to_vec_with_format(&proof.b, format),
+        pi_c: p1_to_vec_with_format(&proof.c, format),
     })
 }
 
+pub fn write_proof_json<W: Write>(proof: &Proof<Bn256>, mut writer: W) -> std::io::Result<()> {
+    writer.write_all(proof_to_json(proof)?.as_bytes())
+}
+
 pub fn proof_to_json_file(proof: &Proof<Bn256>, filename: &str) -> std::io::Result<()> {
-    let str = proof_to_json(proof).unwrap(); // TODO: proper error handling
-    fs::write(filename, str.as_bytes())
+    write_file_atomic(filename, |w| write_proof_json(proof, w))
+}
+
+pub fn proof_to_json_file_with_format(proof: &Proof<Bn256>, format: NumberFormat, filename: &str) -> std::io::Result<()> {
+    write_file_atomic(filename, |w| w.write_all(proof_to_json_with_format(proof, format)?.as_bytes()))
 }
 
 pub fn load_params_file(filename: &str) -> Parameters<Bn256> {
@@ -325,7 +567,163 @@ pub fn load_params_file(filename: &str) -> Parameters<Bn256> {
 }
 
 pub fn load_params<R: Read>(reader: R) -> Parameters<Bn256> {
-    Parameters::read(reader, true).expect("unable to read params")
+    read_params(reader).unwrap_or_else(|e| panic!("unable to read params: {}", e))
+}
+
+/// Reads parameters with a zkutil header, or legacy headerless bellman parameters
+pub fn read_params<R: Read>(reader: R) -> std::io::Result<Parameters<Bn256>> {
+    Ok(read_params_with_header(reader)?.1)
+}
+
+/// Reads parameters and their header, which is `None` for legacy headerless files
+pub fn read_params_with_header<R: Read>(reader: R) -> std::io::Result<(Option<FileHeader>, Parameters<Bn256>)> {
+    read_params_with_header_checked(reader, true)
+}
+
+/// Reads parameters, without `checked` only the verifying key points are checked to be on the
+/// curve and in the right subgroup, which makes loading of large parameters faster
+pub fn read_params_with_header_checked<R: Read>(mut reader: R, checked: bool) -> std::io::Result<(Option<FileHeader>, Parameters<Bn256>)> {
+    let _span = tracing::info_span!("load_params").entered();
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if magic == PARAMS_MAGIC {
+        let header = read_header_after_magic(&mut reader, magic, PARAMS_VERSION)?;
+        let params = Parameters::read(params_reader(reader, header.flags)?, checked)?;
+        return Ok((Some(header), params));
+    }
+    if let Some(kind) = describe_magic(&magic) {
+        return Err(Error::new(ErrorKind::InvalidData, format!("expected params file, got {}", kind)));
+    }
+    let params = Parameters::read(Cursor::new(magic).chain(reader), checked).map_err(|e| Error::new(
+        ErrorKind::InvalidData,
+        format!("not a zkutil params file and not valid legacy bellman params ({})", e),
+    ))?;
+    Ok((None, params))
+}
+
+/// Reads the header and only the verifying key of parameters, queries that follow it are skipped
+pub fn read_verifying_key_with_header<R: Read>(mut reader: R) -> std::io::Result<(Option<FileHeader>, VerifyingKey<Bn256>)> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if magic == PARAMS_MAGIC {
+        let header = read_header_after_magic(&mut reader, magic, PARAMS_VERSION)?;
+        let vk = VerifyingKey::read(params_reader(reader, header.flags)?)?;
+        return Ok((Some(header), vk));
+    }
+    if let Some(kind) = describe_magic(&magic) {
+        return Err(Error::new(ErrorKind::InvalidData, format!("expected params file, got {}", kind)));
+    }
+    let vk = VerifyingKey::read(Cursor::new(magic).chain(reader)).map_err(|e| Error::new(
+        ErrorKind::InvalidData,
+        format!("not a zkutil params file and not valid legacy bellman params ({})", e),
+    ))?;
+    Ok((None, vk))
+}
+
+/// Reads only the header of a params file, `None` for legacy headerless files
+pub fn read_params_header<R: Read>(mut reader: R) -> std::io::Result<Option<FileHeader>> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != PARAMS_MAGIC {
+        return Ok(None);
+    }
+    Ok(Some(read_header_after_magic(&mut reader, magic, PARAMS_VERSION)?))
+}
+
+/// Reader of the parameters that follow a header with `flags`, decrypting and decompressing them if needed
+fn params_reader<'a, R: Read + 'a>(reader: R, flags: u32) -> std::io::Result<Box<dyn Read + 'a>> {
+    if flags & FLAG_ENCRYPTED == 0 {
+        return decompressed_reader(reader, flags);
+    }
+    #[cfg(feature = "encryption")]
+    {
+        decompressed_reader(DecryptingReader::new(reader, &key_from_env()?)?, flags)
+    }
+    #[cfg(not(feature = "encryption"))]
+    {
+        Err(Error::new(ErrorKind::InvalidData, "params are encrypted, but zkutil is built without the encryption feature"))
+    }
+}
+
+fn decompressed_reader<'a, R: Read + 'a>(reader: R, flags: u32) -> std::io::Result<Box<dyn Read + 'a>> {
+    if flags & FLAG_ZSTD == 0 {
+        return Ok(Box::new(reader));
+    }
+    #[cfg(feature = "compression")]
+    {
+        Ok(Box::new(zstd::Decoder::new(reader)?))
+    }
+    #[cfg(not(feature = "compression"))]
+    {
+        Err(Error::new(ErrorKind::InvalidData, "params are zstd compressed, but zkutil is built without the compression feature"))
+    }
+}
+
+/// Writes parameters after a header with `flags`, compressing them if `FLAG_ZSTD` is set and
+/// encrypting the result if `FLAG_ENCRYPTED` is set
+fn write_params_body<W: Write>(params: &Parameters<Bn256>, flags: u32, writer: W) -> std::io::Result<()> {
+    if flags & FLAG_ENCRYPTED == 0 {
+        return write_compressed_params(params, flags, writer);
+    }
+    #[cfg(feature = "encryption")]
+    {
+        let mut encryptor = EncryptingWriter::new(writer, &key_from_env()?, &mut create_rng())?;
+        write_compressed_params(params, flags, &mut encryptor)?;
+        encryptor.finish()?;
+        Ok(())
+    }
+    #[cfg(not(feature = "encryption"))]
+    {
+        Err(Error::new(ErrorKind::InvalidInput, "zkutil is built without the encryption feature"))
+    }
+}
+
+fn write_compressed_params<W: Write>(params: &Parameters<Bn256>, flags: u32, writer: W) -> std::io::Result<()> {
+    if flags & FLAG_ZSTD == 0 {
+        return params.write(writer);
+    }
+    #[cfg(feature = "compression")]
+    {
+        let mut encoder = zstd::Encoder::new(writer, 0)?;
+        params.write(&mut encoder)?;
+        encoder.finish()?;
+        Ok(())
+    }
+    #[cfg(not(feature = "compression"))]
+    {
+        Err(Error::new(ErrorKind::InvalidInput, "zkutil is built without the compression feature"))
+    }
+}
+
+pub fn write_params<W: Write>(params: &Parameters<Bn256>, writer: W) -> std::io::Result<()> {
+    write_params_with_flags(params, 0, writer)
+}
+
+/// Writes parameters with header flags, e.g. `FLAG_INSECURE_DEV`
+pub fn write_params_with_flags<W: Write>(params: &Parameters<Bn256>, flags: u32, mut writer: W) -> std::io::Result<()> {
+    write_header(&mut writer, &FileHeader { magic: PARAMS_MAGIC, version: 2, curve: CURVE_BN254, flags, metadata: None })?;
+    write_params_body(params, flags, writer)
+}
+
+/// Writes parameters with header flags and metadata of the circuit they were generated for
+pub fn write_params_with_metadata<W: Write>(params: &Parameters<Bn256>, flags: u32, metadata: &ParamsMetadata, mut writer: W) -> std::io::Result<()> {
+    // plain params stay readable by zkutil versions that only know version 3
+    let version = if flags & (FLAG_ZSTD | FLAG_ENCRYPTED) != 0 { PARAMS_VERSION } else { 3 };
+    let header = FileHeader { magic: PARAMS_MAGIC, version, curve: CURVE_BN254, flags, metadata: Some(metadata.clone()) };
+    write_header(&mut writer, &header)?;
+    write_params_body(params, flags, writer)
+}
+
+pub fn write_params_file(params: &Parameters<Bn256>, filename: &str) -> std::io::Result<()> {
+    write_params_file_with_flags(params, 0, filename)
+}
+
+pub fn write_params_file_with_flags(params: &Parameters<Bn256>, flags: u32, filename: &str) -> std::io::Result<()> {
+    write_file_atomic(filename, |w| write_params_with_flags(params, flags, w))
+}
+
+pub fn write_params_file_with_metadata(params: &Parameters<Bn256>, flags: u32, metadata: &ParamsMetadata, filename: &str) -> std::io::Result<()> {
+    write_file_atomic(filename, |w| write_params_with_metadata(params, flags, metadata, w))
 }
 
 pub fn load_inputs_json_file<E: Engine>(filename: &str) -> Vec<E::Fr> {
@@ -336,9 +734,9 @@ pub fn load_inputs_json_file<E: Engine>(filename: &str) -> Vec<E::Fr> {
     load_inputs_json::<E, BufReader<File>>(BufReader::new(reader))
 }
 
+/// Reads public inputs given as decimal or `0x`-prefixed hex strings or integers
 pub fn load_inputs_json<E: Engine, R: Read>(reader: R) -> Vec<E::Fr> {
-    let inputs: Vec<String> = serde_json::from_reader(reader).unwrap();
-    inputs.into_iter().map(|x| E::Fr::from_str(&x).unwrap()).collect::<Vec<E::Fr>>()
+    read_field_array::<E::Fr, R>(reader).unwrap_or_else(|e| panic!("unable to read public inputs: {}", e))
 }
 
 pub fn load_proof_json_file<E: Engine>(filename: &str) -> Proof<Bn256> {
@@ -350,38 +748,120 @@ pub fn load_proof_json_file<E: Engine>(filename: &str) -> Proof<Bn256> {
 }
 
 pub fn load_proof_json<R: Read>(reader: R) -> Proof<Bn256> {
-    let proof: ProofJson = serde_json::from_reader(reader).unwrap();
-    Proof {
-        a: G1Affine::from_xy_checked(
-            Fq::from_str(&proof.pi_a[0]).unwrap(),
-            Fq::from_str(&proof.pi_a[1]).unwrap(),
-        ).unwrap(),
-        b: G2Affine::from_xy_checked(
-            Fq2 {
-                c0: Fq::from_str(&proof.pi_b[0][0]).unwrap(),
-                c1: Fq::from_str(&proof.pi_b[0][1]).unwrap(),
-            },
-            Fq2 {
-                c0: Fq::from_str(&proof.pi_b[1][0]).unwrap(),
-                c1: Fq::from_str(&proof.pi_b[1][1]).unwrap(),
-            },
-        ).unwrap(),
-        c: G1Affine::from_xy_checked(
-            Fq::from_str(&proof.pi_c[0]).unwrap(),
-            Fq::from_str(&proof.pi_c[1]).unwrap(),
-        ).unwrap(),
+    read_proof_json(reader, true).unwrap_or_else(|e| panic!("unable to read proof: {}", e))
+}
+
+pub fn read_proof_json_file(filename: &str, checked: bool) -> std::io::Result<Proof<Bn256>> {
+    read_proof_json(BufReader::new(File::open(filename)?), checked)
+}
+
+/// Reads proof JSON with decimal or `0x` hex coordinates, with `checked` points that are not on
+/// the curve or not in the prime order subgroup are rejected
+pub fn read_proof_json<R: Read>(reader: R, checked: bool) -> std::io::Result<Proof<Bn256>> {
+    let proof: ProofJson = serde_json::from_reader(reader)?;
+    if let Some(curve) = &proof.curve {
+        check_curve_name(curve, "Proof")?;
+    }
+    let fq = |coords: &[String], i: usize, name: &str| coords.get(i)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("{}[{}] is missing", name, i)))
+        .and_then(|c| fq_from_coordinate(c, &format!("{}[{}]", name, i)));
+    let fq2 = |i: usize| {
+        let coords = proof.pi_b.get(i).map(Vec::as_slice).unwrap_or(&[]);
+        Ok::<_, Error>(Fq2 { c0: fq(coords, 0, &format!("pi_b[{}]", i))?, c1: fq(coords, 1, &format!("pi_b[{}]", i))? })
+    };
+    let proof = Proof {
+        a: G1Affine::from_xy_unchecked(fq(&proof.pi_a[..], 0, "pi_a")?, fq(&proof.pi_a[..], 1, "pi_a")?),
+        b: G2Affine::from_xy_unchecked(fq2(0)?, fq2(1)?),
+        c: G1Affine::from_xy_unchecked(fq(&proof.pi_c[..], 0, "pi_c")?, fq(&proof.pi_c[..], 1, "pi_c")?),
+    };
+    if checked {
+        check_proof(&proof)?;
+    }
+    Ok(proof)
+}
+
+// coordinates of BLS12-381 and other larger curves don't fit into the BN254 base field
+fn fq_from_coordinate(value: &str, name: &str) -> std::io::Result<Fq> {
+    fr_from_str::<Fq>(value).ok_or_else(|| {
+        let hint = if value.len() > 77 { ", the proof looks like it is for a curve other than BN254" } else { "" };
+        Error::new(ErrorKind::InvalidData, format!("{} is not a valid coordinate{}", name, hint))
+    })
+}
+
+/// Message of a point decoding error. `GroupDecodingError` can't be formatted with `{}`, its
+/// Display impl calls itself for every error but invalid coordinates
+#[allow(deprecated)]
+pub fn decoding_error(e: &GroupDecodingError) -> String {
+    match e {
+        GroupDecodingError::CoordinateDecodingError(..) => e.to_string(),
+        _ => std::error::Error::description(e).to_string(),
     }
 }
 
+/// Rejects points that are not on the curve or not in the prime order subgroup, BN254 G2 has
+/// points of other orders on the curve
+pub fn check_point<G: CurveAffine>(point: &G) -> Result<(), String> {
+    point.into_uncompressed().into_affine().map(|_| ()).map_err(|e| decoding_error(&e))
+}
+
+pub fn check_proof(proof: &Proof<Bn256>) -> std::io::Result<()> {
+    let invalid = |name: &str, e: String| Error::new(ErrorKind::InvalidData, format!("invalid proof point {}: {}", name, e));
+    check_point(&proof.a).map_err(|e| invalid("pi_a", e))?;
+    check_point(&proof.b).map_err(|e| invalid("pi_b", e))?;
+    check_point(&proof.c).map_err(|e| invalid("pi_c", e))
+}
+
+fn point_from_json<G: CurveAffine>(point: Option<G>, name: &str) -> std::io::Result<G> {
+    let point = point.ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("{} is missing or not a valid point", name)))?;
+    check_point(&point).map_err(|e| Error::new(ErrorKind::InvalidData, format!("invalid verifying key point {}: {}", name, e)))?;
+    Ok(point)
+}
+
+fn g1_from_json(point: &[String], name: &str) -> std::io::Result<G1Affine> {
+    let fq = |i: usize| point.get(i).and_then(|c| fr_from_str::<Fq>(c));
+    point_from_json(fq(0).zip(fq(1)).map(|(x, y)| G1Affine::from_xy_unchecked(x, y)), name)
+}
+
+fn g2_from_json(point: &[Vec<String>], name: &str) -> std::io::Result<G2Affine> {
+    let fq = |i: usize, j: usize| point.get(i).and_then(|c| c.get(j)).and_then(|c| fr_from_str::<Fq>(c));
+    let fq2 = |i: usize| fq(i, 0).zip(fq(i, 1)).map(|(c0, c1)| Fq2 { c0, c1 });
+    point_from_json(fq2(0).zip(fq2(1)).map(|(x, y)| G2Affine::from_xy_unchecked(x, y)), name)
+}
+
+/// Reads a snarkjs `verification_key.json` like the one `export-keys` writes. It has only the points
+/// verification uses, `beta_g1` and `delta_g1` of the result are zero
+pub fn read_verifying_key_json<R: Read>(reader: R) -> std::io::Result<VerifyingKey<Bn256>> {
+    let json: VerifyingKeyJson = serde_json::from_reader(reader)?;
+    if !json.curve.is_empty() {
+        check_curve_name(&json.curve, "Verification key")?;
+    }
+    Ok(VerifyingKey {
+        alpha_g1: g1_from_json(&json.vk_alpha_1, "vk_alpha_1")?,
+        beta_g1: G1Affine::zero(),
+        beta_g2: g2_from_json(&json.vk_beta_2, "vk_beta_2")?,
+        gamma_g2: g2_from_json(&json.vk_gamma_2, "vk_gamma_2")?,
+        delta_g1: G1Affine::zero(),
+        delta_g2: g2_from_json(&json.vk_delta_2, "vk_delta_2")?,
+        ic: json.ic.iter().enumerate().map(|(i, p)| g1_from_json(p, &format!("IC[{}]", i))).collect::<std::io::Result<_>>()?,
+    })
+}
+
+/// Drops zero points of the queries, in place for queries that are not shared with other parameters
 pub fn filter_params<E: Engine>(params: &mut Parameters<E>) {
-    params.vk.ic = params.vk.ic.clone().into_iter().filter(|x| !x.is_zero()).collect::<Vec<_>>();
-    params.h = Arc::new((*params.h).clone().into_iter().filter(|x| !x.is_zero()).collect::<Vec<_>>());
-    params.a = Arc::new((*params.a).clone().into_iter().filter(|x| !x.is_zero()).collect::<Vec<_>>());
-    params.b_g1 = Arc::new((*params.b_g1).clone().into_iter().filter(|x| !x.is_zero()).collect::<Vec<_>>());
-    params.b_g2 = Arc::new((*params.b_g2).clone().into_iter().filter(|x| !x.is_zero()).collect::<Vec<_>>());
+    params.vk.ic.retain(|x| !x.is_zero());
+    Arc::make_mut(&mut params.h).retain(|x| !x.is_zero());
+    Arc::make_mut(&mut params.a).retain(|x| !x.is_zero());
+    Arc::make_mut(&mut params.b_g1).retain(|x| !x.is_zero());
+    Arc::make_mut(&mut params.b_g2).retain(|x| !x.is_zero());
 }
 
 pub fn proving_key_json(params: &Parameters<Bn256>, circuit: CircomCircuit<Bn256>) -> Result<String, serde_json::error::Error> {
+    proving_key_json_with_format(params, circuit, NumberFormat::Decimal)
+}
+
+pub fn proving_key_json_with_format(params: &Parameters<Bn256>, circuit: CircomCircuit<Bn256>, format: NumberFormat) -> Result<String, serde_json::error::Error> {
+    let p1_to_vec = |p: &G1Affine| p1_to_vec_with_format(p, format);
+    let p2_to_vec = |p: &G2Affine| p2_to_vec_with_format(p, format);
     let mut pols_a: Vec<BTreeMap<String, String>> = vec![];
     let mut pols_b: Vec<BTreeMap<String, String>> = vec![];
     let mut pols_c: Vec<BTreeMap<String, String>> = vec![];
@@ -392,18 +872,18 @@ pub fn proving_key_json(params: &Parameters<Bn256>, circuit: CircomCircuit<Bn256
     }
     for c in 0..circuit.r1cs.constraints.len() {
         for item in circuit.r1cs.constraints[c].0.iter() {
-            pols_a[item.0].insert(c.to_string(), repr_to_big(item.1.into_repr()));
+            pols_a[item.0].insert(c.to_string(), format.repr(item.1.into_repr()));
         }
         for item in circuit.r1cs.constraints[c].1.iter() {
-            pols_b[item.0].insert(c.to_string(), repr_to_big(item.1.into_repr()));
+            pols_b[item.0].insert(c.to_string(), format.repr(item.1.into_repr()));
         }
         for item in circuit.r1cs.constraints[c].2.iter() {
-            pols_c[item.0].insert(c.to_string(), repr_to_big(item.1.into_repr()));
+            pols_c[item.0].insert(c.to_string(), format.repr(item.1.into_repr()));
         }
     }
 
     for i in 0..circuit.r1cs.num_inputs {
-        pols_a[i].insert((circuit.r1cs.constraints.len() + i).to_string(), String::from("1"));
+        pols_a[i].insert((circuit.r1cs.constraints.len() + i).to_string(), format.small(1));
     }
 
     let domain_bits = log2_floor(circuit.r1cs.constraints.len() + circuit.r1cs.num_inputs) + 1;
@@ -450,6 +930,7 @@ pub fn proving_key_json(params: &Parameters<Bn256>, circuit: CircomCircuit<Bn256
         vk_delta_2: p2_to_vec(&params.vk.delta_g2),
         h: params.h.iter().map(|e| p1_to_vec(e)).collect_vec(),
         protocol: String::from("groth"),
+        curve: Some(CURVE_NAME.to_string()),
         n_public,
         n_vars,
         domain_bits,
@@ -468,31 +949,52 @@ fn log2_floor(num: usize) -> usize {
     pow
 }
 
+pub fn write_proving_key_json<W: Write>(params: &Parameters<Bn256>, circuit: CircomCircuit<Bn256>, mut writer: W) -> std::io::Result<()> {
+    writer.write_all(proving_key_json(params, circuit)?.as_bytes())
+}
+
 pub fn proving_key_json_file(params: &Parameters<Bn256>, circuit: CircomCircuit<Bn256>, filename: &str) -> std::io::Result<()> {
-    let str = proving_key_json(params, circuit).unwrap(); // TODO: proper error handling
-    fs::write(filename, str.as_bytes())
+    write_file_atomic(filename, |w| write_proving_key_json(params, circuit, w))
+}
+
+pub fn proving_key_json_file_with_format(params: &Parameters<Bn256>, circuit: CircomCircuit<Bn256>, format: NumberFormat, filename: &str) -> std::io::Result<()> {
+    write_file_atomic(filename, |w| w.write_all(proving_key_json_with_format(params, circuit, format)?.as_bytes()))
 }
 
 pub fn verification_key_json(params: &Parameters<Bn256>) -> Result<String, serde_json::error::Error> {
+    verification_key_json_with_format(params, NumberFormat::Decimal)
+}
+
+/// Same as `verification_key_json`, byte for byte the file snarkjs exports with decimal numbers
+pub fn verification_key_json_with_format(params: &Parameters<Bn256>, format: NumberFormat) -> Result<String, serde_json::error::Error> {
     let verification_key = VerifyingKeyJson {
-        ic: params.vk.ic.iter().map(|e| p1_to_vec(e)).collect_vec(),
-        vk_alfa_1: p1_to_vec(&params.vk.alpha_g1),
-        vk_alpha_1: p1_to_vec(&params.vk.alpha_g1),
-        vk_beta_2: p2_to_vec(&params.vk.beta_g2),
-        vk_gamma_2: p2_to_vec(&params.vk.gamma_g2),
-        vk_delta_2: p2_to_vec(&params.vk.delta_g2),
-        vk_alfabeta_12: pairing_to_vec(&Bn256::pairing(params.vk.alpha_g1, params.vk.beta_g2)),
-        vk_alphabeta_12: pairing_to_vec(&Bn256::pairing(params.vk.alpha_g1, params.vk.beta_g2)),
+        protocol: String::from("groth16"),
+        curve: CURVE_NAME.to_string(),
         inputs_count: params.vk.ic.len() - 1,
-        curve: String::from("BN254"),
-        protocol: String::from("groth"),
+        vk_alpha_1: p1_to_vec_with_format(&params.vk.alpha_g1, format),
+        vk_beta_2: p2_to_vec_with_format(&params.vk.beta_g2, format),
+        vk_gamma_2: p2_to_vec_with_format(&params.vk.gamma_g2, format),
+        vk_delta_2: p2_to_vec_with_format(&params.vk.delta_g2, format),
+        vk_alphabeta_12: pairing_to_vec_with_format(&Bn256::pairing(params.vk.alpha_g1, params.vk.beta_g2), format),
+        ic: params.vk.ic.iter().map(|p| p1_to_vec_with_format(p, format)).collect_vec(),
     };
-    serde_json::to_string_pretty(&verification_key)
+    // snarkjs writes JSON indented by one space
+    let mut json = vec![];
+    let mut serializer = serde_json::Serializer::with_formatter(&mut json, serde_json::ser::PrettyFormatter::with_indent(b" "));
+    serde::Serialize::serialize(&verification_key, &mut serializer)?;
+    Ok(String::from_utf8(json).expect("serde_json writes UTF-8"))
+}
+
+pub fn write_verification_key_json<W: Write>(params: &Parameters<Bn256>, mut writer: W) -> std::io::Result<()> {
+    writer.write_all(verification_key_json(params)?.as_bytes())
 }
 
 pub fn verification_key_json_file(params: &Parameters<Bn256>, filename: &str) -> std::io::Result<()> {
-    let str = verification_key_json(params).unwrap(); // TODO: proper error handling
-    fs::write(filename, str.as_bytes())
+    write_file_atomic(filename, |w| write_verification_key_json(params, w))
+}
+
+pub fn verification_key_json_file_with_format(params: &Parameters<Bn256>, format: NumberFormat, filename: &str) -> std::io::Result<()> {
+    write_file_atomic(filename, |w| w.write_all(verification_key_json_with_format(params, format)?.as_bytes()))
 }
 
 pub fn witness_from_json_file<E: Engine>(filename: &str) -> Vec<E::Fr> {
@@ -503,9 +1005,26 @@ pub fn witness_from_json_file<E: Engine>(filename: &str) -> Vec<E::Fr> {
     witness_from_json::<E, BufReader<File>>(BufReader::new(reader))
 }
 
+/// Reads witness values given as decimal or `0x`-prefixed hex strings or integers
 pub fn witness_from_json<E: Engine, R: Read>(reader: R) -> Vec<E::Fr> {
-    let witness: Vec<String> = serde_json::from_reader(reader).unwrap();
-    witness.into_iter().map(|x| E::Fr::from_str(&x).unwrap()).collect::<Vec<E::Fr>>()
+    read_witness_json::<E, R>(reader).unwrap_or_else(|e| panic!("unable to read witness: {}", e))
+}
+
+pub fn read_witness_json<E: Engine, R: Read>(reader: R) -> std::io::Result<Vec<E::Fr>> {
+    let _span = tracing::info_span!("load_witness", format = "json").entered();
+    read_field_array::<E::Fr, R>(reader)
+}
+
+pub fn witness_to_json<E: Engine>(witness: &[E::Fr]) -> Result<String, serde_json::error::Error> {
+    serde_json::to_string_pretty(&witness.iter().map(|x| repr_to_big(x.into_repr())).collect_vec())
+}
+
+pub fn write_witness_json<E: Engine, W: Write>(witness: &[E::Fr], mut writer: W) -> std::io::Result<()> {
+    writer.write_all(witness_to_json::<E>(witness)?.as_bytes())
+}
+
+pub fn witness_to_json_file<E: Engine>(witness: &[E::Fr], filename: &str) -> std::io::Result<()> {
+    write_file_atomic(filename, |w| write_witness_json::<E, _>(witness, w))
 }
 
 pub fn witness_from_bin_file<E: Engine>(filename: &str) -> Result<Vec<E::Fr>, std::io::Error> {
@@ -517,10 +1036,41 @@ pub fn witness_from_bin_file<E: Engine>(filename: &str) -> Result<Vec<E::Fr>, st
 }
 
 pub fn witness_from_bin<E: Engine, R: Read>(reader: R) -> Result<Vec<E::Fr>, std::io::Error> {
+    let _span = tracing::info_span!("load_witness", format = "wtns").entered();
     let file = crate::wtns_reader::read::<E, R>(reader)?;
     Ok(file.witness)
 }
 
+pub fn witness_to_bin<E: Engine, W: Write>(witness: &[E::Fr], writer: W) -> Result<(), std::io::Error> {
+    crate::wtns_reader::write::<E, W>(writer, witness)
+}
+
+pub fn witness_to_bin_file<E: Engine>(witness: &[E::Fr], filename: &str) -> Result<(), std::io::Error> {
+    write_file_atomic(filename, |w| witness_to_bin::<E, _>(witness, w))
+}
+
+/// Reads a raw witness array: the number of elements as a little-endian u64, then the elements as
+/// 32-byte little-endian numbers, what simple custom witness generators write
+pub fn witness_from_array<E: Engine, R: Read>(mut reader: R) -> Result<Vec<E::Fr>, std::io::Error> {
+    let _span = tracing::info_span!("load_witness", format = "array").entered();
+    let mut count = [0u8; 8];
+    reader.read_exact(&mut count)?;
+    let count = u64::from_le_bytes(count);
+    let mut witness = Vec::with_capacity(count.min(1 << 20) as usize);
+    for _ in 0..count {
+        witness.push(crate::wtns_reader::read_field::<_, E>(&mut reader)?);
+    }
+    Ok(witness)
+}
+
+pub fn witness_to_array<E: Engine, W: Write>(witness: &[E::Fr], mut writer: W) -> Result<(), std::io::Error> {
+    writer.write_all(&(witness.len() as u64).to_le_bytes())?;
+    for value in witness {
+        value.into_repr().write_le(&mut writer)?;
+    }
+    Ok(())
+}
+
 pub fn r1cs_from_json_file<E: Engine>(filename: &str) -> R1CS<E> {
     let reader = OpenOptions::new()
         .read(true)
@@ -530,36 +1080,135 @@ pub fn r1cs_from_json_file<E: Engine>(filename: &str) -> R1CS<E> {
 }
 
 pub fn r1cs_from_json<E: Engine, R: Read>(reader: R) -> R1CS<E> {
-    let circuit_json: CircuitJson = serde_json::from_reader(reader).unwrap();
+    read_r1cs_json(reader).unwrap_or_else(|e| panic!("{}", e))
+}
 
-    let num_inputs = circuit_json.num_inputs + circuit_json.num_outputs + 1;
-    let num_aux = circuit_json.num_variables - num_inputs;
+pub fn read_r1cs_json_file<E: Engine>(filename: &str) -> Result<R1CS<E>, std::io::Error> {
+    read_r1cs_json(BufReader::new(File::open(filename)?))
+        .map_err(|e| Error::new(e.kind(), format!("{}: {}", filename, e)))
+}
 
-    let convert_constraint = |lc: &BTreeMap<String, String>| {
-        lc.iter().map(|(index, coeff)| (index.parse().unwrap(), E::Fr::from_str(coeff).unwrap())).collect_vec()
-    };
+/// Reads circuit JSON, errors point at the offending value
+pub fn read_r1cs_json<E: Engine, R: Read>(reader: R) -> Result<R1CS<E>, std::io::Error> {
+    let _span = tracing::info_span!("load_r1cs", format = "json").entered();
+    let json: serde_json::Value = serde_json::from_reader(reader)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, format!("invalid circuit JSON: {}", e)))?;
+    r1cs_from_json_value(&json).map_err(|e| Error::new(ErrorKind::InvalidData, format!("invalid circuit JSON: {}", e)))
+}
 
-    let constraints = circuit_json.constraints.iter().map(
-        |c| (convert_constraint(&c[0]), convert_constraint(&c[1]), convert_constraint(&c[2]))
-    ).collect_vec();
+fn json_type(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "a boolean",
+        serde_json::Value::Number(_) => "a number",
+        serde_json::Value::String(_) => "a string",
+        serde_json::Value::Array(_) => "an array",
+        serde_json::Value::Object(_) => "an object",
+    }
+}
 
-    R1CS {
-        num_inputs,
-        num_aux,
-        num_variables: circuit_json.num_variables,
-        constraints,
+fn json_count(json: &serde_json::Value, key: &str) -> Result<usize, String> {
+    match json.get(key) {
+        Some(value) => value.as_u64().map(|n| n as usize)
+            .ok_or_else(|| format!("{} must be a non-negative integer, got {}", key, json_type(value))),
+        None => Err(format!("missing key '{}'", key)),
+    }
+}
+
+fn lc_from_json<E: Engine>(lc: &serde_json::Value, i: usize, j: usize, num_variables: usize) -> Result<Vec<(usize, E::Fr)>, String> {
+    let lc = lc.as_object().ok_or_else(|| format!("constraints[{}][{}] must be an object, got {}", i, j, json_type(lc)))?;
+    let mut terms = Vec::with_capacity(lc.len());
+    for (key, value) in lc {
+        let wire = key.parse::<usize>()
+            .map_err(|_| format!("constraints[{}][{}] key '{}' must be a wire index", i, j, key))?;
+        if wire >= num_variables {
+            return Err(format!("constraints[{}][{}] key '{}' is out of range, nVars is {}", i, j, key, num_variables));
+        }
+        let coeff = value.as_str()
+            .ok_or_else(|| format!("constraints[{}][{}] key '{}' must map to a string, got {}", i, j, key, json_type(value)))?;
+        let coeff = fr_from_str::<E::Fr>(coeff)
+            .ok_or_else(|| format!("constraints[{}][{}] key '{}' maps to \"{}\", which is not a field element", i, j, key, coeff))?;
+        terms.push((wire, coeff));
+    }
+    Ok(terms)
+}
+
+/// Checks the structure of circom circuit JSON and converts it
+pub fn r1cs_from_json_value<E: Engine>(json: &serde_json::Value) -> Result<R1CS<E>, String> {
+    if !json.is_object() {
+        return Err(format!("circuit must be an object, got {}", json_type(json)));
+    }
+    let num_pub_inputs = json_count(json, "nPubInputs")?;
+    let num_outputs = json_count(json, "nOutputs")?;
+    let num_variables = json_count(json, "nVars")?;
+    let num_inputs = num_pub_inputs + num_outputs + 1;
+    if num_inputs > num_variables {
+        return Err(format!("nVars is {}, less than nPubInputs + nOutputs + 1 = {}", num_variables, num_inputs));
+    }
+    let constraints = match json.get("constraints") {
+        Some(serde_json::Value::Array(constraints)) => constraints,
+        Some(value) => return Err(format!("constraints must be an array, got {}", json_type(value))),
+        None => return Err(String::from("missing key 'constraints'")),
+    };
+    let mut parsed = Vec::with_capacity(constraints.len());
+    for (i, constraint) in constraints.iter().enumerate() {
+        let lcs = match constraint {
+            serde_json::Value::Array(lcs) if lcs.len() == 3 => lcs,
+            serde_json::Value::Array(lcs) => return Err(format!("constraints[{}] must have 3 elements, got {}", i, lcs.len())),
+            value => return Err(format!("constraints[{}] must be an array, got {}", i, json_type(value))),
+        };
+        parsed.push((
+            lc_from_json::<E>(&lcs[0], i, 0, num_variables)?,
+            lc_from_json::<E>(&lcs[1], i, 1, num_variables)?,
+            lc_from_json::<E>(&lcs[2], i, 2, num_variables)?,
+        ));
     }
+
+    let mut r1cs = R1CS {
+        num_inputs,
+        num_aux: num_variables - num_inputs,
+        num_variables,
+        constraints: parsed,
+    };
+    // constraint maps are keyed by strings, "10" comes before "2"
+    r1cs.sort_terms();
+    Ok(r1cs)
+}
+
+/// Circuit in circom JSON format, all public inputs are written as inputs and none as outputs
+pub fn r1cs_to_json<E: Engine>(r1cs: &R1CS<E>) -> Result<String, serde_json::error::Error> {
+    let convert_lc = |lc: &[(usize, E::Fr)]| {
+        lc.iter().map(|(index, coeff)| (index.to_string(), repr_to_big(coeff.into_repr()))).collect::<BTreeMap<_, _>>()
+    };
+    let circuit_json = CircuitJson {
+        constraints: r1cs.constraints.iter().map(|(a, b, c)| vec![convert_lc(a), convert_lc(b), convert_lc(c)]).collect(),
+        num_inputs: r1cs.num_inputs - 1,
+        num_outputs: 0,
+        num_variables: r1cs.num_variables,
+    };
+    serde_json::to_string(&circuit_json)
+}
+
+pub fn write_r1cs_json<E: Engine, W: Write>(r1cs: &R1CS<E>, mut writer: W) -> std::io::Result<()> {
+    writer.write_all(r1cs_to_json(r1cs)?.as_bytes())
+}
+
+pub fn r1cs_to_json_file<E: Engine>(r1cs: &R1CS<E>, filename: &str) -> std::io::Result<()> {
+    write_file_atomic(filename, |w| write_r1cs_json(r1cs, w))
 }
 
 pub fn r1cs_from_bin<R: Read + Seek>(reader: R) -> Result<(R1CS<Bn256>, Vec<usize>), std::io::Error> {
+    let _span = tracing::info_span!("load_r1cs", format = "r1cs").entered();
     let file = crate::r1cs_reader::read(reader)?;
+    if !file.custom_gate_uses.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidData, "circuit uses custom gates, they can't be proven with Groth16"));
+    }
     let num_inputs = (1 + file.header.n_pub_in + file.header.n_pub_out) as usize;
     let num_variables = file.header.n_wires as usize;
     let num_aux = num_variables - num_inputs;
-    Ok((
-        R1CS { num_aux, num_inputs, num_variables, constraints: file.constraints, },
-        file.wire_mapping.iter().map(|e| *e as usize).collect_vec()
-    ))
+    let mut r1cs = R1CS { num_aux, num_inputs, num_variables, constraints: file.constraints, };
+    r1cs.sort_terms();
+    Ok((r1cs, file.wire_mapping.iter().map(|e| *e as usize).collect_vec()))
 }
 
 pub fn r1cs_from_bin_file(filename: &str) -> Result<(R1CS<Bn256>, Vec<usize>), std::io::Error> {
@@ -570,6 +1219,197 @@ pub fn r1cs_from_bin_file(filename: &str) -> Result<(R1CS<Bn256>, Vec<usize>), s
     r1cs_from_bin(BufReader::new(reader))
 }
 
+#[cfg(not(target_os = "wasi"))]
 pub fn create_rng() -> Box<dyn Rng> {
     Box::new(OsRng::new().unwrap())
 }
+
+/// OsRng of rand 0.4 doesn't support WASI, seed ChaCha from the WASI `random_get` call instead
+#[cfg(target_os = "wasi")]
+pub fn create_rng() -> Box<dyn Rng> {
+    let mut seed = [0u8; 32];
+    getrandom::getrandom(&mut seed).unwrap();
+    let seed = seed.chunks(4).map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect::<Vec<_>>();
+    Box::new(rand::ChaChaRng::from_seed(&seed[..]))
+}
+
+/// Creates RNGs for setup and proving where zkutil would otherwise call `create_rng`,
+/// implement it to supply an own CSPRNG, e.g. HSM-backed or deterministic in tests
+pub trait RngSource: Send + Sync {
+    fn rng(&self) -> Box<dyn Rng>;
+}
+
+/// Operating system CSPRNG from `create_rng`
+pub struct OsRngSource;
+
+impl RngSource for OsRngSource {
+    fn rng(&self) -> Box<dyn Rng> {
+        create_rng()
+    }
+}
+
+impl<F: Fn() -> Box<dyn Rng> + Send + Sync> RngSource for F {
+    fn rng(&self) -> Box<dyn Rng> {
+        self()
+    }
+}
+
+/// Deterministic RNG for `setup --dev`, anyone can recompute the toxic waste from it
+pub fn create_insecure_dev_rng() -> Box<dyn Rng> {
+    Box::new(XorShiftRng::from_seed([0x7a6b_7574, 0x696c_2d64, 0x6576_2d73, 0x6565_6421]))
+}
+
+/// Deterministic RNG seeded with SHA-256 of `seed` for byte-identical proofs in golden fixtures.
+/// INSECURE: anyone who knows the seed can recompute the proof randomness and extract the witness
+pub fn create_seeded_rng(seed: &str) -> Box<dyn Rng> {
+    use sha2::{Digest, Sha256};
+    let hash = Sha256::digest(seed.as_bytes());
+    let seed = hash.chunks(4).map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect::<Vec<_>>();
+    Box::new(ChaChaRng::from_seed(&seed[..]))
+}
+
+#[test]
+fn batch_verification() {
+    use crate::test_utils::{fr, multiplier_circuit, multiplier_params, multiplier_witness};
+    let (r1cs, params) = (multiplier_circuit(), multiplier_params());
+    let mut rng = create_insecure_dev_rng();
+    let mut proofs = vec![];
+    for (a, b) in [(2, 3), (4, 5)].iter() {
+        let circuit = CircomCircuit { r1cs: r1cs.clone(), witness: Some(multiplier_witness(*a, *b)), wire_mapping: None };
+        proofs.push((prove(circuit, &params, &mut rng).unwrap(), vec![fr(&(a * b).to_string())]));
+    }
+    assert!(verify_batch(&params, &proofs, &mut rng).unwrap());
+    proofs[1].1[0] = fr("21");
+    assert!(!verify_batch(&params, &proofs, &mut rng).unwrap());
+}
+
+#[test]
+fn circuit_json_errors() {
+    let error = |json: &str| r1cs_from_json_value::<Bn256>(&serde_json::from_str(json).unwrap()).err().unwrap();
+    let header = r#""nPubInputs": 1, "nOutputs": 0, "nVars": 3"#;
+    assert_eq!(error(r#"{"nPubInputs": 1, "nOutputs": 0, "constraints": []}"#), "missing key 'nVars'");
+    assert_eq!(
+        error(&format!(r#"{{{}, "constraints": [[{{}}, {{"2": 1}}, {{}}]]}}"#, header)),
+        "constraints[0][1] key '2' must map to a string, got a number",
+    );
+    assert_eq!(
+        error(&format!(r#"{{{}, "constraints": [[{{}}, {{}}, {{"3": "1"}}]]}}"#, header)),
+        "constraints[0][2] key '3' is out of range, nVars is 3",
+    );
+    assert_eq!(error(&format!(r#"{{{}, "constraints": [[{{}}, {{}}]]}}"#, header)), "constraints[0] must have 3 elements, got 2");
+    let r1cs = r1cs_from_json_value::<Bn256>(&serde_json::from_str(&format!(
+        r#"{{{}, "constraints": [[{{"2": "1"}}, {{"10": "1", "2": "1"}}, {{"1": "1"}}]], "signals": []}}"#,
+        r#""nPubInputs": 1, "nOutputs": 0, "nVars": 11"#,
+    )).unwrap()).unwrap();
+    assert_eq!((r1cs.num_inputs, r1cs.num_aux), (2, 9));
+    assert_eq!(r1cs.constraints[0].1.iter().map(|t| t.0).collect::<Vec<_>>(), vec![2, 10]);
+}
+
+#[test]
+fn proof_point_checks() {
+    let proof = Proof::<Bn256> { a: G1Affine::one(), b: G2Affine::one(), c: G1Affine::one() };
+    let json = proof_to_json(&proof).unwrap();
+    assert!(read_proof_json(json.as_bytes(), true).unwrap() == proof);
+    let hex = proof_to_json_with_format(&proof, NumberFormat::Hex).unwrap();
+    assert!(read_proof_json(hex.as_bytes(), true).unwrap() == proof);
+    let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    value["pi_a"][1] = "3".into();
+    let off_curve = value.to_string();
+    assert!(read_proof_json(off_curve.as_bytes(), true).is_err());
+    assert!(read_proof_json(off_curve.as_bytes(), false).is_ok());
+}
+
+#[test]
+fn simplify_constraints() {
+    use bellman_ce::pairing::bn256::Fr;
+    let fr = |s: &str| Fr::from_str(s).unwrap();
+    let mut minus_one = fr("1");
+    minus_one.negate();
+    // wires: one, c (public), a, b
+    let mut r1cs = R1CS::<Bn256> {
+        num_inputs: 2,
+        num_aux: 2,
+        num_variables: 4,
+        constraints: vec![
+            // (a + a - a) * (2 * b - b) = c
+            (vec![(2, fr("1")), (2, fr("1")), (2, minus_one)], vec![(3, fr("2")), (3, minus_one)], vec![(1, fr("1"))]),
+            // 2 * 3 = 6
+            (vec![(0, fr("2"))], vec![(0, fr("3"))], vec![(0, fr("6"))]),
+            // 2 * 3 = c
+            (vec![(0, fr("2"))], vec![(0, fr("3"))], vec![(1, fr("1"))]),
+            // (a - a) * b = 0
+            (vec![(2, fr("1")), (2, minus_one)], vec![(3, fr("1"))], vec![]),
+        ],
+    };
+    let witness = [fr("1"), fr("6"), fr("2"), fr("3")];
+    let stats = r1cs.simplify();
+    assert_eq!(stats, SimplifyStats { constraints: 2, terms: 10 });
+    assert_eq!(r1cs.constraints.len(), 2);
+    assert_eq!(r1cs.constraints[0], (vec![(2, fr("1"))], vec![(3, fr("1"))], vec![(1, fr("1"))]));
+    assert!(r1cs.constraints[1].0.is_empty() && r1cs.constraints[1].1.is_empty());
+    assert_eq!(r1cs.unsatisfied_constraint(&witness), None);
+    assert_eq!(r1cs.unsatisfied_constraint(&[fr("1"), fr("7"), fr("2"), fr("3")]), Some(0));
+    assert_eq!(r1cs.simplify(), SimplifyStats::default());
+}
+
+#[test]
+fn sparse_constraints() {
+    use crate::test_utils::{fr, multiplier_circuit, multiplier_params, multiplier_witness};
+    let variable = |index| Variable::new_unchecked(index);
+    let mut r1cs = multiplier_circuit();
+    // a * (b + c) = c + a, with terms out of order
+    r1cs.constraints.push((vec![(2, fr("1"))], vec![(3, fr("1")), (1, fr("1"))], vec![(2, fr("1")), (1, fr("1"))]));
+    let sparse = SparseConstraints::from_r1cs(r1cs).unwrap();
+    assert_eq!(sparse.len(), 2);
+    let [a, b, c] = sparse.constraint(1);
+    assert_eq!(a, &[(variable(Index::Aux(0)), fr("1"))][..]);
+    assert_eq!(b, &[(variable(Index::Input(1)), fr("1")), (variable(Index::Aux(1)), fr("1"))][..]);
+    assert_eq!(c, &[(variable(Index::Input(1)), fr("1")), (variable(Index::Aux(0)), fr("1"))][..]);
+
+    let mut r1cs = multiplier_circuit();
+    r1cs.constraints[0].2.push((4, fr("1")));
+    assert!(matches!(SparseConstraints::from_r1cs(r1cs.clone()), Err(SynthesisError::IoError(_))));
+    let circuit = CircomCircuit { r1cs, witness: Some(multiplier_witness(2, 3)), wire_mapping: None };
+    assert!(prove(circuit, &multiplier_params(), create_rng()).is_err());
+}
+
+#[test]
+fn snarkjs_verification_key() {
+    let mut rng = create_insecure_dev_rng();
+    let vk = VerifyingKey::<Bn256> {
+        alpha_g1: G1Affine::one(),
+        beta_g1: G1Affine::one(),
+        beta_g2: G2Affine::one(),
+        gamma_g2: G2Affine::one(),
+        delta_g1: G1Affine::one(),
+        delta_g2: G2Affine::one(),
+        ic: vec![G1Affine::one(), rng.gen::<bellman_ce::pairing::bn256::G1>().into_affine()],
+    };
+    let params = Parameters { vk, h: Arc::new(vec![]), l: Arc::new(vec![]), a: Arc::new(vec![]), b_g1: Arc::new(vec![]), b_g2: Arc::new(vec![]) };
+    let json = verification_key_json(&params).unwrap();
+    assert!(json.starts_with("{\n \"protocol\": \"groth16\",\n \"curve\": \"bn128\",\n \"nPublic\": 1,\n \"vk_alpha_1\": [\n  \"1\",\n  \"2\",\n  \"1\"\n ],\n \"vk_beta_2\": [\n  [\n   \""));
+    let keys = serde_json::from_str::<serde_json::Value>(&json).unwrap().as_object().unwrap().keys().cloned().collect::<Vec<_>>();
+    assert_eq!(keys.len(), 9);
+    assert!(json.find("\"vk_alphabeta_12\"").unwrap() < json.find("\"IC\"").unwrap() && json.ends_with("]\n ]\n}"));
+    assert!(read_verifying_key_json(json.as_bytes()).unwrap().ic == params.vk.ic);
+    // websnark and snarkjs 0.1 files
+    let old = json.replace("vk_alpha_1", "vk_alfa_1").replace("vk_alphabeta_12", "vk_alfabeta_12").replace(" \"curve\": \"bn128\",\n", "");
+    assert!(!old.contains("curve") && old.contains("vk_alfabeta_12"));
+    assert!(read_verifying_key_json(old.as_bytes()).unwrap().alpha_g1 == params.vk.alpha_g1);
+}
+
+#[test]
+fn witness_array_roundtrip() {
+    use bellman_ce::pairing::bn256::Fr;
+    let witness = vec![Fr::one(), Fr::from_str("6").unwrap(), Fr::from_str("123456789").unwrap()];
+    let mut array = vec![];
+    witness_to_array::<Bn256, _>(&witness, &mut array).unwrap();
+    assert_eq!(array.len(), 8 + 32 * 3);
+    assert_eq!(&array[..9], &[3, 0, 0, 0, 0, 0, 0, 0, 1]);
+    let format = crate::file_header::detect_stream_format(&mut Cursor::new(&array)).unwrap();
+    assert_eq!(format, crate::file_header::FileFormat::WitnessArray);
+    assert!(crate::circuit::read_witness::<Bn256, _>(Cursor::new(&array)).unwrap() == witness);
+    // elements not reduced modulo the field are rejected
+    array[8..40].copy_from_slice(&[0xff; 32]);
+    assert!(witness_from_array::<Bn256, _>(&array[..]).is_err());
+}
@@ -0,0 +1,134 @@
+//! Loading circuits, witnesses and signal names in any of the supported formats.
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Read, Result, Seek};
+use std::path::Path;
+use bellman_ce::pairing::{Engine, bn256::Bn256};
+use crate::circom_circuit::{
+    R1CS,
+    read_r1cs_json,
+    read_r1cs_json_file,
+    r1cs_from_bin,
+    r1cs_from_bin_file,
+    read_witness_json,
+    witness_from_array,
+    witness_from_bin,
+    witness_from_bin_file,
+};
+use crate::bundle;
+use crate::file_header::{FileFormat, detect_file_format, detect_stream_format};
+use crate::keys::load_bundle;
+use crate::r1cs_cache;
+use crate::r1cs_reader;
+use crate::signals::{self, Signal, SignalCounts};
+#[cfg(feature = "zkif")]
+use crate::zkif;
+
+/// Returns `filename`, or `circuit.r1cs` unless only `circuit.json` exists
+pub fn resolve_circuit_file(filename: Option<String>) -> String {
+    resolve_default(filename, "circuit.r1cs", "circuit.json")
+}
+
+/// Returns `filename`, or `witness.wtns` unless only `witness.json` exists
+pub fn resolve_witness_file(filename: Option<String>) -> String {
+    resolve_default(filename, "witness.wtns", "witness.json")
+}
+
+fn resolve_default(filename: Option<String>, binary: &str, json: &str) -> String {
+    match filename {
+        Some(s) => s,
+        None => if Path::new(binary).exists() || !Path::new(json).exists() {
+            binary.to_string()
+        } else {
+            json.to_string()
+        }
+    }
+}
+
+/// Error for a file or stream named `source` that holds something else than `expected`
+pub fn unexpected_format(source: &str, expected: &str, format: FileFormat) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("expected {} in {}, got {}", expected, source, format.name()))
+}
+
+/// Loads a circuit from circom JSON, binary R1CS, a bundle or zkinterface messages,
+/// parsed circuits are cached
+pub fn load_r1cs(filename: &str) -> Result<R1CS<Bn256>> {
+    let _span = tracing::info_span!("load_circuit").entered();
+    match detect_file_format(filename)? {
+        FileFormat::Json => r1cs_cache::load_cached(filename, || read_r1cs_json_file(filename)),
+        FileFormat::R1cs => r1cs_cache::load_cached(filename, || Ok(r1cs_from_bin_file(filename)?.0)),
+        FileFormat::Bundle => load_bundle(filename)?.r1cs(),
+        #[cfg(feature = "zkif")]
+        FileFormat::Zkif => zkif::load_r1cs(filename),
+        format => Err(unexpected_format(filename, "circuit R1CS or JSON", format)),
+    }
+}
+
+/// Reads a circuit in any format from an in-memory buffer or other seekable stream
+pub fn read_r1cs<R: Read + Seek>(mut reader: R) -> Result<R1CS<Bn256>> {
+    match detect_stream_format(&mut reader)? {
+        FileFormat::Json => read_r1cs_json(reader),
+        FileFormat::R1cs => Ok(r1cs_from_bin(reader)?.0),
+        FileFormat::Bundle => bundle::read(reader)?.r1cs(),
+        format => Err(unexpected_format("input", "circuit R1CS or JSON", format)),
+    }
+}
+
+/// Loads a witness from JSON, binary wtns, a raw witness array or zkinterface messages
+pub fn load_witness<E: Engine>(filename: &str) -> Result<Vec<E::Fr>> {
+    match detect_file_format(filename)? {
+        FileFormat::Json => read_witness_json::<E, _>(BufReader::new(File::open(filename)?))
+            .map_err(|e| Error::new(e.kind(), format!("{}: {}", filename, e))),
+        FileFormat::Wtns => witness_from_bin_file::<E>(filename),
+        FileFormat::WitnessArray => witness_from_array::<E, _>(BufReader::new(File::open(filename)?)),
+        #[cfg(feature = "zkif")]
+        FileFormat::Zkif => zkif::load_witness::<E>(filename),
+        format => Err(unexpected_format(filename, "witness WTNS, JSON or raw array", format)),
+    }
+}
+
+/// Reads a witness in JSON, binary wtns or raw array format from a seekable stream
+pub fn read_witness<E: Engine, R: Read + Seek>(mut reader: R) -> Result<Vec<E::Fr>> {
+    match detect_stream_format(&mut reader)? {
+        FileFormat::Json => read_witness_json::<E, R>(reader),
+        FileFormat::Wtns => witness_from_bin::<E, R>(reader),
+        FileFormat::WitnessArray => witness_from_array::<E, R>(reader),
+        format => Err(unexpected_format("input", "witness WTNS, JSON or raw array", format)),
+    }
+}
+
+fn read_signal_counts(circuit_file: &str) -> Result<(Option<Vec<(usize, String)>>, SignalCounts)> {
+    match detect_file_format(circuit_file)? {
+        FileFormat::Json => {
+            let (names, counts) = signals::signals_from_json(BufReader::new(File::open(circuit_file)?))?;
+            Ok((Some(names), counts))
+        }
+        FileFormat::R1cs => {
+            let file = r1cs_reader::read_info(BufReader::new(File::open(circuit_file)?))?;
+            Ok((None, SignalCounts {
+                num_outputs: file.header.n_pub_out as usize,
+                num_pub_inputs: file.header.n_pub_in as usize,
+                num_prv_inputs: file.header.n_prv_in as usize,
+            }))
+        }
+        format => Err(unexpected_format(circuit_file, "circuit R1CS or JSON", format)),
+    }
+}
+
+/// Loads numbers of outputs and inputs of a circuit, which don't need a `.sym` file
+pub fn load_signal_counts(circuit_file: &str) -> Result<SignalCounts> {
+    Ok(read_signal_counts(circuit_file)?.1)
+}
+
+/// Loads signals of a circuit, names are taken from the `.sym` file if given or from circuit JSON
+pub fn load_signals(circuit_file: &str, sym_file: Option<&str>) -> Result<Vec<Signal>> {
+    let (json_names, counts) = read_signal_counts(circuit_file)?;
+    let names = match (sym_file, json_names) {
+        (Some(sym), _) => signals::read_sym(BufReader::new(File::open(sym)?))?,
+        (None, Some(names)) => names,
+        (None, None) => return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("signal names are not available in {}, a .sym file is required", circuit_file),
+        )),
+    };
+    Ok(signals::classify(names, counts))
+}
@@ -0,0 +1,116 @@
+//! Signal/constraint graph of a circuit in GraphViz DOT format.
+//!
+//! Signals are ellipses and constraints are boxes. Signals in A and B of a constraint point to it
+//! and the constraint points to signals in C, which follows the data flow of `c <== a * b`.
+//! The constant wire 0 is left out.
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Result, Write};
+use bellman_ce::pairing::{bn256::{Bn256, Fr}, ff::Field};
+use crate::circom_circuit::R1CS;
+use crate::signals::{Signal, Visibility};
+use crate::utils::write_file_atomic;
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn wires(lc: &[(usize, Fr)]) -> impl Iterator<Item = usize> + '_ {
+    lc.iter().filter(|(wire, coeff)| *wire != 0 && !coeff.is_zero()).map(|(wire, _)| *wire)
+}
+
+/// Writes the graph, restricted to constraints that touch a signal whose name starts with
+/// `prefix` if given. Signals outside of the prefix are drawn dashed.
+pub fn write_graph<W: Write>(r1cs: &R1CS<Bn256>, signals: Option<&[Signal]>, prefix: Option<&str>, mut writer: W) -> Result<()> {
+    // first name of each wire, aliases are left out
+    let mut names = BTreeMap::new();
+    let mut visibility = BTreeMap::new();
+    for signal in signals.unwrap_or(&[]) {
+        names.entry(signal.wire).or_insert_with(|| signal.name.as_str());
+        visibility.entry(signal.wire).or_insert(signal.visibility);
+    }
+    let selected = |wire: &usize| match prefix {
+        Some(prefix) => signals.unwrap_or(&[]).iter().any(|s| s.wire == *wire && s.name.starts_with(prefix)),
+        None => true,
+    };
+
+    let mut edges = vec![];
+    let mut nodes = BTreeSet::new();
+    for (i, (a, b, c)) in r1cs.constraints.iter().enumerate() {
+        let inputs = wires(a).chain(wires(b)).collect::<BTreeSet<_>>();
+        let outputs = wires(c).collect::<BTreeSet<_>>();
+        if !inputs.iter().chain(outputs.iter()).any(|wire| selected(wire)) {
+            continue;
+        }
+        nodes.extend(inputs.iter().chain(outputs.iter()).copied());
+        edges.push((i, inputs, outputs));
+    }
+
+    writeln!(writer, "digraph circuit {{")?;
+    writeln!(writer, "  rankdir=LR;")?;
+    writeln!(writer, "  node [shape=ellipse];")?;
+    for wire in nodes {
+        let label = names.get(&wire).map_or_else(|| format!("w{}", wire), |name| escape(name));
+        let mut attrs = vec![];
+        let mut style = vec![];
+        match visibility.get(&wire) {
+            Some(Visibility::PublicInput) | Some(Visibility::PublicOutput) => attrs.push(String::from("peripheries=2")),
+            Some(Visibility::PrivateInput) => {
+                style.push("filled");
+                attrs.push(String::from("fillcolor=lightgrey"));
+            }
+            _ if wire < r1cs.num_inputs => attrs.push(String::from("peripheries=2")),
+            _ => {}
+        }
+        if !selected(&wire) {
+            style.push("dashed");
+        }
+        if !style.is_empty() {
+            attrs.push(format!("style=\"{}\"", style.join(",")));
+        }
+        let attrs = attrs.iter().map(|a| format!(", {}", a)).collect::<String>();
+        writeln!(writer, "  w{} [label=\"{}\"{}];", wire, label, attrs)?;
+    }
+    for (i, inputs, outputs) in edges {
+        writeln!(writer, "  c{} [shape=box, label=\"#{}\"];", i, i)?;
+        for wire in inputs {
+            writeln!(writer, "  w{} -> c{};", wire, i)?;
+        }
+        for wire in outputs {
+            writeln!(writer, "  c{} -> w{};", i, wire)?;
+        }
+    }
+    writeln!(writer, "}}")
+}
+
+pub fn write_graph_file(r1cs: &R1CS<Bn256>, signals: Option<&[Signal]>, prefix: Option<&str>, filename: &str) -> Result<()> {
+    write_file_atomic(filename, |w| write_graph(r1cs, signals, prefix, w))
+}
+
+#[test]
+fn prefix_graph() {
+    use bellman_ce::pairing::ff::PrimeField;
+    let one = Fr::from_str("1").unwrap();
+    // main.sq.out = main.in * main.in, main.out = main.sq.out * main.k
+    let r1cs = R1CS::<Bn256> {
+        num_inputs: 2,
+        num_aux: 3,
+        num_variables: 5,
+        constraints: vec![
+            (vec![(2, one)], vec![(2, one)], vec![(3, one)]),
+            (vec![(3, one)], vec![(4, one)], vec![(1, one)]),
+        ],
+    };
+    let signal = |wire, name: &str, visibility| Signal { wire, name: name.to_string(), visibility };
+    let signals = vec![
+        signal(1, "main.out", Visibility::PublicOutput),
+        signal(2, "main.in", Visibility::PrivateInput),
+        signal(3, "main.sq.out", Visibility::Private),
+        signal(4, "main.k", Visibility::Private),
+    ];
+    let mut out = vec![];
+    write_graph(&r1cs, Some(&signals), Some("main.sq."), &mut out).unwrap();
+    let dot = String::from_utf8(out).unwrap();
+    assert!(dot.contains("w2 -> c0;") && dot.contains("c0 -> w3;") && dot.contains("w3 -> c1;"));
+    assert!(dot.contains("w3 [label=\"main.sq.out\"];"));
+    assert!(dot.contains("w2 [label=\"main.in\", fillcolor=lightgrey, style=\"filled,dashed\"];"));
+}
@@ -0,0 +1,183 @@
+//! Encryption of params files at rest with AES-256-GCM.
+//!
+//! Everything after the plaintext header of a `FLAG_ENCRYPTED` file is a 7-byte random nonce prefix
+//! followed by the body in 64 KiB chunks, each sealed with a 16-byte tag. The nonce of a chunk is
+//! the prefix, its big-endian u32 index and a byte that is 1 for the last chunk, so reordered,
+//! dropped or truncated chunks fail to decrypt (the STREAM construction).
+//!
+//! The 32-byte key is read as hex from `ZKUTIL_PARAMS_KEY`, or from the output of the shell command
+//! in `ZKUTIL_PARAMS_KEY_COMMAND`, which lets a KMS client decrypt a data key on demand.
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Result, Write};
+use std::process::Command;
+use aes_gcm::aead::{Aead, NewAead};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use rand::Rng;
+use crate::utils::hex_to_bytes;
+
+pub const KEY_VAR: &str = "ZKUTIL_PARAMS_KEY";
+pub const KEY_COMMAND_VAR: &str = "ZKUTIL_PARAMS_KEY_COMMAND";
+
+const CHUNK: usize = 1 << 16;
+const TAG: usize = 16;
+const PREFIX: usize = 7;
+
+fn invalid<T: Into<Box<dyn std::error::Error + Send + Sync>>>(msg: T) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+fn parse_key(hex: &str) -> Result<[u8; 32]> {
+    let bytes = hex_to_bytes(hex).filter(|b| b.len() == 32).ok_or_else(|| invalid("params key must be 32 bytes in hex"))?;
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&bytes);
+    Ok(key)
+}
+
+/// Key from `ZKUTIL_PARAMS_KEY` or the output of `ZKUTIL_PARAMS_KEY_COMMAND`
+pub fn key_from_env() -> Result<[u8; 32]> {
+    if let Ok(hex) = std::env::var(KEY_VAR) {
+        return parse_key(&hex);
+    }
+    let command = std::env::var(KEY_COMMAND_VAR).map_err(|_| Error::new(
+        ErrorKind::NotFound,
+        format!("params are encrypted, set {} or {} to the key", KEY_VAR, KEY_COMMAND_VAR),
+    ))?;
+    let output = Command::new("sh").arg("-c").arg(&command).output()?;
+    if !output.status.success() {
+        return Err(Error::new(ErrorKind::Other, format!("{} failed: {}", KEY_COMMAND_VAR, String::from_utf8_lossy(&output.stderr).trim())));
+    }
+    parse_key(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn nonce(prefix: &[u8; PREFIX], index: u32, last: bool) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..PREFIX].copy_from_slice(prefix);
+    nonce[PREFIX..PREFIX + 4].copy_from_slice(&index.to_be_bytes());
+    nonce[11] = last as u8;
+    nonce
+}
+
+/// Encrypts everything written to it, `finish` must be called to write the last chunk
+pub struct EncryptingWriter<W: Write> {
+    writer: W,
+    cipher: Aes256Gcm,
+    prefix: [u8; PREFIX],
+    index: u32,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> EncryptingWriter<W> {
+    pub fn new<R: Rng>(mut writer: W, key: &[u8; 32], rng: &mut R) -> Result<Self> {
+        let mut prefix = [0u8; PREFIX];
+        rng.fill_bytes(&mut prefix);
+        writer.write_all(&prefix)?;
+        Ok(EncryptingWriter { writer, cipher: Aes256Gcm::new(Key::from_slice(key)), prefix, index: 0, buf: Vec::with_capacity(CHUNK) })
+    }
+
+    fn seal(&mut self, last: bool) -> Result<()> {
+        let nonce = nonce(&self.prefix, self.index, last);
+        let sealed = self.cipher.encrypt(Nonce::from_slice(&nonce), &self.buf[..]).map_err(|_| invalid("encryption failed"))?;
+        self.writer.write_all(&sealed)?;
+        self.index = self.index.checked_add(1).ok_or_else(|| invalid("file is too large to encrypt"))?;
+        self.buf.clear();
+        Ok(())
+    }
+
+    /// Writes the last chunk and returns the inner writer
+    pub fn finish(mut self) -> Result<W> {
+        self.seal(true)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for EncryptingWriter<W> {
+    fn write(&mut self, data: &[u8]) -> Result<usize> {
+        // a full chunk is sealed only when more data follows, the last chunk may be full too
+        if self.buf.len() == CHUNK && !data.is_empty() {
+            self.seal(false)?;
+        }
+        let n = data.len().min(CHUNK - self.buf.len());
+        self.buf.extend_from_slice(&data[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Decrypts a body written by `EncryptingWriter`
+pub struct DecryptingReader<R: Read> {
+    reader: BufReader<R>,
+    cipher: Aes256Gcm,
+    prefix: [u8; PREFIX],
+    index: u32,
+    chunk: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl<R: Read> DecryptingReader<R> {
+    pub fn new(reader: R, key: &[u8; 32]) -> Result<Self> {
+        let mut reader = BufReader::with_capacity(CHUNK + TAG, reader);
+        let mut prefix = [0u8; PREFIX];
+        reader.read_exact(&mut prefix)?;
+        Ok(DecryptingReader { reader, cipher: Aes256Gcm::new(Key::from_slice(key)), prefix, index: 0, chunk: vec![], pos: 0, done: false })
+    }
+
+    fn next_chunk(&mut self) -> Result<()> {
+        let mut sealed = Vec::with_capacity(CHUNK + TAG);
+        (&mut self.reader).take((CHUNK + TAG) as u64).read_to_end(&mut sealed)?;
+        let last = self.reader.fill_buf()?.is_empty();
+        if last && sealed.len() < TAG {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "encrypted params are truncated"));
+        }
+        let nonce = nonce(&self.prefix, self.index, last);
+        self.chunk = self.cipher.decrypt(Nonce::from_slice(&nonce), &sealed[..])
+            .map_err(|_| invalid("unable to decrypt params, the key is wrong or the file is corrupted"))?;
+        self.pos = 0;
+        self.index += 1;
+        self.done = last;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DecryptingReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> Result<usize> {
+        while self.pos == self.chunk.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.next_chunk()?;
+        }
+        let n = out.len().min(self.chunk.len() - self.pos);
+        out[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[test]
+fn encrypt_and_decrypt() {
+    use rand::{SeedableRng, XorShiftRng};
+    let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+    let key = [7u8; 32];
+    for len in &[0, 1, CHUNK - 1, CHUNK, CHUNK + 1, 3 * CHUNK] {
+        let data = (0..*len).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let mut writer = EncryptingWriter::new(vec![], &key, &mut rng).unwrap();
+        writer.write_all(&data).unwrap();
+        let sealed = writer.finish().unwrap();
+        assert_eq!(sealed.len(), PREFIX + len + TAG * (len / CHUNK + if len % CHUNK == 0 && *len > 0 { 0 } else { 1 }));
+        let mut decrypted = vec![];
+        DecryptingReader::new(&sealed[..], &key).unwrap().read_to_end(&mut decrypted).unwrap();
+        assert_eq!(decrypted, data);
+
+        assert!(DecryptingReader::new(&sealed[..], &[8u8; 32]).unwrap().read_to_end(&mut vec![]).is_err());
+        if *len > CHUNK {
+            // dropping the last chunk must not decrypt to a shorter file
+            let truncated = &sealed[..PREFIX + CHUNK + TAG];
+            assert!(DecryptingReader::new(truncated, &key).unwrap().read_to_end(&mut vec![]).is_err());
+        }
+    }
+    assert!(parse_key("00").is_err());
+}
@@ -0,0 +1,352 @@
+//! Fetching proofs submitted to the generated verifier contract from an Ethereum node, encoding
+//! proofs as arguments of its `verifyProof(bytes proof, uint256[N] input)` and, with the `submit`
+//! feature, sending them in signed transactions.
+use std::io::{Error, ErrorKind, Result};
+use std::time::{Duration, Instant};
+use bellman_ce::{
+    groth16::Proof,
+    pairing::{
+        CurveAffine,
+        bn256::{Bn256, Fq, Fq2, Fr, G1Affine, G2Affine},
+        ff::{PrimeField, PrimeFieldRepr},
+    },
+};
+use sha3::{Digest, Keccak256};
+use crate::circom_circuit::check_proof;
+use crate::remote::{http_post_json, to_hex};
+use crate::utils::{hex_to_bytes, proof_to_hex};
+
+/// Keccak-256 as used by Ethereum, with the original Keccak padding rather than SHA3-256
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+/// Solidity function selector of a signature like `transfer(address,uint256)`
+pub fn selector(signature: &str) -> [u8; 4] {
+    let mut out = [0u8; 4];
+    out.copy_from_slice(&keccak256(signature.as_bytes())[..4]);
+    out
+}
+
+fn invalid<T: Into<Box<dyn std::error::Error + Send + Sync>>>(msg: T) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// Performs a JSON-RPC call and returns its `result` field
+pub fn rpc_call(url: &str, method: &str, params: serde_json::Value) -> Result<serde_json::Value> {
+    let request = serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+    let response = http_post_json(url, request)?;
+    if let Some(error) = response.get("error") {
+        return Err(Error::new(ErrorKind::Other, format!("RPC error: {}", error)));
+    }
+    response.get("result")
+        .cloned()
+        .filter(|r| !r.is_null())
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("{} returned no result", method)))
+}
+
+/// Returns input data of a transaction
+pub fn transaction_input(url: &str, tx_hash: &str) -> Result<Vec<u8>> {
+    let tx = rpc_call(url, "eth_getTransactionByHash", serde_json::json!([tx_hash]))?;
+    let input = tx.get("input").and_then(|i| i.as_str()).ok_or_else(|| invalid("Transaction has no input data"))?;
+    hex_to_bytes(input).ok_or_else(|| invalid("Transaction input is not valid hex"))
+}
+
+fn word(data: &[u8], index: usize) -> Result<&[u8]> {
+    data.get(index * 32..(index + 1) * 32).ok_or_else(|| invalid("Calldata is too short"))
+}
+
+fn word_to_usize(word: &[u8]) -> Result<usize> {
+    if word[..24].iter().any(|b| *b != 0) {
+        return Err(invalid("Calldata offset is too large"));
+    }
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&word[24..]);
+    Ok(u64::from_be_bytes(bytes) as usize)
+}
+
+/// Converts a big-endian 32-byte word into a field element, rejecting non-canonical values
+pub fn word_to_field<F: PrimeField>(word: &[u8]) -> Result<F> {
+    let mut repr = F::zero().into_repr();
+    repr.read_be(word)?;
+    F::from_repr(repr).map_err(|e| invalid(format!("Value is not a valid field element: {}", e)))
+}
+
+/// Builds proof from the 8 uint256 values used by the Solidity verifier:
+/// `[a.x, a.y, b.x.c1, b.x.c0, b.y.c1, b.y.c0, c.x, c.y]`
+pub fn proof_from_words(words: &[&[u8]]) -> Result<Proof<Bn256>> {
+    if words.len() != 8 {
+        return Err(invalid("Proof must consist of 8 values"));
+    }
+    let fq = |i: usize| word_to_field::<Fq>(words[i]);
+    let proof = Proof {
+        a: G1Affine::from_xy_unchecked(fq(0)?, fq(1)?),
+        b: G2Affine::from_xy_unchecked(
+            Fq2 { c0: fq(3)?, c1: fq(2)? },
+            Fq2 { c0: fq(5)?, c1: fq(4)? },
+        ),
+        c: G1Affine::from_xy_unchecked(fq(6)?, fq(7)?),
+    };
+    check_proof(&proof)?;
+    Ok(proof)
+}
+
+// functions of the generated verifiers taking a proof, `verify` is the transaction of the one with custom errors
+const VERIFY_FUNCTIONS: [&str; 2] = ["verifyProof", "verify"];
+
+fn check_selector(calldata: &[u8], num_inputs: usize) -> Result<()> {
+    let signatures = VERIFY_FUNCTIONS.iter().map(|f| format!("{}(bytes,uint256[{}])", f, num_inputs)).collect::<Vec<_>>();
+    if signatures.iter().any(|signature| calldata[..4] == selector(signature)) {
+        return Ok(());
+    }
+    Err(invalid(format!("Selector 0x{} is not the one of {}", to_hex(&calldata[..4]), signatures.join(" or "))))
+}
+
+/// Decodes calldata of `verifyProof(bytes proof, uint256[num_inputs] input)` of the generated
+/// verifier, or of `verify` of the one with custom errors
+pub fn decode_verify_calldata(calldata: &[u8], num_inputs: usize) -> Result<(Proof<Bn256>, Vec<Fr>)> {
+    if calldata.len() < 4 {
+        return Err(invalid("Calldata is too short"));
+    }
+    check_selector(calldata, num_inputs)?;
+    decode_verify_args(&calldata[4..], num_inputs)
+}
+
+/// Decodes `verifyProof` calldata as `encode_verify_calldata` writes it, or just the arguments as
+/// `encode_verify_args` writes them, taking the number of public inputs from the proof offset
+pub fn decode_verify_data(data: &[u8]) -> Result<(Proof<Bn256>, Vec<Fr>)> {
+    let args = if data.len() % 32 == 4 { &data[4..] } else { data };
+    let offset = word_to_usize(word(args, 0)?)?;
+    if offset < 32 || offset % 32 != 0 {
+        return Err(invalid("Unaligned proof offset in calldata"));
+    }
+    let num_inputs = offset / 32 - 1;
+    if args.len() != data.len() {
+        check_selector(data, num_inputs)?;
+    }
+    decode_verify_args(args, num_inputs)
+}
+
+fn decode_verify_args(args: &[u8], num_inputs: usize) -> Result<(Proof<Bn256>, Vec<Fr>)> {
+    let offset = word_to_usize(word(args, 0)?)?;
+    if offset % 32 != 0 {
+        return Err(invalid("Unaligned proof offset in calldata"));
+    }
+    let inputs = (0..num_inputs)
+        .map(|i| word_to_field::<Fr>(word(args, 1 + i)?))
+        .collect::<Result<Vec<_>>>()?;
+    let proof_start = offset / 32;
+    if word_to_usize(word(args, proof_start)?)? != 8 * 32 {
+        return Err(invalid("Proof bytes must be 256 bytes long, calldata doesn't look like a verifyProof call"));
+    }
+    let words = (0..8).map(|i| word(args, proof_start + 1 + i)).collect::<Result<Vec<_>>>()?;
+    Ok((proof_from_words(&words)?, inputs))
+}
+
+/// ABI-encoded arguments of `verifyProof(bytes proof, uint256[inputs.len()] input)`, without the
+/// function selector, in the layout `decode_verify_calldata` reads
+pub fn encode_verify_args(proof: &Proof<Bn256>, inputs: &[Fr]) -> Vec<u8> {
+    let usize_word = |n: usize| {
+        let mut word = [0u8; 32];
+        word[24..].copy_from_slice(&(n as u64).to_be_bytes());
+        word
+    };
+    let mut args = usize_word(32 * (1 + inputs.len())).to_vec();
+    for input in inputs {
+        input.into_repr().write_be(&mut args).expect("writing to memory doesn't fail");
+    }
+    args.extend_from_slice(&usize_word(8 * 32));
+    args.extend(hex_to_bytes(&proof_to_hex(proof)).expect("proof_to_hex returns valid hex"));
+    args
+}
+
+/// Calldata of `verifyProof(bytes proof, uint256[inputs.len()] input)` including the function selector
+pub fn encode_verify_calldata(proof: &Proof<Bn256>, inputs: &[Fr]) -> Vec<u8> {
+    let mut calldata = selector(&format!("verifyProof(bytes,uint256[{}])", inputs.len())).to_vec();
+    calldata.extend(encode_verify_args(proof, inputs));
+    calldata
+}
+
+/// Performs a read-only `eth_call` of `to` with `data` on the latest block, returns the output
+pub fn call(url: &str, to: &str, data: &[u8]) -> Result<Vec<u8>> {
+    let result = rpc_call(url, "eth_call", serde_json::json!([{"to": to, "data": format!("0x{}", to_hex(data))}, "latest"]))?;
+    result.as_str().and_then(hex_to_bytes).ok_or_else(|| invalid("eth_call returned invalid hex"))
+}
+
+/// Calls `verifyProof` of the verifier contract at `contract` and returns its result
+pub fn verify_onchain(url: &str, contract: &str, proof: &Proof<Bn256>, inputs: &[Fr]) -> Result<bool> {
+    let output = call(url, contract, &encode_verify_calldata(proof, inputs))?;
+    match output.len() {
+        0 => Err(invalid(format!("Call returned no data, is there a verifier contract at {}?", contract))),
+        32 if output[..31].iter().all(|b| *b == 0) && output[31] <= 1 => Ok(output[31] == 1),
+        _ => Err(invalid("verifyProof returned something other than a bool")),
+    }
+}
+
+/// Estimates gas of a transaction calling `to` with `data`
+pub fn estimate_gas(url: &str, to: &str, data: &[u8]) -> Result<u64> {
+    let result = rpc_call(url, "eth_estimateGas", serde_json::json!([{"to": to, "data": format!("0x{}", to_hex(data))}]))?;
+    result.as_str()
+        .and_then(|gas| u64::from_str_radix(gas.trim_start_matches("0x"), 16).ok())
+        .ok_or_else(|| invalid("eth_estimateGas returned an invalid quantity"))
+}
+
+/// Gas of a `verifyProof` transaction that doesn't depend on the contract code, with EIP-1108 and
+/// EIP-2028 prices
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VerifyGas {
+    pub transaction: u64,
+    pub calldata: u64,
+    /// One ecMul and one ecAdd per input and a pairing check of 4 pairs
+    pub precompiles: u64,
+}
+
+impl VerifyGas {
+    pub fn total(&self) -> u64 {
+        self.transaction + self.calldata + self.precompiles
+    }
+}
+
+pub fn verify_gas(calldata: &[u8], num_inputs: usize) -> VerifyGas {
+    VerifyGas {
+        transaction: 21000,
+        calldata: calldata.iter().map(|b| if *b == 0 { 4 } else { 16 }).sum(),
+        precompiles: num_inputs as u64 * (6000 + 150) + 45000 + 4 * 34000,
+    }
+}
+
+/// Waits until the transaction is mined and returns its receipt
+pub fn wait_for_receipt(url: &str, tx_hash: &str, timeout: Duration) -> Result<serde_json::Value> {
+    let start = Instant::now();
+    loop {
+        match rpc_call(url, "eth_getTransactionReceipt", serde_json::json!([tx_hash])) {
+            Err(e) if e.kind() == ErrorKind::NotFound && start.elapsed() < timeout => std::thread::sleep(Duration::from_secs(2)),
+            Err(e) if e.kind() == ErrorKind::NotFound => return Err(Error::new(ErrorKind::TimedOut, format!("Transaction {} was not mined in {:?}", tx_hash, timeout))),
+            result => return result,
+        }
+    }
+}
+
+/// Signs a legacy transaction calling `to` with `data` by `private_key` and sends it, returns the
+/// transaction hash. Nonce, gas price, gas limit and chain id are taken from the node
+#[cfg(feature = "submit")]
+pub fn send_transaction(url: &str, private_key: &str, to: &str, data: Vec<u8>) -> Result<String> {
+    use ethers_core::types::{transaction::eip2718::TypedTransaction, Address, TransactionRequest, U256};
+    use ethers_signers::{LocalWallet, Signer};
+    let quantity = |method: &str, params: serde_json::Value| -> Result<U256> {
+        serde_json::from_value(rpc_call(url, method, params)?).map_err(|e| invalid(format!("Invalid {} result: {}", method, e)))
+    };
+    let wallet: LocalWallet = private_key.trim().parse().map_err(|e| invalid(format!("Invalid private key: {}", e)))?;
+    let to: Address = to.parse().map_err(|e| invalid(format!("Invalid contract address {}: {}", to, e)))?;
+    let chain_id = quantity("eth_chainId", serde_json::json!([]))?.as_u64();
+    let wallet = wallet.with_chain_id(chain_id);
+    let call = serde_json::json!({"from": wallet.address(), "to": to, "data": format!("0x{}", to_hex(&data))});
+    let tx: TypedTransaction = TransactionRequest::new()
+        .from(wallet.address())
+        .to(to)
+        .nonce(quantity("eth_getTransactionCount", serde_json::json!([wallet.address(), "pending"]))?)
+        .gas_price(quantity("eth_gasPrice", serde_json::json!([]))?)
+        .gas(quantity("eth_estimateGas", serde_json::json!([call]))?)
+        .data(data)
+        .chain_id(chain_id)
+        .into();
+    let signature = wallet.sign_transaction_sync(&tx).map_err(|e| Error::new(ErrorKind::Other, format!("Unable to sign transaction: {}", e)))?;
+    let raw = tx.rlp_signed(&signature);
+    let hash = rpc_call(url, "eth_sendRawTransaction", serde_json::json!([format!("0x{}", to_hex(&raw))]))?;
+    hash.as_str().map(String::from).ok_or_else(|| invalid("eth_sendRawTransaction returned no transaction hash"))
+}
+
+/// Solidity statements declaring `proof` and `input` for `verifier.verifyProof(proof, input)`
+pub fn solidity_literal(proof: &Proof<Bn256>, inputs: &[Fr]) -> String {
+    let words = proof_to_hex(proof)
+        .as_bytes()
+        .chunks(64)
+        .map(|word| format!("uint256(0x{})", std::str::from_utf8(word).expect("hex is ascii")))
+        .collect::<Vec<_>>();
+    let input = if inputs.is_empty() {
+        String::from("uint256[0] memory input;")
+    } else {
+        let values = inputs.iter().map(|i| format!("uint256({})", i.into_repr())).collect::<Vec<_>>();
+        format!("uint256[{}] memory input = [\n    {}\n];", inputs.len(), values.join(",\n    "))
+    };
+    format!(
+        "bytes memory proof = abi.encode([\n    {}\n]);\n{}\n",
+        words.join(",\n    "),
+        input,
+    )
+}
+
+#[test]
+fn decode_calldata() {
+    use crate::utils::proof_to_hex;
+    use bellman_ce::pairing::CurveProjective;
+    let proof = Proof::<Bn256> {
+        a: G1Affine::one(),
+        b: G2Affine::one(),
+        c: G1Affine::one().mul(Fr::from_str("2").unwrap()).into_affine(),
+    };
+    let mut calldata = selector("verifyProof(bytes,uint256[2])").to_vec();
+    calldata.extend(hex!("0000000000000000000000000000000000000000000000000000000000000060").iter());
+    calldata.extend(hex!("0000000000000000000000000000000000000000000000000000000000000007").iter());
+    calldata.extend(hex!("0000000000000000000000000000000000000000000000000000000000000009").iter());
+    calldata.extend(hex!("0000000000000000000000000000000000000000000000000000000000000100").iter());
+    calldata.extend(hex_to_bytes(&proof_to_hex(&proof)).unwrap());
+    let (decoded, inputs) = decode_verify_calldata(&calldata, 2).unwrap();
+    assert_eq!(decoded.a, proof.a);
+    assert_eq!(decoded.b, proof.b);
+    assert_eq!(decoded.c, proof.c);
+    assert_eq!(inputs, vec![Fr::from_str("7").unwrap(), Fr::from_str("9").unwrap()]);
+    calldata[..4].copy_from_slice(&selector("verify(bytes,uint256[2])"));
+    assert!(decode_verify_calldata(&calldata, 2).is_ok());
+    assert!(decode_verify_calldata(&calldata, 3).is_err());
+    calldata[..4].copy_from_slice(&hex!("deadbeef"));
+    assert!(decode_verify_calldata(&calldata, 2).is_err());
+    assert!(decode_verify_data(&calldata).is_err());
+}
+
+#[test]
+fn encode_args() {
+    use bellman_ce::pairing::CurveProjective;
+    let proof = Proof::<Bn256> {
+        a: G1Affine::one().mul(Fr::from_str("3").unwrap()).into_affine(),
+        b: G2Affine::one(),
+        c: G1Affine::one(),
+    };
+    let inputs = vec![Fr::from_str("5").unwrap(), Fr::from_str("11").unwrap(), Fr::from_str("13").unwrap()];
+    let calldata = encode_verify_calldata(&proof, &inputs);
+    assert_eq!(calldata[..4], selector("verifyProof(bytes,uint256[3])"));
+    assert_eq!(calldata.len(), 4 + 32 * (1 + 3 + 1 + 8));
+    let (decoded, decoded_inputs) = decode_verify_calldata(&calldata, 3).unwrap();
+    assert_eq!((decoded.a, decoded.b, decoded.c), (proof.a, proof.b, proof.c));
+    assert_eq!(decoded_inputs, inputs);
+
+    let gas = verify_gas(&calldata, 3);
+    assert_eq!(gas.precompiles, 3 * 6150 + 181000);
+    assert!(gas.calldata > 16 * 4 && gas.calldata < 16 * calldata.len() as u64);
+
+    let literal = solidity_literal(&proof, &inputs);
+    assert_eq!(literal.matches("uint256(0x").count(), 8 + 3);
+    assert!(literal.contains("uint256[3] memory input = [\n    uint256(0x0000000000000000000000000000000000000000000000000000000000000005),"));
+    assert!(solidity_literal(&proof, &[]).ends_with("uint256[0] memory input;\n"));
+}
+
+#[test]
+fn decode_data() {
+    let proof = Proof::<Bn256> { a: G1Affine::one(), b: G2Affine::one(), c: G1Affine::one() };
+    let inputs = vec![Fr::from_str("5").unwrap(), Fr::from_str("11").unwrap()];
+    for data in &[encode_verify_calldata(&proof, &inputs), encode_verify_args(&proof, &inputs)] {
+        let (decoded, decoded_inputs) = decode_verify_data(data).unwrap();
+        assert_eq!((decoded.a, decoded.b, decoded.c), (proof.a, proof.b, proof.c));
+        assert_eq!(decoded_inputs, inputs);
+    }
+    let mut calldata = encode_verify_calldata(&proof, &inputs);
+    calldata[0] ^= 1;
+    assert!(decode_verify_data(&calldata).unwrap_err().to_string().contains("verifyProof(bytes,uint256[2])"));
+    assert_eq!(decode_verify_data(&encode_verify_args(&proof, &[])).unwrap().1, vec![]);
+}
+
+#[test]
+fn keccak() {
+    assert_eq!(keccak256(b""), hex!("c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"));
+    assert_eq!(selector("transfer(address,uint256)"), hex!("a9059cbb"));
+}
@@ -0,0 +1,446 @@
+//! Differential testing of verifier contracts: the compiled contract runs in an embedded EVM (revm)
+//! and its `verifyProof` results for valid proofs and mutations of them are compared with native
+//! verification, so codegen and encoding regressions are caught without a node.
+//!
+//! The verifier with custom errors is also checked function by function: the reason codes of
+//! `tryVerify`, the custom errors `verifyProof` reverts with and the `ProofVerified` event of `verify`.
+//!
+//! Contracts are compiled with an external `solc` or `huffc`, use one accepting the pragma of the
+//! template: `^0.6.0`, `^0.8.4` with custom errors.
+use std::io::{Error, ErrorKind, Result};
+use std::process::Command;
+use bellman_ce::{
+    groth16::{Proof, VerifyingKey},
+    pairing::{
+        CurveAffine,
+        CurveProjective,
+        ff::{Field, PrimeField, PrimeFieldRepr},
+        bn256::{Bn256, Fq, Fr, G1Affine},
+    },
+};
+use rand::Rng;
+use revm::{
+    db::InMemoryDB,
+    primitives::{keccak256, AccountInfo, Bytecode, Bytes, ExecutionResult, Output, TransactTo, B160, B256, U256},
+    EVM,
+};
+use crate::circom_circuit::verify_with_key;
+use crate::eth::{encode_verify_calldata, selector};
+use crate::remote::to_hex;
+use crate::proof::dummy_proof;
+use crate::huff::create_verifier_huff_file_for_key;
+use crate::solidity::{create_verifier_sol_file_for_key, VerifierOptions};
+use crate::utils::hex_to_bytes;
+
+const VERIFIER_ADDRESS: B160 = B160([0x42; 20]);
+const GAS_LIMIT: u64 = 30_000_000;
+
+/// A `verifyProof` call and what native verification says about it
+pub struct TestCase {
+    pub name: String,
+    pub calldata: Vec<u8>,
+    /// Result of native verification, `false` for encodings it can't represent, like inputs not
+    /// reduced modulo the scalar field, which the contract must reject too
+    pub expected: bool,
+    /// What the verifier with custom errors reports, `None` for calldata it can't decode, which
+    /// reverts without data in every function
+    pub reason: Option<Reason>,
+}
+
+/// Outcome of the checks of the verifier with custom errors, as `tryVerify` reports it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reason {
+    Ok,
+    /// Length of the proof bytes
+    MalformedProof(u64),
+    ProofElementOutOfRange(u64),
+    InputOutOfRange(u64),
+    PrecompileFailed,
+    InvalidProof,
+}
+
+impl Reason {
+    /// Reason code returned by `tryVerify`
+    pub fn code(&self) -> u8 {
+        match self {
+            Reason::Ok => 0,
+            Reason::MalformedProof(_) => 1,
+            Reason::ProofElementOutOfRange(_) => 2,
+            Reason::InputOutOfRange(_) => 3,
+            Reason::PrecompileFailed => 4,
+            Reason::InvalidProof => 5,
+        }
+    }
+
+    /// Data of the custom error `verifyProof` reverts with, `None` if it returns
+    pub fn error_data(&self) -> Option<Vec<u8>> {
+        let with_index = |signature: &str, index: u64| {
+            let mut data = selector(signature).to_vec();
+            data.extend_from_slice(&[0u8; 24]);
+            data.extend_from_slice(&index.to_be_bytes());
+            data
+        };
+        match *self {
+            Reason::Ok | Reason::InvalidProof => None,
+            Reason::MalformedProof(length) => Some(with_index("MalformedProof(uint256)", length)),
+            Reason::ProofElementOutOfRange(index) => Some(with_index("ProofElementOutOfRange(uint256)", index)),
+            Reason::InputOutOfRange(index) => Some(with_index("InputOutOfRange(uint256)", index)),
+            Reason::PrecompileFailed => Some(selector("PrecompileFailed()").to_vec()),
+        }
+    }
+}
+
+pub struct CaseResult {
+    pub name: String,
+    pub expected: bool,
+    pub accepted: bool,
+    /// The call reverted or halted instead of returning
+    pub reverted: bool,
+    pub gas_used: u64,
+}
+
+impl CaseResult {
+    pub fn agrees(&self) -> bool {
+        self.accepted == self.expected
+    }
+}
+
+fn field_modulus<F: PrimeField>() -> [u8; 32] {
+    let mut modulus = [0u8; 32];
+    F::char().write_be(&mut modulus[..]).expect("a field element fits into 32 bytes");
+    modulus
+}
+
+// adds a big-endian 256-bit number to a calldata word, values here never overflow it
+fn add_to_word(word: &mut [u8], value: &[u8; 32]) {
+    let mut carry = 0u16;
+    for (byte, add) in word.iter_mut().zip(value.iter()).rev() {
+        let sum = *byte as u16 + *add as u16 + carry;
+        *byte = sum as u8;
+        carry = sum >> 8;
+    }
+}
+
+/// Calls for `proof` with `inputs`: the call itself, proofs with changed points, each input plus
+/// one and plus the field modulus, a proof coordinate plus the base field modulus and a truncated call
+pub fn differential_cases<R: Rng>(vk: &VerifyingKey<Bn256>, proof: &Proof<Bn256>, inputs: &[Fr], rng: &mut R) -> Vec<TestCase> {
+    let native = |proof: &Proof<Bn256>, inputs: &[Fr]| verify_with_key(vk, proof, inputs).unwrap_or(false);
+    let case = |name: &str, proof: &Proof<Bn256>, inputs: &[Fr]| {
+        let expected = native(proof, inputs);
+        TestCase {
+            name: name.to_string(),
+            calldata: encode_verify_calldata(proof, inputs),
+            expected,
+            reason: Some(if expected { Reason::Ok } else { Reason::InvalidProof }),
+        }
+    };
+    let negated = |p: &G1Affine| {
+        let mut p = p.into_projective();
+        p.negate();
+        p.into_affine()
+    };
+    let mut b = proof.b.into_projective();
+    b.negate();
+
+    let mut cases = vec![
+        case("valid", proof, inputs),
+        case("(-A, -B, C)", &Proof { a: negated(&proof.a), b: b.into_affine(), c: proof.c }, inputs),
+        case("-A", &Proof { a: negated(&proof.a), b: proof.b, c: proof.c }, inputs),
+        case("A and C swapped", &Proof { a: proof.c, b: proof.b, c: proof.a }, inputs),
+        case("random points", &dummy_proof(inputs.len(), rng).0, inputs),
+    ];
+    let calldata = encode_verify_calldata(proof, inputs);
+    for i in 0..inputs.len() {
+        let mut changed = inputs.to_vec();
+        changed[i].add_assign(&Fr::one());
+        cases.push(case(&format!("input {} + 1", i), proof, &changed));
+        let mut unreduced = calldata.clone();
+        add_to_word(&mut unreduced[4 + 32 * (1 + i)..4 + 32 * (2 + i)], &field_modulus::<Fr>());
+        cases.push(TestCase { name: format!("input {} + r", i), calldata: unreduced, expected: false, reason: Some(Reason::InputOutOfRange(i as u64)) });
+    }
+    // A.x is the first proof word, after the offset, the inputs and the length of the proof bytes
+    let a_x = 4 + 32 * (2 + inputs.len());
+    let mut unreduced = calldata.clone();
+    add_to_word(&mut unreduced[a_x..a_x + 32], &field_modulus::<Fq>());
+    cases.push(TestCase { name: String::from("A.x + q"), calldata: unreduced, expected: false, reason: Some(Reason::ProofElementOutOfRange(0)) });
+    // a well-formed call with 7 words of proof bytes
+    let mut short = calldata.clone();
+    short.truncate(calldata.len() - 32);
+    short[a_x - 32..a_x].copy_from_slice(&{
+        let mut length = [0u8; 32];
+        length[31] = 7 * 32;
+        length
+    });
+    cases.push(TestCase { name: String::from("short proof"), calldata: short, expected: false, reason: Some(Reason::MalformedProof(7 * 32)) });
+    cases.push(TestCase { name: String::from("truncated"), calldata: calldata[..calldata.len() - 32].to_vec(), expected: false, reason: None });
+    cases
+}
+
+// EVM with runtime bytecode `code` deployed at `VERIFIER_ADDRESS`, calls go to it
+fn deploy(code: &[u8]) -> EVM<InMemoryDB> {
+    let mut db = InMemoryDB::default();
+    let bytecode = Bytecode::new_raw(Bytes::copy_from_slice(code));
+    db.insert_account_info(VERIFIER_ADDRESS, AccountInfo::new(U256::ZERO, 1, keccak256(code), bytecode));
+    let mut evm = EVM::new();
+    evm.database(db);
+    evm.env.tx.transact_to = TransactTo::Call(VERIFIER_ADDRESS);
+    evm.env.tx.gas_limit = GAS_LIMIT;
+    evm
+}
+
+fn transact(evm: &mut EVM<InMemoryDB>, data: &[u8]) -> Result<ExecutionResult> {
+    evm.env.tx.data = Bytes::copy_from_slice(data);
+    evm.transact_ref()
+        .map(|result| result.result)
+        .map_err(|e| Error::new(ErrorKind::Other, format!("EVM error: {:?}", e)))
+}
+
+/// Runs every case against the contract with runtime bytecode `code`. A call is accepted if it
+/// returns `true` as a 32-byte word, reverts count as rejections
+pub fn run_cases(code: &[u8], cases: &[TestCase]) -> Result<Vec<CaseResult>> {
+    let mut evm = deploy(code);
+    let mut results = Vec::with_capacity(cases.len());
+    for case in cases {
+        let result = transact(&mut evm, &case.calldata)?;
+        let (accepted, reverted) = match &result {
+            ExecutionResult::Success { output: Output::Call(output), .. } => {
+                (output.len() == 32 && output[..31].iter().all(|b| *b == 0) && output[31] == 1, false)
+            }
+            _ => (false, true),
+        };
+        results.push(CaseResult { name: case.name.clone(), expected: case.expected, accepted, reverted, gas_used: result.gas_used() });
+    }
+    Ok(results)
+}
+
+/// Checks the `tryVerify`, `verifyProof` and `verify` functions of the verifier with custom errors
+/// against the reason of every case, returns a description of each disagreement
+pub fn check_custom_errors(code: &[u8], cases: &[TestCase], num_inputs: usize) -> Result<Vec<String>> {
+    let mut evm = deploy(code);
+    let with_selector = |calldata: &[u8], function: &str| {
+        let mut data = selector(&format!("{}(bytes,uint256[{}])", function, num_inputs)).to_vec();
+        data.extend_from_slice(calldata.get(4..).unwrap_or_default());
+        data
+    };
+    let describe = |result: &ExecutionResult| match result {
+        ExecutionResult::Success { output: Output::Call(output), logs, .. } => format!("returned 0x{} with {} logs", to_hex(output), logs.len()),
+        ExecutionResult::Success { .. } => String::from("created a contract"),
+        ExecutionResult::Revert { output, .. } => format!("reverted with 0x{}", to_hex(output)),
+        ExecutionResult::Halt { reason, .. } => format!("halted with {:?}", reason),
+    };
+    let inputs_hash = |calldata: &[u8]| keccak256(&calldata[4 + 32..4 + 32 * (1 + num_inputs)]);
+    let proof_verified = keccak256(b"ProofVerified(address,bytes32)");
+    let mut sender = B256::zero();
+    sender.0[12..].copy_from_slice(&evm.env.tx.caller.0);
+    let mut failures = vec![];
+    for case in cases {
+        let results = [
+            transact(&mut evm, &with_selector(&case.calldata, "tryVerify"))?,
+            transact(&mut evm, &with_selector(&case.calldata, "verifyProof"))?,
+            transact(&mut evm, &with_selector(&case.calldata, "verify"))?,
+        ];
+        let reverted_with = |result: &ExecutionResult, data: &[u8]| matches!(result, ExecutionResult::Revert { output, .. } if output.as_ref() == data);
+        let returned = |result: &ExecutionResult, word: u8| matches!(result, ExecutionResult::Success { output: Output::Call(output), .. }
+            if output.len() == 32 && output[..31].iter().all(|b| *b == 0) && output[31] == word);
+        let agrees = match case.reason {
+            None => results.iter().all(|result| reverted_with(result, &[])),
+            Some(reason) => {
+                let error = reason.error_data();
+                returned(&results[0], reason.code())
+                    && match &error {
+                        Some(data) => reverted_with(&results[1], data),
+                        None => returned(&results[1], (reason == Reason::Ok) as u8),
+                    }
+                    && match (reason, &error) {
+                        (Reason::Ok, _) => matches!(&results[2], ExecutionResult::Success { logs, .. }
+                            if logs.len() == 1 && logs[0].address == VERIFIER_ADDRESS
+                                && logs[0].topics == vec![proof_verified, sender, inputs_hash(&case.calldata)]),
+                        (_, Some(data)) => reverted_with(&results[2], data),
+                        (_, None) => reverted_with(&results[2], &selector("InvalidProof()")),
+                    }
+            }
+        };
+        if !agrees {
+            let expected = match case.reason {
+                Some(reason) => format!("{:?}", reason),
+                None => String::from("revert without data"),
+            };
+            failures.push(format!(
+                "{}: expected {}, tryVerify {}, verifyProof {}, verify {}",
+                case.name, expected, describe(&results[0]), describe(&results[1]), describe(&results[2]),
+            ));
+        }
+    }
+    Ok(failures)
+}
+
+fn run_compiler(command: &mut Command, name: &str) -> Result<String> {
+    let output = command.output().map_err(|e| Error::new(e.kind(), format!("unable to run {}: {}", name, e)))?;
+    if !output.status.success() {
+        return Err(Error::new(ErrorKind::InvalidData, format!("{} failed: {}", name, String::from_utf8_lossy(&output.stderr).trim())));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Runtime bytecode of `contract` in the output of `solc --bin-runtime`
+pub fn runtime_code_from_solc_output(output: &str, contract: &str) -> Result<Vec<u8>> {
+    let header = format!(":{} =======", contract);
+    output.lines()
+        .skip_while(|line| !line.ends_with(&header))
+        .skip(1)
+        .find(|line| !line.is_empty() && !line.starts_with("Binary"))
+        .and_then(|line| hex_to_bytes(line.trim()))
+        .filter(|code| !code.is_empty())
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("no runtime bytecode of {} in the solc output", contract)))
+}
+
+/// Compiles the `Verifier` contract of a Solidity file with the `solc` binary
+pub fn compile_solidity(solc: &str, source: &str) -> Result<Vec<u8>> {
+    let output = run_compiler(Command::new(solc).arg("--optimize").arg("--bin-runtime").arg(source), solc)?;
+    runtime_code_from_solc_output(&output, "Verifier")
+}
+
+/// Compiles a Huff contract with the `huffc` binary
+pub fn compile_huff(huffc: &str, source: &str) -> Result<Vec<u8>> {
+    let output = run_compiler(Command::new(huffc).arg("-r").arg(source), huffc)?;
+    hex_to_bytes(output.trim())
+        .filter(|code| !code.is_empty())
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("{} didn't print runtime bytecode", huffc)))
+}
+
+/// Where the runtime bytecode of the checked verifier comes from
+pub enum VerifierSource<'a> {
+    Bytecode(Vec<u8>),
+    Solidity { file: &'a str, solc: &'a str },
+    Huff { file: &'a str, huffc: &'a str },
+    /// The Solidity contract generated from the verifying key
+    GeneratedSolidity { options: VerifierOptions, solc: &'a str },
+    /// The Huff contract generated from the verifying key
+    GeneratedHuff { huffc: &'a str },
+}
+
+/// Runtime bytecode of a verifier, generated contracts are written to a temporary directory and compiled there
+pub fn verifier_code(vk: &VerifyingKey<Bn256>, source: VerifierSource) -> Result<Vec<u8>> {
+    match source {
+        VerifierSource::Bytecode(code) => Ok(code),
+        VerifierSource::Solidity { file, solc } => compile_solidity(solc, file),
+        VerifierSource::Huff { file, huffc } => compile_huff(huffc, file),
+        VerifierSource::GeneratedSolidity { options, solc } => {
+            let dir = tempfile::tempdir()?;
+            let file = dir.path().join("Verifier.sol").to_string_lossy().to_string();
+            create_verifier_sol_file_for_key(vk, options, &file)?;
+            compile_solidity(solc, &file)
+        }
+        VerifierSource::GeneratedHuff { huffc } => {
+            let dir = tempfile::tempdir()?;
+            let file = dir.path().join("Verifier.huff").to_string_lossy().to_string();
+            create_verifier_huff_file_for_key(vk, None, &file)?;
+            compile_huff(huffc, &file)
+        }
+    }
+}
+
+pub struct VerifierCheck {
+    pub results: Vec<CaseResult>,
+    /// Disagreements found by `check_custom_errors`, `None` if they weren't checked
+    pub custom_errors: Option<Vec<String>>,
+}
+
+impl VerifierCheck {
+    pub fn mismatches(&self) -> usize {
+        self.results.iter().filter(|r| !r.agrees()).count()
+    }
+}
+
+/// Runs the differential cases of every named proof against the verifier, and the custom error
+/// checks if `custom_errors` is set. Case names are prefixed with the proof name
+pub fn check_verifier<R: Rng>(vk: &VerifyingKey<Bn256>, code: &[u8], proofs: &[(String, Proof<Bn256>, Vec<Fr>)], custom_errors: bool, rng: &mut R) -> Result<VerifierCheck> {
+    let mut cases = vec![];
+    for (name, proof, inputs) in proofs {
+        if inputs.len() + 1 != vk.ic.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{} has {} public inputs, the verifying key {}", name, inputs.len(), vk.ic.len() - 1),
+            ));
+        }
+        cases.extend(differential_cases(vk, proof, inputs, rng).into_iter().map(|mut case| {
+            case.name = format!("{}: {}", name, case.name);
+            case
+        }));
+    }
+    let results = run_cases(code, &cases)?;
+    let custom_errors = if custom_errors { Some(check_custom_errors(code, &cases, vk.ic.len() - 1)?) } else { None };
+    Ok(VerifierCheck { results, custom_errors })
+}
+
+#[test]
+fn differential_run() {
+    use crate::circom_circuit::{create_insecure_dev_rng, prove, CircomCircuit};
+    use crate::test_utils::{fr, multiplier_circuit, multiplier_params, multiplier_witness};
+    let params = multiplier_params();
+    let mut rng = create_insecure_dev_rng();
+    let circuit = CircomCircuit { r1cs: multiplier_circuit(), witness: Some(multiplier_witness(2, 3)), wire_mapping: None };
+    let proof = prove(circuit, &params, &mut rng).unwrap();
+    let cases = differential_cases(&params.vk, &proof, &[fr("6")], &mut rng);
+    let expected = cases.iter().map(|c| (c.name.as_str(), c.expected)).collect::<Vec<_>>();
+    assert_eq!(expected, vec![
+        ("valid", true), ("(-A, -B, C)", true), ("-A", false), ("A and C swapped", false), ("random points", false),
+        ("input 0 + 1", false), ("input 0 + r", false), ("A.x + q", false), ("short proof", false), ("truncated", false),
+    ]);
+    let reasons = cases.iter().map(|c| c.reason).collect::<Vec<_>>();
+    assert_eq!(reasons, vec![
+        Some(Reason::Ok), Some(Reason::Ok), Some(Reason::InvalidProof), Some(Reason::InvalidProof), Some(Reason::InvalidProof),
+        Some(Reason::InvalidProof), Some(Reason::InputOutOfRange(0)), Some(Reason::ProofElementOutOfRange(0)),
+        Some(Reason::MalformedProof(224)), None,
+    ]);
+    assert_eq!(Reason::PrecompileFailed.error_data().unwrap(), selector("PrecompileFailed()").to_vec());
+    let mut index = [0u8; 32];
+    index[31] = 2;
+    assert_eq!(Reason::InputOutOfRange(2).error_data().unwrap()[4..], index);
+
+    // PUSH1 1 PUSH1 0 MSTORE PUSH1 32 PUSH1 0 RETURN, accepts everything
+    let results = run_cases(&hex!("600160005260206000f3"), &cases).unwrap();
+    assert!(results.iter().all(|r| r.accepted && !r.reverted && r.gas_used > 21000));
+    assert_eq!(results.iter().filter(|r| r.agrees()).count(), 2);
+    let check = check_verifier(&params.vk, &hex!("600160005260206000f3"), &[(String::from("p"), proof.clone(), vec![fr("6")])], false, &mut rng).unwrap();
+    assert_eq!((check.results.len(), check.mismatches()), (cases.len(), cases.len() - 2));
+    assert!(check.custom_errors.is_none());
+    // PUSH1 0 PUSH1 0 REVERT
+    let results = run_cases(&hex!("60006000fd"), &cases).unwrap();
+    assert!(results.iter().all(|r| r.reverted && !r.accepted));
+    assert_eq!(results.iter().filter(|r| !r.agrees()).map(|r| r.name.as_str()).collect::<Vec<_>>(), vec!["valid", "(-A, -B, C)"]);
+    // only the undecodable call is handled right by a contract that always reverts without data
+    let failures = check_custom_errors(&hex!("60006000fd"), &cases, 1).unwrap();
+    assert_eq!(failures.len(), cases.len() - 1);
+    assert!(failures.iter().all(|f| !f.starts_with("truncated:")));
+
+    let solc = "\n======= Verifier.sol:Pairing =======\nBinary of the runtime part:\n60016002\n\n======= Verifier.sol:Verifier =======\nBinary of the runtime part:\n6003600400\n";
+    assert_eq!(runtime_code_from_solc_output(solc, "Verifier").unwrap(), vec![0x60, 0x03, 0x60, 0x04, 0x00]);
+    assert!(runtime_code_from_solc_output(solc, "Missing").is_err());
+}
+
+/// Runs the verifier with custom errors compiled by `$SOLC` or `solc` through the checks, skipped
+/// if there is no compiler
+#[test]
+fn custom_errors_verifier() {
+    use crate::circom_circuit::{create_insecure_dev_rng, prove, CircomCircuit};
+    use crate::test_utils::{fr, multiplier_circuit, multiplier_params, multiplier_witness};
+    let params = multiplier_params();
+    let solc = std::env::var("SOLC").unwrap_or_else(|_| String::from("solc"));
+    let source = VerifierSource::GeneratedSolidity { options: VerifierOptions { custom_errors: true }, solc: &solc };
+    let code = match verifier_code(&params.vk, source) {
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            eprintln!("skipping the custom errors verifier check: {}", e);
+            return;
+        }
+        code => code.unwrap(),
+    };
+    let mut rng = create_insecure_dev_rng();
+    let circuit = CircomCircuit { r1cs: multiplier_circuit(), witness: Some(multiplier_witness(2, 3)), wire_mapping: None };
+    let proof = prove(circuit, &params, &mut rng).unwrap();
+    let check = check_verifier(&params.vk, &code, &[(String::from("proof.json"), proof.clone(), vec![fr("6")])], true, &mut rng).unwrap();
+    assert_eq!(check.mismatches(), 0);
+    assert!(check.results.iter().all(|r| r.name.starts_with("proof.json: ")));
+    assert_eq!(check.custom_errors, Some(vec![]));
+    assert!(check_verifier(&params.vk, &code, &[(String::from("proof.json"), proof, vec![])], false, &mut rng).is_err());
+}
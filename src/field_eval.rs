@@ -0,0 +1,65 @@
+//! Scalar field calculator for reproducing circuit arithmetic outside of the circuit.
+use bellman_ce::pairing::{
+    Engine,
+    ff::{Field, PrimeField},
+};
+
+/// Applies a named operation to field elements:
+/// `add`, `mul` (any number of arguments), `sub`, `div`, `pow` (two), `inv`, `neg` (one),
+/// `dec` and `hex` (one, returned unchanged for output conversion)
+pub fn eval<E: Engine>(op: &str, args: &[E::Fr]) -> Result<E::Fr, String> {
+    let arity = |n: usize| if args.len() == n {
+        Ok(())
+    } else {
+        Err(format!("{} takes {} argument(s), got {}", op, n, args.len()))
+    };
+    match op {
+        "add" => Ok(args.iter().fold(E::Fr::zero(), |mut acc, x| { acc.add_assign(x); acc })),
+        "mul" => Ok(args.iter().fold(E::Fr::one(), |mut acc, x| { acc.mul_assign(x); acc })),
+        "sub" => {
+            arity(2)?;
+            let mut x = args[0];
+            x.sub_assign(&args[1]);
+            Ok(x)
+        }
+        "div" => {
+            arity(2)?;
+            let inv = args[1].inverse().ok_or_else(|| String::from("division by zero"))?;
+            let mut x = args[0];
+            x.mul_assign(&inv);
+            Ok(x)
+        }
+        "pow" => {
+            arity(2)?;
+            Ok(args[0].pow(args[1].into_repr()))
+        }
+        "inv" => {
+            arity(1)?;
+            args[0].inverse().ok_or_else(|| String::from("zero has no inverse"))
+        }
+        "neg" => {
+            arity(1)?;
+            let mut x = args[0];
+            x.negate();
+            Ok(x)
+        }
+        "dec" | "hex" => {
+            arity(1)?;
+            Ok(args[0])
+        }
+        _ => Err(format!("unknown operation {}", op)),
+    }
+}
+
+#[test]
+fn operations() {
+    use bellman_ce::pairing::bn256::{Bn256, Fr};
+    let fr = |s: &str| Fr::from_str(s).unwrap();
+    assert_eq!(eval::<Bn256>("add", &[fr("1"), fr("2"), fr("3")]), Ok(fr("6")));
+    assert_eq!(eval::<Bn256>("mul", &[fr("4"), fr("5")]), Ok(fr("20")));
+    assert_eq!(eval::<Bn256>("sub", &[fr("1"), fr("2")]), eval::<Bn256>("neg", &[fr("1")]));
+    assert_eq!(eval::<Bn256>("div", &[fr("20"), fr("5")]), Ok(fr("4")));
+    assert_eq!(eval::<Bn256>("pow", &[fr("3"), fr("4")]), Ok(fr("81")));
+    assert!(eval::<Bn256>("inv", &[Fr::zero()]).is_err());
+    assert!(eval::<Bn256>("sub", &[fr("1")]).is_err());
+}
@@ -0,0 +1,137 @@
+//! Fast reader of JSON arrays of field elements, like witness.json and public.json.
+//!
+//! Going through `serde_json::Value` allocates a string per value and `PrimeField::from_str`
+//! multiplies in Montgomery form digit by digit, which for small circuits takes longer than the
+//! proof. This reader streams the input and converts every value as soon as it is read, digits are
+//! accumulated straight into the field representation with `fr_from_str`. Memory use is the
+//! resulting field elements and one value, so witnesses of hundreds of MB don't have to fit into
+//! memory twice. Anything but an array of strings and integers is an error.
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Result};
+use bellman_ce::pairing::ff::PrimeField;
+use crate::utils::fr_from_str;
+
+fn invalid(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+struct Scanner<R> {
+    reader: R,
+    /// Offset of the next byte, for error messages
+    pos: usize,
+    /// The current value, reused between values
+    value: Vec<u8>,
+}
+
+impl<R: BufRead> Scanner<R> {
+    fn peek_byte(&mut self) -> Result<Option<u8>> {
+        Ok(self.reader.fill_buf()?.first().copied())
+    }
+
+    fn bump(&mut self) {
+        self.reader.consume(1);
+        self.pos += 1;
+    }
+
+    fn skip_whitespace(&mut self) -> Result<()> {
+        while self.peek_byte()?.map_or(false, |b| b.is_ascii_whitespace()) {
+            self.bump();
+        }
+        Ok(())
+    }
+
+    /// Next non-whitespace byte, which is consumed
+    fn next(&mut self) -> Result<Option<u8>> {
+        let b = self.peek()?;
+        if b.is_some() {
+            self.bump();
+        }
+        Ok(b)
+    }
+
+    fn peek(&mut self) -> Result<Option<u8>> {
+        self.skip_whitespace()?;
+        self.peek_byte()
+    }
+
+    /// Contents of a string without escapes, or a bare integer
+    fn value(&mut self) -> Result<&str> {
+        let start = self.pos;
+        self.value.clear();
+        if self.peek_byte()? == Some(b'"') {
+            self.bump();
+            loop {
+                match self.peek_byte()? {
+                    Some(b'"') => {
+                        self.bump();
+                        break;
+                    }
+                    Some(b'\\') | None => return Err(invalid(format!("unterminated or escaped string at byte {}", start))),
+                    Some(b) => {
+                        self.value.push(b);
+                        self.bump();
+                    }
+                }
+            }
+        } else {
+            if self.peek_byte()? == Some(b'-') {
+                self.value.push(b'-');
+                self.bump();
+            }
+            while let Some(b) = self.peek_byte()?.filter(|b| b.is_ascii_digit()) {
+                self.value.push(b);
+                self.bump();
+            }
+            if let Some(b'.') | Some(b'e') | Some(b'E') = self.peek_byte()? {
+                return Err(invalid(format!("fractional number at byte {}, field elements must be integers", start)));
+            }
+        }
+        std::str::from_utf8(&self.value).map_err(|e| invalid(e.to_string()))
+    }
+}
+
+/// Reads a JSON array of decimal or `0x`-prefixed hex strings and integers
+pub fn read_field_array<F: PrimeField, R: Read>(reader: R) -> Result<Vec<F>> {
+    let mut scanner = Scanner { reader: BufReader::with_capacity(1 << 16, reader), pos: 0, value: vec![] };
+    if scanner.next()? != Some(b'[') {
+        return Err(invalid(String::from("expected a JSON array")));
+    }
+    let mut values = vec![];
+    if scanner.peek()? == Some(b']') {
+        scanner.bump();
+    } else {
+        loop {
+            scanner.skip_whitespace()?;
+            let pos = scanner.pos;
+            let value = scanner.value()?;
+            values.push(fr_from_str(value).ok_or_else(|| invalid(format!("invalid field element '{}' at byte {}", value, pos)))?);
+            match scanner.next()? {
+                Some(b',') => continue,
+                Some(b']') => break,
+                _ => return Err(invalid(format!("expected ',' or ']' at byte {}", scanner.pos.saturating_sub(1)))),
+            }
+        }
+    }
+    if scanner.next()?.is_some() {
+        return Err(invalid(format!("trailing characters at byte {}", scanner.pos - 1)));
+    }
+    Ok(values)
+}
+
+#[test]
+fn field_arrays() {
+    use bellman_ce::pairing::bn256::Fr;
+    let fr = |s: &str| Fr::from_str(s).unwrap();
+    let json = br#" [ "1", "0x10",
+        3, "21888242871839275222246405745257275088548364400416034343698204186575808495618", -1 ] "#;
+    let values = read_field_array::<Fr, _>(&json[..]).unwrap();
+    // values above the modulus are reduced like PrimeField::from_str does
+    assert_eq!(&values[..4], &[fr("1"), fr("16"), fr("3"), fr("1")]);
+    assert_eq!(values[4], fr("21888242871839275222246405745257275088548364400416034343698204186575808495616"));
+    assert_eq!(read_field_array::<Fr, _>(&b"[]"[..]).unwrap(), vec![]);
+    // values split between reads
+    let chunked = (&b"[\"12"[..]).chain(&b"3\", 4"[..]).chain(&b"5]"[..]);
+    assert_eq!(read_field_array::<Fr, _>(chunked).unwrap(), vec![fr("123"), fr("45")]);
+    for json in &[&b"[\"1\\n\"]"[..], &b"[1.5]"[..], &b"[\"1\" \"2\"]"[..], &b"[--1]"[..], &b"[1] 2"[..], &b"{}"[..]] {
+        assert!(read_field_array::<Fr, _>(*json).is_err());
+    }
+}
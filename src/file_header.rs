@@ -0,0 +1,322 @@
+use byteorder::{ReadBytesExt, WriteBytesExt, LittleEndian};
+use std::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+
+pub const PARAMS_MAGIC: [u8; 4] = [0x7a, 0x6b, 0x70, 0x6d]; // magic = "zkpm"
+/// Version 2 adds flags after the curve id, version 3 adds `ParamsMetadata` after the flags,
+/// version 4 is written for `FLAG_ZSTD` and `FLAG_ENCRYPTED` files so older zkutil rejects them instead
+/// of misreading them
+pub const PARAMS_VERSION: u32 = 4;
+
+pub const CURVE_BN254: u32 = 1;
+
+/// Parameters were generated from a fixed seed by `setup --dev` and must never be used in production
+pub const FLAG_INSECURE_DEV: u32 = 1;
+
+/// Everything after the header is a zstd frame
+pub const FLAG_ZSTD: u32 = 2;
+
+/// Field elements are raw Montgomery-form limbs, set in `raw_params` files
+pub const FLAG_MONTGOMERY: u32 = 4;
+
+/// Field elements of `raw_params` files are big-endian integers instead of little-endian limbs
+pub const FLAG_BIG_ENDIAN: u32 = 8;
+
+/// Sections of `raw_params` files are aligned to 32 bytes instead of 64
+pub const FLAG_PACKED: u32 = 16;
+
+/// Everything after the header is encrypted with AES-256-GCM, see `zkutil::encryption`
+pub const FLAG_ENCRYPTED: u32 = 32;
+
+/// Parameters were generated for the circuit after `R1CS::simplify`, provers simplify it the same way
+pub const FLAG_SIMPLIFIED: u32 = 64;
+
+/// What parameters were generated for, stored in params files since version 3
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParamsMetadata {
+    /// SHA-256 of the circuit constraints, see `keys::circuit_hash`
+    pub circuit_hash: [u8; 32],
+    pub constraints: u64,
+    pub zkutil_version: String,
+    /// Unix timestamp in seconds
+    pub created_at: u64,
+}
+
+/// Header that precedes every binary artifact written by zkutil
+#[derive(Clone, Debug)]
+pub struct FileHeader {
+    pub magic: [u8; 4],
+    pub version: u32,
+    pub curve: u32,
+    pub flags: u32,
+    /// Present in params files of version 3 and later
+    pub metadata: Option<ParamsMetadata>,
+}
+
+impl FileHeader {
+    pub fn is_insecure_dev(&self) -> bool {
+        self.flags & FLAG_INSECURE_DEV != 0
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.flags & FLAG_ZSTD != 0
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.flags & FLAG_ENCRYPTED != 0
+    }
+
+    pub fn is_simplified(&self) -> bool {
+        self.flags & FLAG_SIMPLIFIED != 0
+    }
+}
+
+/// Returns a human readable name of a known file format by its magic bytes
+pub fn describe_magic(magic: &[u8]) -> Option<&'static str> {
+    match format_from_magic(magic) {
+        FileFormat::Unknown => None,
+        format => Some(format.name()),
+    }
+}
+
+pub fn curve_name(curve: u32) -> &'static str {
+    match curve {
+        CURVE_BN254 => "BN254",
+        _ => "unknown curve",
+    }
+}
+
+/// Name of BN254 in the `curve` field of JSON artifacts, as snarkjs writes it
+pub const CURVE_NAME: &str = "bn128";
+
+/// Fails unless the `curve` field of a JSON artifact names BN254, which snarkjs and others also call
+/// bn128 and alt_bn128, so a proof or key for another curve isn't just reported as invalid
+pub fn check_curve_name(curve: &str, artifact: &str) -> Result<()> {
+    let normalized = curve.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_lowercase();
+    match normalized.as_str() {
+        "bn128" | "bn254" | "altbn128" => Ok(()),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{} is for curve {}, only BN254 (bn128) is supported", artifact, curve),
+        )),
+    }
+}
+
+fn has_metadata(magic: [u8; 4], version: u32) -> bool {
+    magic == PARAMS_MAGIC && version >= 3
+}
+
+/// Longest zkutil version string accepted in metadata
+const MAX_VERSION_LEN: u32 = 256;
+
+pub fn write_header<W: Write>(mut writer: W, header: &FileHeader) -> Result<()> {
+    writer.write_all(&header.magic)?;
+    writer.write_u32::<LittleEndian>(header.version)?;
+    writer.write_u32::<LittleEndian>(header.curve)?;
+    if header.version >= 2 {
+        writer.write_u32::<LittleEndian>(header.flags)?;
+    }
+    if has_metadata(header.magic, header.version) {
+        let metadata = header.metadata.as_ref().ok_or_else(|| Error::new(
+            ErrorKind::InvalidInput,
+            format!("params version {} requires metadata", header.version),
+        ))?;
+        writer.write_all(&metadata.circuit_hash)?;
+        writer.write_u64::<LittleEndian>(metadata.constraints)?;
+        writer.write_u32::<LittleEndian>(metadata.zkutil_version.len() as u32)?;
+        writer.write_all(metadata.zkutil_version.as_bytes())?;
+        writer.write_u64::<LittleEndian>(metadata.created_at)?;
+    }
+    Ok(())
+}
+
+fn read_metadata<R: Read>(mut reader: R) -> Result<ParamsMetadata> {
+    let mut circuit_hash = [0u8; 32];
+    reader.read_exact(&mut circuit_hash)?;
+    let constraints = reader.read_u64::<LittleEndian>()?;
+    let version_len = reader.read_u32::<LittleEndian>()?;
+    if version_len > MAX_VERSION_LEN {
+        return Err(Error::new(ErrorKind::InvalidData, format!("invalid zkutil version length {} in metadata", version_len)));
+    }
+    let mut version = vec![0u8; version_len as usize];
+    reader.read_exact(&mut version)?;
+    let zkutil_version = String::from_utf8(version)
+        .map_err(|_| Error::new(ErrorKind::InvalidData, "zkutil version in metadata is not valid UTF-8"))?;
+    let created_at = reader.read_u64::<LittleEndian>()?;
+    Ok(ParamsMetadata { circuit_hash, constraints, zkutil_version, created_at })
+}
+
+/// Reads and validates a header whose magic has already been consumed from `reader`
+pub fn read_header_after_magic<R: Read>(mut reader: R, magic: [u8; 4], max_version: u32) -> Result<FileHeader> {
+    let version = reader.read_u32::<LittleEndian>()?;
+    if version == 0 || version > max_version {
+        return Err(Error::new(ErrorKind::InvalidData, format!(
+            "Unsupported {} version {}, this zkutil supports up to version {}",
+            describe_magic(&magic).unwrap_or("file"), version, max_version,
+        )))
+    }
+    let curve = reader.read_u32::<LittleEndian>()?;
+    if curve != CURVE_BN254 {
+        return Err(Error::new(ErrorKind::InvalidData, format!(
+            "File is built for {} (id {}), only BN254 is supported", curve_name(curve), curve,
+        )))
+    }
+    let flags = if version >= 2 { reader.read_u32::<LittleEndian>()? } else { 0 };
+    let metadata = if has_metadata(magic, version) { Some(read_metadata(&mut reader)?) } else { None };
+    Ok(FileHeader { magic, version, curve, flags, metadata })
+}
+
+/// Formats of input files zkutil knows how to tell apart
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FileFormat {
+    Json,
+    R1cs,
+    Wtns,
+    Zkey,
+    Params,
+    RawParams,
+    Bundle,
+    Zkif,
+    /// Little-endian u64 count followed by that many 32-byte little-endian field elements
+    WitnessArray,
+    Unknown,
+}
+
+impl FileFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FileFormat::Json => "JSON file",
+            FileFormat::R1cs => "circom r1cs circuit",
+            FileFormat::Wtns => "circom wtns witness",
+            FileFormat::Zkey => "snarkjs zkey",
+            FileFormat::Params => "zkutil params file",
+            FileFormat::RawParams => "zkutil raw Montgomery params file",
+            FileFormat::Bundle => "zkutil bundle",
+            FileFormat::Zkif => "zkinterface messages",
+            FileFormat::WitnessArray => "raw witness array",
+            FileFormat::Unknown => "file of unknown format",
+        }
+    }
+}
+
+fn format_from_magic(magic: &[u8]) -> FileFormat {
+    match magic {
+        b"zkpm" => FileFormat::Params,
+        b"zkrw" => FileFormat::RawParams,
+        b"zkbn" => FileFormat::Bundle,
+        b"r1cs" => FileFormat::R1cs,
+        b"wtns" => FileFormat::Wtns,
+        b"zkey" => FileFormat::Zkey,
+        _ => match magic.iter().find(|b| !b.is_ascii_whitespace()) {
+            Some(b'{') | Some(b'[') => FileFormat::Json,
+            _ => FileFormat::Unknown,
+        },
+    }
+}
+
+fn format_from_extension(filename: &str) -> FileFormat {
+    match std::path::Path::new(filename).extension().and_then(|e| e.to_str()) {
+        Some("json") => FileFormat::Json,
+        Some("r1cs") => FileFormat::R1cs,
+        Some("wtns") => FileFormat::Wtns,
+        Some("zkey") => FileFormat::Zkey,
+        Some("bundle") => FileFormat::Bundle,
+        Some("zkif") => FileFormat::Zkif,
+        _ => FileFormat::Unknown,
+    }
+}
+
+/// Detects file format by its leading bytes
+pub fn detect_format<R: Read>(reader: R) -> Result<FileFormat> {
+    let mut head = Vec::with_capacity(16);
+    reader.take(16).read_to_end(&mut head)?;
+    if head.len() >= 4 {
+        if let FileFormat::Json = format_from_magic(&head) {
+            return Ok(FileFormat::Json);
+        }
+        return Ok(match format_from_magic(&head[..4]) {
+            // size-prefixed FlatBuffers keep their file identifier after the size and root offset
+            FileFormat::Unknown if head.get(8..12) == Some(&b"zkif"[..]) => FileFormat::Zkif,
+            format => format,
+        });
+    }
+    Ok(format_from_magic(&head))
+}
+
+// raw witness arrays have no magic, but their count prefix gives the exact size
+fn is_witness_array<R: Read>(reader: R, size: u64) -> Result<bool> {
+    let mut count = [0u8; 8];
+    if reader.take(8).read(&mut count)? < 8 {
+        return Ok(false);
+    }
+    Ok(u64::from_le_bytes(count).checked_mul(32).and_then(|n| n.checked_add(8)) == Some(size))
+}
+
+/// Detects format of a seekable stream and rewinds it to where it was
+pub fn detect_stream_format<R: Read + Seek>(reader: &mut R) -> Result<FileFormat> {
+    let start = reader.stream_position()?;
+    let mut format = detect_format(&mut *reader)?;
+    if format == FileFormat::Unknown {
+        let size = reader.seek(SeekFrom::End(0))? - start;
+        reader.seek(SeekFrom::Start(start))?;
+        if is_witness_array(&mut *reader, size)? {
+            format = FileFormat::WitnessArray;
+        }
+    }
+    reader.seek(SeekFrom::Start(start))?;
+    Ok(format)
+}
+
+/// Detects file format by magic bytes, falling back to file extension
+/// for files without a recognizable header (e.g. legacy bellman params)
+pub fn detect_file_format(filename: &str) -> Result<FileFormat> {
+    match detect_stream_format(&mut std::fs::File::open(filename)?)? {
+        FileFormat::Unknown => Ok(format_from_extension(filename)),
+        format => Ok(format),
+    }
+}
+
+#[test]
+fn detect() {
+    assert_eq!(detect_format(&b"r1cs\x01\x00\x00\x00"[..]).unwrap(), FileFormat::R1cs);
+    assert_eq!(detect_format(&b"wtns\x02\x00\x00\x00"[..]).unwrap(), FileFormat::Wtns);
+    assert_eq!(detect_format(&b"zkpm\x01\x00\x00\x00"[..]).unwrap(), FileFormat::Params);
+    assert_eq!(detect_format(&b"\n  [\"1\", \"2\"]"[..]).unwrap(), FileFormat::Json);
+    assert_eq!(detect_format(&[0u8, 1, 2, 3][..]).unwrap(), FileFormat::Unknown);
+    assert_eq!(detect_format(&b"\x40\x00\x00\x00\x0c\x00\x00\x00zkif"[..]).unwrap(), FileFormat::Zkif);
+    assert_eq!(format_from_extension("circuit.json"), FileFormat::Json);
+    let mut array = 2u64.to_le_bytes().to_vec();
+    array.extend_from_slice(&[7u8; 64]);
+    assert_eq!(detect_stream_format(&mut std::io::Cursor::new(&array)).unwrap(), FileFormat::WitnessArray);
+    assert_eq!(detect_stream_format(&mut std::io::Cursor::new(&array[..40])).unwrap(), FileFormat::Unknown);
+}
+
+#[test]
+fn curve_names() {
+    for name in &["bn128", "BN254", "alt_bn128"] {
+        assert!(check_curve_name(name, "proof").is_ok());
+    }
+    let err = check_curve_name("bls12381", "proof").unwrap_err();
+    assert_eq!(err.to_string(), "proof is for curve bls12381, only BN254 (bn128) is supported");
+}
+
+#[test]
+fn header_roundtrip() {
+    let mut data = vec![];
+    write_header(&mut data, &FileHeader { magic: PARAMS_MAGIC, version: 2, curve: CURVE_BN254, flags: FLAG_INSECURE_DEV, metadata: None }).unwrap();
+    let header = read_header_after_magic(&data[4..], PARAMS_MAGIC, PARAMS_VERSION).unwrap();
+    assert!(header.is_insecure_dev());
+    assert!(header.metadata.is_none());
+
+    let metadata = ParamsMetadata { circuit_hash: [7; 32], constraints: 42, zkutil_version: String::from("0.5.0"), created_at: 1_600_000_000 };
+    let mut data = vec![];
+    write_header(&mut data, &FileHeader { magic: PARAMS_MAGIC, version: 3, curve: CURVE_BN254, flags: 0, metadata: Some(metadata.clone()) }).unwrap();
+    let header = read_header_after_magic(&data[4..], PARAMS_MAGIC, PARAMS_VERSION).unwrap();
+    assert_eq!(header.metadata, Some(metadata));
+    assert!(write_header(vec![], &FileHeader { magic: PARAMS_MAGIC, version: 3, curve: CURVE_BN254, flags: 0, metadata: None }).is_err());
+
+    // version 1 headers have no flags
+    let mut data = vec![];
+    write_header(&mut data, &FileHeader { magic: PARAMS_MAGIC, version: 1, curve: CURVE_BN254, flags: 0, metadata: None }).unwrap();
+    assert_eq!(data.len(), 12);
+    assert!(!read_header_after_magic(&data[4..], PARAMS_MAGIC, PARAMS_VERSION).unwrap().is_insecure_dev());
+}
@@ -0,0 +1,313 @@
+//! Probing circuits for under-constrained signals.
+//!
+//! Starting from a valid witness, inputs are kept fixed and other signals are overwritten with
+//! edge-case and random values. A changed assignment that still satisfies every constraint means
+//! these signals are not determined by the inputs, which is usually a bug in the circuit.
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Error, ErrorKind, Result};
+use bellman_ce::{
+    groth16::Parameters,
+    pairing::{
+        bn256::{Bn256, Fr},
+        ff::{Field, PrimeField},
+    },
+};
+use rand::Rng;
+use crate::circom_circuit::{verify_with_key, R1CS};
+use crate::circuit::{load_r1cs, load_signal_counts, load_signals, load_witness};
+use crate::proof::prove_witness;
+use crate::signals::Visibility;
+use crate::utils::repr_to_big;
+
+/// Assignment that differs from the base witness in `wires` and still satisfies the circuit
+pub struct Finding {
+    pub wires: Vec<(usize, Fr)>,
+}
+
+/// 0, 1, 2, -1, -2, 1/2 and -1/2, values that break range and boolean checks most often
+pub fn edge_values() -> Vec<Fr> {
+    let one = Fr::one();
+    let mut two = one;
+    two.double();
+    let half = two.inverse().unwrap();
+    let neg = |v: Fr| {
+        let mut v = v;
+        v.negate();
+        v
+    };
+    vec![Fr::zero(), one, two, neg(one), neg(two), half, neg(half)]
+}
+
+/// Result of mutating signals one at a time
+pub struct MutationTest {
+    /// Number of mutated witnesses checked
+    pub mutants: usize,
+    /// Mutants that still satisfy the circuit, at most one per wire
+    pub undetected: Vec<(usize, Fr)>,
+}
+
+pub struct Fuzzer<'a> {
+    r1cs: &'a R1CS<Bn256>,
+    witness: Vec<Fr>,
+    /// Constraints each wire appears in
+    constraints_by_wire: Vec<Vec<usize>>,
+}
+
+impl<'a> Fuzzer<'a> {
+    /// Returns the index of the first unsatisfied constraint if `witness` is not valid
+    pub fn new(r1cs: &'a R1CS<Bn256>, witness: Vec<Fr>) -> std::result::Result<Self, usize> {
+        if let Some(i) = r1cs.unsatisfied_constraint(&witness) {
+            return Err(i);
+        }
+        let mut constraints_by_wire = vec![vec![]; r1cs.num_variables];
+        for (i, (a, b, c)) in r1cs.constraints.iter().enumerate() {
+            let wires = a.iter().chain(b.iter()).chain(c.iter()).map(|(wire, _)| *wire).collect::<BTreeSet<_>>();
+            for wire in wires {
+                constraints_by_wire[wire].push(i);
+            }
+        }
+        Ok(Fuzzer { r1cs, witness, constraints_by_wire })
+    }
+
+    /// Checks whether the witness with `changes` applied still satisfies the circuit,
+    /// only constraints touching the changed wires are evaluated
+    pub fn try_assignment(&mut self, changes: &[(usize, Fr)]) -> bool {
+        let original = changes.iter().map(|(wire, _)| (*wire, self.witness[*wire])).collect::<Vec<_>>();
+        if changes.iter().all(|(wire, value)| self.witness[*wire] == *value) {
+            return false;
+        }
+        for (wire, value) in changes {
+            self.witness[*wire] = *value;
+        }
+        let constraints = changes.iter()
+            .flat_map(|(wire, _)| self.constraints_by_wire[*wire].iter().copied())
+            .collect::<BTreeSet<_>>();
+        let satisfied = constraints.into_iter().all(|i| self.r1cs.is_satisfied(i, &self.witness));
+        for (wire, value) in original {
+            self.witness[wire] = value;
+        }
+        satisfied
+    }
+
+    /// Overwrites each of `wires` alone with edge values and `rounds` random values,
+    /// stopping at the first mutant of a wire the circuit accepts
+    pub fn mutation_test<R: Rng>(&mut self, wires: &[usize], rounds: usize, rng: &mut R) -> MutationTest {
+        let edge = edge_values();
+        let mut result = MutationTest { mutants: 0, undetected: vec![] };
+        for &wire in wires {
+            let candidates = edge.iter().copied().chain((0..rounds).map(|_| rng.gen())).collect::<Vec<_>>();
+            for value in candidates {
+                if value == self.witness[wire] {
+                    continue;
+                }
+                result.mutants += 1;
+                if self.try_assignment(&[(wire, value)]) {
+                    result.undetected.push((wire, value));
+                    break;
+                }
+            }
+        }
+        result
+    }
+
+    /// Runs `mutation_test`, then tries `rounds` random groups of up to four wires.
+    /// Reports at most one finding per wire.
+    pub fn run<R: Rng>(&mut self, wires: &[usize], rounds: usize, rng: &mut R) -> Vec<Finding> {
+        let edge = edge_values();
+        let single = self.mutation_test(wires, rounds, rng);
+        let mut found = single.undetected.iter().map(|(wire, _)| *wire).collect::<BTreeSet<_>>();
+        let mut findings = single.undetected.into_iter().map(|change| Finding { wires: vec![change] }).collect::<Vec<_>>();
+        if wires.len() < 2 {
+            return findings;
+        }
+        for _ in 0..rounds {
+            let mut group = wires.to_vec();
+            rng.shuffle(&mut group);
+            group.truncate(rng.gen_range(2, wires.len().min(4) + 1));
+            if group.iter().any(|wire| found.contains(wire)) {
+                continue;
+            }
+            let changes = group.iter()
+                .map(|&wire| (wire, if rng.gen() { edge[rng.gen_range(0, edge.len())] } else { rng.gen() }))
+                .collect::<Vec<_>>();
+            if self.try_assignment(&changes) {
+                found.extend(group);
+                findings.push(Finding { wires: changes });
+            }
+        }
+        findings
+    }
+}
+
+/// A circuit with a witness of the right length and the wires of signals to mutate
+pub struct FuzzTarget {
+    pub r1cs: R1CS<Bn256>,
+    pub witness: Vec<Fr>,
+    pub wires: Vec<usize>,
+    /// Signal names by wire, empty if they are not known
+    pub names: BTreeMap<usize, String>,
+}
+
+impl FuzzTarget {
+    /// Loads a circuit and its witness and picks signals of given visibility. Signal names are
+    /// taken from `sym_file` or circuit JSON, without them wires are picked by the circuit layout
+    pub fn load(circuit_file: &str, witness_file: &str, sym_file: Option<&str>, visibilities: &[Visibility]) -> Result<Self> {
+        let r1cs = load_r1cs(circuit_file)?;
+        let witness = load_witness::<Bn256>(witness_file)?;
+        if witness.len() != r1cs.num_variables {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("witness has {} values, circuit expects {}", witness.len(), r1cs.num_variables),
+            ));
+        }
+        let mut names = BTreeMap::new();
+        let wires = match load_signals(circuit_file, sym_file) {
+            Ok(signals) => {
+                for signal in &signals {
+                    names.entry(signal.wire).or_insert_with(|| signal.name.clone());
+                }
+                let mut wires = signals.iter()
+                    .filter(|s| visibilities.contains(&s.visibility))
+                    .map(|s| s.wire)
+                    .filter(|&wire| wire < r1cs.num_variables)
+                    .collect::<Vec<_>>();
+                wires.sort_unstable();
+                wires.dedup();
+                wires
+            }
+            Err(_) => {
+                // without signal names only the wire layout is known: wire 0, outputs, public inputs,
+                // private inputs, then everything else
+                let counts = load_signal_counts(circuit_file)?;
+                let pub_inputs = 1 + counts.num_outputs;
+                let prv_inputs = pub_inputs + counts.num_pub_inputs;
+                let private = prv_inputs + counts.num_prv_inputs;
+                let ranges = [
+                    (Visibility::PublicOutput, 1..pub_inputs),
+                    (Visibility::PublicInput, pub_inputs..prv_inputs),
+                    (Visibility::PrivateInput, prv_inputs..private),
+                    (Visibility::Private, private..r1cs.num_variables),
+                ];
+                ranges.iter()
+                    .filter(|(visibility, _)| visibilities.contains(visibility))
+                    .flat_map(|(_, range)| range.clone())
+                    .collect()
+            }
+        };
+        Ok(FuzzTarget { r1cs, witness, wires, names })
+    }
+
+    pub fn signal_name(&self, wire: usize) -> &str {
+        self.names.get(&wire).map(|n| n.as_str()).unwrap_or("<unknown>")
+    }
+
+    /// Fuzzer of the target witness, an `InvalidData` error if the witness doesn't satisfy the circuit
+    pub fn fuzzer(&self) -> Result<Fuzzer<'_>> {
+        Fuzzer::new(&self.r1cs, self.witness.clone()).map_err(|i| Error::new(
+            ErrorKind::InvalidData,
+            format!("witness doesn't satisfy constraint {}, a valid witness is required", i),
+        ))
+    }
+}
+
+#[derive(Serialize)]
+pub struct UndetectedMutant {
+    pub wire: usize,
+    pub name: Option<String>,
+    pub original: String,
+    pub mutant: String,
+    /// Whether a proof of the mutant verifies, if params were given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proof_verifies: Option<bool>,
+}
+
+/// Report of `soundness_test`, saved as JSON by `zkutil soundness-test`
+#[derive(Serialize)]
+pub struct SoundnessReport {
+    pub signals_tested: usize,
+    pub mutants_tested: usize,
+    pub undetected: Vec<UndetectedMutant>,
+}
+
+/// Mutates the target wires one at a time, see `Fuzzer::mutation_test`. Undetected mutants are
+/// also proved with `params` if given, which should match the circuit, see `check_params`
+pub fn soundness_test<R: Rng>(target: &FuzzTarget, params: Option<&Parameters<Bn256>>, rounds: usize, rng: &mut R) -> Result<SoundnessReport> {
+    let test = target.fuzzer()?.mutation_test(&target.wires, rounds, rng);
+    let mut undetected = vec![];
+    for (wire, value) in test.undetected {
+        let proof_verifies = match params {
+            Some(params) => {
+                let mut mutant = target.witness.clone();
+                mutant[wire] = value;
+                let (proof, public) = prove_witness(&target.r1cs, params, mutant, &mut *rng)
+                    .map_err(|e| Error::new(ErrorKind::InvalidData, format!("unable to prove a mutant of wire {}: {}", wire, e)))?;
+                Some(verify_with_key(&params.vk, &proof, public.values()).unwrap_or(false))
+            }
+            None => None,
+        };
+        undetected.push(UndetectedMutant {
+            wire,
+            name: target.names.get(&wire).cloned(),
+            original: repr_to_big(target.witness[wire].into_repr()),
+            mutant: repr_to_big(value.into_repr()),
+            proof_verifies,
+        });
+    }
+    Ok(SoundnessReport { signals_tested: target.wires.len(), mutants_tested: test.mutants, undetected })
+}
+
+#[test]
+fn under_constrained() {
+    use rand::{SeedableRng, XorShiftRng};
+    let fr = |s: &str| <Fr as bellman_ce::pairing::ff::PrimeField>::from_str(s).unwrap();
+    // out = in * in, while wire 3 only appears as 0 * w3 = 0
+    let r1cs = R1CS::<Bn256> {
+        num_inputs: 2,
+        num_aux: 2,
+        num_variables: 4,
+        constraints: vec![
+            (vec![(2, fr("1"))], vec![(2, fr("1"))], vec![(1, fr("1"))]),
+            (vec![(0, Fr::zero())], vec![(3, fr("1"))], vec![]),
+        ],
+    };
+    let witness = vec![fr("1"), fr("9"), fr("3"), fr("5")];
+    assert!(Fuzzer::new(&r1cs, vec![fr("1"), fr("8"), fr("3"), fr("5")]).is_err());
+    let mut fuzzer = Fuzzer::new(&r1cs, witness).unwrap();
+    let findings = fuzzer.run(&[1, 3], 10, &mut XorShiftRng::from_seed([1, 2, 3, 4]));
+    assert_eq!(findings.len(), 1);
+    assert_eq!(findings[0].wires[0].0, 3);
+
+    let test = fuzzer.mutation_test(&[2, 3], 10, &mut XorShiftRng::from_seed([1, 2, 3, 4]));
+    assert_eq!(test.undetected.len(), 1);
+    assert_eq!(test.undetected[0].0, 3);
+    // all 17 mutants of wire 2 are rejected, wire 3 is accepted on the first try
+    assert_eq!(test.mutants, 18);
+}
+
+#[test]
+fn soundness_report() {
+    use rand::{SeedableRng, XorShiftRng};
+    use crate::test_utils::fr;
+    // wire 3 only appears as 0 * w3 = 0
+    let r1cs = R1CS::<Bn256> {
+        num_inputs: 2,
+        num_aux: 2,
+        num_variables: 4,
+        constraints: vec![
+            (vec![(2, fr("1"))], vec![(2, fr("1"))], vec![(1, fr("1"))]),
+            (vec![(0, Fr::zero())], vec![(3, fr("1"))], vec![]),
+        ],
+    };
+    let names = vec![(3, String::from("main.unused"))].into_iter().collect();
+    let target = FuzzTarget { r1cs, witness: vec![fr("1"), fr("9"), fr("3"), fr("5")], wires: vec![2, 3], names };
+    assert_eq!((target.signal_name(3), target.signal_name(2)), ("main.unused", "<unknown>"));
+    let report = soundness_test(&target, None, 10, &mut XorShiftRng::from_seed([1, 2, 3, 4])).unwrap();
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!((json["signals_tested"].as_u64(), json["mutants_tested"].as_u64()), (Some(2), Some(18)));
+    assert_eq!(json["undetected"][0]["name"], "main.unused");
+    assert_eq!(json["undetected"][0]["original"], "5");
+    assert!(json["undetected"][0].get("proof_verifies").is_none());
+
+    let invalid = FuzzTarget { witness: vec![fr("1"), fr("8"), fr("3"), fr("5")], ..target };
+    assert_eq!(soundness_test(&invalid, None, 10, &mut XorShiftRng::from_seed([1, 2, 3, 4])).err().unwrap().kind(), ErrorKind::InvalidData);
+}
@@ -0,0 +1,150 @@
+//! Huff verifier contract generation, for teams optimizing verification gas by hand.
+//!
+//! The contract has the ABI of the Solidity verifier, the verifying key is inlined as constants and
+//! the vkX multi-scalar multiplication is unrolled for the number of public inputs.
+use std::fmt::Write as _;
+use std::io::Write;
+use bellman_ce::{
+    groth16::{Proof, VerifyingKey},
+    pairing::{
+        CurveAffine,
+        ff::{Field, PrimeField},
+        bn256::{Bn256, Fq, Fr, G1Affine, G2Affine},
+    },
+};
+use crate::eth::encode_verify_calldata;
+use crate::eth::selector;
+use crate::remote::to_hex;
+use crate::utils::{repr_to_hex, write_file_atomic};
+
+fn fq_hex(value: Fq) -> String {
+    format!("0x{}", repr_to_hex(value.into_repr()))
+}
+
+fn g1_constants(name: &str, p: &G1Affine) -> String {
+    let (x, y) = p.into_xy_unchecked();
+    format!("#define constant {0}_X = {1}\n#define constant {0}_Y = {2}\n", name, fq_hex(x), fq_hex(y))
+}
+
+// in the order of the pairing precompile input: x.c1, x.c0, y.c1, y.c0
+fn g2_constants(name: &str, p: &G2Affine) -> String {
+    let (x, y) = p.into_xy_unchecked();
+    [("X1", x.c1), ("X0", x.c0), ("Y1", y.c1), ("Y0", y.c0)].iter()
+        .map(|(part, value)| format!("#define constant {}_{} = {}\n", name, part, fq_hex(*value)))
+        .collect()
+}
+
+fn store_g2(name: &str, offset: usize) -> String {
+    ["X1", "X0", "Y1", "Y0"].iter().enumerate()
+        .map(|(i, part)| format!("    [{}_{}] {:#05x} mstore\n", name, part, offset + 32 * i))
+        .collect()
+}
+
+fn calldata_comment(calldata: &[u8]) -> String {
+    let mut lines = format!(" *   0x{}\n", to_hex(&calldata[..4]));
+    for word in calldata[4..].chunks(32) {
+        let _ = writeln!(lines, " *   {}", to_hex(word));
+    }
+    lines
+}
+
+fn test_vectors(proof: &Proof<Bn256>, inputs: &[Fr]) -> String {
+    let mut block = String::from("/* Test vectors, calldata as the selector and 32-byte words\n *\n");
+    block += " * Valid proof, returns 1:\n";
+    block += &calldata_comment(&encode_verify_calldata(proof, inputs));
+    if let Some(last) = inputs.last() {
+        let mut changed = inputs.to_vec();
+        let mut value = *last;
+        value.add_assign(&Fr::one());
+        *changed.last_mut().unwrap() = value;
+        block += " *\n * Same proof with the last input incremented, returns 0:\n";
+        block += &calldata_comment(&encode_verify_calldata(proof, &changed));
+    }
+    block + " */"
+}
+
+/// Generates the contract from the verifying key. `test_vector` is a valid proof with its public
+/// inputs, written as calldata in a comment block for testing the compiled contract
+pub fn create_verifier_huff_for_key(vk: &VerifyingKey<Bn256>, test_vector: Option<(&Proof<Bn256>, &[Fr])>) -> String {
+    let template = String::from_utf8_lossy(include_bytes!("verifier_groth.huff"));
+    let inputs = vk.ic.len() - 1;
+
+    let mut constants = g1_constants("ALPHA", &vk.alpha_g1);
+    constants += &g2_constants("BETA", &vk.beta_g2);
+    constants += &g2_constants("GAMMA", &vk.gamma_g2);
+    constants += &g2_constants("DELTA", &vk.delta_g2);
+    for (i, ic) in vk.ic.iter().enumerate() {
+        constants += &g1_constants(&format!("IC{}", i), ic);
+    }
+
+    // proof words are a.x, a.y, b.x.c1, b.x.c0, b.y.c1, b.y.c0, c.x, c.y, a is negated
+    let mut load_proof = String::new();
+    for (i, offset) in [0x000, 0x020, 0x040, 0x060, 0x080, 0x0a0, 0x240, 0x260].iter().enumerate() {
+        let _ = writeln!(load_proof, "    dup1 {:#04x} add calldataload", 32 * i);
+        load_proof += "    [PRIME_Q] dup2 lt iszero fail jumpi\n";
+        if i == 1 {
+            load_proof += "    [PRIME_Q] sub [PRIME_Q] swap1 mod\n";
+        }
+        let _ = writeln!(load_proof, "    {:#05x} mstore", offset);
+    }
+
+    let mut vk_points = String::from("    [ALPHA_X] 0x0c0 mstore\n    [ALPHA_Y] 0x0e0 mstore\n");
+    vk_points += &store_g2("BETA", 0x100);
+    vk_points += &store_g2("GAMMA", 0x1c0);
+    vk_points += &store_g2("DELTA", 0x280);
+
+    let mut vk_x = String::new();
+    for i in 0..inputs {
+        let _ = write!(
+            vk_x,
+            "    // input[{0}]\n    [IC{1}_X] 0x300 mstore\n    [IC{1}_Y] 0x320 mstore\n    {2:#x} calldataload\n",
+            i, i + 1, 0x24 + 32 * i,
+        );
+        vk_x += "    [SNARK_SCALAR_FIELD] dup2 lt iszero fail jumpi\n";
+        vk_x += "    0x340 mstore\n";
+        vk_x += "    0x40 0x3c0 0x60 0x300 0x07 gas staticcall iszero fail jumpi\n";
+        vk_x += "    0x40 0x380 0x80 0x380 0x06 gas staticcall iszero fail jumpi\n";
+    }
+
+    let vectors = match test_vector {
+        Some((proof, inputs)) => test_vectors(proof, inputs),
+        None => String::from("/* No test vectors, the contract was generated without a proof */"),
+    };
+    let signature = format!("verifyProof(bytes,uint256[{}])", inputs);
+    template
+        .replace("<%vk_input_length%>", &inputs.to_string())
+        .replace("<%test_vectors%>", &vectors)
+        .replace("<%vk_constants%>", constants.trim_end())
+        .replace("<%selector%>", &format!("0x{}", to_hex(&selector(&signature))))
+        .replace("<%load_proof%>", load_proof.trim_end())
+        .replace("<%vk_points%>", vk_points.trim_end())
+        .replace("<%vk_x%>", vk_x.trim_end())
+}
+
+pub fn create_verifier_huff_file_for_key(vk: &VerifyingKey<Bn256>, test_vector: Option<(&Proof<Bn256>, &[Fr])>, filename: &str) -> std::io::Result<()> {
+    write_file_atomic(filename, |w| w.write_all(create_verifier_huff_for_key(vk, test_vector).as_bytes()))
+}
+
+#[test]
+fn huff_verifier() {
+    let vk = VerifyingKey::<Bn256> {
+        alpha_g1: G1Affine::one(),
+        beta_g1: G1Affine::one(),
+        beta_g2: G2Affine::one(),
+        gamma_g2: G2Affine::one(),
+        delta_g1: G1Affine::one(),
+        delta_g2: G2Affine::one(),
+        ic: vec![G1Affine::one(); 3],
+    };
+    let proof = Proof::<Bn256> { a: G1Affine::one(), b: G2Affine::one(), c: G1Affine::one() };
+    let inputs = [Fr::from_str("5").unwrap(), Fr::from_str("7").unwrap()];
+    let contract = create_verifier_huff_for_key(&vk, Some((&proof, &inputs[..])));
+    assert!(!contract.contains("<%"));
+    let signature = to_hex(&selector("verifyProof(bytes,uint256[2])"));
+    assert!(contract.contains(&format!("0x{} eq verify jumpi", signature)));
+    assert!(contract.contains("#define constant IC2_Y = 0x0000000000000000000000000000000000000000000000000000000000000002"));
+    assert!(contract.contains("    // input[1]\n    [IC2_X] 0x300 mstore\n    [IC2_Y] 0x320 mstore\n    0x44 calldataload\n"));
+    let word = |n: &str| format!(" *   {:0>64}\n", n);
+    assert!(contract.contains(&format!("returns 0:\n *   0x{}\n{}{}{}{}", signature, word("60"), word("5"), word("8"), word("100"))));
+    assert!(!create_verifier_huff_for_key(&vk, None).contains("Test vectors"));
+}
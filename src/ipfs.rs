@@ -0,0 +1,278 @@
+//! Trustless `ipfs://<cid>[/path]` resolution through an HTTP gateway.
+//!
+//! Every block is fetched separately in raw form and checked against the sha2-256 digest in its CID,
+//! so a malicious or broken gateway can't substitute the content. Files stored as raw blocks or
+//! UnixFS dag-pb DAGs (the `ipfs add` default) are supported.
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use sha2::{Digest, Sha256};
+use crate::remote::http_get;
+
+/// Gateway used unless `ZKUTIL_IPFS_GATEWAY` is set
+pub const DEFAULT_GATEWAY: &str = "https://ipfs.io";
+
+const CODEC_RAW: u64 = 0x55;
+const CODEC_DAG_PB: u64 = 0x70;
+const MULTIHASH_SHA2_256: u64 = 0x12;
+
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+const BASE32_ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+fn invalid<T: Into<Box<dyn std::error::Error + Send + Sync>>>(msg: T) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Cid {
+    pub codec: u64,
+    pub digest: Vec<u8>,
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *data.get(*pos).ok_or_else(|| invalid("Unexpected end of varint"))?;
+        *pos += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid("Varint is too long"))
+}
+
+fn write_varint(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn base58_decode(s: &str) -> Result<Vec<u8>> {
+    let mut bytes: Vec<u8> = vec![];
+    for c in s.bytes() {
+        let mut carry = BASE58_ALPHABET.iter().position(|a| *a == c)
+            .ok_or_else(|| invalid("Invalid base58 character in CID"))?;
+        for b in bytes.iter_mut().rev() {
+            carry += *b as usize * 58;
+            *b = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.insert(0, carry as u8);
+            carry >>= 8;
+        }
+    }
+    let zeros = s.bytes().take_while(|c| *c == b'1').count();
+    Ok([vec![0u8; zeros], bytes].concat())
+}
+
+fn base32_decode(s: &str) -> Result<Vec<u8>> {
+    let (mut buffer, mut bits, mut out) = (0u32, 0, vec![]);
+    for c in s.bytes() {
+        let value = BASE32_ALPHABET.iter().position(|a| *a == c.to_ascii_lowercase())
+            .ok_or_else(|| invalid("Invalid base32 character in CID"))?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Ok(out)
+}
+
+fn base32_encode(data: &[u8]) -> String {
+    let (mut buffer, mut bits, mut out) = (0u32, 0, String::new());
+    for byte in data {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[(buffer >> bits) as usize & 31] as char);
+        }
+        buffer &= (1 << bits) - 1;
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[(buffer << (5 - bits)) as usize & 31] as char);
+    }
+    out
+}
+
+impl Cid {
+    /// Parses binary CID, as stored in dag-pb links
+    pub fn from_bytes(data: &[u8]) -> Result<Cid> {
+        let mut pos = 0;
+        let codec = if data.first() == Some(&(MULTIHASH_SHA2_256 as u8)) {
+            // CIDv0 is a bare sha2-256 multihash of a dag-pb block
+            CODEC_DAG_PB
+        } else {
+            if read_varint(data, &mut pos)? != 1 {
+                return Err(invalid("Unsupported CID version"));
+            }
+            read_varint(data, &mut pos)?
+        };
+        if read_varint(data, &mut pos)? != MULTIHASH_SHA2_256 {
+            return Err(invalid("Only sha2-256 CIDs are supported"));
+        }
+        let len = read_varint(data, &mut pos)? as usize;
+        let digest = data.get(pos..pos + len).filter(|d| d.len() == 32)
+            .ok_or_else(|| invalid("Invalid sha2-256 digest in CID"))?;
+        Ok(Cid { codec, digest: digest.to_vec() })
+    }
+
+    /// Parses `Qm...` CIDv0 or base32 `b...` CIDv1
+    pub fn parse(s: &str) -> Result<Cid> {
+        if s.starts_with("Qm") {
+            Cid::from_bytes(&base58_decode(s)?)
+        } else if let Some(rest) = s.strip_prefix('b') {
+            Cid::from_bytes(&base32_decode(rest)?)
+        } else {
+            Err(invalid(format!("Unsupported CID encoding: {}", s)))
+        }
+    }
+
+    /// CIDv1 in base32, accepted by all gateways
+    pub fn to_v1_string(&self) -> String {
+        let mut bytes = vec![];
+        write_varint(1, &mut bytes);
+        write_varint(self.codec, &mut bytes);
+        write_varint(MULTIHASH_SHA2_256, &mut bytes);
+        write_varint(self.digest.len() as u64, &mut bytes);
+        bytes.extend_from_slice(&self.digest);
+        format!("b{}", base32_encode(&bytes))
+    }
+}
+
+/// Splits protobuf message into `(field number, bytes)` pairs, varint fields have empty bytes
+fn protobuf_fields(data: &[u8]) -> Result<Vec<(u64, &[u8])>> {
+    let mut pos = 0;
+    let mut fields = vec![];
+    while pos < data.len() {
+        let key = read_varint(data, &mut pos)?;
+        match key & 7 {
+            0 => {
+                read_varint(data, &mut pos)?;
+                fields.push((key >> 3, &data[0..0]));
+            }
+            2 => {
+                let len = read_varint(data, &mut pos)? as usize;
+                let bytes = data.get(pos..pos + len).ok_or_else(|| invalid("Truncated protobuf field"))?;
+                pos += len;
+                fields.push((key >> 3, bytes));
+            }
+            _ => return Err(invalid("Unsupported protobuf wire type")),
+        }
+    }
+    Ok(fields)
+}
+
+pub struct Link {
+    pub cid: Cid,
+    pub name: String,
+}
+
+/// dag-pb node: links to children and UnixFS data of the node itself
+pub struct PbNode {
+    pub links: Vec<Link>,
+    pub data: Vec<u8>,
+}
+
+pub fn parse_dag_pb(block: &[u8]) -> Result<PbNode> {
+    let mut node = PbNode { links: vec![], data: vec![] };
+    for (field, bytes) in protobuf_fields(block)? {
+        match field {
+            1 => {
+                // UnixFS Data message, field 2 holds file content of this node
+                for (unixfs_field, content) in protobuf_fields(bytes)? {
+                    if unixfs_field == 2 {
+                        node.data.extend_from_slice(content);
+                    }
+                }
+            }
+            2 => {
+                let mut cid = None;
+                let mut name = String::new();
+                for (link_field, value) in protobuf_fields(bytes)? {
+                    match link_field {
+                        1 => cid = Some(Cid::from_bytes(value)?),
+                        2 => name = String::from_utf8_lossy(value).to_string(),
+                        _ => {}
+                    }
+                }
+                node.links.push(Link { cid: cid.ok_or_else(|| invalid("dag-pb link without hash"))?, name });
+            }
+            _ => {}
+        }
+    }
+    Ok(node)
+}
+
+/// Checks that block content matches the digest in its CID
+pub fn verify_block(cid: &Cid, block: &[u8]) -> Result<()> {
+    if Sha256::digest(block)[..] != cid.digest[..] {
+        return Err(invalid(format!("Block {} doesn't match its hash", cid.to_v1_string())));
+    }
+    Ok(())
+}
+
+fn gateway() -> String {
+    std::env::var("ZKUTIL_IPFS_GATEWAY").unwrap_or_else(|_| String::from(DEFAULT_GATEWAY))
+}
+
+fn fetch_block(cid: &Cid) -> Result<Vec<u8>> {
+    let url = format!("{}/ipfs/{}?format=raw", gateway().trim_end_matches('/'), cid.to_v1_string());
+    let mut block = vec![];
+    http_get(&url, Some("application/vnd.ipld.raw"))?.read_to_end(&mut block)?;
+    verify_block(cid, &block)?;
+    Ok(block)
+}
+
+fn write_file<W: Write>(cid: &Cid, writer: &mut W) -> Result<()> {
+    let block = fetch_block(cid)?;
+    match cid.codec {
+        CODEC_RAW => writer.write_all(&block),
+        CODEC_DAG_PB => {
+            let node = parse_dag_pb(&block)?;
+            writer.write_all(&node.data)?;
+            for link in node.links {
+                write_file(&link.cid, writer)?;
+            }
+            Ok(())
+        }
+        codec => Err(invalid(format!("Unsupported CID codec 0x{:x}", codec))),
+    }
+}
+
+/// Resolves `ipfs://<cid>[/path]` and writes verified file content to `writer`
+pub fn fetch<W: Write>(location: &str, writer: &mut W) -> Result<()> {
+    let path = location.strip_prefix("ipfs://").ok_or_else(|| invalid("Not an ipfs:// location"))?;
+    let mut segments = path.split('/').filter(|s| !s.is_empty());
+    let mut cid = Cid::parse(segments.next().ok_or_else(|| invalid("Missing CID"))?)?;
+    for segment in segments {
+        if cid.codec != CODEC_DAG_PB {
+            return Err(invalid(format!("{} is not a directory", segment)));
+        }
+        let node = parse_dag_pb(&fetch_block(&cid)?)?;
+        cid = node.links.into_iter().find(|l| l.name == segment).map(|l| l.cid)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("{} not found in {}", segment, location)))?;
+    }
+    write_file(&cid, writer)
+}
+
+#[test]
+fn cid_encoding() {
+    // sha2-256 of empty input
+    let digest = hex!("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    let cid = Cid { codec: CODEC_RAW, digest: digest.to_vec() };
+    let s = cid.to_v1_string();
+    assert_eq!(s, "bafkreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku");
+    assert_eq!(Cid::parse(&s).unwrap(), cid);
+    verify_block(&cid, b"").unwrap();
+    assert!(verify_block(&cid, b"x").is_err());
+
+    let v0 = Cid::parse("QmdfTbBqBPQ7VNxZEYEj14VmRuZBkqFbiwReogJgS1zR1n").unwrap();
+    assert_eq!(v0.codec, CODEC_DAG_PB);
+    assert_eq!(v0.to_v1_string(), "bafybeihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku");
+}
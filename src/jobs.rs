@@ -0,0 +1,307 @@
+//! Proving jobs for queue consumers and other long running modes.
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use bellman_ce::{
+    groth16::Parameters,
+    pairing::{
+        bn256::{Bn256, Fr},
+        ff::{PrimeField, PrimeFieldRepr},
+    },
+};
+use sha2::{Digest, Sha256};
+use crate::circom_circuit::{
+    R1CS,
+    RngSource,
+    OsRngSource,
+    proof_to_json,
+};
+use crate::proof::prove_witness;
+use crate::circuit::load_witness;
+use crate::keys::circuit_hash;
+use crate::utils::fr_from_json;
+use crate::metrics::{self, Stage};
+
+/// A proving job, witness is passed either inline as decimal or hex strings or integers, or as a
+/// path to a witness file
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ProveJob {
+    pub id: String,
+    #[serde(default)]
+    pub witness: Option<Vec<serde_json::Value>>,
+    #[serde(default)]
+    pub witness_path: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ProveResult {
+    pub id: String,
+    /// Proof in the same format as proof.json
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proof: Option<serde_json::Value>,
+    /// Public inputs in the same format as public.json
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub public: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Files `witness_path` of a job may refer to
+#[derive(Clone, Copy, Debug)]
+pub enum WitnessPaths<'a> {
+    /// Any file, for jobs from trusted sources only
+    Any,
+    /// Files inside this directory, relative paths are resolved against it
+    Within(&'a Path),
+    /// None, witnesses must be passed inline
+    Disabled,
+}
+
+impl WitnessPaths<'_> {
+    fn resolve(self, path: &str) -> Result<String, String> {
+        match self {
+            WitnessPaths::Any => Ok(path.to_string()),
+            WitnessPaths::Disabled => Err(String::from("witness_path is disabled, pass the witness inline")),
+            WitnessPaths::Within(dir) => {
+                let canonical = |p: &Path| fs::canonicalize(p).map_err(|e| format!("{}: {}", p.display(), e));
+                // canonical paths, so neither `..` nor symlinks lead out of the directory
+                let (dir, file) = (canonical(dir)?, canonical(&dir.join(path))?);
+                if !file.starts_with(&dir) {
+                    return Err(format!("witness_path {} is outside of the witness directory", path));
+                }
+                Ok(file.to_string_lossy().to_string())
+            }
+        }
+    }
+}
+
+fn load_job_witness(job: &ProveJob, witness_paths: WitnessPaths) -> Result<Vec<Fr>, String> {
+    match (&job.witness, &job.witness_path) {
+        (Some(values), None) => values.iter()
+            .map(|v| fr_from_json(v).ok_or_else(|| format!("invalid witness value {}", v)))
+            .collect(),
+        (None, Some(path)) => load_witness::<Bn256>(&witness_paths.resolve(path)?).map_err(|e| e.to_string()),
+        _ => Err(String::from("job must have exactly one of witness and witness_path")),
+    }
+}
+
+/// SHA-256 of a circuit hash and the little-endian representations of witness values
+pub(crate) fn witness_hash(circuit_hash: &[u8; 32], witness: &[Fr]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(circuit_hash);
+    let mut buf = Vec::with_capacity(32);
+    for value in witness {
+        buf.clear();
+        value.into_repr().write_le(&mut buf).expect("writing to memory doesn't fail");
+        hasher.update(&buf);
+    }
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&hasher.finalize());
+    key
+}
+
+/// Proof and public inputs JSON of proved jobs keyed by SHA-256 of the circuit hash and the witness,
+/// so jobs resubmitted by retrying upstreams are answered without proving again. The oldest
+/// entries are evicted beyond `capacity`
+pub struct ProofCache {
+    circuit_hash: [u8; 32],
+    capacity: usize,
+    entries: HashMap<[u8; 32], (serde_json::Value, serde_json::Value)>,
+    order: VecDeque<[u8; 32]>,
+}
+
+impl ProofCache {
+    pub fn new(r1cs: &R1CS<Bn256>, capacity: usize) -> Self {
+        ProofCache { circuit_hash: circuit_hash(r1cs), capacity, entries: HashMap::new(), order: VecDeque::new() }
+    }
+
+    fn key(&self, witness: &[Fr]) -> [u8; 32] {
+        witness_hash(&self.circuit_hash, witness)
+    }
+
+    fn insert(&mut self, key: [u8; 32], result: (serde_json::Value, serde_json::Value)) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.insert(key, result).is_none() {
+            self.order.push_back(key);
+        }
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+fn run_job(r1cs: &R1CS<Bn256>, params: &Parameters<Bn256>, job: &ProveJob, witness_paths: WitnessPaths, rng: &dyn RngSource, cache: Option<&mut ProofCache>) -> Result<(serde_json::Value, serde_json::Value), String> {
+    let witness = metrics::time(Stage::LoadWitness, || load_job_witness(job, witness_paths))?;
+    if witness.len() != r1cs.num_variables {
+        return Err(format!("witness has {} values, circuit expects {}", witness.len(), r1cs.num_variables));
+    }
+    let key = cache.as_ref().map(|cache| cache.key(&witness));
+    if let (Some(cache), Some(key)) = (&cache, &key) {
+        if let Some(result) = cache.entries.get(key) {
+            return Ok(result.clone());
+        }
+    }
+    let (proof, public) = metrics::time(Stage::Prove, || prove_witness(r1cs, params, witness, rng.rng())).map_err(|e| format!("{:?}", e))?;
+    let proof: serde_json::Value = serde_json::from_str(&proof_to_json(&proof).map_err(|e| e.to_string())?).unwrap();
+    let public: serde_json::Value = serde_json::from_str(&public.to_json()).unwrap();
+    if let (Some(cache), Some(key)) = (cache, key) {
+        cache.insert(key, (proof.clone(), public.clone()));
+    }
+    Ok((proof, public))
+}
+
+/// Proves a trusted job, failures are reported in the result instead of aborting the consumer.
+/// `r1cs` has to be prepared for `params` by `keys::circuit_for_params` or `keys::load_prover_pair`
+pub fn process_job(r1cs: &R1CS<Bn256>, params: &Parameters<Bn256>, job: &ProveJob) -> ProveResult {
+    process_job_with_rng(r1cs, params, job, &OsRngSource)
+}
+
+/// Same as `process_job`, but takes proof randomness from `rng`
+pub fn process_job_with_rng(r1cs: &R1CS<Bn256>, params: &Parameters<Bn256>, job: &ProveJob, rng: &dyn RngSource) -> ProveResult {
+    process_job_inner(r1cs, params, job, WitnessPaths::Any, rng, None)
+}
+
+/// Same as `process_job`, but reads witness files only where `witness_paths` allows, so it is fit
+/// for jobs from untrusted publishers, and answers jobs with a witness proved before from `cache`
+pub fn process_job_cached(r1cs: &R1CS<Bn256>, params: &Parameters<Bn256>, job: &ProveJob, witness_paths: WitnessPaths, cache: &mut ProofCache) -> ProveResult {
+    process_job_inner(r1cs, params, job, witness_paths, &OsRngSource, Some(cache))
+}
+
+fn process_job_inner(r1cs: &R1CS<Bn256>, params: &Parameters<Bn256>, job: &ProveJob, witness_paths: WitnessPaths, rng: &dyn RngSource, cache: Option<&mut ProofCache>) -> ProveResult {
+    let _span = tracing::info_span!("job", id = job.id.as_str()).entered();
+    metrics::job_started();
+    // witness loaders panic on malformed files
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| run_job(r1cs, params, job, witness_paths, rng, cache)))
+        .unwrap_or_else(|_| Err(String::from("prover panicked")));
+    metrics::job_finished();
+    metrics::proof_done(result.is_ok());
+    match result {
+        Ok((proof, public)) => ProveResult { id: job.id.clone(), proof: Some(proof), public: Some(public), error: None },
+        Err(e) => ProveResult { id: job.id.clone(), proof: None, public: None, error: Some(e) },
+    }
+}
+
+/// A job waiting to be proved
+pub struct QueuedJob<T> {
+    pub job: T,
+    /// Higher is more urgent, 0 is the default
+    pub priority: i64,
+    /// Time since the job was queued
+    pub waiting: Duration,
+}
+
+/// Index of the job to prove next: the highest priority after aging, where a job gains one level
+/// per `aging` of waiting so low priority jobs are not starved by a stream of urgent ones. Ties go
+/// to the earlier job
+pub fn next_job<T>(queue: &[QueuedJob<T>], aging: Duration) -> Option<usize> {
+    let aging = aging.as_secs_f64().max(1e-3);
+    let effective = |j: &QueuedJob<T>| j.priority as f64 + j.waiting.as_secs_f64() / aging;
+    let mut best: Option<(usize, f64)> = None;
+    for (i, job) in queue.iter().enumerate() {
+        let priority = effective(job);
+        if best.map_or(true, |(_, p)| priority > p) {
+            best = Some((i, priority));
+        }
+    }
+    best.map(|(i, _)| i)
+}
+
+/// Splits a priority suffix off a job name: `batch.p10` is `batch` with priority 10
+pub fn job_priority(name: &str) -> (&str, i64) {
+    match name.rsplit_once(".p").and_then(|(base, p)| p.parse().ok().map(|p| (base, p))) {
+        Some((base, priority)) if !base.is_empty() => (base, priority),
+        _ => (name, 0),
+    }
+}
+
+#[test]
+fn priority_scheduling() {
+    let job = |job: &'static str, priority: i64, waiting: u64| QueuedJob { job, priority, waiting: Duration::from_secs(waiting) };
+    let aging = Duration::from_secs(60);
+    assert_eq!(next_job::<&str>(&[], aging), None);
+    assert_eq!(next_job(&[job("a", 0, 10), job("b", 0, 10)], aging), Some(0));
+    assert_eq!(next_job(&[job("background", 0, 30), job("liquidation", 5, 0)], aging), Some(1));
+    // after waiting 6 minutes a background job goes ahead of fresh urgent ones
+    assert_eq!(next_job(&[job("background", 0, 360), job("liquidation", 5, 0)], aging), Some(0));
+
+    assert_eq!(job_priority("batch.p10"), ("batch", 10));
+    assert_eq!(job_priority("batch.p-1"), ("batch", -1));
+    assert_eq!(job_priority("batch"), ("batch", 0));
+    assert_eq!(job_priority("batch.pending"), ("batch.pending", 0));
+    assert_eq!(job_priority(".p3"), (".p3", 0));
+}
+
+#[test]
+fn cached_jobs() {
+    use crate::test_utils::{multiplier_circuit, multiplier_params};
+    let (r1cs, params) = (multiplier_circuit(), multiplier_params());
+    let job = |id: &str, witness: &[&str]| ProveJob {
+        id: id.to_string(),
+        witness: Some(witness.iter().map(|v| serde_json::json!(v)).collect()),
+        witness_path: None,
+    };
+    let mut cache = ProofCache::new(&r1cs, 1);
+    let first = process_job_cached(&r1cs, &params, &job("1", &["1", "6", "2", "3"]), WitnessPaths::Disabled, &mut cache);
+    let retry = process_job_cached(&r1cs, &params, &job("2", &["1", "6", "2", "3"]), WitnessPaths::Disabled, &mut cache);
+    assert_eq!((retry.id.as_str(), &retry.proof), ("2", &first.proof));
+    let other = process_job_cached(&r1cs, &params, &job("3", &["1", "6", "3", "2"]), WitnessPaths::Disabled, &mut cache);
+    assert!(other.proof.is_some() && other.proof != first.proof);
+    assert_eq!(cache.len(), 1);
+    assert!(process_job_cached(&r1cs, &params, &job("4", &["1", "6"]), WitnessPaths::Disabled, &mut cache).error.is_some());
+}
+
+#[test]
+fn simplified_params() {
+    use crate::circom_circuit::{r1cs_to_json_file, read_proof_json, verify, write_params_file_with_metadata};
+    use crate::file_header::FLAG_SIMPLIFIED;
+    use crate::keys::{check_metadata, load_prover_pair, params_metadata};
+    use crate::test_utils::{fr, multiplier_circuit, multiplier_params};
+    let dir = std::env::temp_dir().join(format!("zkutil-simplified-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+    // the multiplier with a redundant a * 0 = 0, which simplification removes
+    let mut circuit = multiplier_circuit();
+    circuit.constraints.push((vec![(2, fr("1"))], vec![(3, fr("0"))], vec![]));
+    r1cs_to_json_file(&circuit, &path("circuit.json")).unwrap();
+    let params = multiplier_params();
+    write_params_file_with_metadata(&params, FLAG_SIMPLIFIED, &params_metadata(&multiplier_circuit()), &path("params.bin")).unwrap();
+
+    let (header, params, r1cs) = load_prover_pair(&path("params.bin"), &path("circuit.json")).unwrap();
+    assert!(check_metadata(header.as_ref(), &circuit).is_err());
+    assert_eq!(r1cs.constraints.len(), 1);
+    let job = ProveJob { id: "1".to_string(), witness: Some(["1", "6", "2", "3"].iter().map(|v| serde_json::json!(v)).collect()), witness_path: None };
+    let result = process_job(&r1cs, &params, &job);
+    let proof = read_proof_json(serde_json::to_vec(&result.proof.unwrap()).unwrap().as_slice(), true).unwrap();
+    assert!(verify(&params, &proof, &[fr("6")]).unwrap());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn witness_paths() {
+    let dir = std::env::temp_dir().join(format!("zkutil-witness-paths-{}", std::process::id()));
+    let witness_dir = dir.join("witnesses");
+    fs::create_dir_all(&witness_dir).unwrap();
+    fs::write(witness_dir.join("in.json"), r#"["1", "6", "2", "3"]"#).unwrap();
+    fs::write(dir.join("out.json"), r#"["1", "6", "2", "3"]"#).unwrap();
+    let job = |path: &str| ProveJob { id: "1".to_string(), witness: None, witness_path: Some(path.to_string()) };
+    let within = WitnessPaths::Within(&witness_dir);
+    assert_eq!(load_job_witness(&job("in.json"), within).unwrap().len(), 4);
+    assert!(load_job_witness(&job("../out.json"), within).is_err());
+    assert!(load_job_witness(&job(&dir.join("out.json").to_string_lossy()), within).is_err());
+    assert!(load_job_witness(&job("in.json"), WitnessPaths::Disabled).is_err());
+    assert!(load_job_witness(&job(&dir.join("out.json").to_string_lossy()), WitnessPaths::Any).is_ok());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,589 @@
+//! Trusted setup parameters: loading from params files and bundles, generation and export of snarkjs keys.
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Read, Result, Seek, Write};
+use std::time::{Duration, Instant};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use bellman_ce::{
+    SynthesisError,
+    groth16::{Parameters, VerifyingKey},
+    pairing::{
+        CurveAffine,
+        ff::{Field, PrimeField, PrimeFieldRepr},
+        bn256::{Bn256, Fr},
+    },
+};
+use crate::bundle::{self, Bundle};
+use crate::circom_circuit::{
+    CircomCircuit,
+    R1CS,
+    check_params,
+    create_rng,
+    create_insecure_dev_rng,
+    generate_random_parameters,
+    read_params_with_header,
+    read_params_with_header_checked,
+    read_verifying_key_with_header,
+    read_verifying_key_json,
+    write_proving_key_json,
+    write_verification_key_json,
+    proving_key_json_file_with_format,
+    verification_key_json_file_with_format,
+};
+use crate::circuit::{load_r1cs, unexpected_format};
+use crate::file_header::{FileFormat, FileHeader, ParamsMetadata, FLAG_INSECURE_DEV, detect_file_format, detect_stream_format};
+use crate::provenance::unix_time;
+use crate::utils::{self, NumberFormat};
+
+pub fn load_bundle(filename: &str) -> Result<Bundle> {
+    bundle::read(BufReader::new(File::open(filename)?))
+}
+
+/// Loads parameters from a params file or a bundle together with their header,
+/// which is `None` for legacy headerless files
+pub fn load_params(filename: &str) -> Result<(Option<FileHeader>, Parameters<Bn256>)> {
+    load_params_checked(filename, true)
+}
+
+/// Loads parameters like `load_params`, without `checked` only verifying key points of params files
+/// are validated. Parameters in bundles are always checked.
+pub fn load_params_checked(filename: &str, checked: bool) -> Result<(Option<FileHeader>, Parameters<Bn256>)> {
+    match detect_file_format(filename)? {
+        FileFormat::Params | FileFormat::Unknown => read_params_with_header_checked(BufReader::new(File::open(filename)?), checked),
+        FileFormat::Bundle => {
+            let bundle = load_bundle(filename)?;
+            Ok((bundle.params_header()?, bundle.params()?))
+        }
+        format => Err(unexpected_format(filename, "trusted setup parameters", format)),
+    }
+}
+
+/// Loads only the verifying key of a params file or a bundle, without reading the queries, or a
+/// snarkjs verification key JSON, see `read_verifying_key_json`
+pub fn load_verifying_key(filename: &str) -> Result<(Option<FileHeader>, VerifyingKey<Bn256>)> {
+    match detect_file_format(filename)? {
+        FileFormat::Params | FileFormat::Unknown => read_verifying_key_with_header(BufReader::new(File::open(filename)?)),
+        FileFormat::Json => Ok((None, read_verifying_key_json(BufReader::new(File::open(filename)?))?)),
+        FileFormat::Bundle => {
+            let bundle = load_bundle(filename)?;
+            Ok((bundle.params_header()?, bundle.verifying_key()?))
+        }
+        format => Err(unexpected_format(filename, "trusted setup parameters", format)),
+    }
+}
+
+/// Reads parameters or a bundle from a seekable stream, see `load_params`
+pub fn read_params<R: Read + Seek>(mut reader: R) -> Result<(Option<FileHeader>, Parameters<Bn256>)> {
+    match detect_stream_format(&mut reader)? {
+        FileFormat::Params | FileFormat::Unknown => read_params_with_header(reader),
+        FileFormat::Bundle => {
+            let bundle = bundle::read(reader)?;
+            Ok((bundle.params_header()?, bundle.params()?))
+        }
+        format => Err(unexpected_format("input", "trusted setup parameters", format)),
+    }
+}
+
+/// Generates parameters for a circuit and returns them with header flags to write them with.
+/// `dev` parameters are generated from a fixed seed and flagged with `FLAG_INSECURE_DEV`.
+pub fn setup(r1cs: R1CS<Bn256>, dev: bool) -> std::result::Result<(Parameters<Bn256>, u32), SynthesisError> {
+    if dev {
+        Ok((setup_with_rng(r1cs, create_insecure_dev_rng())?, FLAG_INSECURE_DEV))
+    } else {
+        Ok((setup_with_rng(r1cs, create_rng())?, 0))
+    }
+}
+
+/// Generates parameters for a circuit using the toxic waste from `rng`
+pub fn setup_with_rng<R: Rng>(r1cs: R1CS<Bn256>, rng: R) -> std::result::Result<Parameters<Bn256>, SynthesisError> {
+    let circuit = CircomCircuit { r1cs, witness: None, wire_mapping: None };
+    generate_random_parameters(circuit, rng)
+}
+
+/// SHA-256 of circuit shape and constraints: little-endian u64 numbers of inputs, private
+/// variables and constraints, then every linear combination as u64 term count followed by
+/// u64 wire index and 32-byte little-endian coefficient of each term
+pub fn circuit_hash(r1cs: &R1CS<Bn256>) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    let mut buf = Vec::with_capacity(32);
+    for n in &[r1cs.num_inputs, r1cs.num_aux, r1cs.constraints.len()] {
+        hasher.update(&(*n as u64).to_le_bytes());
+    }
+    for (a, b, c) in &r1cs.constraints {
+        for lc in &[a, b, c] {
+            hasher.update(&(lc.len() as u64).to_le_bytes());
+            for (wire, coeff) in lc.iter() {
+                hasher.update(&(*wire as u64).to_le_bytes());
+                buf.clear();
+                coeff.into_repr().write_le(&mut buf).expect("writing to memory doesn't fail");
+                hasher.update(&buf);
+            }
+        }
+    }
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&hasher.finalize());
+    hash
+}
+
+/// SHA-256 of the uncompressed verifying key points verification uses: alpha_g1, beta_g2,
+/// gamma_g2, delta_g2 and ic. A snarkjs verification key has the same hash as its params
+pub fn verifying_key_hash(vk: &VerifyingKey<Bn256>) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(vk.alpha_g1.into_uncompressed().as_ref());
+    for p in &[vk.beta_g2, vk.gamma_g2, vk.delta_g2] {
+        hasher.update(p.into_uncompressed().as_ref());
+    }
+    for p in &vk.ic {
+        hasher.update(p.into_uncompressed().as_ref());
+    }
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&hasher.finalize());
+    hash
+}
+
+/// Metadata to store in params generated for `r1cs` now
+pub fn params_metadata(r1cs: &R1CS<Bn256>) -> ParamsMetadata {
+    ParamsMetadata {
+        circuit_hash: circuit_hash(r1cs),
+        constraints: r1cs.constraints.len() as u64,
+        zkutil_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: unix_time(),
+    }
+}
+
+/// Checks that params with `header` were generated for `r1cs`, if their header has metadata
+pub fn check_metadata(header: Option<&FileHeader>, r1cs: &R1CS<Bn256>) -> std::result::Result<(), String> {
+    let metadata = match header.and_then(|h| h.metadata.as_ref()) {
+        Some(metadata) => metadata,
+        None => return Ok(()),
+    };
+    if metadata.constraints != r1cs.constraints.len() as u64 {
+        return Err(format!(
+            "params were generated for a circuit with {} constraints, this circuit has {}",
+            metadata.constraints, r1cs.constraints.len(),
+        ));
+    }
+    if metadata.circuit_hash != circuit_hash(r1cs) {
+        return Err(String::from("params were generated for a different circuit with the same number of constraints"));
+    }
+    Ok(())
+}
+
+/// Logs a warning for params generated by `setup --dev`, anyone can forge proofs for them
+pub fn warn_if_insecure(header: Option<&FileHeader>) {
+    if header.map_or(false, |h| h.is_insecure_dev()) {
+        tracing::warn!("INSECURE-DEV parameters generated by `setup --dev` are in use, anyone can forge proofs for them");
+    }
+}
+
+/// Simplifies `r1cs` with `R1CS::simplify` if params with `header` were generated for the
+/// simplified circuit, before `check_metadata` and proving
+pub fn simplify_for_params(header: Option<&FileHeader>, r1cs: &mut R1CS<Bn256>) {
+    if header.map_or(false, |h| h.is_simplified()) {
+        r1cs.simplify();
+    }
+}
+
+fn params_mismatch(e: String) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("parameters don't match the circuit: {}", e))
+}
+
+fn with_context(e: Error, context: &str, filename: &str) -> Error {
+    Error::new(e.kind(), format!("{} {}: {}", context, filename, e))
+}
+
+/// Prepares a circuit for proving with params with `header`: simplifies it if the params were
+/// generated for the simplified circuit and checks it against their metadata. A circuit that isn't
+/// simplified like its params gives invalid proofs, so every prover path loads circuits through this
+pub fn circuit_for_params(header: Option<&FileHeader>, mut r1cs: R1CS<Bn256>) -> Result<R1CS<Bn256>> {
+    simplify_for_params(header, &mut r1cs);
+    check_metadata(header, &r1cs).map_err(params_mismatch)?;
+    Ok(r1cs)
+}
+
+/// Loads params and the circuit they were generated for, ready to prove: the circuit is prepared
+/// with `circuit_for_params` and checked against the params queries
+pub fn load_prover_pair(params: &str, circuit: &str) -> Result<(Option<FileHeader>, Parameters<Bn256>, R1CS<Bn256>)> {
+    // independent files, the circuit is parsed while the params are read
+    let (params_data, r1cs) = utils::join(|| load_params(params), || load_r1cs(circuit));
+    let (header, params_data) = params_data.map_err(|e| with_context(e, "unable to load parameters", params))?;
+    let r1cs = r1cs.map_err(|e| with_context(e, "unable to load circuit", circuit))?;
+    let r1cs = circuit_for_params(header.as_ref(), r1cs)?;
+    check_params(&r1cs, &params_data).map_err(params_mismatch)?;
+    Ok((header, params_data, r1cs))
+}
+
+/// Loads params and circuit of a bundle like `load_prover_pair`
+pub fn load_bundle_pair(filename: &str) -> Result<(Option<FileHeader>, Parameters<Bn256>, R1CS<Bn256>)> {
+    let bundle = load_bundle(filename).map_err(|e| with_context(e, "unable to load bundle", filename))?;
+    let header = bundle.params_header()?;
+    let r1cs = circuit_for_params(header.as_ref(), bundle.r1cs()?)?;
+    let params = bundle.params()?;
+    check_params(&r1cs, &params).map_err(params_mismatch)?;
+    Ok((header, params, r1cs))
+}
+
+/// Differences between two keys, see `diff_keys`
+pub struct KeyDiff {
+    /// Numbers of public inputs of both keys
+    pub public_inputs: (usize, usize),
+    /// Points that differ like `delta_g2` and `ic[1]`, and queries with the number of differing points
+    pub differences: Vec<String>,
+    /// Whether proving key queries were compared, only when both files are params files or bundles
+    pub proving_key: bool,
+}
+
+impl KeyDiff {
+    pub fn is_same(&self) -> bool {
+        self.differences.is_empty()
+    }
+}
+
+/// Compares the verifying key points used for verification, `beta_g1` and `delta_g1` are skipped
+/// since snarkjs verification keys don't have them
+pub fn diff_verifying_keys(a: &VerifyingKey<Bn256>, b: &VerifyingKey<Bn256>) -> KeyDiff {
+    let mut differences = [
+        ("alpha_g1", a.alpha_g1 == b.alpha_g1),
+        ("beta_g2", a.beta_g2 == b.beta_g2),
+        ("gamma_g2", a.gamma_g2 == b.gamma_g2),
+        ("delta_g2", a.delta_g2 == b.delta_g2),
+    ].iter().filter(|(_, same)| !same).map(|(name, _)| name.to_string()).collect::<Vec<_>>();
+    for i in 0..a.ic.len().max(b.ic.len()) {
+        if a.ic.get(i) != b.ic.get(i) {
+            differences.push(format!("ic[{}]", i));
+        }
+    }
+    KeyDiff {
+        public_inputs: (a.ic.len().saturating_sub(1), b.ic.len().saturating_sub(1)),
+        differences,
+        proving_key: false,
+    }
+}
+
+fn diff_query<G: PartialEq>(name: &str, a: &[G], b: &[G], differences: &mut Vec<String>) {
+    if a.len() != b.len() {
+        differences.push(format!("{} ({} vs {} points)", name, a.len(), b.len()));
+        return;
+    }
+    let differing = a.iter().zip(b).filter(|(x, y)| x != y).count();
+    if differing > 0 {
+        differences.push(format!("{} ({} of {} points)", name, differing, a.len()));
+    }
+}
+
+/// Compares whole parameters, verifying key and proving key queries
+pub fn diff_params(a: &Parameters<Bn256>, b: &Parameters<Bn256>) -> KeyDiff {
+    let mut diff = diff_verifying_keys(&a.vk, &b.vk);
+    for (name, same) in &[("beta_g1", a.vk.beta_g1 == b.vk.beta_g1), ("delta_g1", a.vk.delta_g1 == b.vk.delta_g1)] {
+        if !same {
+            diff.differences.push(name.to_string());
+        }
+    }
+    diff_query("h", &a.h, &b.h, &mut diff.differences);
+    diff_query("l", &a.l, &b.l, &mut diff.differences);
+    diff_query("a", &a.a, &b.a, &mut diff.differences);
+    diff_query("b_g1", &a.b_g1, &b.b_g1, &mut diff.differences);
+    diff_query("b_g2", &a.b_g2, &b.b_g2, &mut diff.differences);
+    diff.proving_key = true;
+    diff
+}
+
+/// Compares the keys of two params files, bundles or snarkjs verification keys, whole parameters
+/// when neither is a verification key
+pub fn diff_keys(a: &str, b: &str) -> Result<KeyDiff> {
+    if detect_file_format(a)? == FileFormat::Json || detect_file_format(b)? == FileFormat::Json {
+        return Ok(diff_verifying_keys(&load_verifying_key(a)?.1, &load_verifying_key(b)?.1));
+    }
+    Ok(diff_params(&load_params(a)?.1, &load_params(b)?.1))
+}
+
+/// What `setup` would produce for a circuit, computed without generating anything
+pub struct SetupEstimate {
+    /// Circuit constraints plus one per public input added by the generator
+    pub constraints: usize,
+    /// Size of the FFT evaluation domain
+    pub domain_size: usize,
+    pub g1_points: usize,
+    pub g2_points: usize,
+    /// Size of the params file including zkutil header with metadata
+    pub params_size: u64,
+}
+
+const G1_UNCOMPRESSED: u64 = 64;
+const G2_UNCOMPRESSED: u64 = 128;
+
+/// Counts points of parameters the same way bellman generates them: `a`, `b_g1` and `b_g2`
+/// skip variables that never appear in the corresponding matrix
+pub fn estimate_setup(r1cs: &R1CS<Bn256>) -> SetupEstimate {
+    let mut a_vars = (0..r1cs.num_inputs).collect::<HashSet<_>>();
+    let mut b_vars = HashSet::new();
+    for (a, b, c) in &r1cs.constraints {
+        // same condition as in synthesis
+        if !((a.is_empty() || b.is_empty()) && c.is_empty()) {
+            a_vars.extend(a.iter().filter(|(_, coeff)| !coeff.is_zero()).map(|(i, _)| *i));
+            b_vars.extend(b.iter().filter(|(_, coeff)| !coeff.is_zero()).map(|(i, _)| *i));
+        }
+    }
+    let constraints = r1cs.num_enforced_constraints() + r1cs.num_inputs;
+    let domain_size = r1cs.domain_size();
+    let (ic, h, l) = (r1cs.num_inputs, domain_size - 1, r1cs.num_aux);
+    let (a, b) = (a_vars.len(), b_vars.len());
+    // header with metadata, vk with IC, then h, l, a, b_g1, b_g2 each prefixed with u32 length
+    let params_size = 16 + 52 + env!("CARGO_PKG_VERSION").len() as u64
+        + 3 * G1_UNCOMPRESSED + 3 * G2_UNCOMPRESSED + 4 + ic as u64 * G1_UNCOMPRESSED
+        + 5 * 4
+        + (h + l + a + b) as u64 * G1_UNCOMPRESSED
+        + b as u64 * G2_UNCOMPRESSED;
+    SetupEstimate {
+        constraints,
+        domain_size,
+        g1_points: 6 + ic + h + l + a + b,
+        g2_points: 3 + b,
+        params_size,
+    }
+}
+
+/// How `prove` keeps queries of the proving key
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum QueryStorage {
+    /// Whole parameters are loaded and the prover works on a filtered copy
+    Loaded,
+    /// Queries are read from a params file as the prover asks for them, see `params_file::ParamsFile`
+    Streamed,
+    /// Queries are read from disk in every multiexp window, see `ParamsFile::disk_backed`
+    DiskBacked,
+}
+
+/// In-memory sizes of affine points with their infinity flag
+const G1_AFFINE: u64 = 72;
+const G2_AFFINE: u64 = 136;
+
+/// Rough peak memory of proving a circuit in bytes: the circuit and its copy in the prover, the
+/// witness and its assignment, three FFT domains of the witness polynomials, and the queries
+/// unless they are disk-backed. Multiexp buckets and allocator overhead are not included.
+pub fn estimate_prover_memory(r1cs: &R1CS<Bn256>, storage: QueryStorage) -> u64 {
+    prover_memory(r1cs, storage, false)
+}
+
+/// Same as `estimate_prover_memory` for `prover::prove_low_memory`, which proves with the only
+/// copy of the circuit and filters loaded parameters in place
+pub fn estimate_low_memory_prover(r1cs: &R1CS<Bn256>, storage: QueryStorage) -> u64 {
+    prover_memory(r1cs, storage, true)
+}
+
+fn prover_memory(r1cs: &R1CS<Bn256>, storage: QueryStorage, low_memory: bool) -> u64 {
+    let estimate = estimate_setup(r1cs);
+    let copies = if low_memory { 1 } else { 2 };
+    let terms = r1cs.constraints.iter().map(|(a, b, c)| a.len() + b.len() + c.len()).sum::<usize>() as u64;
+    let circuit = copies * terms * std::mem::size_of::<(usize, Fr)>() as u64;
+    let witness = 2 * r1cs.num_variables as u64 * std::mem::size_of::<Fr>() as u64;
+    let fft = 3 * estimate.domain_size as u64 * std::mem::size_of::<Fr>() as u64;
+    let queries = (estimate.g1_points as u64) * G1_AFFINE + (estimate.g2_points as u64) * G2_AFFINE;
+    let queries = match storage {
+        QueryStorage::Loaded => copies * queries,
+        QueryStorage::Streamed => queries,
+        QueryStorage::DiskBacked => 0,
+    };
+    circuit + witness + fft + queries
+}
+
+// G2 multiplication is roughly three times as expensive as G1
+fn setup_work(estimate: &SetupEstimate) -> f64 {
+    (estimate.g1_points + 3 * estimate.g2_points) as f64
+}
+
+/// Threads bellman runs setup and proving on, every core when built with the `multicore` feature
+pub fn worker_threads() -> usize {
+    if cfg!(feature = "multicore") {
+        std::thread::available_parallelism().map_or(1, |n| n.get())
+    } else {
+        1
+    }
+}
+
+/// Estimates `setup` duration on this machine by timing it on a small synthetic circuit
+/// and scaling by the number of points to generate
+pub fn estimate_setup_time(estimate: &SetupEstimate) -> Duration {
+    let n = 1 << 10;
+    let sample = R1CS::<Bn256> {
+        num_inputs: 1,
+        num_aux: n,
+        num_variables: n + 1,
+        constraints: (0..n).map(|i| (
+            vec![(1 + i, Fr::one())],
+            vec![(1 + i, Fr::one())],
+            vec![(1 + (i + 1) % n, Fr::one())],
+        )).collect(),
+    };
+    let sample_work = setup_work(&estimate_setup(&sample));
+    let start = Instant::now();
+    let circuit = CircomCircuit { r1cs: sample, witness: None, wire_mapping: None };
+    generate_random_parameters(circuit, create_insecure_dev_rng()).expect("sample circuit is valid");
+    start.elapsed().mul_f64(setup_work(estimate) / sample_work)
+}
+
+/// Writes snarkjs/websnark compatible proving and verification key JSON files
+pub fn export_keys(params: &Parameters<Bn256>, r1cs: R1CS<Bn256>, pk_file: &str, vk_file: &str) -> Result<()> {
+    export_keys_with_format(params, r1cs, NumberFormat::Decimal, pk_file, vk_file)
+}
+
+/// Same as `export_keys`, with field elements in `format`
+pub fn export_keys_with_format(params: &Parameters<Bn256>, r1cs: R1CS<Bn256>, format: NumberFormat, pk_file: &str, vk_file: &str) -> Result<()> {
+    let circuit = CircomCircuit { r1cs, witness: None, wire_mapping: None };
+    proving_key_json_file_with_format(params, circuit, format, pk_file)?;
+    verification_key_json_file_with_format(params, format, vk_file)
+}
+
+/// Same as `export_keys`, but writes the keys to arbitrary writers
+pub fn write_keys<W1: Write, W2: Write>(params: &Parameters<Bn256>, r1cs: R1CS<Bn256>, pk: W1, vk: W2) -> Result<()> {
+    let circuit = CircomCircuit { r1cs, witness: None, wire_mapping: None };
+    write_proving_key_json(params, circuit, pk)?;
+    write_verification_key_json(params, vk)
+}
+
+#[test]
+fn estimate_params_size() {
+    use crate::circom_circuit::write_params_with_metadata;
+    let fr = |s: &str| Fr::from_str(s).unwrap();
+    let r1cs = R1CS::<Bn256> {
+        num_inputs: 2,
+        num_aux: 3,
+        num_variables: 5,
+        constraints: vec![
+            (vec![(2, fr("1"))], vec![(3, fr("1"))], vec![(4, fr("1"))]),
+            (vec![(4, fr("1"))], vec![(0, fr("1"))], vec![(1, fr("1"))]),
+        ],
+    };
+    let estimate = estimate_setup(&r1cs);
+    assert_eq!(estimate.domain_size, 4);
+    let metadata = params_metadata(&r1cs);
+    let (params, flags) = setup(r1cs, true).unwrap();
+    let mut written = vec![];
+    write_params_with_metadata(&params, flags, &metadata, &mut written).unwrap();
+    assert_eq!(written.len() as u64, estimate.params_size);
+}
+
+#[test]
+fn prover_memory_estimate() {
+    let r1cs = R1CS::<Bn256> {
+        num_inputs: 1,
+        num_aux: 2,
+        num_variables: 3,
+        constraints: vec![(vec![(1, Fr::one())], vec![(1, Fr::one())], vec![(2, Fr::one())])],
+    };
+    let estimate = |storage| estimate_prover_memory(&r1cs, storage);
+    assert!(estimate(QueryStorage::DiskBacked) < estimate(QueryStorage::Streamed));
+    assert!(estimate(QueryStorage::Streamed) < estimate(QueryStorage::Loaded));
+    assert!(estimate_low_memory_prover(&r1cs, QueryStorage::DiskBacked) < estimate(QueryStorage::DiskBacked));
+    assert_eq!(estimate_low_memory_prover(&r1cs, QueryStorage::Loaded), estimate_low_memory_prover(&r1cs, QueryStorage::Streamed));
+}
+
+#[test]
+fn setup_with_seeded_rng() {
+    use rand::{SeedableRng, XorShiftRng};
+    use crate::circom_circuit::write_params;
+    let r1cs = || R1CS::<Bn256> {
+        num_inputs: 1,
+        num_aux: 2,
+        num_variables: 3,
+        constraints: vec![(vec![(1, Fr::one())], vec![(1, Fr::one())], vec![(2, Fr::one())])],
+    };
+    let params = |seed| {
+        let mut written = vec![];
+        write_params(&setup_with_rng(r1cs(), XorShiftRng::from_seed(seed)).unwrap(), &mut written).unwrap();
+        written
+    };
+    assert!(params([1, 2, 3, 4]) == params([1, 2, 3, 4]));
+    assert!(params([1, 2, 3, 4]) != params([4, 3, 2, 1]));
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn compressed_params() {
+    use crate::circom_circuit::{read_params_with_header, write_params_with_metadata};
+    use crate::file_header::FLAG_ZSTD;
+    let r1cs = || R1CS::<Bn256> {
+        num_inputs: 1,
+        num_aux: 2,
+        num_variables: 3,
+        constraints: vec![(vec![(1, Fr::one())], vec![(1, Fr::one())], vec![(2, Fr::one())])],
+    };
+    let (params, flags) = setup(r1cs(), true).unwrap();
+    let mut compressed = vec![];
+    write_params_with_metadata(&params, flags | FLAG_ZSTD, &params_metadata(&r1cs()), &mut compressed).unwrap();
+    let (header, read) = read_params_with_header(&compressed[..]).unwrap();
+    let header = header.unwrap();
+    assert!(header.is_compressed() && header.is_insecure_dev());
+    assert!(read == params);
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn encrypted_params() {
+    use crate::circom_circuit::{read_params_header, read_params_with_header, write_params_with_metadata};
+    use crate::file_header::FLAG_ENCRYPTED;
+    let r1cs = || R1CS::<Bn256> {
+        num_inputs: 1,
+        num_aux: 2,
+        num_variables: 3,
+        constraints: vec![(vec![(1, Fr::one())], vec![(1, Fr::one())], vec![(2, Fr::one())])],
+    };
+    std::env::set_var(crate::encryption::KEY_VAR, "07".repeat(32));
+    let (params, flags) = setup(r1cs(), true).unwrap();
+    let mut encrypted = vec![];
+    write_params_with_metadata(&params, flags | FLAG_ENCRYPTED, &params_metadata(&r1cs()), &mut encrypted).unwrap();
+    // the header stays readable without the key
+    assert!(read_params_header(&encrypted[..]).unwrap().unwrap().is_encrypted());
+    let (header, read) = read_params_with_header(&encrypted[..]).unwrap();
+    assert!(header.unwrap().is_encrypted());
+    assert!(read == params);
+
+    let last = encrypted.len() - 1;
+    encrypted[last] ^= 1;
+    assert!(read_params_with_header(&encrypted[..]).is_err());
+}
+
+#[test]
+fn metadata_mismatch() {
+    use crate::circom_circuit::{read_params_with_header, write_params_with_metadata};
+    let r1cs = |coeff: u64| R1CS::<Bn256> {
+        num_inputs: 1,
+        num_aux: 2,
+        num_variables: 3,
+        constraints: vec![(vec![(1, Fr::one())], vec![(1, Fr::one())], vec![(2, Fr::from_str(&coeff.to_string()).unwrap())])],
+    };
+    let (params, flags) = setup(r1cs(1), true).unwrap();
+    let mut written = vec![];
+    write_params_with_metadata(&params, flags, &params_metadata(&r1cs(1)), &mut written).unwrap();
+    let (header, _) = read_params_with_header(&written[..]).unwrap();
+    assert!(check_metadata(header.as_ref(), &r1cs(1)).is_ok());
+    assert!(check_metadata(header.as_ref(), &r1cs(2)).is_err());
+    assert!(check_metadata(None, &r1cs(2)).is_ok());
+}
+
+#[test]
+fn diff_of_keys() {
+    use rand::{SeedableRng, XorShiftRng};
+    let r1cs = || R1CS::<Bn256> {
+        num_inputs: 2,
+        num_aux: 1,
+        num_variables: 3,
+        constraints: vec![(vec![(2, Fr::one())], vec![(2, Fr::one())], vec![(1, Fr::one())])],
+    };
+    let a = setup_with_rng(r1cs(), XorShiftRng::from_seed([1, 2, 3, 4])).unwrap();
+    let b = setup_with_rng(r1cs(), XorShiftRng::from_seed([4, 3, 2, 1])).unwrap();
+    assert!(diff_params(&a, &a).is_same());
+    let diff = diff_params(&a, &b);
+    assert_eq!(diff.public_inputs, (1, 1));
+    assert!(diff.differences.contains(&"delta_g2".to_string()) && diff.differences.contains(&"ic[1]".to_string()));
+
+    let mut json = vec![];
+    write_verification_key_json(&a, &mut json).unwrap();
+    let vk = crate::circom_circuit::read_verifying_key_json(&json[..]).unwrap();
+    assert!(diff_verifying_keys(&a.vk, &vk).is_same());
+    let mut fewer_inputs = vk.clone();
+    fewer_inputs.ic.pop();
+    let diff = diff_verifying_keys(&vk, &fewer_inputs);
+    assert_eq!((diff.public_inputs, diff.differences), ((1, 0), vec!["ic[1]".to_string()]));
+}
@@ -1,15 +1,135 @@
-#[macro_use]
+//! Library for working with circom circuits.
+//!
+//! Main entry points:
+//! - [`circuit`]: loading circuits, witnesses and signals from any supported format
+//! - [`keys`]: loading, generating and exporting trusted setup parameters
+//! - [`proof`]: proving witnesses and writing proof.json/public.json
+//! - [`solidity`]: verifier contract generation
+//! - [`verifier`]: standalone Groth16 verification, available with only the `verifier` feature
+//!
+//! Lower level readers and writers of individual formats live in [`circom_circuit`],
+//! [`r1cs_reader`], [`wtns_reader`], [`bundle`] and [`file_header`].
+//!
+//! Without the `std` feature, which `prover` enables, the library is `no_std` and has only [`verifier`].
+#![cfg_attr(not(feature = "std"), no_std)]
+extern crate alloc;
+#[cfg_attr(feature = "prover", macro_use)]
 extern crate serde;
-#[macro_use]
+#[cfg_attr(feature = "prover", macro_use)]
 extern crate hex_literal;
 extern crate bellman_ce;
+#[cfg(feature = "prover")]
 extern crate rand;
 extern crate itertools;
 extern crate byteorder;
 extern crate num_bigint;
 extern crate num_traits;
 
+#[cfg(feature = "std")]
 pub mod utils;
+#[cfg(feature = "std")]
+pub mod file_header;
+#[cfg(feature = "std")]
+pub mod field_eval;
+#[cfg(feature = "prover")]
+pub mod field_json;
+#[cfg(feature = "verifier")]
+pub mod verifier;
+#[cfg(feature = "prover")]
 pub mod circom_circuit;
+#[cfg(feature = "prover")]
+pub mod circuit;
+#[cfg(feature = "prover")]
+pub mod keys;
+#[cfg(feature = "prover")]
+pub mod proof;
+#[cfg(feature = "prover")]
+pub mod params_file;
+#[cfg(feature = "prover")]
+pub mod prover;
+#[cfg(feature = "prover")]
+pub mod raw_params;
+#[cfg(feature = "prover")]
+pub mod zkey;
+#[cfg(feature = "prover")]
+pub mod solidity;
+#[cfg(feature = "prover")]
+pub mod huff;
+#[cfg(feature = "prover")]
 pub mod r1cs_reader;
+#[cfg(feature = "prover")]
+pub mod r1cs_cache;
+#[cfg(feature = "prover")]
 pub mod wtns_reader;
+#[cfg(feature = "prover")]
+pub mod bundle;
+#[cfg(feature = "prover")]
+pub mod witness_graph;
+#[cfg(feature = "prover")]
+pub mod witness_cpp;
+#[cfg(feature = "prover")]
+pub mod signals;
+#[cfg(feature = "prover")]
+pub mod poseidon;
+#[cfg(feature = "prover")]
+pub mod babyjubjub;
+#[cfg(feature = "prover")]
+pub mod eth;
+#[cfg(feature = "prover")]
+pub mod provenance;
+#[cfg(feature = "prover")]
+pub mod fuzz;
+#[cfg(feature = "prover")]
+pub mod report;
+#[cfg(feature = "prover")]
+pub mod batch_report;
+#[cfg(feature = "prover")]
+pub mod progress;
+#[cfg(feature = "prover")]
+pub mod metrics;
+#[cfg(feature = "prover")]
+pub mod dot;
+#[cfg(feature = "prover")]
+pub mod matrix;
+#[cfg(feature = "prover")]
+pub mod jobs;
+#[cfg(feature = "prover")]
+pub mod watch;
+#[cfg(feature = "prover")]
+pub mod manifest;
+#[cfg(feature = "prover")]
+pub mod setup_batch;
+#[cfg(feature = "prover")]
+pub mod remote;
+#[cfg(feature = "prover")]
+pub mod ipfs;
+#[cfg(feature = "object-storage")]
+pub mod object_storage;
+#[cfg(feature = "queue")]
+pub mod nats_consumer;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "server")]
+pub mod rate_limit;
+#[cfg(feature = "server")]
+pub mod auth;
+#[cfg(feature = "server")]
+pub mod upload;
+#[cfg(feature = "server")]
+pub mod registry;
+#[cfg(feature = "async")]
+pub mod async_prover;
+#[cfg(feature = "zkif")]
+pub mod zkif;
+#[cfg(feature = "noir")]
+pub mod noir;
+#[cfg(feature = "signing")]
+pub mod signing;
+#[cfg(feature = "encryption")]
+pub mod encryption;
+#[cfg(feature = "otlp")]
+pub mod telemetry;
+#[cfg(feature = "evm")]
+pub mod evm;
+#[cfg(all(test, feature = "prover"))]
+mod test_utils;
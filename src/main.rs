@@ -4,31 +4,68 @@ extern crate zkutil;
 
 use std::fs;
 use std::fs::File;
+use std::io::{BufReader, ErrorKind, Write};
 use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use clap::Clap;
-use bellman_ce::pairing::{
-    Engine,
-    bn256::Bn256
+use rand::Rng;
+use bellman_ce::{
+    groth16::{Parameters, Proof, VerifyingKey},
+    pairing::{
+        Engine,
+        ff::{PrimeField, ScalarEngine},
+        bn256::Bn256
+    },
 };
 use zkutil::circom_circuit::{
-    prove as prove2,
-    verify as verify2,
+    verify_with_key,
+    verify_batch_with_key,
     create_rng,
-    load_params_file,
-    proof_to_json_file,
-    r1cs_from_json_file,
-    r1cs_from_bin_file,
-    witness_from_json_file,
-    witness_from_bin_file,
-    load_proof_json_file,
+    create_seeded_rng,
+    read_params_header,
+    write_params_file,
+    write_params_file_with_metadata,
+    witness_to_bin_file,
+    witness_to_json_file,
+    read_proof_json_file,
+    proof_to_json_file_with_format,
     load_inputs_json_file,
-    create_verifier_sol_file,
-    proving_key_json_file,
-    verification_key_json_file,
-    generate_random_parameters,
-    CircomCircuit,
+    check_params,
     R1CS,
 };
+use zkutil::circuit::{self, resolve_circuit_file, resolve_witness_file};
+use zkutil::keys;
+use zkutil::proof::{self, is_normalized, normalize_proof, prove_witness, write_proof_files_with_format};
+use zkutil::prover::{self, ProverInputs};
+use zkutil::watch::{self, WatchEvent};
+use zkutil::fuzz::{self, FuzzTarget};
+use zkutil::solidity;
+use zkutil::huff;
+use zkutil::verifier;
+use zkutil::raw_params;
+use zkutil::zkey;
+use zkutil::bundle::{self, Bundle, BundleMetadata};
+use zkutil::file_header::{FileFormat, FileHeader, FLAG_INSECURE_DEV, detect_file_format};
+use zkutil::witness_graph;
+use zkutil::witness_cpp;
+use zkutil::signals;
+use zkutil::r1cs_reader;
+use zkutil::poseidon;
+use zkutil::babyjubjub::{PrivateKey, SignatureJson};
+use zkutil::field_eval;
+use zkutil::eth;
+use zkutil::remote;
+use zkutil::provenance;
+use zkutil::report;
+use zkutil::batch_report;
+use zkutil::progress::Progress;
+use zkutil::metrics;
+use zkutil::manifest;
+use zkutil::setup_batch;
+#[cfg(feature = "signing")]
+use zkutil::signing;
+use zkutil::utils::{self, atomic_temp_path, fr_from_str, hex_to_bytes, repr_to_big, repr_to_hex, write_file_atomic, NumberFormat};
 
 /// A tool to work with SNARK circuits generated by circom
 #[derive(Clap)]
@@ -41,27 +78,107 @@ struct Opts {
 enum SubCommand {
     /// Generate a SNARK proof
     Prove(ProveOpts),
+    /// Calculate the witness of circuit inputs and prove it in one step
+    Fullprove(FullproveOpts),
+    /// Prove all witnesses listed in a manifest, possibly for different circuits, in one run
+    ProveManifest(ProveManifestOpts),
+    /// Watch a directory for witness files and prove each of them
+    Watch(WatchOpts),
+    /// Consume proving jobs from a NATS subject
+    #[cfg(feature = "queue")]
+    Consume(ConsumeOpts),
+    /// Serve proving requests over HTTP
+    #[cfg(feature = "server")]
+    Serve(ServeOpts),
     /// Verify a SNARK proof
     Verify(VerifyOpts),
+    /// Verify many SNARK proofs against the same parameters
+    VerifyMany(VerifyManyOpts),
+    /// Replace a proof with its normalized form, the one zkutil prove outputs
+    NormalizeProof(NormalizeProofOpts),
+    /// Generate a proof of random points that doesn't verify, for tests of contracts calling a verifier
+    DummyProof(DummyProofOpts),
+    /// Encode a proof as verifyProof arguments for cast or as a Solidity snippet for Foundry tests
+    EncodeProof(EncodeProofOpts),
+    /// Decode verifyProof calldata or arguments into proof and public inputs JSON files
+    DecodeCalldata(DecodeCalldataOpts),
+    /// Estimate gas of verifying a proof with the generated verifier contract
+    EstimateGas(EstimateGasOpts),
+    /// Send a proof to the verifier contract in a signed transaction and report the receipt
+    #[cfg(feature = "submit")]
+    Submit(SubmitOpts),
     /// Generate trusted setup parameters
     Setup(SetupOpts),
+    /// Generate trusted setup parameters for many circuits and a manifest of their hashes
+    SetupMany(SetupManyOpts),
     /// Generate verifier smart contract
     GenerateVerifier(GenerateVerifierOpts),
+    /// Run the verifier contract in an embedded EVM and compare its results with native verification
+    #[cfg(feature = "evm")]
+    CheckVerifier(CheckVerifierOpts),
     /// Export proving and verifying keys compatible with snarkjs/websnark
     ExportKeys(ExportKeysOpts),
+    /// Export parameters or a witness in raw Montgomery form for loading without conversion
+    ExportRaw(ExportRawOpts),
+    /// Import a snarkjs Groth16 zkey, e.g. from a phase 2 ceremony, as a params file
+    ImportZkey(ImportZkeyOpts),
+    /// Export verifying key with e(alpha, beta) precomputed for verifier services
+    PrepareKey(PrepareKeyOpts),
+    /// Pack circuit, trusted setup parameters and metadata into a single file
+    Bundle(BundleOpts),
+    /// Convert witness JSON to binary wtns format
+    ConvertWitness(ConvertWitnessOpts),
+    /// Calculate witness natively from a precompiled circuit evaluation graph
+    CalculateWitness(CalculateWitnessOpts),
+    /// List public and private signals of a circuit with their wire indices
+    Signals(SignalsOpts),
+    /// Show field, sizes, wire map and custom gates of an R1CS file, or metadata of a params file
+    Inspect(InspectOpts),
+    /// Compare the keys of two params files, bundles or snarkjs verification keys
+    DiffKeys(DiffKeysOpts),
+    /// Look for under-constrained signals by mutating a valid witness
+    FuzzWitness(FuzzWitnessOpts),
+    /// Check that the circuit rejects a witness with any single private signal changed
+    SoundnessTest(SoundnessTestOpts),
+    /// Generate audit report of a circuit and its trusted setup parameters
+    Report(ReportOpts),
+    /// Export signal/constraint graph of a circuit in GraphViz DOT format
+    Graph(GraphOpts),
+    /// Export A, B and C constraint matrices in Matrix Market or scipy NPZ format
+    ExportMatrices(ExportMatricesOpts),
+    /// Export circuit and witness to zkinterface format
+    #[cfg(feature = "zkif")]
+    ExportZkinterface(ExportZkinterfaceOpts),
+    /// Convert a Noir program compiled to ACIR and its witness to circuit JSON and wtns
+    #[cfg(feature = "noir")]
+    ImportAcir(ImportAcirOpts),
+    /// Generate an ed25519 key pair for signing parameter files
+    #[cfg(feature = "signing")]
+    GenerateSigningKey(GenerateSigningKeyOpts),
+    /// Sign a parameter file with a minisign compatible signature
+    #[cfg(feature = "signing")]
+    SignParams(SignParamsOpts),
+    /// Generate input.json skeleton with all circuit input signals
+    InputTemplate(InputTemplateOpts),
+    /// Compute hashes of field elements
+    Hash(HashOpts),
+    /// Generate Baby Jubjub keys and EdDSA-Poseidon signatures compatible with circomlib
+    Eddsa(EddsaOpts),
+    /// Evaluate BN254 scalar field arithmetic
+    Eval(EvalOpts),
 }
 
 /// A subcommand for generating a SNARK proof
 #[derive(Clap)]
 struct ProveOpts {
     /// Snark trusted setup parameters file
-    #[clap(short = "p", long = "params", default_value = "params.bin")]
+    #[clap(short = "p", long = "params", env = "ZKUTIL_PARAMS", default_value = "params.bin")]
     params: String,
     /// Circuit R1CS or JSON file [default: circuit.r1cs|circuit.json]
-    #[clap(short = "c", long = "circuit")]
+    #[clap(short = "c", long = "circuit", env = "ZKUTIL_CIRCUIT")]
     circuit: Option<String>,
     /// Witness JSON file [default: witness.wtns|witness.json]
-    #[clap(short = "w", long = "witness")]
+    #[clap(short = "w", long = "witness", env = "ZKUTIL_WITNESS")]
     witness: Option<String>,
     /// Output file for proof JSON
     #[clap(short = "r", long = "proof", default_value = "proof.json")]
@@ -69,52 +186,613 @@ struct ProveOpts {
     /// Output file for public inputs JSON
     #[clap(short = "o", long = "public", default_value = "public.json")]
     public: String,
+    /// Write proof, public inputs, circuit and verifying key hashes to this single file instead of --proof and --public
+    #[clap(long = "packed")]
+    packed: Option<String>,
+    /// Bundle file to take circuit and parameters from, overrides --params and --circuit
+    #[clap(short = "b", long = "bundle", env = "ZKUTIL_BUNDLE")]
+    bundle: Option<String>,
+    /// Abort with exit code 124 if proving takes longer than this many seconds
+    #[clap(long = "timeout")]
+    timeout: Option<u64>,
+    /// Read params queries from disk in every multiexp window instead of keeping them in memory,
+    /// slower, but proving keys larger than RAM can be used. Needs an uncompressed, unencrypted params file
+    #[clap(long = "disk-backed")]
+    disk_backed: bool,
+    /// Print the expected peak memory of proving with the chosen strategy before proving, add
+    /// --disk-backed to keep the proving key out of memory
+    #[clap(long = "report-memory")]
+    report_memory: bool,
+    /// Pick the most memory frugal strategies and report the expected peak memory: queries are read
+    /// from disk like with --disk-backed, the witness is loaded after the circuit and params, and the
+    /// prover works on the only copy of the circuit and of loaded params instead of filtered copies
+    #[clap(long = "low-memory")]
+    low_memory: bool,
+    /// Write field elements as 0x-prefixed hex instead of decimal
+    #[clap(long = "hex")]
+    hex: bool,
+    /// Verify the proof against the verifying key before writing it, and fail if it doesn't verify
+    #[clap(long = "check")]
+    check: bool,
+    /// Write JSON lines progress events to this file or named pipe, - for stderr
+    #[clap(long = "progress-json")]
+    progress_json: Option<String>,
+    /// INSECURE: derive proof randomness from this seed for byte-identical golden fixtures. Anyone
+    /// knowing the seed can extract the witness from the proof
+    #[clap(long = "insecure-seed")]
+    insecure_seed: Option<String>,
+    /// Refuse parameters or bundle without a valid <file>.minisig signature by this public key
+    #[cfg(feature = "signing")]
+    #[clap(long = "trusted-key", env = "ZKUTIL_TRUSTED_KEY")]
+    trusted_key: Option<String>,
+    /// Overwrite existing output files
+    #[clap(long = "force")]
+    force: bool,
+}
+
+/// A subcommand for calculating the witness and generating a SNARK proof in one step
+#[derive(Clap)]
+struct FullproveOpts {
+    /// Snark trusted setup parameters file
+    #[clap(short = "p", long = "params", env = "ZKUTIL_PARAMS", default_value = "params.bin")]
+    params: String,
+    /// Circuit R1CS or JSON file [default: circuit.r1cs|circuit.json]
+    #[clap(short = "c", long = "circuit", env = "ZKUTIL_CIRCUIT")]
+    circuit: Option<String>,
+    /// Circuit inputs JSON file
+    #[clap(short = "i", long = "input", default_value = "input.json")]
+    input: String,
+    /// circom-witnesscalc circuit evaluation graph to calculate the witness with
+    #[clap(short = "g", long = "graph", default_value = "graph.bin")]
+    graph: String,
+    /// Calculate the witness with the C++ calculator circom generates with --c instead of the graph
+    #[clap(long = "cpp")]
+    cpp: bool,
+    /// C++ witness calculator binary, implies --cpp [default: <circuit dir>/<name>_cpp/<name>]
+    #[clap(long = "witness-binary")]
+    witness_binary: Option<String>,
+    /// Also save the witness to this wtns file
+    #[clap(short = "w", long = "save-witness")]
+    save_witness: Option<String>,
+    /// Output file for proof JSON
+    #[clap(short = "r", long = "proof", default_value = "proof.json")]
+    proof: String,
+    /// Output file for public inputs JSON
+    #[clap(short = "o", long = "public", default_value = "public.json")]
+    public: String,
+    /// Write field elements as 0x-prefixed hex instead of decimal
+    #[clap(long = "hex")]
+    hex: bool,
+    /// Overwrite existing output files
+    #[clap(long = "force")]
+    force: bool,
+}
+
+/// A subcommand for proving the entries of a manifest
+#[derive(Clap)]
+struct ProveManifestOpts {
+    /// Manifest JSON file listing witness, proof and public files and optionally params and circuit
+    #[clap(short = "m", long = "manifest", default_value = "manifest.json")]
+    manifest: String,
+    /// Snark trusted setup parameters file for entries the manifest doesn't set params for
+    #[clap(short = "p", long = "params", env = "ZKUTIL_PARAMS", default_value = "params.bin")]
+    params: String,
+    /// Circuit R1CS or JSON file for entries the manifest doesn't set a circuit for [default: circuit.r1cs|circuit.json]
+    #[clap(short = "c", long = "circuit", env = "ZKUTIL_CIRCUIT")]
+    circuit: Option<String>,
+    /// Output file for the results of all entries
+    #[clap(long = "results", default_value = "results.json")]
+    results: String,
+    /// Write the status, duration and proof size of every entry to this file, CSV for *.csv and JSON otherwise
+    #[clap(long = "report")]
+    report: Option<String>,
+    /// Overwrite existing output files
+    #[clap(long = "force")]
+    force: bool,
+}
+
+/// A subcommand for proving witness files as they appear in a directory
+#[derive(Clap)]
+struct WatchOpts {
+    /// Snark trusted setup parameters file
+    #[clap(short = "p", long = "params", env = "ZKUTIL_PARAMS", default_value = "params.bin")]
+    params: String,
+    /// Circuit R1CS or JSON file [default: circuit.r1cs|circuit.json]
+    #[clap(short = "c", long = "circuit", env = "ZKUTIL_CIRCUIT")]
+    circuit: Option<String>,
+    /// Bundle file to take circuit and parameters from, overrides --params and --circuit
+    #[clap(short = "b", long = "bundle", env = "ZKUTIL_BUNDLE")]
+    bundle: Option<String>,
+    /// Directory to watch for witness .wtns and .json files, <name>.p<priority>.wtns sets a priority
+    #[clap(short = "i", long = "input-dir", default_value = "queue")]
+    input_dir: String,
+    /// Directory for <name>.proof.json and <name>.public.json results
+    #[clap(short = "o", long = "output-dir", default_value = "proofs")]
+    output_dir: String,
+    /// Directory where successfully proven witness files are moved
+    #[clap(long = "done-dir", default_value = "queue/done")]
+    done_dir: String,
+    /// Directory where witness files that failed to prove are moved
+    #[clap(long = "failed-dir", default_value = "queue/failed")]
+    failed_dir: String,
+    /// Polling interval in seconds
+    #[clap(long = "interval", default_value = "1")]
+    interval: u64,
+    /// Seconds of waiting that raise a job's priority by one, so low priority jobs are not starved
+    #[clap(long = "aging", default_value = "60")]
+    aging: u64,
+    /// Serve Prometheus metrics at http://<address>/metrics, e.g. 0.0.0.0:9100
+    #[clap(long = "metrics")]
+    metrics: Option<String>,
+    /// Refuse parameters or bundle without a valid <file>.minisig signature by this public key
+    #[cfg(feature = "signing")]
+    #[clap(long = "trusted-key", env = "ZKUTIL_TRUSTED_KEY")]
+    trusted_key: Option<String>,
+}
+
+/// A subcommand for consuming proving jobs from a message queue
+#[cfg(feature = "queue")]
+#[derive(Clap)]
+struct ConsumeOpts {
+    /// Snark trusted setup parameters file
+    #[clap(short = "p", long = "params", env = "ZKUTIL_PARAMS", default_value = "params.bin")]
+    params: String,
+    /// Circuit R1CS or JSON file [default: circuit.r1cs|circuit.json]
+    #[clap(short = "c", long = "circuit", env = "ZKUTIL_CIRCUIT")]
+    circuit: Option<String>,
+    /// Bundle file to take circuit and parameters from, overrides --params and --circuit
+    #[clap(short = "b", long = "bundle", env = "ZKUTIL_BUNDLE")]
+    bundle: Option<String>,
+    /// NATS server URL
+    #[clap(long = "nats", default_value = "nats://localhost:4222")]
+    nats: String,
+    /// Subject to consume jobs from
+    #[clap(long = "subject", default_value = "zkutil.prove")]
+    subject: String,
+    /// Queue group, consumers in the same group share jobs
+    #[clap(long = "group")]
+    group: Option<String>,
+    /// Subject for results of jobs published without a reply subject
+    #[clap(long = "result-subject", default_value = "zkutil.proved")]
+    result_subject: String,
+    /// Number of results to keep for answering resubmitted jobs with the same witness, 0 disables it
+    #[clap(long = "cache-size", default_value = "1000")]
+    cache_size: usize,
+    /// Accept witness_path jobs for witness files inside this directory, without it witnesses must be inline
+    #[clap(long = "witness-dir")]
+    witness_dir: Option<String>,
+    /// Serve Prometheus metrics at http://<address>/metrics, e.g. 0.0.0.0:9100
+    #[clap(long = "metrics")]
+    metrics: Option<String>,
+    /// Refuse parameters or bundle without a valid <file>.minisig signature by this public key
+    #[cfg(feature = "signing")]
+    #[clap(long = "trusted-key", env = "ZKUTIL_TRUSTED_KEY")]
+    trusted_key: Option<String>,
+}
+
+/// A subcommand for serving proving requests over HTTP
+#[cfg(feature = "server")]
+#[derive(Clap)]
+struct ServeOpts {
+    /// Snark trusted setup parameters file
+    #[clap(short = "p", long = "params", env = "ZKUTIL_PARAMS", default_value = "params.bin")]
+    params: String,
+    /// Circuit R1CS or JSON file [default: circuit.r1cs|circuit.json]
+    #[clap(short = "c", long = "circuit", env = "ZKUTIL_CIRCUIT")]
+    circuit: Option<String>,
+    /// Bundle file to take circuit and parameters from, overrides --params and --circuit
+    #[clap(short = "b", long = "bundle", env = "ZKUTIL_BUNDLE")]
+    bundle: Option<String>,
+    /// Address to listen on
+    #[clap(long = "listen", default_value = "127.0.0.1:8080")]
+    listen: String,
+    /// Size limit of request bodies in bytes
+    #[clap(long = "max-body", default_value = "67108864")]
+    max_body: u64,
+    /// Requests of all clients, <requests>/<s|m|h>[:<burst>] like 10/m:5
+    #[clap(long = "rate-limit")]
+    rate_limit: Option<zkutil::rate_limit::Limit>,
+    /// Requests of every client, <requests>/<s|m|h>[:<burst>] like 2/m. Clients are API keys or JWT
+    /// subjects with --auth, IP addresses without
+    #[clap(long = "client-rate-limit")]
+    client_rate_limit: Option<zkutil::rate_limit::Limit>,
+    /// JSON file with API key hashes and the JWT secret, requests without a valid bearer token are refused
+    #[clap(long = "auth")]
+    auth: Option<String>,
+    /// Circuit name that key and token allowlists of --auth refer to
+    #[clap(long = "name", default_value = "circuit")]
+    name: String,
+    /// Seconds of waiting after which a request goes ahead of those with one more level of Priority
+    #[clap(long = "aging", default_value = "60")]
+    aging: u64,
+    /// Number of results every circuit keeps for answering resubmitted jobs with the same witness, 0 disables it
+    #[clap(long = "cache-size", default_value = "1000")]
+    cache_size: usize,
+    /// JSON registry of several circuits to serve at /prove/<name>/<version> instead of --params and
+    /// --circuit, circuits registered or retired with the admin API are saved to it
+    #[clap(long = "registry")]
+    registry: Option<String>,
+    /// Directory to store witness uploads in, jobs can refer to completed uploads in witness_path
+    #[clap(long = "upload-dir")]
+    upload_dir: Option<String>,
+    /// Size limit of witness uploads in bytes
+    #[clap(long = "max-upload", default_value = "1073741824")]
+    max_upload: u64,
+    /// Refuse parameters or bundle without a valid <file>.minisig signature by this public key
+    #[cfg(feature = "signing")]
+    #[clap(long = "trusted-key", env = "ZKUTIL_TRUSTED_KEY")]
+    trusted_key: Option<String>,
 }
 
 /// A subcommand for verifying a SNARK proof
 #[derive(Clap)]
 struct VerifyOpts {
     /// Snark trusted setup parameters file
-    #[clap(short = "p", long = "params", default_value = "params.bin")]
+    #[clap(short = "p", long = "params", env = "ZKUTIL_PARAMS", default_value = "params.bin")]
+    params: String,
+    /// Proof JSON file
+    #[clap(short = "r", long = "proof", default_value = "proof.json")]
+    proof: String,
+    /// Public inputs JSON file
+    #[clap(short = "i", long = "public", default_value = "public.json")]
+    public: String,
+    /// Packed proof file written by prove --packed, overrides --proof and --public
+    #[clap(long = "packed")]
+    packed: Option<String>,
+    /// Bundle file to take parameters from, overrides --params
+    #[clap(short = "b", long = "bundle", env = "ZKUTIL_BUNDLE")]
+    bundle: Option<String>,
+    /// Hash of a verifyProof transaction to take proof and public inputs from, overrides --proof and --public
+    #[clap(long = "tx")]
+    tx: Option<String>,
+    /// Ethereum JSON-RPC endpoint used with --tx and --onchain
+    #[clap(long = "rpc", env = "ZKUTIL_RPC", default_value = "http://localhost:8545")]
+    rpc: String,
+    /// Also call verifyProof of the deployed verifier at --contract and check that it agrees
+    #[clap(long = "onchain")]
+    onchain: bool,
+    /// Address of the verifier contract used with --onchain
+    #[clap(long = "contract", env = "ZKUTIL_CONTRACT")]
+    contract: Option<String>,
+    /// Skip on-curve and subgroup checks of proof points, verifying key points are always checked
+    #[clap(long = "unchecked")]
+    unchecked: bool,
+    /// Reject proofs that are not normalized, see normalize-proof
+    #[clap(long = "canonical")]
+    canonical: bool,
+}
+
+/// A subcommand for verifying many SNARK proofs
+#[derive(Clap)]
+struct VerifyManyOpts {
+    /// Snark trusted setup parameters file
+    #[clap(short = "p", long = "params", env = "ZKUTIL_PARAMS", default_value = "params.bin")]
+    params: String,
+    /// Proof JSON files
+    #[clap(short = "r", long = "proof", required = true, multiple = true)]
+    proofs: Vec<String>,
+    /// Public inputs JSON files, in the same order as proofs
+    #[clap(short = "i", long = "public", required = true, multiple = true)]
+    publics: Vec<String>,
+    /// Verify all proofs with a single randomized batch check
+    #[clap(long = "batched")]
+    batched: bool,
+    /// Skip on-curve and subgroup checks of proof points, verifying key points are always checked
+    #[clap(long = "unchecked")]
+    unchecked: bool,
+    /// Reject proofs that are not normalized, see normalize-proof
+    #[clap(long = "canonical")]
+    canonical: bool,
+    /// Write the status, duration and proof size of every proof to this file, CSV for *.csv and JSON otherwise
+    #[clap(long = "report")]
+    report: Option<String>,
+}
+
+/// A subcommand for normalizing a SNARK proof
+#[derive(Clap)]
+struct NormalizeProofOpts {
+    /// Proof JSON file
+    #[clap(short = "r", long = "proof", default_value = "proof.json")]
+    proof: String,
+    /// Output file for the normalized proof [default: overwrite --proof]
+    #[clap(short = "o", long = "output")]
+    output: Option<String>,
+    /// Write field elements as 0x-prefixed hex instead of decimal
+    #[clap(long = "hex")]
+    hex: bool,
+}
+
+/// A subcommand for generating a dummy proof for contract tests
+#[derive(Clap)]
+struct DummyProofOpts {
+    /// Snark trusted setup parameters file or bundle to take the number of public inputs from
+    #[clap(short = "p", long = "params", env = "ZKUTIL_PARAMS", default_value = "params.bin")]
     params: String,
+    /// Number of public inputs, overrides --params
+    #[clap(short = "n", long = "inputs")]
+    inputs: Option<usize>,
+    /// Output file for proof JSON
+    #[clap(short = "r", long = "proof", default_value = "proof.json")]
+    proof: String,
+    /// Output file for public inputs JSON
+    #[clap(short = "o", long = "public", default_value = "public.json")]
+    public: String,
+    /// Write field elements as 0x-prefixed hex instead of decimal
+    #[clap(long = "hex")]
+    hex: bool,
+    /// Overwrite existing output files
+    #[clap(long = "force")]
+    force: bool,
+}
+
+/// A subcommand for encoding a proof as arguments of the generated verifier
+#[derive(Clap)]
+struct EncodeProofOpts {
+    /// Proof JSON file
+    #[clap(short = "r", long = "proof", default_value = "proof.json")]
+    proof: String,
+    /// Public inputs JSON file
+    #[clap(short = "i", long = "public", default_value = "public.json")]
+    public: String,
+    /// Print Solidity statements declaring `proof` and `input` instead of ABI-encoded hex
+    #[clap(long = "solidity")]
+    solidity: bool,
+    /// Output file [default: print to stdout]
+    #[clap(short = "o", long = "output")]
+    output: Option<String>,
+}
+
+/// A subcommand for decoding verifyProof calldata into proof and public inputs
+#[derive(Clap)]
+struct DecodeCalldataOpts {
+    /// Hex calldata with or without the function selector, or a file containing it
+    #[clap(short = "d", long = "calldata")]
+    calldata: String,
+    /// Output file for proof JSON
+    #[clap(short = "r", long = "proof", default_value = "proof.json")]
+    proof: String,
+    /// Output file for public inputs JSON
+    #[clap(short = "o", long = "public", default_value = "public.json")]
+    public: String,
+    /// Write field elements as 0x-prefixed hex instead of decimal
+    #[clap(long = "hex")]
+    hex: bool,
+    /// Overwrite existing output files
+    #[clap(long = "force")]
+    force: bool,
+}
+
+/// A subcommand for estimating gas of verifying a proof on-chain
+#[derive(Clap)]
+struct EstimateGasOpts {
+    /// Proof JSON file
+    #[clap(short = "r", long = "proof", default_value = "proof.json")]
+    proof: String,
+    /// Public inputs JSON file
+    #[clap(short = "i", long = "public", default_value = "public.json")]
+    public: String,
+    /// Ethereum JSON-RPC endpoint used with --contract
+    #[clap(long = "rpc", env = "ZKUTIL_RPC", default_value = "http://localhost:8545")]
+    rpc: String,
+    /// Address of a deployed verifier contract to ask the node for an exact estimate
+    #[clap(long = "contract", env = "ZKUTIL_CONTRACT")]
+    contract: Option<String>,
+}
+
+/// A subcommand for sending a proof to the verifier contract
+#[cfg(feature = "submit")]
+#[derive(Clap)]
+struct SubmitOpts {
     /// Proof JSON file
     #[clap(short = "r", long = "proof", default_value = "proof.json")]
     proof: String,
     /// Public inputs JSON file
     #[clap(short = "i", long = "public", default_value = "public.json")]
     public: String,
+    /// Ethereum JSON-RPC endpoint
+    #[clap(long = "rpc", env = "ZKUTIL_RPC", default_value = "http://localhost:8545")]
+    rpc: String,
+    /// Address of the verifier contract
+    #[clap(long = "contract", env = "ZKUTIL_CONTRACT")]
+    contract: String,
+    /// Environment variable holding the hex private key of the sending account
+    #[clap(long = "key", default_value = "ZKUTIL_PRIVATE_KEY")]
+    key: String,
+    /// Seconds to wait for the transaction to be mined
+    #[clap(long = "timeout", default_value = "300")]
+    timeout: u64,
 }
 
 /// A subcommand for generating a trusted setup parameters
 #[derive(Clap)]
 struct SetupOpts {
     /// Snark trusted setup parameters file
-    #[clap(short = "p", long = "params", default_value = "params.bin")]
+    #[clap(short = "p", long = "params", env = "ZKUTIL_PARAMS", default_value = "params.bin")]
     params: String,
     /// Circuit R1CS or JSON file [default: circuit.r1cs|circuit.json]
-    #[clap(short = "c", long = "circuit")]
+    #[clap(short = "c", long = "circuit", env = "ZKUTIL_CIRCUIT")]
     circuit: Option<String>,
+    /// Generate INSECURE parameters from a fixed seed, for development only
+    #[clap(long = "dev")]
+    dev: bool,
+    /// Merge terms and fold constants of the constraints first, provers apply the same pass to the circuit
+    #[clap(long = "simplify")]
+    simplify: bool,
+    /// Only report params size, FFT domain and estimated generation time
+    #[clap(long = "dry-run")]
+    dry_run: bool,
+    /// Output file for the provenance record [default: <params>.provenance.json]
+    #[clap(long = "provenance")]
+    provenance: Option<String>,
+    /// Compress parameters with zstd, readers detect it from the header
+    #[cfg(feature = "compression")]
+    #[clap(long = "compress")]
+    compress: bool,
+    /// Encrypt parameters with the AES-256-GCM key in ZKUTIL_PARAMS_KEY or printed by ZKUTIL_PARAMS_KEY_COMMAND
+    #[cfg(feature = "encryption")]
+    #[clap(long = "encrypt")]
+    encrypt: bool,
+    /// Secret key to sign the parameters with, the signature is written to <params>.minisig
+    #[cfg(feature = "signing")]
+    #[clap(long = "sign-key")]
+    sign_key: Option<String>,
+    /// Overwrite existing output files
+    #[clap(long = "force")]
+    force: bool,
+}
+
+/// A subcommand for generating trusted setup parameters for many circuits
+#[derive(Clap)]
+struct SetupManyOpts {
+    /// Circuit R1CS or JSON files, the params of dir/name.r1cs are written to <output-dir>/name.params.bin
+    #[clap(short = "c", long = "circuit", required = true, multiple = true)]
+    circuits: Vec<String>,
+    /// Directory for the params files
+    #[clap(short = "o", long = "output-dir", default_value = ".")]
+    output_dir: String,
+    /// Output file for the circuit and params hashes
+    #[clap(short = "m", long = "manifest", default_value = "setup-manifest.json")]
+    manifest: String,
+    /// Number of circuits to set up at the same time, each setup already uses all threads
+    #[clap(short = "j", long = "jobs", default_value = "1")]
+    jobs: usize,
+    /// Generate INSECURE parameters from a fixed seed, for development only
+    #[clap(long = "dev")]
+    dev: bool,
+    /// Merge terms and fold constants of the constraints first, provers apply the same pass to the circuit
+    #[clap(long = "simplify")]
+    simplify: bool,
+    /// Compress parameters with zstd, readers detect it from the header
+    #[cfg(feature = "compression")]
+    #[clap(long = "compress")]
+    compress: bool,
+    /// Overwrite existing output files
+    #[clap(long = "force")]
+    force: bool,
 }
 
 /// A subcommand for generating a Solidity verifier smart contract
 #[derive(Clap)]
 struct GenerateVerifierOpts {
     /// Snark trusted setup parameters file
+    #[clap(short = "p", long = "params", env = "ZKUTIL_PARAMS", default_value = "params.bin")]
+    params: String,
+    /// Output smart contract name [default: Verifier.sol|Verifier.huff]
+    #[clap(short = "v", long = "verifier")]
+    verifier: Option<String>,
+    /// Generate a Solidity 0.8 contract with custom errors, ProofVerified event and non-reverting tryVerify
+    #[clap(long = "custom-errors")]
+    custom_errors: bool,
+    /// Generate a Huff contract with the same ABI instead of Solidity
+    #[clap(long = "huff", conflicts_with = "custom_errors")]
+    huff: bool,
+    /// Proof JSON file to include as a test vector in the Huff contract, requires --public
+    #[clap(short = "r", long = "proof", requires = "public")]
+    proof: Option<String>,
+    /// Public inputs JSON file of the test vector proof
+    #[clap(short = "i", long = "public", requires = "proof")]
+    public: Option<String>,
+    /// Overwrite existing output files
+    #[clap(long = "force")]
+    force: bool,
+}
+
+/// A subcommand for differential testing of the verifier contract against native verification
+#[cfg(feature = "evm")]
+#[derive(Clap)]
+struct CheckVerifierOpts {
+    /// Snark trusted setup parameters file
+    #[clap(short = "p", long = "params", env = "ZKUTIL_PARAMS", default_value = "params.bin")]
+    params: String,
+    /// Valid proof JSON files, the calls are derived from them
+    #[clap(short = "r", long = "proof", default_value = "proof.json", multiple = true)]
+    proofs: Vec<String>,
+    /// Public inputs JSON files, in the same order as proofs
+    #[clap(short = "i", long = "public", default_value = "public.json", multiple = true)]
+    publics: Vec<String>,
+    /// Contract to compile instead of generating one from the params, .huff files are compiled with huffc
+    #[clap(short = "v", long = "verifier")]
+    verifier: Option<String>,
+    /// Generate the Solidity 0.8 contract with custom errors, or take --verifier or --bytecode to be
+    /// one, and also check its tryVerify reason codes, custom errors and ProofVerified event
+    #[clap(long = "custom-errors")]
+    custom_errors: bool,
+    /// Generate the Huff contract instead of Solidity
+    #[clap(long = "huff", conflicts_with = "custom_errors")]
+    huff: bool,
+    /// Hex file with runtime bytecode to run instead of compiling a contract
+    #[clap(long = "bytecode", conflicts_with = "verifier")]
+    bytecode: Option<String>,
+    /// Solidity compiler binary
+    #[clap(long = "solc", default_value = "solc")]
+    solc: String,
+    /// Huff compiler binary
+    #[clap(long = "huffc", default_value = "huffc")]
+    huffc: String,
+}
+
+/// A subcommand for exporting parameters or witnesses in raw Montgomery form, see `zkutil::raw_params`
+#[derive(Clap)]
+struct ExportRawOpts {
+    /// Snark trusted setup parameters file
+    #[clap(short = "p", long = "params", env = "ZKUTIL_PARAMS", default_value = "params.bin")]
+    params: String,
+    /// Export this witness instead of the parameters
+    #[clap(short = "w", long = "witness")]
+    witness: Option<String>,
+    /// Output file for raw parameters or witness
+    #[clap(short = "o", long = "output", default_value = "params.raw")]
+    output: String,
+    /// Write field elements as big-endian integers instead of little-endian limbs
+    #[clap(long = "big-endian")]
+    big_endian: bool,
+    /// Align sections to 32 bytes instead of 64
+    #[clap(long = "packed")]
+    packed: bool,
+    /// Overwrite existing output files
+    #[clap(long = "force")]
+    force: bool,
+}
+
+/// A subcommand for importing snarkjs zkey files, see `zkutil::zkey`
+#[derive(Clap)]
+struct ImportZkeyOpts {
+    /// snarkjs Groth16 zkey file
+    #[clap(short = "z", long = "zkey", default_value = "circuit_final.zkey")]
+    zkey: String,
+    /// Output file for the parameters
     #[clap(short = "p", long = "params", default_value = "params.bin")]
     params: String,
-    /// Output smart contract name
-    #[clap(short = "v", long = "verifier", default_value = "Verifier.sol")]
-    verifier: String,
+    /// Circuit R1CS or JSON file to check the zkey against and record in the params metadata
+    #[clap(short = "c", long = "circuit")]
+    circuit: Option<String>,
+    /// Overwrite existing output files
+    #[clap(long = "force")]
+    force: bool,
+}
+
+/// A subcommand for exporting verifying key with e(alpha, beta) precomputed, see `zkutil::verifier::encode_prepared_key`
+#[derive(Clap)]
+struct PrepareKeyOpts {
+    /// Snark trusted setup parameters file
+    #[clap(short = "p", long = "params", env = "ZKUTIL_PARAMS", default_value = "params.bin")]
+    params: String,
+    /// Output file for prepared verifying key
+    #[clap(short = "o", long = "output", default_value = "vk.prepared")]
+    output: String,
+    /// Overwrite existing output files
+    #[clap(long = "force")]
+    force: bool,
 }
 
 /// A subcommand for exporting proving and verifying keys compatible with snarkjs/websnark
 #[derive(Clap)]
 struct ExportKeysOpts {
     /// Snark trusted setup parameters file
-    #[clap(short = "p", long = "params", default_value = "params.bin")]
+    #[clap(short = "p", long = "params", env = "ZKUTIL_PARAMS", default_value = "params.bin")]
     params: String,
     /// Circuit R1CS or JSON file [default: circuit.r1cs|circuit.json]
-    #[clap(short = "c", long = "circuit")]
+    #[clap(short = "c", long = "circuit", env = "ZKUTIL_CIRCUIT")]
     circuit: Option<String>,
     /// Output proving key file
     #[clap(short = "r", long = "pk", default_value = "proving_key.json")]
@@ -122,132 +800,1857 @@ struct ExportKeysOpts {
     /// Output verifying key file
     #[clap(short = "v", long = "vk", default_value = "verification_key.json")]
     vk: String,
+    /// Write field elements as 0x-prefixed hex instead of decimal
+    #[clap(long = "hex")]
+    hex: bool,
+    /// Overwrite existing output files
+    #[clap(long = "force")]
+    force: bool,
+}
+
+/// A subcommand for packing circuit and parameters into a single bundle file
+#[derive(Clap)]
+struct BundleOpts {
+    /// Snark trusted setup parameters file
+    #[clap(short = "p", long = "params", env = "ZKUTIL_PARAMS", default_value = "params.bin")]
+    params: String,
+    /// Circuit R1CS or JSON file [default: circuit.r1cs|circuit.json]
+    #[clap(short = "c", long = "circuit", env = "ZKUTIL_CIRCUIT")]
+    circuit: Option<String>,
+    /// Optional circuit debug symbols file
+    #[clap(short = "s", long = "sym")]
+    sym: Option<String>,
+    /// Output bundle file
+    #[clap(short = "o", long = "output", default_value = "circuit.bundle")]
+    output: String,
+    /// Overwrite existing output files
+    #[clap(long = "force")]
+    force: bool,
+}
+
+/// A subcommand for converting witness JSON to binary wtns format
+#[derive(Clap)]
+struct ConvertWitnessOpts {
+    /// Witness JSON file
+    #[clap(short = "w", long = "witness", env = "ZKUTIL_WITNESS", default_value = "witness.json")]
+    witness: String,
+    /// Output witness wtns file
+    #[clap(short = "o", long = "output", default_value = "witness.wtns")]
+    output: String,
+    /// Overwrite existing output files
+    #[clap(long = "force")]
+    force: bool,
+}
+
+/// A subcommand for calculating witness from a circuit evaluation graph
+#[derive(Clap)]
+struct CalculateWitnessOpts {
+    /// circom-witnesscalc circuit evaluation graph, as built by its build-circuit
+    #[clap(short = "g", long = "graph", default_value = "graph.bin")]
+    graph: String,
+    /// Circuit inputs JSON file
+    #[clap(short = "i", long = "input", default_value = "input.json")]
+    input: String,
+    /// Output witness file, JSON if the name ends with .json, wtns otherwise
+    #[clap(short = "o", long = "output", default_value = "witness.wtns")]
+    output: String,
+    /// Overwrite existing output files
+    #[clap(long = "force")]
+    force: bool,
+}
+
+/// A subcommand for listing signal visibility
+#[derive(Clap)]
+struct SignalsOpts {
+    /// Circuit R1CS or JSON file [default: circuit.r1cs|circuit.json]
+    #[clap(short = "c", long = "circuit", env = "ZKUTIL_CIRCUIT")]
+    circuit: Option<String>,
+    /// Circuit debug symbols file, required for R1CS circuits [default: circuit.sym]
+    #[clap(short = "s", long = "sym")]
+    sym: Option<String>,
+}
+
+/// A subcommand for inspecting an R1CS file
+#[derive(Clap)]
+struct InspectOpts {
+    /// Circuit R1CS or params file
+    #[clap(short = "c", long = "circuit", default_value = "circuit.r1cs")]
+    circuit: String,
+}
+
+/// A subcommand for comparing two keys
+#[derive(Clap)]
+struct DiffKeysOpts {
+    /// Params file, bundle or verification key JSON
+    a: String,
+    /// Params file, bundle or verification key JSON to compare with
+    b: String,
+}
+
+/// A subcommand for probing a circuit for under-constrained signals
+#[derive(Clap)]
+struct FuzzWitnessOpts {
+    /// Circuit R1CS or JSON file [default: circuit.r1cs|circuit.json]
+    #[clap(short = "c", long = "circuit", env = "ZKUTIL_CIRCUIT")]
+    circuit: Option<String>,
+    /// Valid witness to start from [default: witness.wtns|witness.json]
+    #[clap(short = "w", long = "witness", env = "ZKUTIL_WITNESS")]
+    witness: Option<String>,
+    /// Circuit debug symbols file for signal names [default: circuit.sym]
+    #[clap(short = "s", long = "sym")]
+    sym: Option<String>,
+    /// Random values to try per signal, and random signal groups to try
+    #[clap(long = "rounds", default_value = "100")]
+    rounds: usize,
+}
+
+/// A subcommand for mutation-based soundness self-test of a circuit
+#[derive(Clap)]
+struct SoundnessTestOpts {
+    /// Circuit R1CS or JSON file [default: circuit.r1cs|circuit.json]
+    #[clap(short = "c", long = "circuit", env = "ZKUTIL_CIRCUIT")]
+    circuit: Option<String>,
+    /// Valid witness to mutate [default: witness.wtns|witness.json]
+    #[clap(short = "w", long = "witness", env = "ZKUTIL_WITNESS")]
+    witness: Option<String>,
+    /// Circuit debug symbols file for signal names [default: circuit.sym]
+    #[clap(short = "s", long = "sym")]
+    sym: Option<String>,
+    /// Trusted setup parameters to also prove and verify undetected mutants with
+    #[clap(short = "p", long = "params")]
+    params: Option<String>,
+    /// Random values to try per signal in addition to edge cases
+    #[clap(long = "rounds", default_value = "10")]
+    rounds: usize,
+    /// Output file for the JSON report
+    #[clap(short = "o", long = "report", default_value = "soundness-report.json")]
+    report: String,
+    /// Overwrite existing output files
+    #[clap(long = "force")]
+    force: bool,
+}
+
+/// A subcommand for generating an audit report
+#[derive(Clap)]
+struct ReportOpts {
+    /// Snark trusted setup parameters file
+    #[clap(short = "p", long = "params", env = "ZKUTIL_PARAMS", default_value = "params.bin")]
+    params: String,
+    /// Circuit R1CS or JSON file [default: circuit.r1cs|circuit.json]
+    #[clap(short = "c", long = "circuit", env = "ZKUTIL_CIRCUIT")]
+    circuit: Option<String>,
+    /// Circuit debug symbols file for public input names [default: circuit.sym]
+    #[clap(short = "s", long = "sym")]
+    sym: Option<String>,
+    /// Output file, JSON if it ends with .json and markdown otherwise
+    #[clap(short = "o", long = "output", default_value = "report.md")]
+    output: String,
+    /// Overwrite existing output files
+    #[clap(long = "force")]
+    force: bool,
+}
+
+/// A subcommand for exporting a circuit graph
+#[derive(Clap)]
+struct GraphOpts {
+    /// Circuit R1CS or JSON file [default: circuit.r1cs|circuit.json]
+    #[clap(short = "c", long = "circuit", env = "ZKUTIL_CIRCUIT")]
+    circuit: Option<String>,
+    /// Circuit debug symbols file for signal names [default: circuit.sym]
+    #[clap(short = "s", long = "sym")]
+    sym: Option<String>,
+    /// Only show constraints of signals whose names start with this prefix, e.g. `main.hasher.`
+    #[clap(long = "prefix")]
+    prefix: Option<String>,
+    /// Output DOT file
+    #[clap(short = "o", long = "output", default_value = "circuit.dot")]
+    output: String,
+    /// Overwrite existing output files
+    #[clap(long = "force")]
+    force: bool,
+}
+
+/// A subcommand for exporting constraint matrices
+#[derive(Clap)]
+struct ExportMatricesOpts {
+    /// Circuit R1CS or JSON file [default: circuit.r1cs|circuit.json]
+    #[clap(short = "c", long = "circuit", env = "ZKUTIL_CIRCUIT")]
+    circuit: Option<String>,
+    /// Output files are <output>.A.mtx, <output>.B.mtx and <output>.C.mtx
+    #[clap(short = "o", long = "output", default_value = "matrix")]
+    output: String,
+    /// Write NPZ files loadable with scipy.sparse.load_npz instead of Matrix Market
+    #[clap(long = "npz")]
+    npz: bool,
+    /// Overwrite existing output files
+    #[clap(long = "force")]
+    force: bool,
+}
+
+/// A subcommand for exporting a circuit to zkinterface
+#[cfg(feature = "zkif")]
+#[derive(Clap)]
+struct ExportZkinterfaceOpts {
+    /// Circuit R1CS or JSON file [default: circuit.r1cs|circuit.json]
+    #[clap(short = "c", long = "circuit", env = "ZKUTIL_CIRCUIT")]
+    circuit: Option<String>,
+    /// Witness to export with the circuit, only the constraint system is exported if omitted
+    #[clap(short = "w", long = "witness")]
+    witness: Option<String>,
+    /// Output zkinterface file
+    #[clap(short = "o", long = "output", default_value = "circuit.zkif")]
+    output: String,
+    /// Overwrite existing output files
+    #[clap(long = "force")]
+    force: bool,
+}
+
+/// A subcommand for importing a Noir program
+#[cfg(feature = "noir")]
+#[derive(Clap)]
+struct ImportAcirOpts {
+    /// Program artifact JSON produced by `nargo compile` or raw ACIR bytecode
+    #[clap(short = "a", long = "program")]
+    program: String,
+    /// Witness file produced by `nargo execute`, only the circuit is converted if omitted
+    #[clap(short = "i", long = "nargo-witness")]
+    nargo_witness: Option<String>,
+    /// Output file for circuit JSON
+    #[clap(short = "c", long = "circuit", default_value = "circuit.json")]
+    circuit: String,
+    /// Output file for witness, JSON if it ends with .json and binary wtns otherwise
+    #[clap(short = "w", long = "witness", default_value = "witness.wtns")]
+    witness: String,
+    /// Overwrite existing output files
+    #[clap(long = "force")]
+    force: bool,
+}
+
+/// A subcommand for generating a signing key pair
+#[cfg(feature = "signing")]
+#[derive(Clap)]
+struct GenerateSigningKeyOpts {
+    /// Output file for the secret key
+    #[clap(short = "k", long = "key", default_value = "zkutil.key")]
+    key: String,
+    /// Output file for the minisign public key
+    #[clap(short = "P", long = "public", default_value = "zkutil.pub")]
+    public: String,
+    /// Overwrite existing output files
+    #[clap(long = "force")]
+    force: bool,
+}
+
+/// A subcommand for signing a parameter file
+#[cfg(feature = "signing")]
+#[derive(Clap)]
+struct SignParamsOpts {
+    /// Snark trusted setup parameters or bundle file
+    #[clap(short = "p", long = "params", env = "ZKUTIL_PARAMS", default_value = "params.bin")]
+    params: String,
+    /// Secret key generated by generate-signing-key
+    #[clap(short = "k", long = "key", default_value = "zkutil.key")]
+    key: String,
+    /// Output signature file [default: <params>.minisig]
+    #[clap(short = "s", long = "signature")]
+    signature: Option<String>,
+    /// Overwrite existing output files
+    #[clap(long = "force")]
+    force: bool,
+}
+
+/// A subcommand for generating an input template
+#[derive(Clap)]
+struct InputTemplateOpts {
+    /// Circuit R1CS or JSON file [default: circuit.r1cs|circuit.json]
+    #[clap(short = "c", long = "circuit", env = "ZKUTIL_CIRCUIT")]
+    circuit: Option<String>,
+    /// Circuit debug symbols file, required for R1CS circuits [default: circuit.sym]
+    #[clap(short = "s", long = "sym")]
+    sym: Option<String>,
+    /// Output input template file
+    #[clap(short = "o", long = "output", default_value = "input.template.json")]
+    output: String,
+    /// Overwrite existing output files
+    #[clap(long = "force")]
+    force: bool,
+}
+
+/// A subcommand for computing hashes used in circuits
+#[derive(Clap)]
+struct HashOpts {
+    #[clap(subcommand)]
+    function: HashFunction,
+}
+
+#[derive(Clap)]
+enum HashFunction {
+    /// Poseidon hash compatible with circomlib
+    Poseidon(PoseidonOpts),
+}
+
+/// Poseidon hash of up to 16 field elements
+#[derive(Clap)]
+struct PoseidonOpts {
+    /// Field elements to hash, decimal or 0x-prefixed hex
+    #[clap(required = true)]
+    inputs: Vec<String>,
+}
+
+/// A subcommand for preparing EdDSA signed circuit inputs
+#[derive(Clap)]
+struct EddsaOpts {
+    #[clap(subcommand)]
+    command: EddsaCommand,
+}
+
+#[derive(Clap)]
+enum EddsaCommand {
+    /// Generate a random private key and print it with the public key
+    Keygen,
+    /// Sign a field element, print signature as EdDSAPoseidonVerifier inputs
+    Sign(EddsaSignOpts),
+}
+
+/// Sign a field element with EdDSA-Poseidon
+#[derive(Clap)]
+struct EddsaSignOpts {
+    /// Private key, 32 bytes hex
+    #[clap(short = "k", long = "key")]
+    key: String,
+    /// Message field element, decimal or 0x-prefixed hex
+    message: String,
+}
+
+/// A subcommand for scalar field arithmetic
+#[derive(Clap)]
+struct EvalOpts {
+    /// Operation: add, sub, mul, div, pow, inv, neg, dec, hex
+    op: String,
+    /// Field elements, decimal or 0x-prefixed hex; negative values are negated mod p
+    #[clap(allow_hyphen_values = true)]
+    args: Vec<String>,
 }
 
 fn main() {
     let opts: Opts = Opts::parse();
+    #[cfg(not(target_os = "wasi"))]
+    install_interrupt_handler();
+    #[cfg(feature = "otlp")]
+    if let Err(e) = zkutil::telemetry::init() {
+        println!("Unable to initialize OTLP exporter: {}", e);
+    }
+    #[cfg(feature = "otlp")]
+    let root_span = zkutil::telemetry::root_span().entered();
     match opts.command {
         SubCommand::Prove(o) => {
             prove(o);
         }
+        SubCommand::Fullprove(o) => {
+            fullprove(o);
+        }
+        SubCommand::ProveManifest(o) => {
+            prove_manifest(o);
+        }
+        SubCommand::Watch(o) => {
+            watch(o);
+        }
+        #[cfg(feature = "queue")]
+        SubCommand::Consume(o) => {
+            consume(o);
+        }
+        #[cfg(feature = "server")]
+        SubCommand::Serve(o) => {
+            serve(o);
+        }
         SubCommand::Verify(o) => {
             verify(o);
         }
+        SubCommand::VerifyMany(o) => {
+            verify_many(o);
+        }
+        SubCommand::NormalizeProof(o) => {
+            normalize_proof_file(o);
+        }
+        SubCommand::DummyProof(o) => {
+            dummy_proof(o);
+        }
+        SubCommand::EncodeProof(o) => {
+            encode_proof(o);
+        }
+        SubCommand::DecodeCalldata(o) => {
+            decode_calldata(o);
+        }
+        SubCommand::EstimateGas(o) => {
+            estimate_gas(o);
+        }
+        #[cfg(feature = "submit")]
+        SubCommand::Submit(o) => {
+            submit(o);
+        }
         SubCommand::Setup(o) => {
             setup(o);
         }
+        SubCommand::SetupMany(o) => {
+            setup_many(o);
+        }
         SubCommand::GenerateVerifier(o) => {
             generate_verifier(o);
         }
+        #[cfg(feature = "evm")]
+        SubCommand::CheckVerifier(o) => {
+            check_verifier(o);
+        }
         SubCommand::ExportKeys(o) => {
             export_keys(o);
         }
-    }
-}
-
-fn load_r1cs(filename: &str) -> R1CS<Bn256> {
-    if filename.ends_with("json") {
-        r1cs_from_json_file(filename)
-    } else {
-        let (r1cs, _wire_mapping) = r1cs_from_bin_file(filename).unwrap();
-        r1cs
-    }
-}
-
-fn resolve_circuit_file(filename: Option<String>) -> String {
-    match filename {
-        Some(s) => s,
-        None => if Path::new("circuit.r1cs").exists() || !Path::new("circuit.json").exists() {
-            "circuit.r1cs".to_string()
-        } else {
-            "circuit.json".to_string()
+        SubCommand::ExportRaw(o) => {
+            export_raw(o);
         }
-    }
-}
-
-fn load_witness<E: Engine>(filename: &str) -> Vec<E::Fr> {
-    if filename.ends_with("json") {
-        witness_from_json_file::<E>(filename)
+        SubCommand::ImportZkey(o) => {
+            import_zkey(o);
+        }
+        SubCommand::PrepareKey(o) => {
+            prepare_key(o);
+        }
+        SubCommand::Bundle(o) => {
+            create_bundle(o);
+        }
+        SubCommand::ConvertWitness(o) => {
+            convert_witness(o);
+        }
+        SubCommand::CalculateWitness(o) => {
+            calculate_witness(o);
+        }
+        SubCommand::Signals(o) => {
+            list_signals(o);
+        }
+        SubCommand::Inspect(o) => {
+            inspect(o);
+        }
+        SubCommand::DiffKeys(o) => {
+            diff_keys(o);
+        }
+        SubCommand::FuzzWitness(o) => {
+            fuzz_witness(o);
+        }
+        SubCommand::SoundnessTest(o) => {
+            soundness_test(o);
+        }
+        SubCommand::Report(o) => {
+            audit_report(o);
+        }
+        SubCommand::Graph(o) => {
+            graph(o);
+        }
+        SubCommand::ExportMatrices(o) => {
+            export_matrices(o);
+        }
+        #[cfg(feature = "zkif")]
+        SubCommand::ExportZkinterface(o) => {
+            export_zkinterface(o);
+        }
+        #[cfg(feature = "noir")]
+        SubCommand::ImportAcir(o) => {
+            import_acir(o);
+        }
+        #[cfg(feature = "signing")]
+        SubCommand::GenerateSigningKey(o) => {
+            generate_signing_key(o);
+        }
+        #[cfg(feature = "signing")]
+        SubCommand::SignParams(o) => {
+            sign_params(o);
+        }
+        SubCommand::InputTemplate(o) => {
+            input_template(o);
+        }
+        SubCommand::Hash(o) => {
+            hash(o);
+        }
+        SubCommand::Eddsa(o) => {
+            eddsa(o);
+        }
+        SubCommand::Eval(o) => {
+            eval(o);
+        }
+    }
+    #[cfg(feature = "otlp")]
+    {
+        drop(root_span);
+        zkutil::telemetry::shutdown();
+    }
+}
+
+/// Exits with `code` after flushing pending spans, every exit goes through it instead of `std::process::exit`
+fn exit(code: i32) -> ! {
+    #[cfg(feature = "otlp")]
+    zkutil::telemetry::shutdown();
+    std::process::exit(code)
+}
+
+/// Downloads remote artifacts, returns local file name
+fn fetch_artifact(location: &str) -> String {
+    if !remote::is_remote(location) {
+        return location.to_string();
+    }
+    println!("Fetching {}...", location);
+    match remote::fetch(location) {
+        Ok(path) => path.to_string_lossy().to_string(),
+        Err(e) => {
+            println!("Unable to fetch {}: {}", location, e);
+            exit(exitcode::UNAVAILABLE);
+        }
+    }
+}
+
+/// Returns a local file name to write an output to, remote outputs are written to a temporary file
+fn output_file(location: &str) -> String {
+    if remote::is_writable_remote(location) {
+        remote::temp_path(location).unwrap().to_string_lossy().to_string()
     } else {
-        witness_from_bin_file::<E>(filename).unwrap()
+        location.to_string()
     }
 }
 
-fn resolve_witness_file(filename: Option<String>) -> String {
-    match filename {
-        Some(s) => s,
-        None => if Path::new("witness.wtns").exists() || !Path::new("witness.json").exists() {
-            "witness.wtns".to_string()
-        } else {
-            "witness.json".to_string()
+/// Output files being written, removed if zkutil is interrupted
+static PENDING_OUTPUTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Marks a temporary file of an atomic write as pending until `output_done`
+fn output_started(filename: &Path) {
+    PENDING_OUTPUTS.lock().unwrap().push(filename.to_string_lossy().to_string());
+}
+
+fn output_done(filename: &Path) {
+    let filename = filename.to_string_lossy();
+    PENDING_OUTPUTS.lock().unwrap().retain(|f| *f != filename);
+}
+
+fn remove_pending_outputs() {
+    for filename in PENDING_OUTPUTS.lock().unwrap().iter() {
+        if fs::remove_file(filename).is_ok() {
+            println!("Removed partially written {}", filename);
+        }
+    }
+}
+
+/// Removes temporary files of unfinished outputs on SIGINT/SIGTERM
+#[cfg(not(target_os = "wasi"))]
+fn install_interrupt_handler() {
+    let result = ctrlc::set_handler(|| {
+        remove_pending_outputs();
+        println!("Interrupted");
+        exit(130);
+    });
+    if let Err(e) = result {
+        println!("Unable to install interrupt handler: {}", e);
+    }
+}
+
+/// Exits if any of local outputs already exists, unless `--force` is given
+fn check_overwrite(locations: &[&str], force: bool) {
+    if force {
+        return;
+    }
+    for location in locations {
+        if !remote::is_remote(location) && Path::new(location).exists() {
+            println!("{} already exists, use --force to overwrite it", location);
+            exit(exitcode::CANTCREAT);
+        }
+    }
+}
+
+/// Uploads an output written to `output_file(location)` to its remote location
+fn store_output(location: &str) {
+    if remote::is_writable_remote(location) {
+        println!("Uploading {}...", location);
+        if let Err(e) = remote::upload(Path::new(&output_file(location)), location) {
+            println!("Unable to upload {}: {}", location, e);
+            exit(exitcode::UNAVAILABLE);
+        }
+    }
+}
+
+/// Writes proof and public inputs through temporary files that are removed if zkutil is
+/// interrupted, and uploads them to remote locations
+fn save_proof(proof: &Proof<Bn256>, public: &proof::PublicInputs, format: NumberFormat, proof_location: &str, public_location: &str) {
+    let (proof_file, public_file) = (output_file(proof_location), output_file(public_location));
+    let temp_files = [atomic_temp_path(&proof_file), atomic_temp_path(&public_file)];
+    temp_files.iter().for_each(|f| output_started(f));
+    or_exit(write_proof_files_with_format(proof, public, format, &proof_file, &public_file), "Unable to write proof");
+    temp_files.iter().for_each(|f| output_done(f));
+    store_output(proof_location);
+    store_output(public_location);
+}
+
+fn detect_format(filename: &str) -> FileFormat {
+    detect_file_format(filename).unwrap_or_else(|e| {
+        println!("Unable to open {}: {}", filename, e);
+        exit(exitcode::NOINPUT);
+    })
+}
+
+/// Unwraps a library result, printing the error and exiting on failure
+fn or_exit<T>(result: std::io::Result<T>, context: &str) -> T {
+    result.unwrap_or_else(|e| {
+        println!("{}: {}", context, e);
+        exit(match e.kind() {
+            ErrorKind::NotFound | ErrorKind::PermissionDenied => exitcode::NOINPUT,
+            _ => exitcode::DATAERR,
+        });
+    })
+}
+
+fn load_r1cs(filename: &str) -> R1CS<Bn256> {
+    let filename = &fetch_artifact(filename);
+    or_exit(circuit::load_r1cs(filename), &format!("Unable to load circuit {}", filename))
+}
+
+fn warn_if_insecure(header: Option<&FileHeader>) {
+    if header.map_or(false, |h| h.is_insecure_dev()) {
+        println!("**************************************************************************");
+        println!("* WARNING: INSECURE-DEV parameters generated by `setup --dev` are in use. *");
+        println!("* Anyone can forge proofs for them, never use them in production!         *");
+        println!("**************************************************************************");
+    }
+}
+
+fn load_params_with_header(filename: &str) -> (Option<FileHeader>, Parameters<Bn256>) {
+    let filename = &fetch_artifact(filename);
+    let (header, params) = or_exit(keys::load_params(filename), &format!("Unable to load parameters {}", filename));
+    warn_if_insecure(header.as_ref());
+    (header, params)
+}
+
+/// Loads only the verifying key of a params file or a bundle
+fn load_verifying_key(filename: &str) -> VerifyingKey<Bn256> {
+    load_verifying_key_with_header(filename).1
+}
+
+fn load_verifying_key_with_header(filename: &str) -> (Option<FileHeader>, VerifyingKey<Bn256>) {
+    let filename = &fetch_artifact(filename);
+    let (header, vk) = or_exit(keys::load_verifying_key(filename), &format!("Unable to load verifying key from {}", filename));
+    warn_if_insecure(header.as_ref());
+    (header, vk)
+}
+
+fn load_proof(filename: &str, checked: bool) -> Proof<Bn256> {
+    let filename = &fetch_artifact(filename);
+    or_exit(read_proof_json_file(filename, checked), &format!("Unable to load proof {}", filename))
+}
+
+/// Exits unless `location` has a valid `<location>.minisig` signature by `public_key`
+#[cfg(feature = "signing")]
+fn check_signature(location: &str, public_key: &str) {
+    let key = or_exit(signing::VerifyingKey::load(&fetch_artifact(public_key)), &format!("Unable to load trusted key {}", public_key));
+    let signature_file = fetch_artifact(&signing::signature_file(location));
+    let filename = fetch_artifact(location);
+    match key.verify_file(&filename, &signature_file) {
+        Ok(comment) => println!("{} is signed by key {} ({})", location, signing::key_id_hex(&key.key_id), comment),
+        Err(e) => {
+            println!("Refusing to use {}: {}", location, e);
+            exit(exitcode::DATAERR);
+        }
+    }
+}
+
+fn load_witness<E: Engine>(filename: &str) -> Vec<E::Fr> {
+    let filename = &fetch_artifact(filename);
+    or_exit(circuit::load_witness::<E>(filename), &format!("Unable to load witness {}", filename))
+}
+
+/// Loads params and circuit either from a bundle or from separate files and makes sure they match
+fn load_prover_inputs(bundle: Option<String>, params: &str, circuit: Option<String>) -> (Parameters<Bn256>, R1CS<Bn256>) {
+    let result = match bundle {
+        Some(bundle_file) => {
+            let bundle_file = fetch_artifact(&bundle_file);
+            println!("Loading bundle {}...", bundle_file);
+            keys::load_bundle_pair(&bundle_file)
+        }
+        None => {
+            let circuit_file = fetch_artifact(&resolve_circuit_file(circuit));
+            println!("Loading circuit from {}...", circuit_file);
+            keys::load_prover_pair(&fetch_artifact(params), &circuit_file)
         }
+    };
+    let (header, params, r1cs) = or_exit(result, "Unable to load prover inputs");
+    warn_if_insecure(header.as_ref());
+    (params, r1cs)
+}
+
+fn report_prover_memory(r1cs: &R1CS<Bn256>, storage: keys::QueryStorage, low_memory: bool) {
+    let bytes = if low_memory { keys::estimate_low_memory_prover(r1cs, storage) } else { keys::estimate_prover_memory(r1cs, storage) };
+    println!("Expected peak memory: ~{:.1} MiB", bytes as f64 / (1 << 20) as f64);
+}
+
+/// Exit code when proving exceeds `--timeout`, the same as of coreutils `timeout`
+const EXIT_TIMEOUT: i32 = 124;
+
+fn start_timeout(seconds: u64) {
+    let watchdog = prover::start_timeout(std::time::Duration::from_secs(seconds), move || {
+        println!("Proving timed out after {} seconds", seconds);
+        remove_pending_outputs();
+        exit(EXIT_TIMEOUT);
+    });
+    if let Err(e) = watchdog {
+        println!("Unable to enforce timeout: {}", e);
+        exit(exitcode::OSERR);
     }
 }
 
 fn prove(opts: ProveOpts) {
-    let rng = create_rng();
-    let params = load_params_file(&opts.params);
-    let circuit_file = resolve_circuit_file(opts.circuit);
+    match &opts.packed {
+        Some(packed) => check_overwrite(&[packed.as_str()], opts.force),
+        None => check_overwrite(&[opts.proof.as_str(), opts.public.as_str()], opts.force),
+    }
+    if let Some(seconds) = opts.timeout {
+        start_timeout(seconds);
+    }
     let witness_file = resolve_witness_file(opts.witness);
-    println!("Loading circuit from {}...", circuit_file);
-    let circuit = CircomCircuit {
-        r1cs: load_r1cs(&circuit_file),
-        witness: Some(load_witness::<Bn256>(&witness_file)),
-        wire_mapping: None,
+    let mut progress = match &opts.progress_json {
+        Some(target) => or_exit(Progress::open(target, prover::progress_stages(opts.check)), &format!("Unable to open {} for progress events", target)),
+        None => Progress::disabled(),
+    };
+    #[cfg(feature = "signing")]
+    if let Some(key) = &opts.trusted_key {
+        check_signature(opts.bundle.as_deref().unwrap_or(&opts.params), key);
+    }
+    let insecure_seed = opts.insecure_seed.clone();
+    let proof_rng = || match &insecure_seed {
+        Some(seed) => create_seeded_rng(seed),
+        None => create_rng(),
+    };
+    if insecure_seed.is_some() {
+        println!("WARNING: proving with a seeded RNG, the proof reveals the witness to anyone who knows the seed. Use it for test fixtures only");
+    }
+    progress.start("load");
+    // the witness doesn't depend on params or circuit, it's loaded alongside them unless memory is short
+    let loader_file = witness_file.clone();
+    let witness_loader = if opts.low_memory { None } else { Some(utils::background(move || load_witness::<Bn256>(&loader_file))) };
+    let inputs = match opts.bundle {
+        Some(bundle_file) => {
+            let bundle_file = fetch_artifact(&bundle_file);
+            println!("Loading bundle {}...", bundle_file);
+            or_exit(ProverInputs::open_bundle(&bundle_file), "Unable to load prover inputs")
+        }
+        None => {
+            let params_file = fetch_artifact(&opts.params);
+            let circuit_file = fetch_artifact(&resolve_circuit_file(opts.circuit));
+            println!("Loading circuit from {}...", circuit_file);
+            or_exit(ProverInputs::open(&params_file, &circuit_file), "Unable to load prover inputs")
+        }
+    };
+    warn_if_insecure(inputs.header());
+    let disk_backed = opts.disk_backed || opts.low_memory;
+    if disk_backed && !inputs.is_streamed() {
+        println!("WARNING: disk-backed queries need an uncompressed, unencrypted params file, loading parameters into memory");
+    }
+    if opts.report_memory || opts.low_memory {
+        report_prover_memory(inputs.r1cs(), inputs.storage(disk_backed), opts.low_memory);
+    }
+    progress.done("load");
+    println!("Proving...");
+    let vk = inputs.verifying_key().clone();
+    let circuit_hash = if opts.packed.is_some() { Some(keys::circuit_hash(inputs.r1cs())) } else { None };
+    let options = prover::ProveOptions { disk_backed, check: opts.check };
+    let witness = || match witness_loader {
+        Some(loader) => Ok(loader.join()),
+        None => Ok(load_witness::<Bn256>(&witness_file)),
+    };
+    let result = if opts.low_memory {
+        prover::prove_low_memory(inputs, witness, options, &mut progress, proof_rng())
+    } else {
+        prover::prove(&inputs, witness, options, &mut progress, proof_rng())
     };
+    let (proof, public) = or_exit(result, "Unable to prove");
+    progress.start("write");
+    if let Some(packed) = &opts.packed {
+        let packed_file = output_file(packed);
+        output_started(&atomic_temp_path(&packed_file));
+        let artifact = proof::PackedProof { proof, public, circuit_hash, vk_hash: keys::verifying_key_hash(&vk) };
+        or_exit(proof::write_packed_proof_file(&artifact, number_format(opts.hex), &packed_file), "Unable to write packed proof");
+        output_done(&atomic_temp_path(&packed_file));
+        store_output(packed);
+        progress.done("write");
+        progress.finish();
+        println!("Saved {}", packed);
+        return;
+    }
+    save_proof(&proof, &public, number_format(opts.hex), &opts.proof, &opts.public);
+    progress.done("write");
+    progress.finish();
+    println!("Saved {} and {}", opts.proof, opts.public);
+}
+
+fn fullprove(opts: FullproveOpts) {
+    let mut outputs = vec![opts.proof.as_str(), opts.public.as_str()];
+    outputs.extend(opts.save_witness.as_deref());
+    check_overwrite(&outputs, opts.force);
+    let circuit_file = resolve_circuit_file(opts.circuit);
+    let witness = if opts.cpp || opts.witness_binary.is_some() {
+        let binary = opts.witness_binary.unwrap_or_else(|| witness_cpp::default_binary_for(&circuit_file));
+        println!("Calculating witness with {}...", binary);
+        let started = Instant::now();
+        let witness = or_exit(witness_cpp::calculate_witness(&binary, &opts.input), "Unable to calculate witness");
+        println!("Calculated {} witness values in {:.1}s", witness.len(), started.elapsed().as_secs_f64());
+        witness
+    } else {
+        graph_witness(&opts.graph, &opts.input)
+    };
+    if let Some(witness_file) = &opts.save_witness {
+        or_exit(witness_to_bin_file::<Bn256>(&witness, witness_file), &format!("Unable to save witness to {}", witness_file));
+    }
+    let (params, r1cs) = load_prover_inputs(None, &opts.params, Some(circuit_file));
     println!("Proving...");
-    let proof = prove2(circuit.clone(), &params, rng).unwrap();
-    proof_to_json_file(&proof, &opts.proof).unwrap();
-    fs::write(&opts.public, circuit.get_public_inputs_json().as_bytes()).unwrap();
+    let (proof, public) = prove_witness(&r1cs, &params, witness, create_rng()).unwrap_or_else(|e| {
+        println!("Unable to prove: {:?}", e);
+        exit(exitcode::DATAERR);
+    });
+    save_proof(&proof, &public, number_format(opts.hex), &opts.proof, &opts.public);
     println!("Saved {} and {}", opts.proof, opts.public);
 }
 
+fn serve_metrics(address: &str) {
+    or_exit(metrics::serve(address), &format!("Unable to serve metrics at {}", address));
+    println!("Serving metrics at http://{}/metrics", address);
+}
+
+fn prove_manifest(opts: ProveManifestOpts) {
+    let manifest = or_exit(manifest::load_manifest(&opts.manifest), &format!("Unable to load manifest {}", opts.manifest));
+    let mut outputs = manifest.proofs.iter().flat_map(|e| vec![e.proof.as_str(), e.public.as_str()]).collect::<Vec<_>>();
+    outputs.push(&opts.results);
+    outputs.extend(opts.report.as_deref());
+    check_overwrite(&outputs, opts.force);
+    println!("Proving {} entries of {}...", manifest.proofs.len(), opts.manifest);
+    let circuit_file = resolve_circuit_file(opts.circuit);
+    let results = manifest::run_manifest(&manifest, &opts.params, &circuit_file, |result| match &result.error {
+        None => println!("Saved {} and {}", result.proof, result.public),
+        Some(e) => println!("Failed to prove {}: {}", result.id, e),
+    });
+    or_exit(manifest::write_results_file(&results, &opts.results), &format!("Unable to write {}", opts.results));
+    if let Some(report) = &opts.report {
+        let items = results.iter().map(|r| batch_report::BatchItem {
+            file: r.witness.clone(),
+            status: if r.error.is_none() { batch_report::ItemStatus::Ok } else { batch_report::ItemStatus::Failed },
+            seconds: r.seconds,
+            proof_size: r.error.as_ref().map_or_else(|| fs::metadata(&r.proof).ok().map(|m| m.len()), |_| None),
+            error: r.error.clone(),
+        }).collect::<Vec<_>>();
+        or_exit(batch_report::write_report_file(&items, report), &format!("Unable to write report {}", report));
+    }
+    let failed = results.iter().filter(|r| r.error.is_some()).count();
+    println!("Proved {} of {} entries, results are in {}", results.len() - failed, results.len(), opts.results);
+    if failed > 0 {
+        exit(exitcode::DATAERR);
+    }
+}
+
+fn watch(opts: WatchOpts) {
+    #[cfg(feature = "signing")]
+    if let Some(key) = &opts.trusted_key {
+        check_signature(opts.bundle.as_deref().unwrap_or(&opts.params), key);
+    }
+    let (params, r1cs) = load_prover_inputs(opts.bundle, &opts.params, opts.circuit);
+    let dirs = watch::WatchDirs {
+        input: opts.input_dir.clone().into(),
+        output: opts.output_dir.into(),
+        done: opts.done_dir.into(),
+        failed: opts.failed_dir.into(),
+    };
+    let mut watcher = or_exit(watch::Watcher::new(&r1cs, &params, dirs, std::time::Duration::from_secs(opts.aging)), "Unable to create watched directories");
+    if let Some(address) = &opts.metrics {
+        serve_metrics(address);
+    }
+    println!("Watching {} for witness files...", opts.input_dir);
+    let result = watcher.run(std::time::Duration::from_secs(opts.interval), |event| match event {
+        WatchEvent::Proving(file) => println!("Proving {}...", file.display()),
+        WatchEvent::Proved { proof, public, .. } => println!("Saved {} and {}", proof.display(), public.display()),
+        WatchEvent::Failed { witness, error } => println!("Failed to prove {}: {}", witness.display(), error),
+        WatchEvent::Stuck { witness, target, error } => {
+            println!("Unable to move {} to {}, skipping it from now on: {}", witness.display(), target.display(), error)
+        }
+    });
+    or_exit(result, &format!("Unable to read {}", opts.input_dir));
+}
+
+#[cfg(feature = "queue")]
+fn consume(opts: ConsumeOpts) {
+    #[cfg(feature = "signing")]
+    if let Some(key) = &opts.trusted_key {
+        check_signature(opts.bundle.as_deref().unwrap_or(&opts.params), key);
+    }
+    let (params, r1cs) = load_prover_inputs(opts.bundle, &opts.params, opts.circuit);
+    if let Some(address) = &opts.metrics {
+        serve_metrics(address);
+    }
+    println!("Consuming jobs from {} on {}...", opts.subject, opts.nats);
+    if let Err(e) = zkutil::nats_consumer::consume_with_cache(&opts.nats, &opts.subject, opts.group.as_deref(), &opts.result_subject, &r1cs, &params, opts.witness_dir.as_deref().map(Path::new), opts.cache_size) {
+        println!("NATS error: {}", e);
+        exit(exitcode::UNAVAILABLE);
+    }
+}
+
+#[cfg(feature = "server")]
+fn serve(opts: ServeOpts) {
+    let listener = std::net::TcpListener::bind(&opts.listen).unwrap_or_else(|e| {
+        println!("Unable to listen on {}: {}", opts.listen, e);
+        std::process::exit(exitcode::UNAVAILABLE);
+    });
+    let options = zkutil::server::ServeOptions {
+        max_body: opts.max_body,
+        rate_limit: opts.rate_limit,
+        client_rate_limit: opts.client_rate_limit,
+        aging: std::time::Duration::from_secs(opts.aging),
+        cache_size: opts.cache_size,
+    };
+    let loaded;
+    let mut server = match &opts.registry {
+        Some(file) => {
+            let registry = or_exit(zkutil::registry::Registry::load(Path::new(file), opts.cache_size), "Unable to load circuit registry");
+            println!("Serving {} registered circuits", registry.entries().len());
+            zkutil::server::Server::from_registry(registry, options)
+        }
+        None => {
+            #[cfg(feature = "signing")]
+            if let Some(key) = &opts.trusted_key {
+                check_signature(opts.bundle.as_deref().unwrap_or(&opts.params), key);
+            }
+            loaded = load_prover_inputs(opts.bundle, &opts.params, opts.circuit);
+            zkutil::server::Server::new(&loaded.1, &loaded.0, options)
+        }
+    };
+    if let Some(dir) = &opts.upload_dir {
+        or_exit(fs::create_dir_all(dir), "Unable to create upload directory");
+        server = server.with_uploads(zkutil::upload::Uploads::new(Path::new(dir), opts.max_upload));
+    }
+    if let Some(auth) = &opts.auth {
+        server = server.with_auth(or_exit(zkutil::auth::Auth::load(auth), "Unable to load auth config"), &opts.name);
+    }
+    println!("Serving proving requests on http://{}...", opts.listen);
+    server.serve(listener);
+}
+
 fn verify(opts: VerifyOpts) {
-    let params = load_params_file(&opts.params);
-    let proof = load_proof_json_file::<Bn256>(&opts.proof);
-    let inputs = load_inputs_json_file::<Bn256>(&opts.public);
-    let correct = verify2(&params, &proof, &inputs).unwrap();
+    if opts.onchain && opts.contract.is_none() {
+        println!("--onchain requires --contract");
+        exit(exitcode::USAGE);
+    }
+    let (header, vk) = load_verifying_key_with_header(opts.bundle.as_deref().unwrap_or(&opts.params));
+    let (proof, inputs) = match (&opts.packed, &opts.tx) {
+        (Some(packed), _) => {
+            let packed = or_exit(proof::read_packed_proof_file(&fetch_artifact(packed), !opts.unchecked), &format!("Unable to load packed proof {}", packed));
+            if packed.vk_hash != keys::verifying_key_hash(&vk) {
+                println!("Packed proof was made for a different verifying key!");
+                exit(exitcode::DATAERR);
+            }
+            let params_circuit = header.as_ref().and_then(|h| h.metadata.as_ref()).map(|m| m.circuit_hash);
+            if let (Some(expected), Some(actual)) = (params_circuit, packed.circuit_hash) {
+                if expected != actual {
+                    println!("Packed proof was made for a different circuit than the parameters!");
+                    exit(exitcode::DATAERR);
+                }
+            }
+            (packed.proof, packed.public.into_values())
+        }
+        (None, Some(tx)) => {
+            println!("Fetching transaction {} from {}...", tx, opts.rpc);
+            let calldata = eth::transaction_input(&opts.rpc, tx).unwrap_or_else(|e| {
+                println!("Unable to fetch transaction: {}", e);
+                exit(exitcode::UNAVAILABLE);
+            });
+            eth::decode_verify_calldata(&calldata, vk.ic.len() - 1).unwrap_or_else(|e| {
+                println!("Unable to decode transaction input: {}", e);
+                exit(exitcode::DATAERR);
+            })
+        }
+        (None, None) => (load_proof(&opts.proof, !opts.unchecked), load_inputs_json_file::<Bn256>(&opts.public)),
+    };
+    if opts.canonical && !is_normalized(&proof) {
+        println!("Proof is not normalized!");
+        exit(400);
+    }
+    let correct = verify_with_key(&vk, &proof, &inputs).unwrap();
+    if let Some(contract) = opts.contract.as_deref().filter(|_| opts.onchain) {
+        println!("Calling verifier {} on {}...", contract, opts.rpc);
+        let onchain = eth::verify_onchain(&opts.rpc, contract, &proof, &inputs).unwrap_or_else(|e| {
+            println!("Unable to call verifier: {}", e);
+            exit(exitcode::UNAVAILABLE);
+        });
+        println!("Verifier contract {} the proof", if onchain { "accepts" } else { "rejects" });
+        if onchain != correct {
+            println!("On-chain and local verification disagree! Was the contract generated from these parameters?");
+            exit(exitcode::DATAERR);
+        }
+    }
     if correct {
         println!("Proof is correct");
     } else {
         println!("Proof is invalid!");
-        std::process::exit(400);
+        exit(400);
+    }
+}
+
+fn verify_many(opts: VerifyManyOpts) {
+    if opts.proofs.len() != opts.publics.len() {
+        println!("Got {} proof files and {} public input files", opts.proofs.len(), opts.publics.len());
+        exit(exitcode::USAGE);
+    }
+    let vk = load_verifying_key(&opts.params);
+    let proofs = opts.proofs.iter().zip(opts.publics.iter())
+        .map(|(proof, public)| (load_proof(proof, !opts.unchecked), load_inputs_json_file::<Bn256>(public)))
+        .collect::<Vec<_>>();
+    if opts.canonical {
+        let denormalized = proofs.iter().zip(opts.proofs.iter()).filter(|((proof, _), _)| !is_normalized(proof)).collect::<Vec<_>>();
+        for (_, file) in &denormalized {
+            println!("Proof {} is not normalized!", file);
+        }
+        if !denormalized.is_empty() {
+            exit(400);
+        }
+    }
+    let item = |file: &String, status: batch_report::ItemStatus, seconds: f64| batch_report::BatchItem {
+        file: file.clone(),
+        status,
+        seconds,
+        proof_size: fs::metadata(file).ok().map(|m| m.len()),
+        error: None,
+    };
+    if opts.batched {
+        println!("Verifying {} proofs in a batch...", proofs.len());
+        let started = Instant::now();
+        if verify_batch_with_key(&vk, &proofs, &mut create_rng()).unwrap() {
+            // the batch is checked at once, every proof is reported with an equal share of its time
+            let share = started.elapsed().as_secs_f64() / proofs.len().max(1) as f64;
+            if let Some(report) = &opts.report {
+                let items = opts.proofs.iter().map(|file| item(file, batch_report::ItemStatus::Ok, share)).collect::<Vec<_>>();
+                or_exit(batch_report::write_report_file(&items, report), &format!("Unable to write report {}", report));
+            }
+            println!("All proofs are correct");
+            return;
+        }
+        println!("Batch verification failed, checking proofs one by one...");
+    }
+    let mut items = vec![];
+    for ((proof, inputs), file) in proofs.iter().zip(opts.proofs.iter()) {
+        let started = Instant::now();
+        let valid = verify_with_key(&vk, proof, inputs).unwrap();
+        let status = if valid { batch_report::ItemStatus::Ok } else { batch_report::ItemStatus::Invalid };
+        items.push(item(file, status, started.elapsed().as_secs_f64()));
+        if !valid {
+            println!("Proof {} is invalid!", file);
+        }
+    }
+    if let Some(report) = &opts.report {
+        or_exit(batch_report::write_report_file(&items, report), &format!("Unable to write report {}", report));
+    }
+    let invalid = items.iter().filter(|i| i.status != batch_report::ItemStatus::Ok).count();
+    if invalid > 0 {
+        println!("{} of {} proofs are invalid", invalid, proofs.len());
+        exit(400);
+    }
+    println!("All proofs are correct");
+}
+
+fn number_format(hex: bool) -> NumberFormat {
+    if hex { NumberFormat::Hex } else { NumberFormat::Decimal }
+}
+
+fn normalize_proof_file(opts: NormalizeProofOpts) {
+    let proof = load_proof(&opts.proof, true);
+    let output = opts.output.clone().unwrap_or_else(|| opts.proof.clone());
+    if is_normalized(&proof) {
+        println!("Proof is already normalized");
+        if output == opts.proof && !opts.hex {
+            return;
+        }
+    }
+    or_exit(proof_to_json_file_with_format(&normalize_proof(&proof), number_format(opts.hex), &output), "Unable to save proof");
+    println!("Saved normalized proof to {}", output);
+}
+
+fn dummy_proof(opts: DummyProofOpts) {
+    check_overwrite(&[opts.proof.as_str(), opts.public.as_str()], opts.force);
+    let num_inputs = opts.inputs.unwrap_or_else(|| load_verifying_key(&opts.params).ic.len() - 1);
+    let (proof, public) = proof::dummy_proof(num_inputs, &mut create_rng());
+    or_exit(
+        proof::write_dummy_proof_files(&proof, &public, number_format(opts.hex), &opts.proof, &opts.public),
+        "Unable to save dummy proof",
+    );
+    println!("Saved {} and {} with {} public inputs", opts.proof, opts.public, num_inputs);
+    println!("WARNING: this is a DUMMY proof, it does not verify and is meant for contract tests only");
+}
+
+fn encode_proof(opts: EncodeProofOpts) {
+    let proof = load_proof(&opts.proof, true);
+    let inputs = load_inputs_json_file::<Bn256>(&opts.public);
+    let encoded = if opts.solidity {
+        eth::solidity_literal(&proof, &inputs)
+    } else {
+        format!("0x{}\n", remote::to_hex(&eth::encode_verify_args(&proof, &inputs)))
+    };
+    match opts.output {
+        Some(output) => {
+            or_exit(write_file_atomic(&output, |w| w.write_all(encoded.as_bytes())), "Unable to save encoded proof");
+            println!("Saved encoded proof to {}", output);
+        }
+        None => print!("{}", encoded),
+    }
+}
+
+fn decode_calldata(opts: DecodeCalldataOpts) {
+    check_overwrite(&[opts.proof.as_str(), opts.public.as_str()], opts.force);
+    let hex = if Path::new(&opts.calldata).is_file() {
+        or_exit(fs::read_to_string(&opts.calldata), &format!("Unable to read {}", opts.calldata))
+    } else {
+        opts.calldata.clone()
+    };
+    let calldata = hex_to_bytes(&hex).unwrap_or_else(|| {
+        println!("Calldata is not valid hex");
+        exit(exitcode::DATAERR);
+    });
+    let (proof, inputs) = or_exit(eth::decode_verify_data(&calldata), "Unable to decode calldata");
+    let public = proof::PublicInputs::new(inputs);
+    or_exit(write_proof_files_with_format(&proof, &public, number_format(opts.hex), &opts.proof, &opts.public), "Unable to save proof");
+    println!("Decoded a proof with {} public inputs to {} and {}", public.len(), opts.proof, opts.public);
+}
+
+fn estimate_gas(opts: EstimateGasOpts) {
+    let proof = load_proof(&opts.proof, true);
+    let inputs = load_inputs_json_file::<Bn256>(&opts.public);
+    let calldata = eth::encode_verify_calldata(&proof, &inputs);
+    let gas = eth::verify_gas(&calldata, inputs.len());
+    println!("Transaction:  {} gas", gas.transaction);
+    println!("Calldata:     {} gas ({} bytes)", gas.calldata, calldata.len());
+    println!("Precompiles:  {} gas ({} ecMul, {} ecAdd, pairing check of 4 pairs)", gas.precompiles, inputs.len(), inputs.len());
+    println!("Total:        {} gas, plus execution of the contract code", gas.total());
+    if let Some(contract) = opts.contract {
+        let estimate = eth::estimate_gas(&opts.rpc, &contract, &calldata).unwrap_or_else(|e| {
+            println!("Unable to estimate gas: {}", e);
+            exit(exitcode::UNAVAILABLE);
+        });
+        println!("Node estimate for {}: {} gas", contract, estimate);
+    }
+}
+
+#[cfg(feature = "submit")]
+fn submit(opts: SubmitOpts) {
+    let private_key = std::env::var(&opts.key).unwrap_or_else(|_| {
+        println!("Environment variable {} with the private key is not set", opts.key);
+        exit(exitcode::CONFIG);
+    });
+    let proof = load_proof(&opts.proof, true);
+    let inputs = load_inputs_json_file::<Bn256>(&opts.public);
+    let calldata = eth::encode_verify_calldata(&proof, &inputs);
+    println!("Sending proof to {} on {}...", opts.contract, opts.rpc);
+    let tx_hash = eth::send_transaction(&opts.rpc, &private_key, &opts.contract, calldata).unwrap_or_else(|e| {
+        println!("Unable to send transaction: {}", e);
+        exit(exitcode::UNAVAILABLE);
+    });
+    println!("Transaction: {}", tx_hash);
+    let receipt = eth::wait_for_receipt(&opts.rpc, &tx_hash, std::time::Duration::from_secs(opts.timeout)).unwrap_or_else(|e| {
+        println!("Unable to get transaction receipt: {}", e);
+        exit(exitcode::UNAVAILABLE);
+    });
+    let field = |name: &str| receipt.get(name).and_then(|v| v.as_str()).unwrap_or("?").to_string();
+    let quantity = |name: &str| u64::from_str_radix(field(name).trim_start_matches("0x"), 16).map(|v| v.to_string()).unwrap_or_else(|_| field(name));
+    println!("Block:    {}", quantity("blockNumber"));
+    println!("Gas used: {}", quantity("gasUsed"));
+    if field("status") == "0x1" {
+        println!("Transaction succeeded");
+    } else {
+        println!("Transaction reverted!");
+        exit(exitcode::DATAERR);
     }
 }
 
 fn setup(opts: SetupOpts) {
+    let provenance_file = opts.provenance.clone().unwrap_or_else(|| provenance::provenance_file(&opts.params));
+    #[cfg(feature = "signing")]
+    let sign_key = opts.sign_key.as_ref().map(|key| or_exit(signing::SigningKey::load(key), &format!("Unable to load signing key {}", key)));
+    if !opts.dry_run {
+        check_overwrite(&[opts.params.as_str(), provenance_file.as_str()], opts.force);
+        // fail before the setup instead of after it when the key is missing
+        #[cfg(feature = "encryption")]
+        if opts.encrypt {
+            or_exit(zkutil::encryption::key_from_env(), "Unable to read the params encryption key");
+        }
+        #[cfg(feature = "signing")]
+        if sign_key.is_some() {
+            check_overwrite(&[signing::signature_file(&opts.params).as_str()], opts.force);
+        }
+    }
     let circuit_file = resolve_circuit_file(opts.circuit);
     println!("Loading circuit from {}...", circuit_file);
-    let rng = create_rng();
-    let circuit = CircomCircuit {
-        r1cs: load_r1cs(&circuit_file),
-        witness: None,
-        wire_mapping: None,
-    };
-    println!("Generating trusted setup parameters...");
-    let params = generate_random_parameters(circuit, rng).unwrap();
+    let local_circuit = fetch_artifact(&circuit_file);
+    let mut r1cs = load_r1cs(&local_circuit);
+    if opts.simplify {
+        let stats = r1cs.simplify();
+        println!("Simplified circuit: removed {} constraints and {} terms", stats.constraints, stats.terms);
+    }
+    if opts.dry_run {
+        let estimate = keys::estimate_setup(&r1cs);
+        println!("Constraints:     {} (including public inputs)", estimate.constraints);
+        println!("FFT domain:      2^{} = {}", estimate.domain_size.trailing_zeros(), estimate.domain_size);
+        println!("Points:          {} G1, {} G2", estimate.g1_points, estimate.g2_points);
+        println!("Params size:     {} bytes ({:.1} MiB)", estimate.params_size, estimate.params_size as f64 / (1 << 20) as f64);
+        println!(
+            "Prover memory:   ~{:.1} MiB, ~{:.1} MiB with prove --low-memory",
+            keys::estimate_prover_memory(&r1cs, keys::QueryStorage::Streamed) as f64 / (1 << 20) as f64,
+            keys::estimate_low_memory_prover(&r1cs, keys::QueryStorage::DiskBacked) as f64 / (1 << 20) as f64,
+        );
+        println!("Estimating generation time...");
+        println!("Generation time: ~{}s on {} threads", keys::estimate_setup_time(&estimate).as_secs(), keys::worker_threads());
+        return;
+    }
+    if opts.dev {
+        println!("WARNING: generating INSECURE-DEV parameters from a fixed seed, never use them in production!");
+    }
+    println!("Generating trusted setup parameters on {} threads...", keys::worker_threads());
+    // the circuit file is hashed for the provenance record while the parameters are generated
+    let circuit_sha256 = {
+        let local_circuit = local_circuit.clone();
+        utils::background(move || provenance::file_sha256(&local_circuit))
+    };
+    let metadata = keys::params_metadata(&r1cs);
+    let started_at = provenance::unix_time();
+    let (generation, cpu_time) = (Instant::now(), utils::process_cpu_time());
+    let (params, flags) = keys::setup(r1cs, opts.dev).unwrap();
+    let elapsed = generation.elapsed().as_secs_f64();
+    match (cpu_time, utils::process_cpu_time()) {
+        // CPU time is about what the generation would take on one thread
+        (Some(start), Some(end)) => {
+            let cpu_time = (end - start).as_secs_f64();
+            println!("Generated in {:.1}s using {:.1}s of CPU time, {:.1}x speedup over one thread", elapsed, cpu_time, cpu_time / elapsed);
+        }
+        _ => println!("Generated in {:.1}s", elapsed),
+    }
+    let flags = if opts.simplify { flags | zkutil::file_header::FLAG_SIMPLIFIED } else { flags };
+    #[cfg(feature = "compression")]
+    let flags = if opts.compress { flags | zkutil::file_header::FLAG_ZSTD } else { flags };
+    #[cfg(feature = "encryption")]
+    let flags = if opts.encrypt { flags | zkutil::file_header::FLAG_ENCRYPTED } else { flags };
+    let finished_at = provenance::unix_time();
     println!("Writing to file...");
-    let writer = File::create(&opts.params).unwrap();
-    params.write(writer).unwrap();
-    println!("Saved parameters to {}", opts.params);
+    let params_file = output_file(&opts.params);
+    let params_tmp = atomic_temp_path(&params_file);
+    output_started(&params_tmp);
+    write_params_file_with_metadata(&params, flags, &metadata, &params_file).unwrap();
+    output_done(&params_tmp);
+    let record = provenance::SetupProvenance {
+        zkutil_version: env!("CARGO_PKG_VERSION").to_string(),
+        circuit: circuit_file.clone(),
+        circuit_sha256: circuit_sha256.join().unwrap(),
+        params: opts.params.clone(),
+        params_sha256: provenance::file_sha256(&params_file).unwrap(),
+        entropy: vec![provenance::setup_entropy(opts.dev)],
+        started_at,
+        finished_at,
+        machine: provenance::machine_info(),
+    };
+    let record_file = output_file(&provenance_file);
+    provenance::write_provenance_file(&record, &record_file).unwrap();
+    store_output(&opts.params);
+    store_output(&provenance_file);
+    println!("Saved parameters to {} and provenance record to {}", opts.params, provenance_file);
+    #[cfg(feature = "signing")]
+    if let Some(key) = sign_key {
+        let signature = signing::signature_file(&opts.params);
+        let comment = format!("circuit_sha256:{}", record.circuit_sha256);
+        key.sign_file_to(&params_file, &comment, &output_file(&signature)).unwrap();
+        store_output(&signature);
+        println!("Signed parameters with key {} to {}", signing::key_id_hex(&key.key_id), signature);
+    }
+}
+
+fn setup_many(opts: SetupManyOpts) {
+    let mut outputs = opts.circuits.iter().map(|c| setup_batch::params_file_for(c, &opts.output_dir)).collect::<Vec<_>>();
+    outputs.push(opts.manifest.clone());
+    check_overwrite(&outputs.iter().map(String::as_str).collect::<Vec<_>>(), opts.force);
+    fs::create_dir_all(&opts.output_dir).unwrap();
+    if opts.dev {
+        println!("WARNING: generating INSECURE-DEV parameters from a fixed seed, never use them in production!");
+    }
+    let mut flags = if opts.simplify { zkutil::file_header::FLAG_SIMPLIFIED } else { 0 };
+    #[cfg(feature = "compression")]
+    if opts.compress {
+        flags |= zkutil::file_header::FLAG_ZSTD;
+    }
+    println!("Generating trusted setup parameters for {} circuits, {} at a time...", opts.circuits.len(), opts.jobs.max(1));
+    let results = or_exit(
+        setup_batch::setup_many(&opts.circuits, &opts.output_dir, opts.dev, flags, opts.jobs, |circuit, result| match result {
+            Ok(entry) => println!("Saved {} in {:.1}s", entry.params, entry.seconds),
+            Err(e) => println!("Failed to set up {}: {}", circuit, e),
+        }),
+        "Unable to set up circuits",
+    );
+    let failed = results.iter().filter(|r| r.is_err()).count();
+    let entries = results.into_iter().filter_map(|r| r.ok()).collect::<Vec<_>>();
+    or_exit(setup_batch::write_setup_manifest_file(entries, opts.dev, &opts.manifest), &format!("Unable to write {}", opts.manifest));
+    println!("Set up {} of {} circuits, hashes are in {}", opts.circuits.len() - failed, opts.circuits.len(), opts.manifest);
+    if failed > 0 {
+        exit(exitcode::DATAERR);
+    }
 }
 
 fn generate_verifier(opts: GenerateVerifierOpts) {
-    let params = load_params_file(&opts.params);
-    create_verifier_sol_file(&params, &opts.verifier).unwrap();
-    println!("Created {}", opts.verifier);
+    let verifier = opts.verifier.clone().unwrap_or_else(|| String::from(if opts.huff { "Verifier.huff" } else { "Verifier.sol" }));
+    check_overwrite(&[verifier.as_str()], opts.force);
+    let vk = load_verifying_key(&opts.params);
+    let options = solidity::VerifierOptions { custom_errors: opts.custom_errors };
+    if opts.huff {
+        let test_vector = opts.proof.as_ref().zip(opts.public.as_ref()).map(|(proof, public)| {
+            let (proof, inputs) = (load_proof(proof, true), load_inputs_json_file::<Bn256>(&fetch_artifact(public)));
+            if inputs.len() + 1 != vk.ic.len() || !verify_with_key(&vk, &proof, &inputs).unwrap() {
+                println!("Test vector proof doesn't verify with these parameters!");
+                exit(exitcode::DATAERR);
+            }
+            (proof, inputs)
+        });
+        let test_vector = test_vector.as_ref().map(|(proof, inputs)| (proof, inputs.as_slice()));
+        or_exit(huff::create_verifier_huff_file_for_key(&vk, test_vector, &verifier), "Unable to write verifier");
+    } else {
+        solidity::create_verifier_sol_file_for_key(&vk, options, &verifier).unwrap();
+    }
+    println!("Created {}", verifier);
+}
+
+#[cfg(feature = "evm")]
+fn check_verifier(opts: CheckVerifierOpts) {
+    use zkutil::evm;
+    if opts.proofs.len() != opts.publics.len() {
+        println!("Got {} proof files and {} public input files", opts.proofs.len(), opts.publics.len());
+        exit(exitcode::USAGE);
+    }
+    let vk = load_verifying_key(&opts.params);
+    let source = match (&opts.bytecode, &opts.verifier) {
+        (Some(file), _) => {
+            let hex = or_exit(fs::read_to_string(file), &format!("Unable to read bytecode {}", file));
+            evm::VerifierSource::Bytecode(hex_to_bytes(&hex).unwrap_or_else(|| {
+                println!("{} is not a hex string", file);
+                exit(exitcode::DATAERR);
+            }))
+        }
+        (None, Some(file)) if file.ends_with(".huff") => {
+            println!("Compiling {} with {}...", file, opts.huffc);
+            evm::VerifierSource::Huff { file, huffc: &opts.huffc }
+        }
+        (None, Some(file)) => {
+            println!("Compiling {} with {}...", file, opts.solc);
+            evm::VerifierSource::Solidity { file, solc: &opts.solc }
+        }
+        (None, None) => {
+            println!("Compiling the generated verifier...");
+            if opts.huff {
+                evm::VerifierSource::GeneratedHuff { huffc: &opts.huffc }
+            } else {
+                evm::VerifierSource::GeneratedSolidity { options: solidity::VerifierOptions { custom_errors: opts.custom_errors }, solc: &opts.solc }
+            }
+        }
+    };
+    let code = or_exit(evm::verifier_code(&vk, source), "Unable to compile verifier");
+    let proofs = opts.proofs.iter().zip(opts.publics.iter())
+        .map(|(proof_file, public_file)| (proof_file.clone(), load_proof(proof_file, true), load_inputs_json_file::<Bn256>(&fetch_artifact(public_file))))
+        .collect::<Vec<_>>();
+    let check = or_exit(evm::check_verifier(&vk, &code, &proofs, opts.custom_errors, &mut create_rng()), "Unable to run verifier");
+    let verdict = |accepted: bool| if accepted { "accept" } else { "reject" };
+    for result in &check.results {
+        println!(
+            "{:<48} native: {}  evm: {}{}  gas: {:>7}{}",
+            result.name,
+            verdict(result.expected),
+            verdict(result.accepted),
+            if result.reverted { " (revert)" } else { "         " },
+            result.gas_used,
+            if result.agrees() { "" } else { "  MISMATCH" },
+        );
+    }
+    let calls = check.results.len();
+    if check.mismatches() > 0 {
+        println!("{} of {} calls disagree with native verification!", check.mismatches(), calls);
+        exit(exitcode::DATAERR);
+    }
+    println!("Verifier agrees with native verification in all {} calls", calls);
+    if let Some(failures) = &check.custom_errors {
+        for failure in failures {
+            println!("{}", failure);
+        }
+        if !failures.is_empty() {
+            println!("{} of {} calls get wrong reason codes, errors or events!", failures.len(), calls);
+            exit(exitcode::DATAERR);
+        }
+        println!("tryVerify, custom errors and ProofVerified are right in all {} calls", calls);
+    }
 }
 
 fn export_keys(opts: ExportKeysOpts) {
+    check_overwrite(&[opts.pk.as_str(), opts.vk.as_str()], opts.force);
     println!("Exporting {}...", opts.params);
-    let params = load_params_file(&opts.params);
+    let (header, params) = load_params_with_header(&opts.params);
     let circuit_file = resolve_circuit_file(opts.circuit);
-    let circuit = CircomCircuit {
-        r1cs: load_r1cs(&circuit_file),
-        witness: None,
-        wire_mapping: None,
-    };
-    proving_key_json_file(&params, circuit, &opts.pk).unwrap();
-    verification_key_json_file(&params, &opts.vk).unwrap();
+    let mut r1cs = load_r1cs(&circuit_file);
+    keys::simplify_for_params(header.as_ref(), &mut r1cs);
+    keys::export_keys_with_format(&params, r1cs, number_format(opts.hex), &opts.pk, &opts.vk).unwrap();
     println!("Created {} and {}.", opts.pk, opts.vk);
 }
+
+fn export_raw(opts: ExportRawOpts) {
+    check_overwrite(&[opts.output.as_str()], opts.force);
+    let layout = raw_params::RawLayout { big_endian: opts.big_endian, packed: opts.packed };
+    if let Some(witness_file) = &opts.witness {
+        println!("Exporting {}...", witness_file);
+        let witness = load_witness::<Bn256>(witness_file);
+        or_exit(raw_params::write_raw_witness_file(&witness, layout, &opts.output), "Unable to save raw witness");
+    } else {
+        println!("Exporting {}...", opts.params);
+        let (header, params) = load_params_with_header(&opts.params);
+        let flags = header.map_or(0, |h| h.flags & FLAG_INSECURE_DEV);
+        or_exit(raw_params::write_raw_params_file(&params, flags, layout, &opts.output), "Unable to save raw parameters");
+    }
+    println!("Created {}", opts.output);
+}
+
+fn import_zkey(opts: ImportZkeyOpts) {
+    check_overwrite(&[opts.params.as_str()], opts.force);
+    println!("Importing {}...", opts.zkey);
+    let params = or_exit(zkey::load_zkey(&fetch_artifact(&opts.zkey)), &format!("Unable to load zkey {}", opts.zkey));
+    let metadata = opts.circuit.as_ref().map(|circuit_file| {
+        let r1cs = load_r1cs(circuit_file);
+        if let Err(e) = check_params(&r1cs, &params) {
+            println!("zkey doesn't match the circuit: {}", e);
+            exit(exitcode::DATAERR);
+        }
+        keys::params_metadata(&r1cs)
+    });
+    let result = match &metadata {
+        Some(metadata) => write_params_file_with_metadata(&params, 0, metadata, &output_file(&opts.params)),
+        None => write_params_file(&params, &output_file(&opts.params)),
+    };
+    or_exit(result, "Unable to save parameters");
+    store_output(&opts.params);
+    println!("Created {}", opts.params);
+}
+
+fn prepare_key(opts: PrepareKeyOpts) {
+    check_overwrite(&[opts.output.as_str()], opts.force);
+    let vk = load_verifying_key(&opts.params);
+    let encoded = verifier::encode_prepared_key(&verifier::prepare_key(&vk));
+    write_file_atomic(&opts.output, |w| w.write_all(&encoded)).unwrap();
+    println!("Created {}", opts.output);
+}
+
+fn create_bundle(opts: BundleOpts) {
+    check_overwrite(&[opts.output.as_str()], opts.force);
+    let circuit_file = resolve_circuit_file(opts.circuit);
+    println!("Loading circuit from {}...", circuit_file);
+    let mut r1cs = load_r1cs(&circuit_file);
+    let (header, params) = load_params_with_header(&opts.params);
+    keys::simplify_for_params(header.as_ref(), &mut r1cs);
+    if let Err(e) = check_params(&r1cs, &params) {
+        println!("Parameters in {} don't match circuit {}: {}", opts.params, circuit_file, e);
+        exit(exitcode::DATAERR);
+    }
+    let bundle = Bundle {
+        metadata: BundleMetadata {
+            zkutil_version: env!("CARGO_PKG_VERSION").to_string(),
+            circuit_format: String::from(match detect_format(&circuit_file) {
+                FileFormat::Json => "json",
+                _ => "r1cs",
+            }),
+            num_inputs: r1cs.num_inputs,
+            num_aux: r1cs.num_aux,
+            num_constraints: r1cs.constraints.len(),
+            created: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+        },
+        circuit: fs::read(&circuit_file).unwrap(),
+        params: fs::read(&opts.params).unwrap(),
+        sym: opts.sym.map(|f| fs::read(f).unwrap()),
+    };
+    write_file_atomic(&opts.output, |w| bundle::write(&bundle, w)).unwrap();
+    println!("Saved bundle to {}", opts.output);
+}
+
+fn convert_witness(opts: ConvertWitnessOpts) {
+    check_overwrite(&[opts.output.as_str()], opts.force);
+    println!("Loading witness from {}...", opts.witness);
+    let witness = load_witness::<Bn256>(&opts.witness);
+    witness_to_bin_file::<Bn256>(&witness, &opts.output).unwrap();
+    println!("Saved {} witness values to {}", witness.len(), opts.output);
+}
+
+fn graph_witness(graph_file: &str, input_file: &str) -> Vec<<Bn256 as ScalarEngine>::Fr> {
+    println!("Loading graph from {}...", graph_file);
+    let graph = or_exit(File::open(graph_file).and_then(|f| witness_graph::read(BufReader::new(f))), &format!("Unable to load graph {}", graph_file));
+    let inputs = graph.inputs_from_json::<Bn256, _>(BufReader::new(File::open(input_file).unwrap()))
+        .unwrap_or_else(|e| {
+            println!("Invalid inputs in {}: {}", input_file, e);
+            exit(exitcode::DATAERR);
+        });
+    println!("Calculating witness...");
+    graph.calculate_witness::<Bn256>(&inputs).unwrap_or_else(|e| {
+        println!("Unable to calculate witness: {}", e);
+        exit(exitcode::DATAERR);
+    })
+}
+
+fn calculate_witness(opts: CalculateWitnessOpts) {
+    check_overwrite(&[opts.output.as_str()], opts.force);
+    let witness = graph_witness(&opts.graph, &opts.input);
+    if opts.output.ends_with(".json") {
+        witness_to_json_file::<Bn256>(&witness, &opts.output).unwrap();
+    } else {
+        witness_to_bin_file::<Bn256>(&witness, &opts.output).unwrap();
+    }
+    println!("Saved {} witness values to {}", witness.len(), opts.output);
+}
+
+fn load_signals(circuit: Option<String>, sym: Option<String>) -> Vec<signals::Signal> {
+    let circuit_file = resolve_circuit_file(circuit);
+    let sym_file = sym.or_else(|| Some("circuit.sym".to_string()).filter(|f| Path::new(f).exists()));
+    or_exit(circuit::load_signals(&circuit_file, sym_file.as_deref()), "Unable to load signals")
+}
+
+fn list_signals(opts: SignalsOpts) {
+    println!("{:<8} {:<8} {:<16} name", "wire", "public", "visibility");
+    for signal in load_signals(opts.circuit, opts.sym) {
+        let public = signal.public_index().map(|i| i.to_string()).unwrap_or_else(|| "-".to_string());
+        println!("{:<8} {:<8} {:<16} {}", signal.wire, public, signal.visibility.name(), signal.name);
+    }
+}
+
+fn inspect_params(filename: &str) {
+    let header = or_exit(
+        read_params_header(BufReader::new(or_exit(File::open(filename), "Unable to open parameters"))),
+        &format!("Unable to read {}", filename),
+    ).unwrap_or_else(|| {
+        println!("Legacy params file without header");
+        exit(exitcode::OK);
+    });
+    println!("Version:      {}", header.version);
+    println!("Curve:        {}", zkutil::file_header::curve_name(header.curve));
+    println!("Insecure dev: {}", if header.is_insecure_dev() { "YES, never use in production" } else { "no" });
+    println!("Compression:  {}", if header.is_compressed() { "zstd" } else { "none" });
+    println!("Encryption:   {}", if header.is_encrypted() { "AES-256-GCM" } else { "none" });
+    println!("Simplified:   {}", if header.is_simplified() { "yes, circuits are simplified before proving" } else { "no" });
+    match &header.metadata {
+        Some(metadata) => {
+            println!("Circuit hash: {}", remote::to_hex(&metadata.circuit_hash));
+            println!("Constraints:  {}", metadata.constraints);
+            println!("Created by:   zkutil {}", metadata.zkutil_version);
+            println!("Created at:   {} (unix time)", metadata.created_at);
+        }
+        None => println!("Metadata:     none"),
+    }
+}
+
+fn inspect(opts: InspectOpts) {
+    let filename = &fetch_artifact(&opts.circuit);
+    if detect_format(filename) == FileFormat::Params {
+        inspect_params(filename);
+        return;
+    }
+    let info = or_exit(
+        r1cs_reader::read_info(BufReader::new(or_exit(File::open(filename), "Unable to open circuit"))),
+        &format!("Unable to read {}", filename),
+    );
+    let header = &info.header;
+    println!("Version:      {}", info.version);
+    println!("Field:        {} ({})", header.field_name().unwrap_or("unknown"), header.prime());
+    println!("Wires:        {}", header.n_wires);
+    println!("Outputs:      {}", header.n_pub_out);
+    println!("Public in:    {}", header.n_pub_in);
+    println!("Private in:   {}", header.n_prv_in);
+    println!("Labels:       {}", header.n_labels);
+    println!("Constraints:  {}", header.n_constraints);
+    match &info.wire_mapping {
+        Some(map) => {
+            let removed = header.n_labels.saturating_sub(map.len() as u64);
+            println!("Wire map:     {} wires, {} labels optimized away", map.len(), removed);
+        }
+        None => println!("Wire map:     none"),
+    }
+    for (i, gate) in info.custom_gates.iter().enumerate() {
+        let uses = info.custom_gate_uses.iter().filter(|u| u.gate as usize == i).count();
+        let params = gate.parameters.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(", ");
+        println!("Custom gate:  {}({}) used {} times", gate.name, params, uses);
+    }
+    for (sec_type, size) in &info.extra_sections {
+        println!("Section {}:    {} bytes, unknown", sec_type, size);
+    }
+}
+
+/// Loads a circuit with its witness and picks signals of given visibility to mutate
+fn load_fuzz_target(circuit: Option<String>, witness: Option<String>, sym: Option<String>, visibilities: &[signals::Visibility]) -> FuzzTarget {
+    let circuit_file = resolve_circuit_file(circuit);
+    let witness_file = fetch_artifact(&resolve_witness_file(witness));
+    println!("Loading circuit from {}...", circuit_file);
+    let local_circuit = fetch_artifact(&circuit_file);
+    let sym_file = sym.or_else(|| Some("circuit.sym".to_string()).filter(|f| Path::new(f).exists()));
+    or_exit(FuzzTarget::load(&local_circuit, &witness_file, sym_file.as_deref(), visibilities), "Unable to load circuit and witness")
+}
+
+fn diff_keys(opts: DiffKeysOpts) {
+    let (a, b) = (fetch_artifact(&opts.a), fetch_artifact(&opts.b));
+    let diff = or_exit(keys::diff_keys(&a, &b), "Unable to compare keys");
+    let (a_inputs, b_inputs) = diff.public_inputs;
+    if a_inputs == b_inputs {
+        println!("Public inputs: {}", a_inputs);
+    } else {
+        println!("Public inputs: {} in {}, {} in {}", a_inputs, opts.a, b_inputs, opts.b);
+    }
+    let compared = if diff.proving_key { "verifying and proving keys" } else { "verifying keys" };
+    if diff.is_same() {
+        println!("Same {}", compared);
+        return;
+    }
+    println!("Different {}, differing points:", compared);
+    for difference in &diff.differences {
+        println!("  {}", difference);
+    }
+    exit(exitcode::DATAERR);
+}
+
+fn fuzz_witness(opts: FuzzWitnessOpts) {
+    let visibilities = [signals::Visibility::PublicOutput, signals::Visibility::Private];
+    let target = load_fuzz_target(opts.circuit, opts.witness, opts.sym, &visibilities);
+    let mut fuzzer = or_exit(target.fuzzer(), "Unable to fuzz");
+    println!("Mutating {} signals...", target.wires.len());
+    let findings = fuzzer.run(&target.wires, opts.rounds, &mut create_rng());
+    if findings.is_empty() {
+        println!("No under-constrained signals found");
+        return;
+    }
+    for finding in &findings {
+        println!("Constraints are still satisfied with the same inputs when setting:");
+        for (wire, value) in &finding.wires {
+            println!("  {} (wire {}) = {}", target.signal_name(*wire), wire, repr_to_big(value.into_repr()));
+        }
+    }
+    println!("Found {} under-constrained signal assignments", findings.len());
+    exit(exitcode::DATAERR);
+}
+
+fn soundness_test(opts: SoundnessTestOpts) {
+    check_overwrite(&[opts.report.as_str()], opts.force);
+    let visibilities = [signals::Visibility::PrivateInput, signals::Visibility::Private];
+    let mut target = load_fuzz_target(opts.circuit, opts.witness, opts.sym, &visibilities);
+    let params = opts.params.as_ref().map(|p| load_params_with_header(p));
+    if let Some((header, params)) = &params {
+        keys::simplify_for_params(header.as_ref(), &mut target.r1cs);
+        if let Err(e) = check_params(&target.r1cs, params) {
+            println!("{}", e);
+            exit(exitcode::DATAERR);
+        }
+    }
+    println!("Mutating {} private signals one at a time...", target.wires.len());
+    let report = or_exit(
+        fuzz::soundness_test(&target, params.as_ref().map(|(_, params)| params), opts.rounds, &mut create_rng()),
+        "Unable to run soundness test",
+    );
+    for mutant in &report.undetected {
+        println!("Mutation of {} (wire {}) went undetected", target.signal_name(mutant.wire), mutant.wire);
+    }
+    write_file_atomic(&opts.report, |w| Ok(serde_json::to_writer_pretty(w, &report)?)).unwrap();
+    println!("Tested {} mutants of {} signals, saved report to {}", report.mutants_tested, report.signals_tested, opts.report);
+    if !report.undetected.is_empty() {
+        println!("{} signals are not constrained enough!", report.undetected.len());
+        exit(exitcode::DATAERR);
+    }
+}
+
+fn audit_report(opts: ReportOpts) {
+    check_overwrite(&[opts.output.as_str()], opts.force);
+    let circuit_file = resolve_circuit_file(opts.circuit);
+    println!("Loading circuit from {}...", circuit_file);
+    let local_circuit = fetch_artifact(&circuit_file);
+    let r1cs = load_r1cs(&local_circuit);
+    let local_params = fetch_artifact(&opts.params);
+    let (header, params) = or_exit(keys::load_params(&local_params), &format!("Unable to load parameters {}", opts.params));
+    let sym_file = opts.sym.or_else(|| Some("circuit.sym".to_string()).filter(|f| Path::new(f).exists()));
+    let signals = circuit::load_signals(&local_circuit, sym_file.as_deref()).ok();
+    let params_sha256 = or_exit(provenance::file_sha256(&local_params), "Unable to read parameters");
+    let report = report::AuditReport {
+        circuit: circuit_file,
+        circuit_sha256: or_exit(provenance::file_sha256(&local_circuit), "Unable to read circuit"),
+        stats: report::constraint_stats(&r1cs),
+        public_inputs: report::public_inputs(&r1cs, signals.as_deref()),
+        params: report::params_info(&opts.params, params_sha256, header.as_ref(), &params, &r1cs),
+        toolchain: report::toolchain(),
+    };
+    let content = if opts.output.ends_with(".json") { report.to_json() } else { report.to_markdown() };
+    write_file_atomic(&opts.output, |w| w.write_all(content.as_bytes())).unwrap();
+    println!("Saved report to {}", opts.output);
+}
+
+fn graph(opts: GraphOpts) {
+    check_overwrite(&[opts.output.as_str()], opts.force);
+    let circuit_file = resolve_circuit_file(opts.circuit);
+    println!("Loading circuit from {}...", circuit_file);
+    let r1cs = load_r1cs(&circuit_file);
+    let sym_file = opts.sym.or_else(|| Some("circuit.sym".to_string()).filter(|f| Path::new(f).exists()));
+    let signals = match circuit::load_signals(&circuit_file, sym_file.as_deref()) {
+        Ok(signals) => Some(signals),
+        Err(e) if opts.prefix.is_some() => or_exit(Err(e), "Signal names are required for --prefix"),
+        Err(_) => None,
+    };
+    or_exit(
+        zkutil::dot::write_graph_file(&r1cs, signals.as_deref(), opts.prefix.as_deref(), &opts.output),
+        "Unable to export graph",
+    );
+    println!("Saved graph to {}, render it with `dot -Tsvg {} -o circuit.svg`", opts.output, opts.output);
+}
+
+fn export_matrices(opts: ExportMatricesOpts) {
+    use zkutil::matrix::{self, Matrix};
+    let extension = if opts.npz { "npz" } else { "mtx" };
+    let outputs = Matrix::all().iter().map(|m| format!("{}.{}.{}", opts.output, m.name(), extension)).collect::<Vec<_>>();
+    check_overwrite(&outputs.iter().map(String::as_str).collect::<Vec<_>>(), opts.force);
+    let circuit_file = resolve_circuit_file(opts.circuit);
+    println!("Loading circuit from {}...", circuit_file);
+    let r1cs = load_r1cs(&circuit_file);
+    for (m, output) in Matrix::all().iter().zip(outputs.iter()) {
+        let result = if opts.npz {
+            matrix::write_npz_file(&r1cs, *m, output)
+        } else {
+            matrix::write_matrix_market_file(&r1cs, *m, output)
+        };
+        or_exit(result, "Unable to export matrices");
+        println!("Saved {} matrix to {}", m.name(), output);
+    }
+}
+
+#[cfg(feature = "zkif")]
+fn export_zkinterface(opts: ExportZkinterfaceOpts) {
+    check_overwrite(&[opts.output.as_str()], opts.force);
+    let circuit_file = resolve_circuit_file(opts.circuit);
+    println!("Loading circuit from {}...", circuit_file);
+    let r1cs = load_r1cs(&circuit_file);
+    let witness = opts.witness.map(|w| load_witness::<Bn256>(&w));
+    or_exit(zkutil::zkif::write_file(&r1cs, witness.as_deref(), &opts.output), "Unable to export circuit");
+    println!("Saved {}", opts.output);
+}
+
+#[cfg(feature = "noir")]
+fn import_acir(opts: ImportAcirOpts) {
+    let mut outputs = vec![opts.circuit.as_str()];
+    if opts.nargo_witness.is_some() {
+        outputs.push(opts.witness.as_str());
+    }
+    check_overwrite(&outputs, opts.force);
+    println!("Loading program from {}...", opts.program);
+    let program = or_exit(zkutil::noir::read_program_file(&opts.program), "Unable to load program");
+    let circuit = or_exit(zkutil::noir::main_circuit(&program), "Unable to import program");
+    let (r1cs, layout) = or_exit(zkutil::noir::import(circuit), "Unable to import program");
+    zkutil::circom_circuit::r1cs_to_json_file(&r1cs, &opts.circuit).unwrap();
+    println!("Saved circuit with {} constraints and {} public inputs to {}", r1cs.constraints.len(), r1cs.num_inputs - 1, opts.circuit);
+    if let Some(nargo_witness) = opts.nargo_witness {
+        let values = or_exit(zkutil::noir::read_witness_file(&nargo_witness), "Unable to load witness");
+        let witness = or_exit(layout.witness(&values), "Unable to convert witness");
+        if let Some(i) = r1cs.unsatisfied_constraint(&witness) {
+            println!("Witness doesn't satisfy constraint {}", i);
+            exit(exitcode::DATAERR);
+        }
+        if opts.witness.ends_with(".json") {
+            witness_to_json_file::<Bn256>(&witness, &opts.witness).unwrap();
+        } else {
+            witness_to_bin_file::<Bn256>(&witness, &opts.witness).unwrap();
+        }
+        println!("Saved {} witness values to {}", witness.len(), opts.witness);
+    }
+}
+
+#[cfg(feature = "signing")]
+fn generate_signing_key(opts: GenerateSigningKeyOpts) {
+    check_overwrite(&[opts.key.as_str(), opts.public.as_str()], opts.force);
+    let key = signing::SigningKey::generate(&mut create_rng());
+    or_exit(key.save(&opts.key), &format!("Unable to write {}", opts.key));
+    or_exit(key.verifying_key().save(&opts.public), &format!("Unable to write {}", opts.public));
+    println!("Generated key {}, keep {} private and distribute {}", signing::key_id_hex(&key.key_id), opts.key, opts.public);
+}
+
+#[cfg(feature = "signing")]
+fn sign_params(opts: SignParamsOpts) {
+    let signature = opts.signature.unwrap_or_else(|| signing::signature_file(&opts.params));
+    check_overwrite(&[signature.as_str()], opts.force);
+    let key = or_exit(signing::SigningKey::load(&opts.key), &format!("Unable to load signing key {}", opts.key));
+    let params_file = fetch_artifact(&opts.params);
+    let comment = format!("zkutil:{}", env!("CARGO_PKG_VERSION"));
+    or_exit(key.sign_file_to(&params_file, &comment, &output_file(&signature)), &format!("Unable to sign {}", opts.params));
+    store_output(&signature);
+    println!("Signed {} with key {} to {}", opts.params, signing::key_id_hex(&key.key_id), signature);
+}
+
+fn input_template(opts: InputTemplateOpts) {
+    check_overwrite(&[opts.output.as_str()], opts.force);
+    let signals = load_signals(opts.circuit, opts.sym);
+    let template = signals::input_template(&signals);
+    fs::write(&opts.output, serde_json::to_string_pretty(&template).unwrap()).unwrap();
+    println!("Saved input template to {}", opts.output);
+    println!("Expected public.json order:");
+    let mut last_wire = None;
+    for signal in signals.iter().filter(|s| s.public_index().is_some()) {
+        // print only the first name of aliased wires
+        if last_wire != Some(signal.wire) {
+            println!("  [{}] {}", signal.public_index().unwrap(), signal.name);
+            last_wire = Some(signal.wire);
+        }
+    }
+}
+
+fn parse_field_elements(values: &[String]) -> Vec<<Bn256 as ScalarEngine>::Fr> {
+    values.iter().map(|v| fr_from_str(v).unwrap_or_else(|| {
+        println!("Invalid field element: {}", v);
+        exit(exitcode::USAGE);
+    })).collect()
+}
+
+fn hash(opts: HashOpts) {
+    match opts.function {
+        HashFunction::Poseidon(o) => {
+            if o.inputs.len() > poseidon::MAX_INPUTS {
+                println!("Poseidon supports at most {} inputs", poseidon::MAX_INPUTS);
+                exit(exitcode::USAGE);
+            }
+            let inputs = parse_field_elements(&o.inputs);
+            println!("{}", repr_to_big(poseidon::poseidon::<Bn256>(&inputs).into_repr()));
+        }
+    }
+}
+
+fn eddsa(opts: EddsaOpts) {
+    match opts.command {
+        EddsaCommand::Keygen => {
+            let mut key = PrivateKey { key: [0u8; 32] };
+            create_rng().fill_bytes(&mut key.key);
+            let public = key.public();
+            println!("Private key: {}", key.key.iter().map(|b| format!("{:02x}", b)).collect::<String>());
+            println!("Ax: {}", repr_to_big(public.x.into_repr()));
+            println!("Ay: {}", repr_to_big(public.y.into_repr()));
+        }
+        EddsaCommand::Sign(o) => {
+            let mut key = PrivateKey { key: [0u8; 32] };
+            match hex_to_bytes(&o.key) {
+                Some(ref bytes) if bytes.len() == 32 => key.key.copy_from_slice(bytes),
+                _ => {
+                    println!("Private key must be 32 bytes hex");
+                    exit(exitcode::USAGE);
+                }
+            }
+            let msg = parse_field_elements(&[o.message])[0];
+            let signature = key.sign_poseidon(&msg);
+            let json = SignatureJson::new(&key.public(), &msg, &signature);
+            println!("{}", serde_json::to_string_pretty(&json).unwrap());
+        }
+    }
+}
+
+fn eval(opts: EvalOpts) {
+    let args = parse_field_elements(&opts.args);
+    match field_eval::eval::<Bn256>(&opts.op, &args) {
+        Ok(result) if opts.op == "hex" => println!("0x{}", repr_to_hex(result.into_repr())),
+        Ok(result) => println!("{}", repr_to_big(result.into_repr())),
+        Err(e) => {
+            println!("Error: {}", e);
+            exit(exitcode::USAGE);
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//! Many proofs, possibly of different circuits, listed in a manifest and proved in one run.
+//!
+//! A manifest is a JSON file like
+//! `{"params": "params.bin", "circuit": "circuit.r1cs", "proofs": [{"witness": "1.wtns", "proof": "1.proof.json", "public": "1.public.json"}]}`
+//! where entries may override `params` and `circuit`. Relative paths are resolved against the
+//! directory of the manifest. Entries are proved grouped by params and circuit, so every pair is
+//! loaded once, and a witness that repeats for the same circuit is proved once.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Result};
+use std::path::Path;
+use std::time::Instant;
+use bellman_ce::{
+    groth16::{Parameters, Proof},
+    pairing::bn256::Bn256,
+};
+use crate::circom_circuit::{create_rng, R1CS};
+use crate::circuit::load_witness;
+use crate::jobs::witness_hash;
+use crate::keys::{circuit_hash, load_prover_pair};
+use crate::proof::{prove_witness, write_proof_files, PublicInputs};
+use crate::utils::write_file_atomic;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Manifest {
+    /// Params of entries that don't set their own
+    #[serde(default)]
+    pub params: Option<String>,
+    /// Circuit of entries that don't set their own
+    #[serde(default)]
+    pub circuit: Option<String>,
+    pub proofs: Vec<ManifestEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ManifestEntry {
+    /// Defaults to the position of the entry in the manifest
+    #[serde(default)]
+    pub id: Option<String>,
+    pub witness: String,
+    pub proof: String,
+    pub public: String,
+    #[serde(default)]
+    pub params: Option<String>,
+    #[serde(default)]
+    pub circuit: Option<String>,
+}
+
+/// Outcome of a manifest entry in the consolidated results file
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ManifestResult {
+    pub id: String,
+    pub witness: String,
+    pub proof: String,
+    pub public: String,
+    /// Same witness and circuit as an earlier entry, whose proof was written again
+    pub deduplicated: bool,
+    pub seconds: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+fn resolve(base: &Path, path: &str) -> String {
+    base.join(path).to_string_lossy().to_string()
+}
+
+/// Reads a manifest and resolves its paths against the directory it is in
+pub fn load_manifest(filename: &str) -> Result<Manifest> {
+    let mut manifest: Manifest = serde_json::from_reader(BufReader::new(File::open(filename)?))
+        .map_err(|e| Error::new(ErrorKind::InvalidData, format!("invalid manifest {}: {}", filename, e)))?;
+    let base = Path::new(filename).parent().unwrap_or_else(|| Path::new(""));
+    let resolve_opt = |path: &mut Option<String>| *path = path.as_ref().map(|p| resolve(base, p));
+    resolve_opt(&mut manifest.params);
+    resolve_opt(&mut manifest.circuit);
+    for entry in manifest.proofs.iter_mut() {
+        resolve_opt(&mut entry.params);
+        resolve_opt(&mut entry.circuit);
+        entry.witness = resolve(base, &entry.witness);
+        entry.proof = resolve(base, &entry.proof);
+        entry.public = resolve(base, &entry.public);
+    }
+    Ok(manifest)
+}
+
+fn load_pair(params: &str, circuit: &str) -> std::result::Result<(Parameters<Bn256>, R1CS<Bn256>), String> {
+    let (_, params, r1cs) = load_prover_pair(params, circuit).map_err(|e| e.to_string())?;
+    Ok((params, r1cs))
+}
+
+/// Proves all entries of a manifest, with `default_params` and `default_circuit` for entries the
+/// manifest sets neither for. Failures are reported in the results, which are in manifest order,
+/// `on_result` is called as soon as an entry is done
+pub fn run_manifest<F: FnMut(&ManifestResult)>(manifest: &Manifest, default_params: &str, default_circuit: &str, mut on_result: F) -> Vec<ManifestResult> {
+    let mut groups: Vec<((String, String), Vec<usize>)> = vec![];
+    for (i, entry) in manifest.proofs.iter().enumerate() {
+        let params = entry.params.as_deref().or_else(|| manifest.params.as_deref()).unwrap_or(default_params);
+        let circuit = entry.circuit.as_deref().or_else(|| manifest.circuit.as_deref()).unwrap_or(default_circuit);
+        let key = (params.to_string(), circuit.to_string());
+        match groups.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, entries)) => entries.push(i),
+            None => groups.push((key, vec![i])),
+        }
+    }
+    let mut results: Vec<Option<ManifestResult>> = vec![None; manifest.proofs.len()];
+    for ((params, circuit), entries) in groups {
+        // only one pair is in memory at a time
+        let inputs = load_pair(&params, &circuit);
+        let hash = inputs.as_ref().ok().map(|(_, r1cs)| circuit_hash(r1cs));
+        let mut proved: HashMap<[u8; 32], (Proof<Bn256>, PublicInputs)> = HashMap::new();
+        for i in entries {
+            let entry = &manifest.proofs[i];
+            let start = Instant::now();
+            let mut deduplicated = false;
+            let outcome = inputs.as_ref().map_err(|e| e.clone()).and_then(|(params, r1cs)| {
+                let witness = load_witness::<Bn256>(&entry.witness).map_err(|e| format!("unable to load witness {}: {}", entry.witness, e))?;
+                let key = witness_hash(hash.as_ref().unwrap(), &witness);
+                let (proof, public) = match proved.get(&key) {
+                    Some(result) => {
+                        deduplicated = true;
+                        result.clone()
+                    }
+                    None => {
+                        let result = prove_witness(r1cs, params, witness, create_rng()).map_err(|e| format!("unable to prove: {:?}", e))?;
+                        proved.insert(key, result.clone());
+                        result
+                    }
+                };
+                write_proof_files(&proof, &public, &entry.proof, &entry.public).map_err(|e| format!("unable to write proof: {}", e))
+            });
+            let result = ManifestResult {
+                id: entry.id.clone().unwrap_or_else(|| i.to_string()),
+                witness: entry.witness.clone(),
+                proof: entry.proof.clone(),
+                public: entry.public.clone(),
+                deduplicated,
+                seconds: start.elapsed().as_secs_f64(),
+                error: outcome.err(),
+            };
+            on_result(&result);
+            results[i] = Some(result);
+        }
+    }
+    results.into_iter().map(|r| r.expect("every entry is in a group")).collect()
+}
+
+/// Writes the consolidated results of a run as a JSON array
+pub fn write_results_file(results: &[ManifestResult], filename: &str) -> Result<()> {
+    write_file_atomic(filename, |w| serde_json::to_writer_pretty(w, results).map_err(Error::from))
+}
+
+#[test]
+fn manifest_run() {
+    use crate::circom_circuit::{write_params_file, witness_to_bin_file};
+    use crate::test_utils::{multiplier_params, multiplier_witness};
+    let dir = std::env::temp_dir().join(format!("zkutil-manifest-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+    write_params_file(&multiplier_params(), &path("params.bin")).unwrap();
+    let circuit = r#"{"nPubInputs": 1, "nOutputs": 0, "nVars": 4, "constraints": [[{"2": "1"}, {"3": "1"}, {"1": "1"}]]}"#;
+    std::fs::write(path("circuit.json"), circuit).unwrap();
+    for (name, (a, b)) in &[("a.wtns", (2, 3)), ("b.wtns", (3, 2))] {
+        witness_to_bin_file::<Bn256>(&multiplier_witness(*a, *b), &path(name)).unwrap();
+    }
+    let manifest = r#"{"params": "params.bin", "circuit": "circuit.json", "proofs": [
+        {"witness": "a.wtns", "proof": "a.proof.json", "public": "a.public.json"},
+        {"id": "other", "witness": "b.wtns", "proof": "b.proof.json", "public": "b.public.json"},
+        {"witness": "a.wtns", "proof": "c.proof.json", "public": "c.public.json"},
+        {"witness": "a.wtns", "proof": "d.proof.json", "public": "d.public.json", "circuit": "missing.json"}
+    ]}"#;
+    std::fs::write(path("manifest.json"), manifest).unwrap();
+    let manifest = load_manifest(&path("manifest.json")).unwrap();
+    let mut done = 0;
+    let results = run_manifest(&manifest, "unused.bin", "unused.json", |_| done += 1);
+    assert_eq!(done, 4);
+    assert_eq!(results.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(), ["0", "other", "2", "3"]);
+    assert!(results[..3].iter().all(|r| r.error.is_none()));
+    assert!(results[2].deduplicated && !results[1].deduplicated);
+    assert_eq!(std::fs::read(path("a.proof.json")).unwrap(), std::fs::read(path("c.proof.json")).unwrap());
+    assert!(results[3].error.as_ref().unwrap().contains("missing.json"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,199 @@
+//! Export of the A, B and C constraint matrices in sparse formats.
+//!
+//! Rows are constraints and columns are wires. Coefficients are written as signed representatives
+//! in `(-p/2, p/2]`, so `-1` stays `-1` instead of becoming a 254-bit number.
+//!
+//! Matrix Market files are exact. NPZ files follow `scipy.sparse.save_npz` for COO matrices and can
+//! be loaded with `scipy.sparse.load_npz`. Their data is float64, which is exact only up to 2^53.
+use std::io::{Result, Write};
+use byteorder::{WriteBytesExt, LittleEndian};
+use num_bigint::{BigInt, BigUint, Sign};
+use num_traits::ToPrimitive;
+use bellman_ce::pairing::{
+    bn256::{Bn256, Fr},
+    ff::{Field, PrimeField},
+};
+use crate::circom_circuit::R1CS;
+use crate::utils::{repr_to_biguint, write_file_atomic};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Matrix {
+    A,
+    B,
+    C,
+}
+
+impl Matrix {
+    pub fn all() -> [Matrix; 3] {
+        [Matrix::A, Matrix::B, Matrix::C]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Matrix::A => "A",
+            Matrix::B => "B",
+            Matrix::C => "C",
+        }
+    }
+}
+
+/// Non-zero entries of a matrix as `(row, column, value)`
+pub fn entries(r1cs: &R1CS<Bn256>, matrix: Matrix) -> Vec<(usize, usize, BigInt)> {
+    let modulus = repr_to_biguint(Fr::char());
+    let half = &modulus >> 1;
+    let mut entries = vec![];
+    for (row, (a, b, c)) in r1cs.constraints.iter().enumerate() {
+        let lc = match matrix {
+            Matrix::A => a,
+            Matrix::B => b,
+            Matrix::C => c,
+        };
+        for (col, coeff) in lc.iter().filter(|(_, coeff)| !coeff.is_zero()) {
+            let value: BigUint = repr_to_biguint(coeff.into_repr());
+            let signed = if value > half {
+                BigInt::from_biguint(Sign::Minus, &modulus - value)
+            } else {
+                BigInt::from_biguint(Sign::Plus, value)
+            };
+            entries.push((row, *col, signed));
+        }
+    }
+    entries
+}
+
+pub fn write_matrix_market<W: Write>(r1cs: &R1CS<Bn256>, matrix: Matrix, mut writer: W) -> Result<()> {
+    let entries = entries(r1cs, matrix);
+    writeln!(writer, "%%MatrixMarket matrix coordinate integer general")?;
+    writeln!(writer, "% R1CS matrix {}, rows are constraints and columns are wires", matrix.name())?;
+    writeln!(writer, "% values are signed representatives modulo the BN254 scalar field order")?;
+    writeln!(writer, "{} {} {}", r1cs.constraints.len(), r1cs.num_variables, entries.len())?;
+    for (row, col, value) in entries {
+        writeln!(writer, "{} {} {}", row + 1, col + 1, value)?;
+    }
+    Ok(())
+}
+
+/// NumPy `.npy` file with a header describing `descr` and `shape`
+fn npy(descr: &str, shape: &str, data: &[u8]) -> Vec<u8> {
+    let mut header = format!("{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}", descr, shape);
+    // magic, version and header length take 10 bytes, the whole header is aligned to 64
+    let padding = 63 - (10 + header.len()) % 64;
+    header.push_str(&" ".repeat(padding));
+    header.push('\n');
+    let mut out = b"\x93NUMPY\x01\x00".to_vec();
+    out.write_u16::<LittleEndian>(header.len() as u16).expect("writing to memory doesn't fail");
+    out.extend_from_slice(header.as_bytes());
+    out.extend_from_slice(data);
+    out
+}
+
+fn i64_array(values: impl Iterator<Item = i64>) -> Vec<u8> {
+    let mut out = vec![];
+    for v in values {
+        out.write_i64::<LittleEndian>(v).expect("writing to memory doesn't fail");
+    }
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Uncompressed zip archive, which is what `numpy.savez` writes
+fn write_zip<W: Write>(files: &[(&str, Vec<u8>)], mut writer: W) -> Result<()> {
+    // 1980-01-01 00:00, the earliest date zip can represent
+    const DATE: u16 = (1 << 5) | 1;
+    let mut out = vec![];
+    let mut central = vec![];
+    for (name, data) in files {
+        let offset = out.len() as u32;
+        let crc = crc32(data);
+        out.write_u32::<LittleEndian>(0x0403_4b50)?;
+        for v in &[20, 0, 0, 0, DATE] {
+            out.write_u16::<LittleEndian>(*v)?;
+        }
+        out.write_u32::<LittleEndian>(crc)?;
+        out.write_u32::<LittleEndian>(data.len() as u32)?;
+        out.write_u32::<LittleEndian>(data.len() as u32)?;
+        out.write_u16::<LittleEndian>(name.len() as u16)?;
+        out.write_u16::<LittleEndian>(0)?;
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(data);
+
+        central.write_u32::<LittleEndian>(0x0201_4b50)?;
+        for v in &[20, 20, 0, 0, 0, DATE] {
+            central.write_u16::<LittleEndian>(*v)?;
+        }
+        central.write_u32::<LittleEndian>(crc)?;
+        central.write_u32::<LittleEndian>(data.len() as u32)?;
+        central.write_u32::<LittleEndian>(data.len() as u32)?;
+        for v in &[name.len() as u16, 0, 0, 0, 0] {
+            central.write_u16::<LittleEndian>(*v)?;
+        }
+        central.write_u32::<LittleEndian>(0)?;
+        central.write_u32::<LittleEndian>(offset)?;
+        central.extend_from_slice(name.as_bytes());
+    }
+    let central_offset = out.len() as u32;
+    out.extend_from_slice(&central);
+    out.write_u32::<LittleEndian>(0x0605_4b50)?;
+    for v in &[0, 0, files.len() as u16, files.len() as u16] {
+        out.write_u16::<LittleEndian>(*v)?;
+    }
+    out.write_u32::<LittleEndian>(central.len() as u32)?;
+    out.write_u32::<LittleEndian>(central_offset)?;
+    out.write_u16::<LittleEndian>(0)?;
+    writer.write_all(&out)
+}
+
+pub fn write_npz<W: Write>(r1cs: &R1CS<Bn256>, matrix: Matrix, writer: W) -> Result<()> {
+    let entries = entries(r1cs, matrix);
+    let n = entries.len();
+    let mut data = vec![];
+    for (_, _, value) in &entries {
+        data.write_f64::<LittleEndian>(value.to_f64().unwrap_or(std::f64::NAN))?;
+    }
+    let files = [
+        ("row.npy", npy("<i8", &format!("({},)", n), &i64_array(entries.iter().map(|e| e.0 as i64)))),
+        ("col.npy", npy("<i8", &format!("({},)", n), &i64_array(entries.iter().map(|e| e.1 as i64)))),
+        ("data.npy", npy("<f8", &format!("({},)", n), &data)),
+        ("shape.npy", npy("<i8", "(2,)", &i64_array(vec![r1cs.constraints.len() as i64, r1cs.num_variables as i64].into_iter()))),
+        ("format.npy", npy("|S3", "()", b"coo")),
+    ];
+    write_zip(&files, writer)
+}
+
+pub fn write_matrix_market_file(r1cs: &R1CS<Bn256>, matrix: Matrix, filename: &str) -> Result<()> {
+    write_file_atomic(filename, |w| write_matrix_market(r1cs, matrix, w))
+}
+
+pub fn write_npz_file(r1cs: &R1CS<Bn256>, matrix: Matrix, filename: &str) -> Result<()> {
+    write_file_atomic(filename, |w| write_npz(r1cs, matrix, w))
+}
+
+#[test]
+fn matrix_market() {
+    let fr = |s: &str| Fr::from_str(s).unwrap();
+    let mut minus_two = fr("2");
+    minus_two.negate();
+    let r1cs = R1CS::<Bn256> {
+        num_inputs: 2,
+        num_aux: 1,
+        num_variables: 3,
+        constraints: vec![(vec![(2, fr("3"))], vec![(0, fr("1")), (2, Fr::zero())], vec![(1, minus_two)])],
+    };
+    let mut out = vec![];
+    write_matrix_market(&r1cs, Matrix::C, &mut out).unwrap();
+    let mtx = String::from_utf8(out).unwrap();
+    assert!(mtx.ends_with("1 3 1\n1 2 -2\n"));
+    assert_eq!(entries(&r1cs, Matrix::B).len(), 1);
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    assert_eq!(npy("|S3", "()", b"coo").len() % 64, 3);
+}
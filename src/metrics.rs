@@ -0,0 +1,190 @@
+//! Prometheus metrics of the long running modes, served at `/metrics` in the text exposition format.
+//!
+//! Counters are process wide, so library calls made by `watch`, `consume` and `server::Server`
+//! record into the same registry that `serve` and the server expose.
+use std::io::{BufRead, BufReader, Result, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Upper bounds of histogram buckets in seconds
+const BUCKETS: [f64; 10] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stage {
+    LoadWitness,
+    Prove,
+    Write,
+}
+
+impl Stage {
+    fn all() -> [Stage; 3] {
+        [Stage::LoadWitness, Stage::Prove, Stage::Write]
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Stage::LoadWitness => "load_witness",
+            Stage::Prove => "prove",
+            Stage::Write => "write",
+        }
+    }
+}
+
+struct Histogram {
+    /// Cumulative counts of observations up to each bound of `BUCKETS`
+    buckets: [AtomicU64; 10],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU64 = AtomicU64::new(0);
+
+impl Histogram {
+    const fn new() -> Self {
+        Histogram { buckets: [ZERO; 10], count: ZERO, sum_micros: ZERO }
+    }
+
+    fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        for (bucket, bound) in self.buckets.iter().zip(BUCKETS.iter()) {
+            if seconds <= *bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+struct Metrics {
+    proofs_ok: AtomicU64,
+    proofs_failed: AtomicU64,
+    queue_depth: AtomicU64,
+    jobs_in_progress: AtomicU64,
+    stages: [Histogram; 3],
+}
+
+static METRICS: Metrics = Metrics {
+    proofs_ok: ZERO,
+    proofs_failed: ZERO,
+    queue_depth: ZERO,
+    jobs_in_progress: ZERO,
+    stages: [Histogram::new(), Histogram::new(), Histogram::new()],
+};
+
+fn histogram(stage: Stage) -> &'static Histogram {
+    &METRICS.stages[stage as usize]
+}
+
+/// Runs `f` and records its duration as `stage`
+pub fn time<T, F: FnOnce() -> T>(stage: Stage, f: F) -> T {
+    let start = Instant::now();
+    let result = f();
+    histogram(stage).observe(start.elapsed());
+    result
+}
+
+/// Counts a finished proving job
+pub fn proof_done(ok: bool) {
+    let counter = if ok { &METRICS.proofs_ok } else { &METRICS.proofs_failed };
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Number of jobs waiting to be proved
+pub fn set_queue_depth(depth: usize) {
+    METRICS.queue_depth.store(depth as u64, Ordering::Relaxed);
+}
+
+pub fn job_started() {
+    METRICS.jobs_in_progress.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn job_finished() {
+    METRICS.jobs_in_progress.fetch_sub(1, Ordering::Relaxed);
+}
+
+/// All metrics in the Prometheus text format
+pub fn render() -> String {
+    let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+    let mut out = String::new();
+    out.push_str("# HELP zkutil_proofs_total Proving jobs by result\n# TYPE zkutil_proofs_total counter\n");
+    out.push_str(&format!("zkutil_proofs_total{{result=\"ok\"}} {}\n", load(&METRICS.proofs_ok)));
+    out.push_str(&format!("zkutil_proofs_total{{result=\"error\"}} {}\n", load(&METRICS.proofs_failed)));
+    out.push_str("# HELP zkutil_queue_depth Jobs waiting to be proved\n# TYPE zkutil_queue_depth gauge\n");
+    out.push_str(&format!("zkutil_queue_depth {}\n", load(&METRICS.queue_depth)));
+    out.push_str("# HELP zkutil_jobs_in_progress Jobs being proved\n# TYPE zkutil_jobs_in_progress gauge\n");
+    out.push_str(&format!("zkutil_jobs_in_progress {}\n", load(&METRICS.jobs_in_progress)));
+    out.push_str("# HELP zkutil_stage_seconds Duration of proving stages\n# TYPE zkutil_stage_seconds histogram\n");
+    for stage in Stage::all().iter() {
+        let h = histogram(*stage);
+        for (bucket, bound) in h.buckets.iter().zip(BUCKETS.iter()) {
+            out.push_str(&format!("zkutil_stage_seconds_bucket{{stage=\"{}\",le=\"{}\"}} {}\n", stage.name(), bound, load(bucket)));
+        }
+        out.push_str(&format!("zkutil_stage_seconds_bucket{{stage=\"{}\",le=\"+Inf\"}} {}\n", stage.name(), load(&h.count)));
+        out.push_str(&format!("zkutil_stage_seconds_sum{{stage=\"{}\"}} {}\n", stage.name(), load(&h.sum_micros) as f64 / 1e6));
+        out.push_str(&format!("zkutil_stage_seconds_count{{stage=\"{}\"}} {}\n", stage.name(), load(&h.count)));
+    }
+    out
+}
+
+fn respond(stream: TcpStream) -> Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // the rest of the request must be read, closing a socket with unread data resets the connection
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && header.trim_end() != "" {
+        header.clear();
+    }
+    let path = request.split_whitespace().nth(1).unwrap_or("");
+    let (status, body) = if request.starts_with("GET ") && path.split('?').next() == Some("/metrics") {
+        ("200 OK", render())
+    } else {
+        ("404 Not Found", String::from("not found, metrics are at /metrics\n"))
+    };
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body,
+    )?;
+    stream.flush()
+}
+
+/// Serves `/metrics` at `addr` like `0.0.0.0:9100` from a background thread
+pub fn serve(addr: &str) -> Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let _ = respond(stream);
+        }
+    });
+    Ok(())
+}
+
+#[test]
+fn prometheus_text() {
+    time(Stage::Write, || ());
+    proof_done(true);
+    let text = render();
+    assert!(text.contains("# TYPE zkutil_stage_seconds histogram\n"));
+    assert!(text.contains("zkutil_stage_seconds_bucket{stage=\"write\",le=\"0.1\"} "));
+    assert!(text.contains("zkutil_stage_seconds_bucket{stage=\"prove\",le=\"+Inf\"} "));
+    assert!(!text.contains("zkutil_proofs_total{result=\"ok\"} 0\n"));
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+    serve(&addr.to_string()).unwrap();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let mut response = String::new();
+    std::io::Read::read_to_string(&mut stream, &mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("zkutil_queue_depth "));
+}
@@ -0,0 +1,49 @@
+//! NATS integration: consumes `ProveJob` messages and publishes `ProveResult` replies.
+use std::path::Path;
+use bellman_ce::{
+    groth16::Parameters,
+    pairing::bn256::Bn256,
+};
+use crate::circom_circuit::R1CS;
+use crate::jobs::{ProofCache, ProveJob, ProveResult, WitnessPaths, process_job_cached};
+
+/// Consumes jobs from `subject` until the connection is closed. Results are sent to the
+/// message reply subject if present, and to `result_subject` otherwise.
+/// Anyone who can publish to `subject` is untrusted, so jobs must carry their witness inline.
+pub fn consume(url: &str, subject: &str, queue_group: Option<&str>, result_subject: &str, r1cs: &R1CS<Bn256>, params: &Parameters<Bn256>) -> std::io::Result<()> {
+    consume_with_cache(url, subject, queue_group, result_subject, r1cs, params, None, 0)
+}
+
+/// Same as `consume`, also accepting `witness_path` jobs for files inside `witness_dir` and
+/// answering jobs with a witness proved before from a cache of the last `cache_size` results
+#[allow(clippy::too_many_arguments)]
+pub fn consume_with_cache(
+    url: &str,
+    subject: &str,
+    queue_group: Option<&str>,
+    result_subject: &str,
+    r1cs: &R1CS<Bn256>,
+    params: &Parameters<Bn256>,
+    witness_dir: Option<&Path>,
+    cache_size: usize,
+) -> std::io::Result<()> {
+    let witness_paths = witness_dir.map_or(WitnessPaths::Disabled, WitnessPaths::Within);
+    let mut cache = ProofCache::new(r1cs, cache_size);
+    let connection = nats::connect(url)?;
+    let subscription = match queue_group {
+        Some(group) => connection.queue_subscribe(subject, group)?,
+        None => connection.subscribe(subject)?,
+    };
+    for message in subscription.messages() {
+        let result = match serde_json::from_slice::<ProveJob>(&message.data) {
+            Ok(job) => process_job_cached(r1cs, params, &job, witness_paths, &mut cache),
+            Err(e) => ProveResult { id: String::new(), proof: None, public: None, error: Some(format!("invalid job: {}", e)) },
+        };
+        let payload = serde_json::to_vec(&result).unwrap();
+        match &message.reply {
+            Some(reply) => connection.publish(reply, payload)?,
+            None => connection.publish(result_subject, payload)?,
+        }
+    }
+    Ok(())
+}
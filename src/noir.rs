@@ -0,0 +1,234 @@
+//! Import of Noir programs compiled to ACIR.
+//!
+//! Arithmetic opcodes become R1CS constraints directly and range checks are decomposed into bits.
+//! Brillig calls and directives are unconstrained hints whose results are already in the witness,
+//! so they are skipped. Memory opcodes, calls and other black box functions are not supported.
+//!
+//! Wire 0 is the constant one, public parameters and return values follow in ascending witness
+//! index order, then all other ACIR witnesses and finally the bits of range checks.
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use ::acir::{
+    FieldElement,
+    circuit::{Circuit, Opcode, Program, opcodes::{BlackBoxFuncCall, FunctionInput}},
+    native_types::{Expression, Witness, WitnessMap, WitnessStack},
+};
+use bellman_ce::pairing::{
+    bn256::{Bn256, Fr, FrRepr},
+    ff::{Field, PrimeField, PrimeFieldRepr},
+};
+use crate::circom_circuit::{Constraint, R1CS};
+
+/// The part of a `nargo compile` artifact zkutil needs
+#[derive(Deserialize)]
+struct ProgramArtifact {
+    /// Base64 of gzipped bincode ACIR
+    bytecode: String,
+}
+
+fn invalid_data<E: ToString>(e: E) -> Error {
+    Error::new(ErrorKind::InvalidData, e.to_string())
+}
+
+/// Reads a program from a `nargo compile` JSON artifact or raw ACIR bytecode
+pub fn read_program_file(filename: &str) -> Result<Program> {
+    let mut bytes = fs::read(filename)?;
+    if bytes.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{') {
+        let artifact: ProgramArtifact = serde_json::from_slice(&bytes)?;
+        bytes = base64::decode(artifact.bytecode.trim()).map_err(invalid_data)?;
+    }
+    Program::deserialize_program(&bytes)
+}
+
+/// Reads witness of the main function from a `nargo execute` witness file
+pub fn read_witness_file(filename: &str) -> Result<WitnessMap> {
+    let bytes = fs::read(filename)?;
+    let mut stack = WitnessStack::try_from(&bytes[..]).map_err(invalid_data)?;
+    while let Some(item) = stack.pop() {
+        if item.index == 0 {
+            return Ok(item.witness);
+        }
+    }
+    Err(Error::new(ErrorKind::InvalidData, format!("{} has no witness of the main function", filename)))
+}
+
+/// The only circuit of a program, calls between functions are not supported
+pub fn main_circuit(program: &Program) -> Result<&Circuit> {
+    match program.functions.as_slice() {
+        [circuit] => Ok(circuit),
+        functions => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("program has {} functions, only programs with a single function are supported", functions.len()),
+        )),
+    }
+}
+
+fn to_fr(value: &FieldElement) -> Result<Fr> {
+    let mut repr = FrRepr::default();
+    repr.read_be(&value.to_be_bytes()[..])?;
+    Fr::from_repr(repr).map_err(invalid_data)
+}
+
+/// Where ACIR witnesses and range check bits ended up in the R1CS
+pub struct AcirLayout {
+    /// Wire of each ACIR witness index
+    wires: Vec<usize>,
+    /// Checked witness, number of bits and wire of the lowest bit of each range check
+    ranges: Vec<(Witness, usize, usize)>,
+    pub num_inputs: usize,
+    pub num_variables: usize,
+}
+
+impl AcirLayout {
+    fn new(num_witnesses: usize, public: &BTreeSet<Witness>) -> Result<Self> {
+        let mut wires = vec![0; num_witnesses];
+        let mut next = 1;
+        for w in public {
+            let index = w.witness_index() as usize;
+            if index >= num_witnesses {
+                return Err(Error::new(ErrorKind::InvalidData, format!("public witness {} is out of range", index)));
+            }
+            wires[index] = next;
+            next += 1;
+        }
+        let num_inputs = next;
+        for index in 0..num_witnesses {
+            if !public.contains(&Witness(index as u32)) {
+                wires[index] = next;
+                next += 1;
+            }
+        }
+        Ok(AcirLayout { wires, ranges: vec![], num_inputs, num_variables: next })
+    }
+
+    fn wire(&self, w: &Witness) -> Result<usize> {
+        self.wires.get(w.witness_index() as usize).copied()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("witness {} is out of range", w.witness_index())))
+    }
+
+    /// `expr = 0` as `A * B = C`, with at most one multiplication in `expr`
+    fn constraint(&self, expr: &Expression) -> Result<Constraint<Bn256>> {
+        let mut lc = expr.linear_combinations.iter()
+            .map(|(coeff, w)| Ok((self.wire(w)?, to_fr(coeff)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let q_c = to_fr(&expr.q_c)?;
+        if !q_c.is_zero() {
+            lc.push((0, q_c));
+        }
+        match expr.mul_terms.as_slice() {
+            [] => Ok((lc, vec![(0, Fr::one())], vec![])),
+            [(q, l, r)] => {
+                for (_, coeff) in lc.iter_mut() {
+                    coeff.negate();
+                }
+                Ok((vec![(self.wire(l)?, to_fr(q)?)], vec![(self.wire(r)?, Fr::one())], lc))
+            }
+            terms => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("expression has {} multiplications, recompile with a bounded --expression-width", terms.len()),
+            )),
+        }
+    }
+
+    /// Bits `b` of `input` with `b * (b - 1) = 0` and `input * 1 = sum(2^i * b_i)`
+    fn range(&mut self, input: &FunctionInput, constraints: &mut Vec<Constraint<Bn256>>) -> Result<()> {
+        let bits = input.num_bits as usize;
+        // every field element fits into 254 bits
+        if bits >= Fr::NUM_BITS as usize {
+            return Ok(());
+        }
+        let wire = self.wire(&input.witness)?;
+        let first = self.num_variables;
+        let mut minus_one = Fr::one();
+        minus_one.negate();
+        let mut sum = vec![];
+        let mut power = Fr::one();
+        for bit in first..first + bits {
+            constraints.push((vec![(bit, Fr::one())], vec![(bit, Fr::one()), (0, minus_one)], vec![]));
+            sum.push((bit, power));
+            power.double();
+        }
+        constraints.push((vec![(wire, Fr::one())], vec![(0, Fr::one())], sum));
+        self.ranges.push((input.witness, bits, first));
+        self.num_variables += bits;
+        Ok(())
+    }
+
+    /// Full R1CS witness from ACIR witness values, missing values are set to zero
+    pub fn witness(&self, values: &WitnessMap) -> Result<Vec<Fr>> {
+        let mut witness = vec![Fr::zero(); self.num_variables];
+        witness[0] = Fr::one();
+        for (index, wire) in self.wires.iter().enumerate() {
+            if let Some(value) = values.get(&Witness(index as u32)) {
+                witness[*wire] = to_fr(value)?;
+            }
+        }
+        for (input, bits, first) in &self.ranges {
+            let value = witness[self.wire(input)?].into_repr();
+            for i in 0..*bits {
+                if (value.as_ref()[i / 64] >> (i % 64)) & 1 == 1 {
+                    witness[first + i] = Fr::one();
+                }
+            }
+        }
+        Ok(witness)
+    }
+}
+
+/// Converts ACIR opcodes over `num_witnesses` witnesses to R1CS
+pub fn import_opcodes(num_witnesses: usize, public: &BTreeSet<Witness>, opcodes: &[Opcode]) -> Result<(R1CS<Bn256>, AcirLayout)> {
+    let mut layout = AcirLayout::new(num_witnesses, public)?;
+    let mut constraints = vec![];
+    for opcode in opcodes {
+        match opcode {
+            Opcode::AssertZero(expr) => constraints.push(layout.constraint(expr)?),
+            Opcode::BlackBoxFuncCall(BlackBoxFuncCall::RANGE { input }) => layout.range(input, &mut constraints)?,
+            Opcode::Directive(_) | Opcode::BrilligCall { .. } => {}
+            other => return Err(Error::new(ErrorKind::InvalidData, format!("unsupported ACIR opcode: {}", other))),
+        }
+    }
+    let mut r1cs = R1CS {
+        num_inputs: layout.num_inputs,
+        num_aux: layout.num_variables - layout.num_inputs,
+        num_variables: layout.num_variables,
+        constraints,
+    };
+    r1cs.sort_terms();
+    Ok((r1cs, layout))
+}
+
+/// Converts a circuit with its public parameters and return values as public inputs
+pub fn import(circuit: &Circuit) -> Result<(R1CS<Bn256>, AcirLayout)> {
+    let public = circuit.public_parameters.0.union(&circuit.return_values.0).copied().collect();
+    import_opcodes(circuit.current_witness_index as usize + 1, &public, &circuit.opcodes)
+}
+
+#[test]
+fn square_with_range_check() {
+    // w0 * w0 - w1 = 0 with public w1 and w0 < 2^4
+    let square = Expression {
+        mul_terms: vec![(FieldElement::one(), Witness(0), Witness(0))],
+        linear_combinations: vec![(-FieldElement::one(), Witness(1))],
+        q_c: FieldElement::zero(),
+    };
+    let opcodes = vec![
+        Opcode::AssertZero(square),
+        Opcode::BlackBoxFuncCall(BlackBoxFuncCall::RANGE { input: FunctionInput { witness: Witness(0), num_bits: 4 } }),
+    ];
+    let public = vec![Witness(1)].into_iter().collect();
+    let (r1cs, layout) = import_opcodes(2, &public, &opcodes).unwrap();
+    assert_eq!((r1cs.num_inputs, r1cs.num_variables, r1cs.constraints.len()), (2, 7, 6));
+
+    let mut values = WitnessMap::new();
+    values.insert(Witness(0), FieldElement::from(11u128));
+    values.insert(Witness(1), FieldElement::from(121u128));
+    let witness = layout.witness(&values).unwrap();
+    assert_eq!(witness[1], Fr::from_str("121").unwrap());
+    assert_eq!(r1cs.unsatisfied_constraint(&witness), None);
+
+    values.insert(Witness(0), FieldElement::from(17u128));
+    values.insert(Witness(1), FieldElement::from(289u128));
+    assert!(r1cs.unsatisfied_constraint(&layout.witness(&values).unwrap()).is_some());
+}
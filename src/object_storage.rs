@@ -0,0 +1,62 @@
+//! `s3://bucket/key` and `gs://bucket/key` artifact locations, enabled with the `object-storage` feature.
+//!
+//! S3 credentials and region are taken from the standard AWS environment variables and profile,
+//! `AWS_ENDPOINT` selects an S3 compatible endpoint. GCS is accessed through its S3 compatible
+//! XML API using HMAC keys passed in the same variables.
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Result, Write};
+use std::path::Path;
+use s3::{bucket::Bucket, creds::Credentials, region::Region};
+
+const GCS_ENDPOINT: &str = "https://storage.googleapis.com";
+
+pub fn is_object_storage(location: &str) -> bool {
+    location.starts_with("s3://") || location.starts_with("gs://")
+}
+
+fn other<E: std::fmt::Display>(e: E) -> Error {
+    Error::new(ErrorKind::Other, e.to_string())
+}
+
+/// Splits location into bucket handle and object key
+fn open(location: &str) -> Result<(Bucket, String)> {
+    let (scheme, rest) = location.split_at(5);
+    let (bucket_name, key) = rest.split_once('/')
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("{} has no object key", location)))?;
+    let region = match (scheme, std::env::var("AWS_ENDPOINT")) {
+        ("gs://", _) => Region::Custom { region: String::from("auto"), endpoint: String::from(GCS_ENDPOINT) },
+        (_, Ok(endpoint)) => Region::Custom {
+            region: std::env::var("AWS_REGION").unwrap_or_else(|_| String::from("us-east-1")),
+            endpoint,
+        },
+        (_, Err(_)) => std::env::var("AWS_REGION").unwrap_or_else(|_| String::from("us-east-1")).parse().map_err(other)?,
+    };
+    let credentials = Credentials::default().map_err(other)?;
+    let bucket = Bucket::new(bucket_name, region, credentials).map_err(other)?;
+    Ok((bucket, key.to_string()))
+}
+
+fn check_status(location: &str, status: u16) -> Result<()> {
+    match status {
+        200..=299 => Ok(()),
+        404 => Err(Error::new(ErrorKind::NotFound, format!("{} not found", location))),
+        _ => Err(other(format!("{} request failed with HTTP status {}", location, status))),
+    }
+}
+
+/// Streams object into a local file
+pub fn download(location: &str, path: &Path) -> Result<()> {
+    let (bucket, key) = open(location)?;
+    let mut writer = BufWriter::new(File::create(path)?);
+    let status = bucket.get_object_stream_blocking(&key, &mut writer).map_err(other)?;
+    writer.flush()?;
+    check_status(location, status)
+}
+
+/// Uploads a local file, large files are sent with multipart upload
+pub fn upload(path: &Path, location: &str) -> Result<()> {
+    let (bucket, key) = open(location)?;
+    let mut reader = BufReader::new(File::open(path)?);
+    let status = bucket.put_object_stream_blocking(&mut reader, &key).map_err(other)?;
+    check_status(location, status)
+}
@@ -0,0 +1,296 @@
+//! Section-wise reading of params files.
+//!
+//! After the header a params file holds the verifying key and the H, L, A, B in G1 and B in G2
+//! queries, each a big-endian u32 length followed by uncompressed points. `ParamsFile` reads the
+//! verifying key and the query offsets upfront and a query only when the prover asks for it.
+//! Zero points are dropped while reading, like `filter_params` does for loaded parameters, so
+//! proving doesn't need a second, filtered copy of the whole parameters in memory.
+//!
+//! `ParamsFile::disk_backed` doesn't keep queries in memory at all: every multiexp window reads
+//! its bases from the file again, which is slower, but lets proving keys larger than RAM be used.
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Read, Result, Seek, SeekFrom};
+use std::marker::PhantomData;
+use std::sync::Arc;
+use byteorder::{BigEndian, ReadBytesExt};
+use bellman_ce::{
+    SynthesisError,
+    groth16::{ParameterSource, VerifyingKey},
+    source::{Source, SourceBuilder},
+    pairing::{
+        CurveAffine,
+        CurveProjective,
+        EncodedPoint,
+        bn256::{Bn256, G1Affine, G2Affine},
+    },
+};
+use crate::circom_circuit::{R1CS, check_query_sizes, decoding_error, read_params_header, read_verifying_key_with_header};
+use crate::file_header::FileHeader;
+
+/// Offset of the first point and number of points of a query
+#[derive(Clone, Copy, Debug)]
+struct Section {
+    offset: u64,
+    len: usize,
+}
+
+pub struct ParamsFile {
+    filename: Arc<String>,
+    header: Option<FileHeader>,
+    vk: VerifyingKey<Bn256>,
+    checked: bool,
+    h: Section,
+    l: Section,
+    a: Section,
+    b_g1: Section,
+    b_g2: Section,
+}
+
+impl ParamsFile {
+    /// Whether params in `filename` can be opened section-wise, compressed and encrypted ones can't
+    pub fn is_sectioned(filename: &str) -> Result<bool> {
+        let header = read_params_header(BufReader::new(File::open(filename)?))?;
+        Ok(header.map_or(true, |h| !h.is_compressed() && !h.is_encrypted()))
+    }
+
+    /// Reads the header, verifying key and query offsets of an uncompressed, unencrypted params file.
+    /// Without `checked` query points are not checked to be on the curve and in the right subgroup.
+    pub fn open(filename: &str, checked: bool) -> Result<Self> {
+        let mut reader = BufReader::new(File::open(filename)?);
+        if let Some(header) = read_params_header(&mut reader)? {
+            // checked before the verifying key, which can't be read from encrypted params without the key
+            if header.is_compressed() || header.is_encrypted() {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("{} is compressed or encrypted and can't be read section-wise", filename)));
+            }
+        }
+        reader.seek(SeekFrom::Start(0))?;
+        let (header, vk) = read_verifying_key_with_header(&mut reader)?;
+        let g1_size = <G1Affine as CurveAffine>::Uncompressed::size() as u64;
+        let g2_size = <G2Affine as CurveAffine>::Uncompressed::size() as u64;
+        let mut offset = reader.stream_position()?;
+        let mut section = |point_size: u64| -> Result<Section> {
+            reader.seek(SeekFrom::Start(offset))?;
+            let len = reader.read_u32::<BigEndian>()? as u64;
+            let section = Section { offset: offset + 4, len: len as usize };
+            offset += 4 + len * point_size;
+            Ok(section)
+        };
+        let (h, l, a, b_g1, b_g2) = (section(g1_size)?, section(g1_size)?, section(g1_size)?, section(g1_size)?, section(g2_size)?);
+        if reader.get_ref().metadata()?.len() < offset {
+            return Err(Error::new(ErrorKind::UnexpectedEof, format!("{} is truncated", filename)));
+        }
+        Ok(ParamsFile { filename: Arc::new(filename.to_string()), header, vk, checked, h, l, a, b_g1, b_g2 })
+    }
+
+    pub fn header(&self) -> Option<&FileHeader> {
+        self.header.as_ref()
+    }
+
+    pub fn vk(&self) -> &VerifyingKey<Bn256> {
+        &self.vk
+    }
+
+    /// Checks that the parameters were generated for a circuit of the same shape, see `check_params`
+    pub fn check(&self, r1cs: &R1CS<Bn256>) -> std::result::Result<(), String> {
+        check_query_sizes(r1cs, self.vk.ic.len(), self.l.len, self.h.len)
+    }
+
+    /// Parameter source that reads query points from disk every time a multiexp needs them
+    pub fn disk_backed(&self) -> DiskBacked<'_> {
+        DiskBacked(self)
+    }
+
+    fn read_points<G: CurveAffine>(&self, section: Section, skip_zeros: bool) -> Result<Vec<G>> {
+        let mut reader = self.query::<G>(section, skip_zeros, 0).new();
+        let mut points = Vec::with_capacity(section.len);
+        while let Some(point) = reader.next_point()? {
+            points.push(point);
+        }
+        Ok(points)
+    }
+
+    fn query<G: CurveAffine>(&self, section: Section, skip_zeros: bool, start: usize) -> DiskQuery<G> {
+        DiskQuery { filename: self.filename.clone(), section, checked: self.checked, skip_zeros, start, point: PhantomData }
+    }
+}
+
+/// Query of a params file, bases are read from disk whenever a source is created from it
+#[derive(Clone)]
+pub struct DiskQuery<G> {
+    filename: Arc<String>,
+    section: Section,
+    checked: bool,
+    skip_zeros: bool,
+    /// Number of bases to skip, e.g. public inputs of the A query
+    start: usize,
+    point: PhantomData<G>,
+}
+
+pub struct DiskQuerySource<G: CurveAffine> {
+    reader: Result<BufReader<File>>,
+    remaining: usize,
+    checked: bool,
+    skip_zeros: bool,
+    repr: G::Uncompressed,
+}
+
+impl<G: CurveAffine> DiskQuerySource<G> {
+    /// Next point, zero points are skipped if the query is filtered
+    fn next_point(&mut self) -> Result<Option<G>> {
+        let reader = self.reader.as_mut().map_err(|e| Error::new(e.kind(), e.to_string()))?;
+        while self.remaining > 0 {
+            self.remaining -= 1;
+            reader.read_exact(self.repr.as_mut())?;
+            let point = if self.checked { self.repr.into_affine() } else { self.repr.into_affine_unchecked() }
+                .map_err(|e| Error::new(ErrorKind::InvalidData, decoding_error(&e)))?;
+            if !(self.skip_zeros && point.is_zero()) {
+                return Ok(Some(point));
+            }
+        }
+        Ok(None)
+    }
+
+    fn expect_point(&mut self) -> std::result::Result<G, SynthesisError> {
+        self.next_point()?.ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "expected more bases from source").into())
+    }
+}
+
+impl<G: CurveAffine> SourceBuilder<G> for DiskQuery<G> {
+    type Source = DiskQuerySource<G>;
+
+    fn new(self) -> DiskQuerySource<G> {
+        let section = self.section;
+        let reader = File::open(self.filename.as_str()).and_then(|file| {
+            let mut reader = BufReader::new(file);
+            reader.seek(SeekFrom::Start(section.offset))?;
+            Ok(reader)
+        });
+        let mut source = DiskQuerySource {
+            reader,
+            remaining: section.len,
+            checked: self.checked,
+            skip_zeros: self.skip_zeros,
+            repr: G::Uncompressed::empty(),
+        };
+        // a failure is kept in the reader and reported by the first read
+        for _ in 0..self.start {
+            match source.next_point() {
+                Ok(Some(_)) => {}
+                Ok(None) => break,
+                Err(e) => {
+                    source.reader = Err(e);
+                    break;
+                }
+            }
+        }
+        source
+    }
+}
+
+impl<G: CurveAffine> Source<G> for DiskQuerySource<G> {
+    fn add_assign_mixed(&mut self, to: &mut G::Projective) -> std::result::Result<(), SynthesisError> {
+        let point = self.expect_point()?;
+        if point.is_zero() {
+            return Err(SynthesisError::UnexpectedIdentity);
+        }
+        to.add_assign_mixed(&point);
+        Ok(())
+    }
+
+    fn skip(&mut self, amt: usize) -> std::result::Result<(), SynthesisError> {
+        for _ in 0..amt {
+            self.expect_point()?;
+        }
+        Ok(())
+    }
+}
+
+/// See `ParamsFile::disk_backed`
+pub struct DiskBacked<'a>(&'a ParamsFile);
+
+impl<'a> ParameterSource<Bn256> for DiskBacked<'a> {
+    type G1Builder = DiskQuery<G1Affine>;
+    type G2Builder = DiskQuery<G2Affine>;
+
+    fn get_vk(&mut self, num_ic: usize) -> std::result::Result<VerifyingKey<Bn256>, SynthesisError> {
+        ParameterSource::get_vk(&mut self.0, num_ic)
+    }
+
+    fn get_h(&mut self, _: usize) -> std::result::Result<Self::G1Builder, SynthesisError> {
+        Ok(self.0.query(self.0.h, true, 0))
+    }
+
+    fn get_l(&mut self, _: usize) -> std::result::Result<Self::G1Builder, SynthesisError> {
+        Ok(self.0.query(self.0.l, false, 0))
+    }
+
+    fn get_a(&mut self, num_inputs: usize, _: usize) -> std::result::Result<(Self::G1Builder, Self::G1Builder), SynthesisError> {
+        Ok((self.0.query(self.0.a, true, 0), self.0.query(self.0.a, true, num_inputs)))
+    }
+
+    fn get_b_g1(&mut self, num_inputs: usize, _: usize) -> std::result::Result<(Self::G1Builder, Self::G1Builder), SynthesisError> {
+        Ok((self.0.query(self.0.b_g1, true, 0), self.0.query(self.0.b_g1, true, num_inputs)))
+    }
+
+    fn get_b_g2(&mut self, num_inputs: usize, _: usize) -> std::result::Result<(Self::G2Builder, Self::G2Builder), SynthesisError> {
+        Ok((self.0.query(self.0.b_g2, true, 0), self.0.query(self.0.b_g2, true, num_inputs)))
+    }
+}
+
+impl<'a> ParameterSource<Bn256> for &'a ParamsFile {
+    type G1Builder = (Arc<Vec<G1Affine>>, usize);
+    type G2Builder = (Arc<Vec<G2Affine>>, usize);
+
+    fn get_vk(&mut self, _: usize) -> std::result::Result<VerifyingKey<Bn256>, SynthesisError> {
+        let mut vk = self.vk.clone();
+        vk.ic.retain(|p| !p.is_zero());
+        Ok(vk)
+    }
+
+    fn get_h(&mut self, _: usize) -> std::result::Result<Self::G1Builder, SynthesisError> {
+        Ok((Arc::new(self.read_points(self.h, true)?), 0))
+    }
+
+    fn get_l(&mut self, _: usize) -> std::result::Result<Self::G1Builder, SynthesisError> {
+        Ok((Arc::new(self.read_points(self.l, false)?), 0))
+    }
+
+    fn get_a(&mut self, num_inputs: usize, _: usize) -> std::result::Result<(Self::G1Builder, Self::G1Builder), SynthesisError> {
+        let a = Arc::new(self.read_points(self.a, true)?);
+        Ok(((a.clone(), 0), (a, num_inputs)))
+    }
+
+    fn get_b_g1(&mut self, num_inputs: usize, _: usize) -> std::result::Result<(Self::G1Builder, Self::G1Builder), SynthesisError> {
+        let b_g1 = Arc::new(self.read_points(self.b_g1, true)?);
+        Ok(((b_g1.clone(), 0), (b_g1, num_inputs)))
+    }
+
+    fn get_b_g2(&mut self, num_inputs: usize, _: usize) -> std::result::Result<(Self::G2Builder, Self::G2Builder), SynthesisError> {
+        let b_g2 = Arc::new(self.read_points(self.b_g2, true)?);
+        Ok(((b_g2.clone(), 0), (b_g2, num_inputs)))
+    }
+}
+
+#[test]
+fn sections_match_loaded_params() {
+    use crate::circom_circuit::{create_insecure_dev_rng, filter_params, verify, write_params};
+    use crate::proof::prove_witness_streamed;
+    use crate::test_utils::{multiplier_circuit, multiplier_params, multiplier_witness};
+    let (r1cs, params) = (multiplier_circuit(), multiplier_params());
+    let filename = std::env::temp_dir().join(format!("zkutil-params-file-{}.bin", std::process::id())).to_string_lossy().to_string();
+    write_params(&params, File::create(&filename).unwrap()).unwrap();
+    let file = ParamsFile::open(&filename, true).unwrap();
+    assert!(file.check(&r1cs).is_ok());
+    assert!(*file.vk() == params.vk);
+
+    let mut filtered = params.clone();
+    filter_params(&mut filtered);
+    let mut source = &file;
+    assert!(source.get_h(0).unwrap().0 == filtered.h);
+    assert!(source.get_l(0).unwrap().0 == filtered.l);
+    assert!((source.get_b_g2(2, 2).unwrap().1).0 == filtered.b_g2);
+
+    let (proof, public) = prove_witness_streamed(&r1cs, file.disk_backed(), multiplier_witness(2, 3), create_insecure_dev_rng()).unwrap();
+    assert!(verify(&params, &proof, public.values()).unwrap());
+    std::fs::remove_file(&filename).unwrap();
+}
@@ -0,0 +1,158 @@
+//! Poseidon hash over the BN254 scalar field, compatible with circomlib `Poseidon(nInputs)`.
+//!
+//! Round constants and MDS matrices are derived with the Grain LFSR from the
+//! Poseidon reference implementation, which is how circomlib constants were generated.
+use num_bigint::BigUint;
+use bellman_ce::pairing::{
+    Engine,
+    ff::{Field, PrimeField},
+};
+use crate::utils::repr_to_biguint;
+
+const FULL_ROUNDS: usize = 8;
+/// Number of partial rounds for state width t = 2..17, as in circomlib
+const PARTIAL_ROUNDS: [usize; 16] = [56, 57, 56, 60, 60, 63, 64, 63, 60, 66, 60, 65, 70, 60, 64, 68];
+pub const MAX_INPUTS: usize = PARTIAL_ROUNDS.len();
+
+struct Grain {
+    state: Vec<bool>,
+}
+
+impl Grain {
+    fn new(field_bits: usize, t: usize, full_rounds: usize, partial_rounds: usize) -> Self {
+        let mut state = Vec::with_capacity(80);
+        // prime field, x^5 S-box
+        for (value, bits) in [(1, 2), (0, 4), (field_bits, 12), (t, 12), (full_rounds, 10), (partial_rounds, 10)].iter() {
+            for i in (0..*bits).rev() {
+                state.push((value >> i) & 1 == 1);
+            }
+        }
+        state.extend(std::iter::repeat(true).take(30));
+        let mut grain = Grain { state };
+        for _ in 0..160 {
+            grain.step();
+        }
+        grain
+    }
+
+    fn step(&mut self) -> bool {
+        let s = &self.state;
+        let bit = s[62] ^ s[51] ^ s[38] ^ s[23] ^ s[13] ^ s[0];
+        self.state.remove(0);
+        self.state.push(bit);
+        bit
+    }
+
+    /// Self-shrinking output: a pair of bits (1, b) yields b, pairs starting with 0 are dropped
+    fn next_bit(&mut self) -> bool {
+        while !self.step() {
+            self.step();
+        }
+        self.step()
+    }
+
+    fn next_int(&mut self, bits: usize) -> BigUint {
+        let mut value = BigUint::from(0u32);
+        for _ in 0..bits {
+            value = (value << 1) + BigUint::from(self.next_bit() as u32);
+        }
+        value
+    }
+}
+
+fn from_big<E: Engine>(big: &BigUint) -> E::Fr {
+    E::Fr::from_str(&big.to_str_radix(10)).unwrap()
+}
+
+pub struct Poseidon<E: Engine> {
+    t: usize,
+    partial_rounds: usize,
+    constants: Vec<E::Fr>,
+    mds: Vec<Vec<E::Fr>>,
+}
+
+impl<E: Engine> Poseidon<E> {
+    /// Creates a hasher for `num_inputs` field elements
+    pub fn new(num_inputs: usize) -> Self {
+        assert!(num_inputs >= 1 && num_inputs <= MAX_INPUTS, "Poseidon supports 1 to {} inputs", MAX_INPUTS);
+        let t = num_inputs + 1;
+        let partial_rounds = PARTIAL_ROUNDS[t - 2];
+        let field_bits = E::Fr::NUM_BITS as usize;
+        let modulus = repr_to_biguint(E::Fr::char());
+        let mut grain = Grain::new(field_bits, t, FULL_ROUNDS, partial_rounds);
+
+        let constants = (0..(FULL_ROUNDS + partial_rounds) * t).map(|_| {
+            let mut value = grain.next_int(field_bits);
+            while value >= modulus {
+                value = grain.next_int(field_bits);
+            }
+            from_big::<E>(&value)
+        }).collect::<Vec<_>>();
+
+        // Cauchy matrix M[i][j] = 1 / (x_i + y_j)
+        let xy = (0..2 * t).map(|_| from_big::<E>(&grain.next_int(field_bits))).collect::<Vec<_>>();
+        let mds = (0..t).map(|i| (0..t).map(|j| {
+            let mut sum = xy[i];
+            sum.add_assign(&xy[t + j]);
+            sum.inverse().unwrap()
+        }).collect()).collect();
+
+        Poseidon { t, partial_rounds, constants, mds }
+    }
+
+    pub fn hash(&self, inputs: &[E::Fr]) -> E::Fr {
+        assert_eq!(inputs.len(), self.t - 1, "Poseidon hasher was created for a different number of inputs");
+        let mut state = vec![E::Fr::zero()];
+        state.extend_from_slice(inputs);
+        let half_full = FULL_ROUNDS / 2;
+        for round in 0..FULL_ROUNDS + self.partial_rounds {
+            for (i, x) in state.iter_mut().enumerate() {
+                x.add_assign(&self.constants[round * self.t + i]);
+            }
+            if round < half_full || round >= half_full + self.partial_rounds {
+                state.iter_mut().for_each(sbox);
+            } else {
+                sbox(&mut state[0]);
+            }
+            state = self.mds.iter().map(|row| {
+                row.iter().zip(state.iter()).fold(E::Fr::zero(), |mut acc, (m, x)| {
+                    let mut term = *m;
+                    term.mul_assign(x);
+                    acc.add_assign(&term);
+                    acc
+                })
+            }).collect();
+        }
+        state[0]
+    }
+}
+
+fn sbox<F: Field>(x: &mut F) {
+    let x1 = *x;
+    x.square();
+    x.square();
+    x.mul_assign(&x1);
+}
+
+/// Hashes inputs with circomlib compatible Poseidon
+pub fn poseidon<E: Engine>(inputs: &[E::Fr]) -> E::Fr {
+    Poseidon::<E>::new(inputs.len()).hash(inputs)
+}
+
+#[test]
+fn circomlib_vectors() {
+    use bellman_ce::pairing::bn256::{Bn256, Fr};
+    let fr = |s: &str| Fr::from_str(s).unwrap();
+    assert_eq!(
+        poseidon::<Bn256>(&[fr("1")]),
+        fr("18586133768512220936620570745912940619677854269274689475585506675881198879027"),
+    );
+    assert_eq!(
+        poseidon::<Bn256>(&[fr("1"), fr("2")]),
+        fr("7853200120776062878684798364095072458815029376092732009249414926327459813530"),
+    );
+    assert_eq!(
+        poseidon::<Bn256>(&[fr("1"), fr("2"), fr("3"), fr("4")]),
+        fr("18821383157269793795438455681495246036402687001665670618754263018637548127333"),
+    );
+}
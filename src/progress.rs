@@ -0,0 +1,103 @@
+//! Machine readable progress of long running commands as JSON lines.
+//!
+//! Every stage emits a `start` and a `done` event like
+//! `{"event":"start","stage":"prove","percent":35.0,"elapsed":1.52,"eta":2.83}`, the last one has
+//! `"event":"finished"`. Percent is weighted by the expected share of a stage in the total time,
+//! `eta` is extrapolated from the time taken so far and is `null` until the first stage is done.
+use std::fs::OpenOptions;
+use std::io::{Result, Write};
+use std::time::Instant;
+
+pub struct Progress {
+    writer: Option<Box<dyn Write + Send>>,
+    /// Stage names with their weights
+    stages: Vec<(&'static str, u32)>,
+    completed: u32,
+    start: Instant,
+}
+
+impl Progress {
+    /// Progress that emits nothing
+    pub fn disabled() -> Self {
+        Self::to_writer(None, vec![])
+    }
+
+    pub fn to_writer(writer: Option<Box<dyn Write + Send>>, stages: Vec<(&'static str, u32)>) -> Self {
+        Progress { writer, stages, completed: 0, start: Instant::now() }
+    }
+
+    /// Writes events to stderr for `-` and appends them to a file or named pipe otherwise
+    pub fn open(target: &str, stages: Vec<(&'static str, u32)>) -> Result<Self> {
+        let writer: Box<dyn Write + Send> = match target {
+            "-" => Box::new(std::io::stderr()),
+            path => Box::new(OpenOptions::new().append(true).create(true).open(path)?),
+        };
+        Ok(Self::to_writer(Some(writer), stages))
+    }
+
+    fn percent(&self) -> f64 {
+        let total: u32 = self.stages.iter().map(|(_, weight)| weight).sum();
+        if total == 0 {
+            100.0
+        } else {
+            100.0 * self.completed as f64 / total as f64
+        }
+    }
+
+    fn emit(&mut self, event: &str, stage: Option<&str>) {
+        let percent = self.percent();
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let eta = if percent > 0.0 { Some(elapsed * (100.0 - percent) / percent) } else { None };
+        if let Some(writer) = &mut self.writer {
+            let line = serde_json::json!({"event": event, "stage": stage, "percent": percent, "elapsed": elapsed, "eta": eta});
+            // progress is best effort, a closed pipe must not fail the command
+            if writeln!(writer, "{}", line).and_then(|_| writer.flush()).is_err() {
+                self.writer = None;
+            }
+        }
+    }
+
+    pub fn start(&mut self, stage: &'static str) {
+        self.emit("start", Some(stage));
+    }
+
+    pub fn done(&mut self, stage: &'static str) {
+        self.completed += self.stages.iter().filter(|(name, _)| *name == stage).map(|(_, weight)| weight).sum::<u32>();
+        self.emit("done", Some(stage));
+    }
+
+    pub fn finish(&mut self) {
+        self.completed = self.stages.iter().map(|(_, weight)| weight).sum();
+        self.emit("finished", None);
+    }
+}
+
+#[test]
+fn progress_events() {
+    use std::sync::{Arc, Mutex};
+    #[derive(Clone)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+    let out = Shared(Arc::new(Mutex::new(vec![])));
+    let mut progress = Progress::to_writer(Some(Box::new(out.clone())), vec![("load", 1), ("prove", 3)]);
+    progress.start("load");
+    progress.done("load");
+    progress.start("prove");
+    progress.finish();
+    let out = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+    let events = out.lines().map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap()).collect::<Vec<_>>();
+    assert_eq!(events.len(), 4);
+    assert_eq!(events[0]["stage"], "load");
+    assert!(events[0]["eta"].is_null());
+    assert_eq!(events[1]["percent"], 25.0);
+    assert!(events[2]["eta"].is_number());
+    assert_eq!(events[3]["event"], "finished");
+    assert_eq!(events[3]["percent"], 100.0);
+}
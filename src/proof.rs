@@ -0,0 +1,401 @@
+//! Proving circom circuits and reading and writing proof.json and public.json.
+use std::io::{Error, ErrorKind, Read, Result, Seek, Write};
+use rand::Rng;
+use bellman_ce::{
+    SynthesisError,
+    groth16::{ParameterSource, Parameters, Proof, create_random_proof},
+    pairing::{
+        CurveAffine,
+        CurveProjective,
+        ff::{PrimeField, PrimeFieldRepr, ScalarEngine},
+        bn256::{Bn256, Fq, G1, G2},
+    },
+};
+use crate::circom_circuit::{
+    CircomCircuit,
+    R1CS,
+    prove,
+    proof_to_json_file_with_format,
+    proof_to_json_with_format,
+    proof_to_json_with_note,
+    read_proof_json,
+    write_proof_json,
+};
+use crate::circuit::{load_witness, read_witness};
+use crate::signals::Signal;
+use crate::field_json::read_field_array;
+use crate::file_header::{check_curve_name, CURVE_NAME};
+use crate::metrics::{self, Stage};
+use crate::remote::to_hex;
+use crate::utils::{hex_to_bytes, write_file_atomic, NumberFormat};
+
+pub type Fr = <Bn256 as ScalarEngine>::Fr;
+
+/// Public inputs of a proof in public.json order, optionally with signal names
+#[derive(Clone, Debug, PartialEq)]
+pub struct PublicInputs {
+    values: Vec<Fr>,
+    /// Names of the signal at each position, several if signals are aliased; empty if unknown
+    names: Vec<Vec<String>>,
+}
+
+impl PublicInputs {
+    pub fn new(values: Vec<Fr>) -> Self {
+        let names = vec![vec![]; values.len()];
+        PublicInputs { values, names }
+    }
+
+    /// Attaches names of public signals, e.g. from `circuit::load_signals`
+    pub fn with_signals(values: Vec<Fr>, signals: &[Signal]) -> Self {
+        let mut inputs = PublicInputs::new(values);
+        for signal in signals {
+            if let Some(names) = signal.public_index().and_then(|i| inputs.names.get_mut(i)) {
+                names.push(signal.name.clone());
+            }
+        }
+        inputs
+    }
+
+    pub fn values(&self) -> &[Fr] {
+        &self.values
+    }
+
+    pub fn into_values(self) -> Vec<Fr> {
+        self.values
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Position of a signal by its full name (`main.root`) or name without the `main.` prefix (`root`)
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|names| names.iter().any(|n| n == name || n.strip_prefix("main.") == Some(name)))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Fr> {
+        self.position(name).map(|i| &self.values[i])
+    }
+
+    /// Name of the value at `index`, the first one for aliased signals
+    pub fn name(&self, index: usize) -> Option<&str> {
+        self.names.get(index).and_then(|names| names.first()).map(|n| n.as_str())
+    }
+
+    /// Serializes values in public.json format
+    pub fn to_json(&self) -> String {
+        self.to_json_with_format(NumberFormat::Decimal)
+    }
+
+    pub fn to_json_with_format(&self, format: NumberFormat) -> String {
+        serde_json::to_string_pretty(&self.values.iter().map(|x| format.repr(x.into_repr())).collect::<Vec<_>>()).unwrap()
+    }
+
+    /// Reads values from public.json
+    pub fn from_json<R: Read>(reader: R) -> Result<Self> {
+        Ok(PublicInputs::new(read_field_array(reader)?))
+    }
+}
+
+/// Whether y of A is at most (q - 1) / 2, the canonical one of `proof` and its negation
+pub fn is_normalized(proof: &Proof<Bn256>) -> bool {
+    if proof.a.is_zero() {
+        return true;
+    }
+    let mut half = Fq::char();
+    half.div2();
+    proof.a.into_xy_unchecked().1.into_repr() <= half
+}
+
+/// Maps a proof and its negation `(-A, -B, C)`, which is valid for the same inputs, to a single
+/// representative. Other rerandomizations can't be undone without their randomness, so proofs
+/// are unique only up to them and systems should not rely on proof bytes being unique.
+pub fn normalize_proof(proof: &Proof<Bn256>) -> Proof<Bn256> {
+    let mut proof = proof.clone();
+    if !is_normalized(&proof) {
+        proof.a.negate();
+        proof.b.negate();
+    }
+    proof
+}
+
+/// Proves a witness of a parsed circuit, returns the normalized proof and public inputs.
+/// Witness must have a value for every wire, starting with the constant one.
+pub fn prove_witness<R: Rng>(r1cs: &R1CS<Bn256>, params: &Parameters<Bn256>, witness: Vec<Fr>, rng: R) -> std::result::Result<(Proof<Bn256>, PublicInputs), SynthesisError> {
+    let (circuit, public) = witness_circuit(r1cs.clone(), witness)?;
+    Ok((normalize_proof(&prove(circuit, params, rng)?), public))
+}
+
+/// Proves a witness like `prove_witness`, reading queries from a params file as the prover needs them,
+/// `params` is a `&ParamsFile` or `ParamsFile::disk_backed`
+pub fn prove_witness_streamed<P, R>(r1cs: &R1CS<Bn256>, params: P, witness: Vec<Fr>, rng: R) -> std::result::Result<(Proof<Bn256>, PublicInputs), SynthesisError>
+where
+    P: ParameterSource<Bn256>,
+    R: Rng,
+{
+    prove_witness_moved(r1cs.clone(), params, witness, rng)
+}
+
+/// Same as `prove_witness_streamed`, but proves with `r1cs` itself instead of a copy of it
+pub fn prove_witness_moved<P, R>(r1cs: R1CS<Bn256>, params: P, witness: Vec<Fr>, mut rng: R) -> std::result::Result<(Proof<Bn256>, PublicInputs), SynthesisError>
+where
+    P: ParameterSource<Bn256>,
+    R: Rng,
+{
+    let _span = tracing::info_span!("prove", constraints = r1cs.constraints.len()).entered();
+    let (circuit, public) = witness_circuit(r1cs, witness)?;
+    Ok((normalize_proof(&create_random_proof(circuit, params, &mut rng)?), public))
+}
+
+fn witness_circuit(r1cs: R1CS<Bn256>, witness: Vec<Fr>) -> std::result::Result<(CircomCircuit<Bn256>, PublicInputs), SynthesisError> {
+    if witness.len() != r1cs.num_variables {
+        return Err(SynthesisError::AssignmentMissing);
+    }
+    let public = PublicInputs::new(witness[1..r1cs.num_inputs].to_vec());
+    let circuit = CircomCircuit {
+        r1cs,
+        witness: Some(witness),
+        wire_mapping: None,
+    };
+    Ok((circuit, public))
+}
+
+fn proving_error(e: SynthesisError) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("unable to prove: {:?}", e))
+}
+
+/// Writes proof.json and public.json files
+pub fn write_proof_files(proof: &Proof<Bn256>, public: &PublicInputs, proof_file: &str, public_file: &str) -> Result<()> {
+    write_proof_files_with_format(proof, public, NumberFormat::Decimal, proof_file, public_file)
+}
+
+/// Note in the `note` field of dummy proof.json files
+pub const DUMMY_PROOF_NOTE: &str = "DUMMY PROOF from zkutil dummy-proof, it does not verify and is meant for contract tests only";
+
+/// A proof of random curve points with random public inputs, which has the shape of a real proof
+/// but doesn't verify. For tests of contracts that pass proofs on to a verifier
+pub fn dummy_proof<R: Rng>(num_inputs: usize, rng: &mut R) -> (Proof<Bn256>, PublicInputs) {
+    let proof = Proof {
+        a: rng.gen::<G1>().into_affine(),
+        b: rng.gen::<G2>().into_affine(),
+        c: rng.gen::<G1>().into_affine(),
+    };
+    let public = PublicInputs::new((0..num_inputs).map(|_| rng.gen()).collect());
+    (normalize_proof(&proof), public)
+}
+
+/// Writes a dummy proof.json with `DUMMY_PROOF_NOTE` and its public.json
+pub fn write_dummy_proof_files(proof: &Proof<Bn256>, public: &PublicInputs, format: NumberFormat, proof_file: &str, public_file: &str) -> Result<()> {
+    let json = proof_to_json_with_note(proof, format, Some(DUMMY_PROOF_NOTE))?;
+    write_file_atomic(proof_file, |w| w.write_all(json.as_bytes()))?;
+    write_file_atomic(public_file, |w| w.write_all(public.to_json_with_format(format).as_bytes()))
+}
+
+/// Writes proof.json and public.json files with field elements in `format`
+pub fn write_proof_files_with_format(proof: &Proof<Bn256>, public: &PublicInputs, format: NumberFormat, proof_file: &str, public_file: &str) -> Result<()> {
+    proof_to_json_file_with_format(proof, format, proof_file)?;
+    write_file_atomic(public_file, |w| w.write_all(public.to_json_with_format(format).as_bytes()))
+}
+
+/// Proves a witness file and writes the proof and public inputs.
+/// `r1cs` has to be prepared for `params` by `keys::circuit_for_params` or `keys::load_prover_pair`
+pub fn prove_witness_file<R: Rng>(r1cs: &R1CS<Bn256>, params: &Parameters<Bn256>, witness_file: &str, proof_file: &str, public_file: &str, rng: R) -> Result<()> {
+    let witness = metrics::time(Stage::LoadWitness, || load_witness::<Bn256>(witness_file))?;
+    let (proof, public) = metrics::time(Stage::Prove, || prove_witness(r1cs, params, witness, rng)).map_err(proving_error)?;
+    metrics::time(Stage::Write, || write_proof_files(&proof, &public, proof_file, public_file))
+}
+
+/// Proves a witness read from a seekable stream and writes proof and public inputs JSON to the writers
+pub fn prove_witness_reader<R, W1, W2, G>(r1cs: &R1CS<Bn256>, params: &Parameters<Bn256>, witness: R, proof_writer: W1, mut public_writer: W2, rng: G) -> Result<()>
+where
+    R: Read + Seek,
+    W1: Write,
+    W2: Write,
+    G: Rng,
+{
+    let witness = read_witness::<Bn256, R>(witness)?;
+    let (proof, public) = prove_witness(r1cs, params, witness, rng).map_err(proving_error)?;
+    write_proof_json(&proof, proof_writer)?;
+    public_writer.write_all(public.to_json().as_bytes())
+}
+
+/// `format` of packed proof files
+pub const PACKED_PROOF_FORMAT: &str = "zkutil-packed-proof";
+
+/// Proof, public inputs and hashes identifying the circuit and verifying key in a single JSON file,
+/// so proof and public inputs can't get mismatched in transport
+#[derive(Clone)]
+pub struct PackedProof {
+    pub proof: Proof<Bn256>,
+    pub public: PublicInputs,
+    /// `keys::circuit_hash` of the circuit the proof is for
+    pub circuit_hash: Option<[u8; 32]>,
+    /// `keys::verifying_key_hash` of the key the proof verifies with
+    pub vk_hash: [u8; 32],
+}
+
+fn hash_from_json(json: &serde_json::Value, name: &str) -> Result<Option<[u8; 32]>> {
+    match json.get(name).filter(|v| !v.is_null()) {
+        None => Ok(None),
+        Some(value) => {
+            let bytes = value.as_str().and_then(hex_to_bytes).filter(|b| b.len() == 32)
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("{} must be 32 bytes in hex", name)))?;
+            let mut hash = [0u8; 32];
+            hash.copy_from_slice(&bytes);
+            Ok(Some(hash))
+        }
+    }
+}
+
+impl PackedProof {
+    pub fn to_json_with_format(&self, format: NumberFormat) -> Result<String> {
+        let proof: serde_json::Value = serde_json::from_str(&proof_to_json_with_format(&self.proof, format)?)?;
+        let public: serde_json::Value = serde_json::from_str(&self.public.to_json_with_format(format))?;
+        Ok(serde_json::to_string_pretty(&serde_json::json!({
+            "format": PACKED_PROOF_FORMAT,
+            "version": 1,
+            "curve": CURVE_NAME,
+            "circuit_hash": self.circuit_hash.map(|h| to_hex(&h)),
+            "vk_hash": to_hex(&self.vk_hash),
+            "proof": proof,
+            "public": public,
+        }))?)
+    }
+
+    /// Reads a packed proof, with `checked` proof points are validated like `read_proof_json` does
+    pub fn from_json<R: Read>(reader: R, checked: bool) -> Result<Self> {
+        let json: serde_json::Value = serde_json::from_reader(reader)?;
+        if json.get("format").and_then(|f| f.as_str()) != Some(PACKED_PROOF_FORMAT) {
+            return Err(Error::new(ErrorKind::InvalidData, "not a packed proof file"));
+        }
+        if json.get("version").and_then(|v| v.as_u64()) != Some(1) {
+            return Err(Error::new(ErrorKind::InvalidData, "unsupported packed proof version"));
+        }
+        check_curve_name(json.get("curve").and_then(|c| c.as_str()).unwrap_or("unknown"), "Packed proof")?;
+        let part = |name: &str| json.get(name).map(|v| v.to_string()).ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("{} is missing", name)));
+        Ok(PackedProof {
+            proof: read_proof_json(part("proof")?.as_bytes(), checked)?,
+            public: PublicInputs::from_json(part("public")?.as_bytes())?,
+            circuit_hash: hash_from_json(&json, "circuit_hash")?,
+            vk_hash: hash_from_json(&json, "vk_hash")?.ok_or_else(|| Error::new(ErrorKind::InvalidData, "vk_hash is missing"))?,
+        })
+    }
+}
+
+pub fn write_packed_proof_file(packed: &PackedProof, format: NumberFormat, filename: &str) -> Result<()> {
+    let json = packed.to_json_with_format(format)?;
+    write_file_atomic(filename, |w| w.write_all(json.as_bytes()))
+}
+
+pub fn read_packed_proof_file(filename: &str, checked: bool) -> Result<PackedProof> {
+    PackedProof::from_json(std::io::BufReader::new(std::fs::File::open(filename)?), checked)
+}
+
+#[test]
+fn prove_in_memory() {
+    use std::io::Cursor;
+    use crate::circom_circuit::{create_insecure_dev_rng, load_proof_json, load_inputs_json, verify, witness_to_bin};
+    use crate::test_utils::{fr, multiplier_circuit, multiplier_params, multiplier_witness};
+    let (r1cs, params) = (multiplier_circuit(), multiplier_params());
+    let mut rng = create_insecure_dev_rng();
+    let mut witness = vec![];
+    witness_to_bin::<Bn256, _>(&multiplier_witness(2, 3), &mut witness).unwrap();
+    let (mut proof, mut public) = (vec![], vec![]);
+    prove_witness_reader(&r1cs, &params, Cursor::new(witness), &mut proof, &mut public, &mut rng).unwrap();
+    let proof = load_proof_json(&proof[..]);
+    let inputs = load_inputs_json::<Bn256, _>(&public[..]);
+    assert_eq!(inputs, vec![fr("6")]);
+    assert!(verify(&params, &proof, &inputs).unwrap());
+}
+
+#[test]
+fn prove_typed() {
+    use crate::circom_circuit::{create_insecure_dev_rng, verify};
+    use crate::test_utils::{fr, multiplier_circuit, multiplier_params, multiplier_witness};
+    let (r1cs, params) = (multiplier_circuit(), multiplier_params());
+    let mut rng = create_insecure_dev_rng();
+    let (proof, public) = prove_witness(&r1cs, &params, multiplier_witness(4, 5), &mut rng).unwrap();
+    assert_eq!(public.values(), &[fr("20")]);
+    assert!(verify(&params, &proof, public.values()).unwrap());
+    assert!(prove_witness(&r1cs, &params, vec![fr("1")], &mut rng).is_err());
+
+    assert!(is_normalized(&proof));
+    let mut negated = proof.clone();
+    negated.a.negate();
+    negated.b.negate();
+    assert!(verify(&params, &negated, public.values()).unwrap());
+    assert!(!is_normalized(&negated));
+    assert!(normalize_proof(&negated) == proof);
+}
+
+#[test]
+fn deterministic_proofs() {
+    use crate::circom_circuit::{create_seeded_rng, verify};
+    use crate::test_utils::{fr, multiplier_circuit, multiplier_params, multiplier_witness};
+    let (r1cs, params) = (multiplier_circuit(), multiplier_params());
+    let witness = multiplier_witness(2, 3);
+    let prove_seeded = |seed: &str| prove_witness(&r1cs, &params, witness.clone(), create_seeded_rng(seed)).unwrap().0;
+    let proof = prove_seeded("golden");
+    assert!(verify(&params, &proof, &[fr("6")]).unwrap());
+    assert!(prove_seeded("golden") == proof);
+    assert!(prove_seeded("other") != proof);
+}
+
+#[test]
+fn public_inputs() {
+    use crate::signals::Visibility;
+    let fr = |s: &str| Fr::from_str(s).unwrap();
+    let signal = |wire: usize, name: &str, visibility: Visibility| Signal { wire, name: name.to_string(), visibility };
+    let signals = vec![
+        signal(0, "one", Visibility::One),
+        signal(1, "main.out", Visibility::PublicOutput),
+        signal(1, "main.hasher.out", Visibility::PublicOutput),
+        signal(2, "main.root", Visibility::PublicInput),
+        signal(3, "main.secret", Visibility::PrivateInput),
+    ];
+    let inputs = PublicInputs::with_signals(vec![fr("7"), fr("42")], &signals);
+    assert_eq!(inputs.get("root"), Some(&fr("42")));
+    assert_eq!(inputs.get("main.hasher.out"), Some(&fr("7")));
+    assert_eq!(inputs.get("secret"), None);
+    assert_eq!(inputs.name(0), Some("main.out"));
+    let parsed = PublicInputs::from_json(inputs.to_json().as_bytes()).unwrap();
+    assert_eq!(parsed.values(), inputs.values());
+}
+
+#[test]
+fn dummy_proofs() {
+    use crate::circom_circuit::{create_insecure_dev_rng, read_proof_json};
+    let (proof, public) = dummy_proof(3, &mut create_insecure_dev_rng());
+    assert_eq!(public.len(), 3);
+    assert!(is_normalized(&proof));
+    let json = proof_to_json_with_note(&proof, NumberFormat::Decimal, Some(DUMMY_PROOF_NOTE)).unwrap();
+    assert!(json.contains(DUMMY_PROOF_NOTE));
+    // points are on the curve and in the right subgroup
+    assert!(read_proof_json(json.as_bytes(), true).unwrap() == proof);
+}
+
+#[test]
+fn packed_proof() {
+    use crate::circom_circuit::{create_insecure_dev_rng, verify};
+    use crate::keys::{circuit_hash, verifying_key_hash};
+    use crate::test_utils::{fr, multiplier_circuit, multiplier_params, multiplier_witness};
+    let (r1cs, params) = (multiplier_circuit(), multiplier_params());
+    let mut rng = create_insecure_dev_rng();
+    let (proof, public) = prove_witness(&r1cs, &params, multiplier_witness(2, 3), &mut rng).unwrap();
+    let packed = PackedProof { proof, public, circuit_hash: Some(circuit_hash(&r1cs)), vk_hash: verifying_key_hash(&params.vk) };
+    let json = packed.to_json_with_format(NumberFormat::Hex).unwrap();
+    let read = PackedProof::from_json(json.as_bytes(), true).unwrap();
+    assert!(read.proof == packed.proof);
+    assert_eq!(read.public.values(), &[fr("6")]);
+    assert_eq!((read.circuit_hash, read.vk_hash), (packed.circuit_hash, packed.vk_hash));
+    assert!(verify(&params, &read.proof, read.public.values()).unwrap());
+
+    let without_circuit = PackedProof { circuit_hash: None, ..packed };
+    let read = PackedProof::from_json(without_circuit.to_json_with_format(NumberFormat::Decimal).unwrap().as_bytes(), true).unwrap();
+    assert_eq!(read.circuit_hash, None);
+    assert!(PackedProof::from_json(&b"{\"pi_a\": []}"[..], true).is_err());
+}
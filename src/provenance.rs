@@ -0,0 +1,107 @@
+//! Machine-readable provenance records of generated trusted setup parameters.
+//!
+//! A record lists the entropy sources used, when the generation started and finished, a fingerprint
+//! of the machine it ran on and SHA-256 hashes of the circuit and resulting params file, so ceremony
+//! coordinators can tell which parameters came from where.
+use std::fs::{self, File};
+use std::io::{Read, Result, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use sha2::{Digest, Sha256};
+use crate::remote::to_hex;
+use crate::utils::write_file_atomic;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EntropySource {
+    pub name: String,
+    /// Whether this source is unpredictable, false for fixed seeds
+    pub secure: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MachineInfo {
+    pub os: String,
+    pub arch: String,
+    pub hostname: Option<String>,
+    /// SHA-256 of the machine id and hostname, identifies the machine without disclosing them
+    pub fingerprint: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SetupProvenance {
+    pub zkutil_version: String,
+    pub circuit: String,
+    pub circuit_sha256: String,
+    pub params: String,
+    pub params_sha256: String,
+    pub entropy: Vec<EntropySource>,
+    /// Unix timestamps in seconds
+    pub started_at: u64,
+    pub finished_at: u64,
+    pub machine: MachineInfo,
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Entropy source `keys::setup` uses
+pub fn setup_entropy(dev: bool) -> EntropySource {
+    let name = if dev {
+        "fixed seed XorShiftRng (setup --dev)"
+    } else if cfg!(target_os = "wasi") {
+        "ChaChaRng seeded from WASI random_get"
+    } else {
+        "OsRng (operating system CSPRNG)"
+    };
+    EntropySource { name: name.to_string(), secure: !dev }
+}
+
+fn hostname() -> Option<String> {
+    std::env::var("HOSTNAME").ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+}
+
+pub fn machine_info() -> MachineInfo {
+    let hostname = hostname();
+    let machine_id = fs::read_to_string("/etc/machine-id").unwrap_or_default();
+    let mut hasher = Sha256::new();
+    hasher.update(machine_id.trim().as_bytes());
+    hasher.update(b"\n");
+    hasher.update(hostname.as_deref().unwrap_or("").as_bytes());
+    MachineInfo {
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        hostname,
+        fingerprint: to_hex(&hasher.finalize()),
+    }
+}
+
+pub fn file_sha256(filename: &str) -> Result<String> {
+    let mut file = File::open(filename)?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(to_hex(&hasher.finalize()))
+}
+
+/// Default sidecar file of a params file
+pub fn provenance_file(params_file: &str) -> String {
+    format!("{}.provenance.json", params_file)
+}
+
+pub fn write_provenance<W: Write>(provenance: &SetupProvenance, writer: W) -> Result<()> {
+    serde_json::to_writer_pretty(writer, provenance)?;
+    Ok(())
+}
+
+pub fn write_provenance_file(provenance: &SetupProvenance, filename: &str) -> Result<()> {
+    write_file_atomic(filename, |w| write_provenance(provenance, w))
+}
@@ -0,0 +1,222 @@
+//! The proving pipeline of `zkutil prove`: params are read section-wise when the file allows it and
+//! loaded as a whole otherwise, queries can stay on disk, and the proof is optionally checked
+//! against the verifying key before it is returned. Stages are reported to a `Progress`.
+//!
+//! `prove_low_memory` is `prove --low-memory`: it takes the inputs, so the prover gets the only copy
+//! of the circuit and loaded params are filtered in place, and streamed queries stay on disk.
+use std::io::{Error, ErrorKind, Result};
+use std::time::Duration;
+use rand::Rng;
+use bellman_ce::{
+    groth16::{Parameters, Proof, VerifyingKey},
+    pairing::bn256::{Bn256, Fr},
+};
+use crate::circom_circuit::{filter_params, verify_with_key, R1CS};
+use crate::circuit::load_r1cs;
+use crate::file_header::{detect_file_format, FileFormat, FileHeader};
+use crate::keys::{self, QueryStorage};
+use crate::params_file::ParamsFile;
+use crate::progress::Progress;
+use crate::proof::{prove_witness, prove_witness_moved, prove_witness_streamed, PublicInputs};
+use crate::utils;
+
+/// Stages `prove` reports with their weights, `load` is up to the caller
+pub fn progress_stages(check: bool) -> Vec<(&'static str, u32)> {
+    let mut stages = vec![("load", 30), ("load_witness", 5), ("prove", 60)];
+    if check {
+        stages.push(("check", 3));
+    }
+    stages.push(("write", 2));
+    stages
+}
+
+/// Circuit and params checked to match it
+pub enum ProverInputs {
+    /// Params read section-wise as the prover needs them, see `ParamsFile`
+    Streamed { params: ParamsFile, r1cs: R1CS<Bn256> },
+    Loaded { header: Option<FileHeader>, params: Parameters<Bn256>, r1cs: R1CS<Bn256> },
+}
+
+impl ProverInputs {
+    /// Opens params and circuit files. Uncompressed, unencrypted params files are streamed, only
+    /// their verifying key and section offsets are read upfront, other params and bundles are loaded
+    pub fn open(params: &str, circuit: &str) -> Result<Self> {
+        if detect_file_format(params)? != FileFormat::Bundle && ParamsFile::is_sectioned(params)? {
+            // the circuit is parsed while the verifying key and section offsets are read
+            let (params_file, r1cs) = utils::join(|| ParamsFile::open(params, true), || load_r1cs(circuit));
+            let params = params_file?;
+            let r1cs = keys::circuit_for_params(params.header(), r1cs?)?;
+            params.check(&r1cs)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, format!("parameters don't match the circuit: {}", e)))?;
+            return Ok(ProverInputs::Streamed { params, r1cs });
+        }
+        let (header, params, r1cs) = keys::load_prover_pair(params, circuit)?;
+        Ok(ProverInputs::Loaded { header, params, r1cs })
+    }
+
+    /// Loads circuit and params of a bundle
+    pub fn open_bundle(bundle: &str) -> Result<Self> {
+        let (header, params, r1cs) = keys::load_bundle_pair(bundle)?;
+        Ok(ProverInputs::Loaded { header, params, r1cs })
+    }
+
+    pub fn header(&self) -> Option<&FileHeader> {
+        match self {
+            ProverInputs::Streamed { params, .. } => params.header(),
+            ProverInputs::Loaded { header, .. } => header.as_ref(),
+        }
+    }
+
+    pub fn r1cs(&self) -> &R1CS<Bn256> {
+        match self {
+            ProverInputs::Streamed { r1cs, .. } | ProverInputs::Loaded { r1cs, .. } => r1cs,
+        }
+    }
+
+    pub fn verifying_key(&self) -> &VerifyingKey<Bn256> {
+        match self {
+            ProverInputs::Streamed { params, .. } => params.vk(),
+            ProverInputs::Loaded { params, .. } => &params.vk,
+        }
+    }
+
+    pub fn is_streamed(&self) -> bool {
+        matches!(self, ProverInputs::Streamed { .. })
+    }
+
+    /// How queries are kept when proving, disk-backed only for streamed params
+    pub fn storage(&self, disk_backed: bool) -> QueryStorage {
+        match self {
+            ProverInputs::Streamed { .. } if disk_backed => QueryStorage::DiskBacked,
+            ProverInputs::Streamed { .. } => QueryStorage::Streamed,
+            ProverInputs::Loaded { .. } => QueryStorage::Loaded,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ProveOptions {
+    /// Read queries from disk in every multiexp window, ignored for loaded params
+    pub disk_backed: bool,
+    /// Verify the proof against the verifying key, a proof that doesn't verify is an `InvalidData` error
+    pub check: bool,
+}
+
+/// Proves the witness returned by `witness`, which is called when the `load_witness` stage starts
+pub fn prove<W, R>(inputs: &ProverInputs, witness: W, options: ProveOptions, progress: &mut Progress, rng: R) -> Result<(Proof<Bn256>, PublicInputs)>
+where
+    W: FnOnce() -> Result<Vec<Fr>>,
+    R: Rng,
+{
+    progress.start("load_witness");
+    let witness = witness()?;
+    progress.done("load_witness");
+    progress.start("prove");
+    let result = match inputs {
+        ProverInputs::Streamed { params, r1cs } if options.disk_backed => prove_witness_streamed(r1cs, params.disk_backed(), witness, rng),
+        ProverInputs::Streamed { params, r1cs } => prove_witness_streamed(r1cs, params, witness, rng),
+        ProverInputs::Loaded { params, r1cs, .. } => prove_witness(r1cs, params, witness, rng),
+    };
+    let (proof, public) = result.map_err(|e| Error::new(ErrorKind::InvalidData, format!("unable to prove: {}", e)))?;
+    progress.done("prove");
+    if options.check {
+        check_verifies(inputs.verifying_key(), &proof, &public, progress)?;
+    }
+    Ok((proof, public))
+}
+
+/// Same as `prove` with the most memory frugal strategies, see `keys::estimate_low_memory_prover`.
+/// Queries of streamed params are read from disk in every multiexp window, `options.disk_backed`
+/// is implied
+pub fn prove_low_memory<W, R>(inputs: ProverInputs, witness: W, options: ProveOptions, progress: &mut Progress, rng: R) -> Result<(Proof<Bn256>, PublicInputs)>
+where
+    W: FnOnce() -> Result<Vec<Fr>>,
+    R: Rng,
+{
+    let vk = inputs.verifying_key().clone();
+    progress.start("load_witness");
+    let witness = witness()?;
+    progress.done("load_witness");
+    progress.start("prove");
+    let result = match inputs {
+        ProverInputs::Streamed { params, r1cs } => prove_witness_moved(r1cs, params.disk_backed(), witness, rng),
+        ProverInputs::Loaded { mut params, r1cs, .. } => {
+            filter_params(&mut params);
+            prove_witness_moved(r1cs, &params, witness, rng)
+        }
+    };
+    let (proof, public) = result.map_err(|e| Error::new(ErrorKind::InvalidData, format!("unable to prove: {}", e)))?;
+    progress.done("prove");
+    if options.check {
+        check_verifies(&vk, &proof, &public, progress)?;
+    }
+    Ok((proof, public))
+}
+
+fn check_verifies(vk: &VerifyingKey<Bn256>, proof: &Proof<Bn256>, public: &PublicInputs, progress: &mut Progress) -> Result<()> {
+    progress.start("check");
+    if !verify_with_key(vk, proof, public.values()).unwrap_or(false) {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "proof doesn't verify against the verifying key, parameters don't match the circuit or are corrupted",
+        ));
+    }
+    progress.done("check");
+    Ok(())
+}
+
+/// Calls `on_timeout` from a watchdog thread if the process is still running after `timeout`.
+/// Proving can't be interrupted, `on_timeout` is expected to exit the process
+pub fn start_timeout<F: FnOnce() + Send + 'static>(timeout: Duration, on_timeout: F) -> Result<()> {
+    std::thread::Builder::new().name("timeout".to_string()).spawn(move || {
+        std::thread::sleep(timeout);
+        on_timeout();
+    })?;
+    Ok(())
+}
+
+#[test]
+fn prove_from_files() {
+    use crate::circom_circuit::{r1cs_to_json_file, write_params_file};
+    use crate::test_utils::{fr, multiplier_circuit, multiplier_params, multiplier_witness};
+    let dir = std::env::temp_dir().join(format!("zkutil-prover-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+    r1cs_to_json_file(&multiplier_circuit(), &path("circuit.json")).unwrap();
+    write_params_file(&multiplier_params(), &path("params.bin")).unwrap();
+
+    let inputs = ProverInputs::open(&path("params.bin"), &path("circuit.json")).unwrap();
+    assert!(inputs.is_streamed());
+    assert_eq!(inputs.storage(true), QueryStorage::DiskBacked);
+    for disk_backed in [false, true].iter() {
+        let options = ProveOptions { disk_backed: *disk_backed, check: true };
+        let (_, public) = prove(&inputs, || Ok(multiplier_witness(2, 3)), options, &mut Progress::disabled(), crate::circom_circuit::create_rng()).unwrap();
+        assert_eq!(public.values(), &[fr("6")]);
+    }
+    // a witness that doesn't satisfy the circuit gives a proof that doesn't verify
+    let mut witness = multiplier_witness(2, 3);
+    witness[1] = fr("7");
+    let options = ProveOptions { disk_backed: false, check: true };
+    let error = prove(&inputs, || Ok(witness), options, &mut Progress::disabled(), crate::circom_circuit::create_rng()).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+    // low memory proving of streamed and loaded params
+    for loaded in [false, true].iter() {
+        let inputs = if *loaded {
+            let (header, params, r1cs) = keys::load_prover_pair(&path("params.bin"), &path("circuit.json")).unwrap();
+            ProverInputs::Loaded { header, params, r1cs }
+        } else {
+            ProverInputs::open(&path("params.bin"), &path("circuit.json")).unwrap()
+        };
+        let options = ProveOptions { disk_backed: false, check: true };
+        let (_, public) = prove_low_memory(inputs, || Ok(multiplier_witness(2, 3)), options, &mut Progress::disabled(), crate::circom_circuit::create_rng()).unwrap();
+        assert_eq!(public.values(), &[fr("6")]);
+    }
+
+    let mut circuit = multiplier_circuit();
+    circuit.num_inputs = 1;
+    circuit.num_aux = 3;
+    r1cs_to_json_file(&circuit, &path("other.json")).unwrap();
+    assert!(ProverInputs::open(&path("params.bin"), &path("other.json")).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,178 @@
+//! Binary cache of parsed circuits.
+//!
+//! Parsing a large circuit JSON takes longer than proving small circuits, so the parsed
+//! constraint system is stored in a compact binary file, keyed by the SHA-256 of the circuit
+//! file, and reused while its contents don't change. Caches live in the per-user
+//! `ZKUTIL_CACHE_DIR`, `$XDG_CACHE_HOME/zkutil` or `~/.cache/zkutil`, setting `ZKUTIL_NO_CACHE`
+//! disables them.
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Read, Result, Write};
+use std::path::{Path, PathBuf};
+use byteorder::{ReadBytesExt, WriteBytesExt, LittleEndian};
+use sha2::{Digest, Sha256};
+use bellman_ce::pairing::{
+    bn256::Bn256,
+    ff::{Field, PrimeField, PrimeFieldRepr, ScalarEngine},
+};
+use crate::circom_circuit::R1CS;
+use crate::file_header::{FileHeader, CURVE_BN254, read_header_after_magic, write_header};
+use crate::provenance::file_sha256;
+use crate::remote::to_hex;
+use crate::utils::{create_private_dir, user_cache_dir, write_file_atomic};
+
+pub const CACHE_MAGIC: [u8; 4] = [0x7a, 0x6b, 0x63, 0x63]; // magic = "zkcc"
+pub const CACHE_VERSION: u32 = 1;
+
+type Fr = <Bn256 as ScalarEngine>::Fr;
+
+fn write_lc<W: Write>(mut writer: W, lc: &[(usize, Fr)]) -> Result<()> {
+    writer.write_u32::<LittleEndian>(lc.len() as u32)?;
+    for (index, coeff) in lc {
+        writer.write_u32::<LittleEndian>(*index as u32)?;
+        coeff.into_repr().write_le(&mut writer)?;
+    }
+    Ok(())
+}
+
+fn read_lc<R: Read>(mut reader: R, num_variables: usize) -> Result<Vec<(usize, Fr)>> {
+    let len = reader.read_u32::<LittleEndian>()? as usize;
+    let mut lc = Vec::with_capacity(len);
+    for _ in 0..len {
+        let index = reader.read_u32::<LittleEndian>()? as usize;
+        if index >= num_variables {
+            return Err(Error::new(ErrorKind::InvalidData, "wire index out of range"));
+        }
+        let mut repr = Fr::zero().into_repr();
+        repr.read_le(&mut reader)?;
+        lc.push((index, Fr::from_repr(repr).map_err(|e| Error::new(ErrorKind::InvalidData, e))?));
+    }
+    Ok(lc)
+}
+
+pub fn write<W: Write>(r1cs: &R1CS<Bn256>, mut writer: W) -> Result<()> {
+    write_header(&mut writer, &FileHeader { magic: CACHE_MAGIC, version: CACHE_VERSION, curve: CURVE_BN254, flags: 0, metadata: None })?;
+    writer.write_u64::<LittleEndian>(r1cs.num_inputs as u64)?;
+    writer.write_u64::<LittleEndian>(r1cs.num_aux as u64)?;
+    writer.write_u64::<LittleEndian>(r1cs.num_variables as u64)?;
+    writer.write_u64::<LittleEndian>(r1cs.constraints.len() as u64)?;
+    for (a, b, c) in &r1cs.constraints {
+        write_lc(&mut writer, a)?;
+        write_lc(&mut writer, b)?;
+        write_lc(&mut writer, c)?;
+    }
+    writer.flush()
+}
+
+pub fn read<R: Read>(mut reader: R) -> Result<R1CS<Bn256>> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != CACHE_MAGIC {
+        return Err(Error::new(ErrorKind::InvalidData, "not a circuit cache file"));
+    }
+    read_header_after_magic(&mut reader, magic, CACHE_VERSION)?;
+    let num_inputs = reader.read_u64::<LittleEndian>()? as usize;
+    let num_aux = reader.read_u64::<LittleEndian>()? as usize;
+    let num_variables = reader.read_u64::<LittleEndian>()? as usize;
+    let num_constraints = reader.read_u64::<LittleEndian>()? as usize;
+    if num_inputs + num_aux != num_variables {
+        return Err(Error::new(ErrorKind::InvalidData, "inconsistent variable counts"));
+    }
+    let mut constraints = Vec::with_capacity(num_constraints.min(1 << 24));
+    for _ in 0..num_constraints {
+        constraints.push((
+            read_lc(&mut reader, num_variables)?,
+            read_lc(&mut reader, num_variables)?,
+            read_lc(&mut reader, num_variables)?,
+        ));
+    }
+    Ok(R1CS { num_inputs, num_aux, num_variables, constraints })
+}
+
+/// Per-user cache directory, never a shared one like the temp dir where other users could plant caches
+fn cache_dir() -> Option<PathBuf> {
+    match std::env::var_os("ZKUTIL_CACHE_DIR") {
+        Some(dir) => Some(PathBuf::from(dir)),
+        None => user_cache_dir(),
+    }
+}
+
+/// Cache file of a circuit in `dir`, keyed by the SHA-256 of its contents and the zkutil version
+fn cache_file(dir: &Path, filename: &str) -> Result<PathBuf> {
+    let key = format!("{}:{}", file_sha256(filename)?, env!("CARGO_PKG_VERSION"));
+    Ok(dir.join(format!("{}.r1cs-cache", to_hex(&Sha256::digest(key.as_bytes())[..16]))))
+}
+
+/// Cache file of a circuit, `NotFound` if there is no per-user cache directory
+pub fn cache_path(filename: &str) -> Result<PathBuf> {
+    let dir = cache_dir().ok_or_else(|| Error::new(ErrorKind::NotFound, "no cache directory, set ZKUTIL_CACHE_DIR or HOME"))?;
+    cache_file(&dir, filename)
+}
+
+fn store(r1cs: &R1CS<Bn256>, path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        create_private_dir(dir)?;
+    }
+    // concurrent runs must never see a partial cache
+    write_file_atomic(&path.to_string_lossy(), |w| write(r1cs, w))
+}
+
+/// Returns the cached circuit of `filename` or parses it with `parse` and caches the result.
+/// Cache failures are not fatal, the circuit is parsed as usual.
+pub fn load_cached<F: FnOnce() -> Result<R1CS<Bn256>>>(filename: &str, parse: F) -> Result<R1CS<Bn256>> {
+    if std::env::var_os("ZKUTIL_NO_CACHE").is_some() {
+        return parse();
+    }
+    let path = match cache_path(filename) {
+        Ok(path) => path,
+        Err(_) => return parse(),
+    };
+    if let Ok(file) = File::open(&path) {
+        match read(BufReader::new(file)) {
+            Ok(r1cs) => return Ok(r1cs),
+            Err(e) => tracing::warn!("ignoring broken circuit cache {}: {}", path.display(), e),
+        }
+    }
+    let r1cs = parse()?;
+    if let Err(e) = store(&r1cs, &path) {
+        tracing::warn!("unable to write circuit cache {}: {}", path.display(), e);
+    }
+    Ok(r1cs)
+}
+
+#[test]
+fn roundtrip() {
+    let fr = |s: &str| Fr::from_str(s).unwrap();
+    let r1cs = R1CS::<Bn256> {
+        num_inputs: 2,
+        num_aux: 2,
+        num_variables: 4,
+        constraints: vec![
+            (vec![(2, fr("1"))], vec![(3, fr("5"))], vec![(1, fr("1"))]),
+            (vec![], vec![], vec![(0, fr("7")), (3, fr("1"))]),
+        ],
+    };
+    let mut data = vec![];
+    write(&r1cs, &mut data).unwrap();
+    let read_back = read(&data[..]).unwrap();
+    assert_eq!(read_back.num_inputs, 2);
+    assert_eq!(read_back.num_variables, 4);
+    assert_eq!(read_back.constraints, r1cs.constraints);
+    data[12] = 9;
+    assert!(read(&data[..]).is_err());
+}
+
+#[test]
+fn content_keyed() {
+    use std::fs;
+    let dir = std::env::temp_dir().join(format!("zkutil-cache-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+    fs::write(path("a.json"), "{}").unwrap();
+    fs::write(path("b.json"), "{}").unwrap();
+    let cached = cache_file(&dir, &path("a.json")).unwrap();
+    assert_eq!(cache_file(&dir, &path("b.json")).unwrap(), cached);
+    // same size, different contents
+    fs::write(path("a.json"), "[]").unwrap();
+    assert_ne!(cache_file(&dir, &path("a.json")).unwrap(), cached);
+    fs::remove_dir_all(&dir).unwrap();
+}
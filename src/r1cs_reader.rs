@@ -1,6 +1,7 @@
 #![allow(unused_variables, dead_code)]
 use byteorder::{ReadBytesExt, LittleEndian};
-use std::{collections::HashMap, io::{Error, ErrorKind, Read, Result, Seek, SeekFrom}};
+use std::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom};
+use num_bigint::BigUint;
 use bellman_ce::pairing::{
     Engine,
     bn256::Bn256,
@@ -23,11 +24,66 @@ pub struct Header {
     pub n_constraints: u32,
 }
 
+const HEADER_TYPE: u32 = 1;
+const CONSTRAINT_TYPE: u32 = 2;
+const WIRE2LABEL_TYPE: u32 = 3;
+const CUSTOM_GATES_LIST_TYPE: u32 = 4;
+const CUSTOM_GATES_USE_TYPE: u32 = 5;
+
+/// BN254 scalar field prime, little-endian
+pub const BN254_PRIME: [u8; 32] = hex!("010000f093f5e1439170b97948e833285d588181b64550b829a031e1724e6430");
+const BLS12_381_PRIME: [u8; 32] = hex!("01000000fffffffffe5bfeff02a4bd5305d8a10908d83933487d9d2953a7ed73");
+const GOLDILOCKS_PRIME: [u8; 8] = hex!("01000000ffffffff");
+
+impl Header {
+    /// Field prime of the circuit
+    pub fn prime(&self) -> BigUint {
+        BigUint::from_bytes_le(&self.prime_size)
+    }
+
+    /// Name of the circuit field as in circom `--prime` option, if known
+    pub fn field_name(&self) -> Option<&'static str> {
+        match &self.prime_size[..] {
+            p if p == BN254_PRIME => Some("bn128"),
+            p if p == BLS12_381_PRIME => Some("bls12381"),
+            p if p == GOLDILOCKS_PRIME => Some("goldilocks"),
+            _ => None,
+        }
+    }
+}
+
+/// Custom gate template used by a circom 2 circuit (`pragma custom_templates`)
+pub struct CustomGate {
+    pub name: String,
+    pub parameters: Vec<BigUint>,
+}
+
+/// Application of a custom gate to a list of signals
+pub struct CustomGateUse {
+    /// Index in the custom gates list
+    pub gate: u32,
+    pub signals: Vec<u64>,
+}
+
 pub struct R1CSFile<E: Engine> {
     pub version: u32,
     pub header: Header,
     pub constraints: Vec<Constraint<E>>,
     pub wire_mapping: Vec<u64>,
+    pub custom_gates: Vec<CustomGate>,
+    pub custom_gate_uses: Vec<CustomGateUse>,
+}
+
+/// Contents of an r1cs file except constraints
+pub struct R1CSInfo {
+    pub version: u32,
+    pub header: Header,
+    /// Label of each wire, `None` if the file has no map section
+    pub wire_mapping: Option<Vec<u64>>,
+    pub custom_gates: Vec<CustomGate>,
+    pub custom_gate_uses: Vec<CustomGateUse>,
+    /// Type and size of sections unknown to this parser
+    pub extra_sections: Vec<(u32, u64)>,
 }
 
 fn read_field<R: Read, E: Engine>(mut reader: R) -> Result<E::Fr> {
@@ -97,7 +153,53 @@ fn read_map<R: Read>(mut reader: R, size: u64, header: &Header) -> Result<Vec<u6
     Ok(vec)
 }
 
-pub fn read<R: Read + Seek>(mut reader: R) -> Result<R1CSFile<Bn256>> {
+fn read_big<R: Read>(mut reader: R, header: &Header) -> Result<BigUint> {
+    let mut bytes = vec![0u8; header.field_size as usize];
+    reader.read_exact(&mut bytes)?;
+    Ok(BigUint::from_bytes_le(&bytes))
+}
+
+fn read_custom_gates<R: Read>(mut reader: R, header: &Header) -> Result<Vec<CustomGate>> {
+    let n_gates = reader.read_u32::<LittleEndian>()?;
+    let mut gates = vec![];
+    for _ in 0..n_gates {
+        let mut name = vec![];
+        loop {
+            match reader.read_u8()? {
+                0 => break,
+                b => name.push(b),
+            }
+        }
+        let n_params = reader.read_u32::<LittleEndian>()?;
+        let mut parameters = vec![];
+        for _ in 0..n_params {
+            parameters.push(read_big(&mut reader, header)?);
+        }
+        gates.push(CustomGate {
+            name: String::from_utf8(name).map_err(|e| Error::new(ErrorKind::InvalidData, e))?,
+            parameters,
+        });
+    }
+    Ok(gates)
+}
+
+fn read_custom_gate_uses<R: Read>(mut reader: R) -> Result<Vec<CustomGateUse>> {
+    let n_uses = reader.read_u32::<LittleEndian>()?;
+    let mut uses = vec![];
+    for _ in 0..n_uses {
+        let gate = reader.read_u32::<LittleEndian>()?;
+        let n_signals = reader.read_u32::<LittleEndian>()?;
+        let mut signals = vec![];
+        for _ in 0..n_signals {
+            signals.push(reader.read_u64::<LittleEndian>()?);
+        }
+        uses.push(CustomGateUse { gate, signals });
+    }
+    Ok(uses)
+}
+
+/// Section type, file offset and size of every section
+fn read_section_table<R: Read + Seek>(mut reader: R) -> Result<(u32, Vec<(u32, u64, u64)>)> {
     let mut magic = [0u8; 4];
     reader.read_exact(&mut magic)?;
     if magic != [0x72, 0x31, 0x63, 0x73] { // magic = "r1cs"
@@ -110,42 +212,89 @@ pub fn read<R: Read + Seek>(mut reader: R) -> Result<R1CSFile<Bn256>> {
     }
 
     let num_sections = reader.read_u32::<LittleEndian>()?;
-
-    // section type -> file offset
-    let mut sec_offsets = HashMap::<u32, u64>::new();
-    let mut sec_sizes = HashMap::<u32, u64>::new();
-
-    // get file offset of each section
+    let mut sections = vec![];
     for _ in 0..num_sections {
         let sec_type = reader.read_u32::<LittleEndian>()?;
         let sec_size = reader.read_u64::<LittleEndian>()?;
-        let offset = reader.seek(SeekFrom::Current(0))?;
-        sec_offsets.insert(sec_type, offset);
-        sec_sizes.insert(sec_type, sec_size);
+        let offset = reader.stream_position()?;
+        sections.push((sec_type, offset, sec_size));
         reader.seek(SeekFrom::Current(sec_size as i64))?;
     }
+    Ok((version, sections))
+}
 
-    let header_type = 1;
-    let constraint_type = 2;
-    let wire2label_type = 3;
+fn find_section(sections: &[(u32, u64, u64)], sec_type: u32) -> Option<(u64, u64)> {
+    sections.iter().find(|s| s.0 == sec_type).map(|s| (s.1, s.2))
+}
 
-    reader.seek(SeekFrom::Start(*sec_offsets.get(&header_type).unwrap()))?;
-    let header = read_header(&mut reader, *sec_sizes.get(&header_type).unwrap())?;
-    if header.field_size != 32 {
-        return Err(Error::new(ErrorKind::InvalidData, "This parser only supports 32-byte fields"))
-    }
-    if header.prime_size != hex!("010000f093f5e1439170b97948e833285d588181b64550b829a031e1724e6430") {
-        return Err(Error::new(ErrorKind::InvalidData, "This parser only supports bn256"))
-    }
+/// Reads everything except constraints, works for circuits over any field
+pub fn read_info<R: Read + Seek>(mut reader: R) -> Result<R1CSInfo> {
+    let (version, sections) = read_section_table(&mut reader)?;
 
-    reader.seek(SeekFrom::Start(*sec_offsets.get(&constraint_type).unwrap()))?;
-    let constraints = read_constraints::<&mut R, Bn256>(&mut reader, *sec_sizes.get(&constraint_type).unwrap(), &header)?;
+    let (offset, size) = find_section(&sections, HEADER_TYPE)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Missing header section"))?;
+    reader.seek(SeekFrom::Start(offset))?;
+    let header = read_header(&mut reader, size)?;
 
-    reader.seek(SeekFrom::Start(*sec_offsets.get(&wire2label_type).unwrap()))?;
-    let wire_mapping = read_map(&mut reader, *sec_sizes.get(&wire2label_type).unwrap(), &header)?;
+    let wire_mapping = match find_section(&sections, WIRE2LABEL_TYPE) {
+        Some((offset, size)) => {
+            reader.seek(SeekFrom::Start(offset))?;
+            Some(read_map(&mut reader, size, &header)?)
+        }
+        None => None,
+    };
+    let custom_gates = match find_section(&sections, CUSTOM_GATES_LIST_TYPE) {
+        Some((offset, _)) => {
+            reader.seek(SeekFrom::Start(offset))?;
+            read_custom_gates(&mut reader, &header)?
+        }
+        None => vec![],
+    };
+    let custom_gate_uses = match find_section(&sections, CUSTOM_GATES_USE_TYPE) {
+        Some((offset, _)) => {
+            reader.seek(SeekFrom::Start(offset))?;
+            read_custom_gate_uses(&mut reader)?
+        }
+        None => vec![],
+    };
+    let extra_sections = sections.iter()
+        .filter(|s| s.0 > CUSTOM_GATES_USE_TYPE)
+        .map(|s| (s.0, s.2))
+        .collect();
 
+    Ok(R1CSInfo { version, header, wire_mapping, custom_gates, custom_gate_uses, extra_sections })
+}
+
+pub fn read<R: Read + Seek>(mut reader: R) -> Result<R1CSFile<Bn256>> {
+    let start = reader.stream_position()?;
+    let info = read_info(&mut reader)?;
+    let header = info.header;
+    if header.field_size != 32 || header.prime_size != BN254_PRIME {
+        return Err(Error::new(ErrorKind::InvalidData, format!(
+            "Circuit is compiled for {} field with prime {}, only bn128 is supported",
+            header.field_name().unwrap_or("unknown"),
+            header.prime(),
+        )))
+    }
 
-    Ok(R1CSFile { version, header, constraints, wire_mapping })
+    reader.seek(SeekFrom::Start(start))?;
+    let (_, sections) = read_section_table(&mut reader)?;
+    let (offset, size) = find_section(&sections, CONSTRAINT_TYPE)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Missing constraints section"))?;
+    reader.seek(SeekFrom::Start(offset))?;
+    let constraints = read_constraints::<&mut R, Bn256>(&mut reader, size, &header)?;
+
+    // the map is optional, without it wires are labels
+    let wire_mapping = info.wire_mapping.unwrap_or_else(|| (0..header.n_wires as u64).collect());
+
+    Ok(R1CSFile {
+        version: info.version,
+        header,
+        constraints,
+        wire_mapping,
+        custom_gates: info.custom_gates,
+        custom_gate_uses: info.custom_gate_uses,
+    })
 }
 
 #[test]
@@ -224,4 +373,41 @@ fn sample() {
 
     assert_eq!(file.wire_mapping.len(), 7);
     assert_eq!(file.wire_mapping[1], 3);
-}
\ No newline at end of file
+}
+#[test]
+fn custom_gates() {
+    let data = hex!("
+        72316373
+        01000000
+        03000000
+        01000000 40000000 00000000
+        20000000
+        010000f0 93f5e143 9170b979 48e83328 5d588181 b64550b8 29a031e1 724e6430
+        03000000
+        00000000
+        01000000
+        01000000
+        03000000 00000000
+        00000000
+        04000000 2d000000 00000000
+        01000000
+        5465737400
+        01000000
+        05000000 00000000 00000000 00000000 00000000 00000000 00000000 00000000
+        05000000 1c000000 00000000
+        01000000
+        00000000
+        02000000
+        01000000 00000000
+        02000000 00000000
+    ");
+    let info = read_info(Cursor::new(&data[..])).unwrap();
+    assert_eq!(info.header.field_name(), Some("bn128"));
+    assert!(info.wire_mapping.is_none());
+    assert_eq!(info.custom_gates.len(), 1);
+    assert_eq!(info.custom_gates[0].name, "Test");
+    assert_eq!(info.custom_gates[0].parameters, vec![BigUint::from(5u32)]);
+    assert_eq!(info.custom_gate_uses[0].gate, 0);
+    assert_eq!(info.custom_gate_uses[0].signals, vec![1, 2]);
+    assert!(info.extra_sections.is_empty());
+}
@@ -0,0 +1,134 @@
+//! Token bucket rate limits of `serve`: a global limit and one per client, so a single misbehaving
+//! upstream can't starve the prover. Rejected requests are told how long to wait in `Retry-After`.
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Requests per second with up to `burst` requests at once, parsed from `<requests>/<s|m|h>[:<burst>]`
+/// like `10/m:5`.
+/// The burst is 1 if not given
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Limit {
+    pub rate: f64,
+    pub burst: u32,
+}
+
+impl FromStr for Limit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid rate limit {}, expected <requests>/<s|m|h>[:<burst>] like 10/m:5", s);
+        let (rate, burst) = match s.split_once(':') {
+            Some((rate, burst)) => (rate, burst.parse::<u32>().map_err(|_| invalid())?),
+            None => (s, 1),
+        };
+        let (requests, period) = rate.split_once('/').ok_or_else(invalid)?;
+        let requests = requests.parse::<f64>().map_err(|_| invalid())?;
+        let seconds = match period {
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            _ => return Err(invalid()),
+        };
+        if !(requests > 0.0 && requests.is_finite()) || burst == 0 {
+            return Err(invalid());
+        }
+        Ok(Limit { rate: requests / seconds, burst })
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(limit: Limit, now: Instant) -> Self {
+        Bucket { tokens: limit.burst as f64, updated: now }
+    }
+
+    fn refill(&mut self, limit: Limit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.rate).min(limit.burst as f64);
+        self.updated = now;
+    }
+
+    /// Time until a token is available, zero if one is
+    fn wait(&self, limit: Limit) -> Duration {
+        Duration::from_secs_f64((1.0 - self.tokens).max(0.0) / limit.rate)
+    }
+}
+
+/// Buckets of idle clients are dropped beyond this many clients, a full bucket is the same as none
+const MAX_IDLE_CLIENTS: usize = 10_000;
+
+pub struct RateLimiter {
+    global: Option<(Limit, Bucket)>,
+    per_client: Option<Limit>,
+    clients: HashMap<String, Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(global: Option<Limit>, per_client: Option<Limit>) -> Self {
+        let now = Instant::now();
+        RateLimiter { global: global.map(|limit| (limit, Bucket::full(limit, now))), per_client, clients: HashMap::new() }
+    }
+
+    /// Takes a token for a request of `client` at `now`, or returns how long the client should wait.
+    /// A rejected request doesn't use up tokens of either limit
+    pub fn check(&mut self, client: &str, now: Instant) -> Result<(), Duration> {
+        let mut wait = Duration::from_secs(0);
+        if let Some((limit, bucket)) = &mut self.global {
+            bucket.refill(*limit, now);
+            wait = wait.max(bucket.wait(*limit));
+        }
+        if let Some(limit) = self.per_client {
+            if self.clients.len() >= MAX_IDLE_CLIENTS && !self.clients.contains_key(client) {
+                self.clients.retain(|_, bucket| {
+                    bucket.refill(limit, now);
+                    bucket.tokens < limit.burst as f64
+                });
+            }
+            let bucket = self.clients.entry(client.to_string()).or_insert_with(|| Bucket::full(limit, now));
+            bucket.refill(limit, now);
+            wait = wait.max(bucket.wait(limit));
+        }
+        if wait > Duration::from_secs(0) {
+            return Err(wait);
+        }
+        if let Some((_, bucket)) = &mut self.global {
+            bucket.tokens -= 1.0;
+        }
+        if self.per_client.is_some() {
+            self.clients.get_mut(client).expect("bucket is created above").tokens -= 1.0;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn token_buckets() {
+    assert_eq!("10/m:5".parse::<Limit>().unwrap(), Limit { rate: 10.0 / 60.0, burst: 5 });
+    assert_eq!("2/s".parse::<Limit>().unwrap(), Limit { rate: 2.0, burst: 1 });
+    for invalid in ["10", "10/d", "0/s", "-1/s", "1/s:0", "1/s:x", "x/s"].iter() {
+        assert!(invalid.parse::<Limit>().is_err(), "{}", invalid);
+    }
+
+    let start = Instant::now();
+    let at = |ms: u64| start + Duration::from_millis(ms);
+    let wait_ms = |result: Result<(), Duration>| (result.unwrap_err().as_secs_f64() * 1000.0).round() as u64;
+    let mut limiter = RateLimiter::new(Some("10/s:3".parse().unwrap()), Some("1/s:2".parse().unwrap()));
+    assert!(limiter.check("a", at(0)).is_ok());
+    assert!(limiter.check("a", at(0)).is_ok());
+    // the burst of a is used up, b has its own
+    assert_eq!(wait_ms(limiter.check("a", at(0))), 1000);
+    assert!(limiter.check("b", at(0)).is_ok());
+    // the global burst is used up, the rejected request of a didn't take a global token
+    assert_eq!(wait_ms(limiter.check("c", at(0))), 100);
+    assert!(limiter.check("c", at(100)).is_ok());
+    assert_eq!(wait_ms(limiter.check("a", at(500))), 500);
+    assert!(limiter.check("a", at(1000)).is_ok());
+
+    let mut unlimited = RateLimiter::new(None, None);
+    assert!((0..100).all(|_| unlimited.check("a", start).is_ok()));
+}
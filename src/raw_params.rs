@@ -0,0 +1,359 @@
+//! Parameters and witnesses with raw Montgomery-form field elements, for consumers like GPU, FPGA
+//! and embedded provers and verifiers that load them into memory without any conversion.
+//!
+//! Layout, all header and table integers are little-endian:
+//! - zkutil header with magic `zkrw`, version 2 (the first header version with flags) and
+//!   `FLAG_MONTGOMERY` set, which readers must check. `FLAG_BIG_ENDIAN` and `FLAG_PACKED` describe
+//!   the layout of the data, see `RawLayout`
+//! - u32 number of sections, then for every section a 16-byte zero-padded ASCII name, u32 element
+//!   kind (1 for G1 points, 2 for G2 points, 3 for scalars), u64 number of elements and u64 offset
+//!   of the first element in the file
+//! - elements of every section, starting at 64-byte aligned offsets, or 32-byte with `FLAG_PACKED`
+//!
+//! Field elements are in Montgomery form `x * 2^256 mod p`, 32 bytes each. By default they are four
+//! 64-bit little-endian limbs from least to most significant, with `FLAG_BIG_ENDIAN` they are
+//! 32-byte big-endian integers. G1 points are `x, y` (64 bytes), G2 points are
+//! `x.c0, x.c1, y.c0, y.c1` (128 bytes), where `c1` is the coefficient of `u`. The point at
+//! infinity is all zeros, which no point on the curve is.
+//!
+//! Parameters have sections `alpha_g1`, `beta_g1`, `beta_g2`, `gamma_g2`, `delta_g1`, `delta_g2`,
+//! `ic`, `h`, `l`, `a`, `b_g1` and `b_g2`, in this order, with the same points as the params file.
+//! Witnesses have a single `witness` section of BN254 scalar field elements.
+use std::collections::HashMap;
+use std::io::{self, Error, ErrorKind, Read, Result, Write};
+use std::sync::Arc;
+use byteorder::{ReadBytesExt, WriteBytesExt, LittleEndian};
+use bellman_ce::{
+    groth16::{Parameters, VerifyingKey},
+    pairing::{
+        CurveAffine,
+        ff::{Field, PrimeField, PrimeFieldRepr},
+        bn256::{Bn256, Fq, Fq2, Fr, G1Affine, G2Affine},
+    },
+};
+use crate::file_header::{FileHeader, CURVE_BN254, FLAG_BIG_ENDIAN, FLAG_MONTGOMERY, FLAG_PACKED, write_header, read_header_after_magic};
+use crate::utils::write_file_atomic;
+
+pub const RAW_PARAMS_MAGIC: [u8; 4] = [0x7a, 0x6b, 0x72, 0x77]; // magic = "zkrw"
+pub const RAW_PARAMS_VERSION: u32 = 2;
+
+/// Magic, version, curve and flags
+const HEADER_LEN: u64 = 16;
+const KIND_G1: u32 = 1;
+const KIND_G2: u32 = 2;
+const KIND_FR: u32 = 3;
+const NAME_LEN: usize = 16;
+const SECTION_ENTRY_LEN: u64 = NAME_LEN as u64 + 4 + 8 + 8;
+
+/// Byte order and alignment of field elements, recorded in the header flags
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RawLayout {
+    /// Field elements are 32-byte big-endian integers instead of little-endian limbs
+    pub big_endian: bool,
+    /// Sections are aligned to 32 bytes instead of 64
+    pub packed: bool,
+}
+
+impl RawLayout {
+    pub fn flags(&self) -> u32 {
+        (if self.big_endian { FLAG_BIG_ENDIAN } else { 0 }) | (if self.packed { FLAG_PACKED } else { 0 })
+    }
+
+    pub fn from_flags(flags: u32) -> Self {
+        RawLayout { big_endian: flags & FLAG_BIG_ENDIAN != 0, packed: flags & FLAG_PACKED != 0 }
+    }
+
+    fn align(&self, offset: u64) -> u64 {
+        let align = if self.packed { 32 } else { 64 };
+        (offset + align - 1) / align * align
+    }
+
+    fn write<F: PrimeField, W: Write>(&self, writer: &mut W, x: &F) -> Result<()> {
+        if self.big_endian {
+            x.into_raw_repr().write_be(writer)
+        } else {
+            x.into_raw_repr().write_le(writer)
+        }
+    }
+
+    fn read<F: PrimeField, R: Read>(&self, reader: &mut R) -> Result<F> {
+        let mut repr = F::Repr::default();
+        if self.big_endian {
+            repr.read_be(reader)?;
+        } else {
+            repr.read_le(reader)?;
+        }
+        F::from_raw_repr(repr).map_err(|e| invalid(format!("invalid field element: {}", e)))
+    }
+}
+
+enum Elements<'a> {
+    G1(&'a [G1Affine]),
+    G2(&'a [G2Affine]),
+    Fr(&'a [Fr]),
+}
+
+impl Elements<'_> {
+    fn kind(&self) -> u32 {
+        match self {
+            Elements::G1(_) => KIND_G1,
+            Elements::G2(_) => KIND_G2,
+            Elements::Fr(_) => KIND_FR,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Elements::G1(points) => points.len(),
+            Elements::G2(points) => points.len(),
+            Elements::Fr(scalars) => scalars.len(),
+        }
+    }
+}
+
+fn element_size(kind: u32) -> u64 {
+    match kind {
+        KIND_G2 => 128,
+        KIND_G1 => 64,
+        _ => 32,
+    }
+}
+
+fn params_sections(params: &Parameters<Bn256>) -> Vec<(&'static str, Elements<'_>)> {
+    let vk = &params.vk;
+    vec![
+        ("alpha_g1", Elements::G1(std::slice::from_ref(&vk.alpha_g1))),
+        ("beta_g1", Elements::G1(std::slice::from_ref(&vk.beta_g1))),
+        ("beta_g2", Elements::G2(std::slice::from_ref(&vk.beta_g2))),
+        ("gamma_g2", Elements::G2(std::slice::from_ref(&vk.gamma_g2))),
+        ("delta_g1", Elements::G1(std::slice::from_ref(&vk.delta_g1))),
+        ("delta_g2", Elements::G2(std::slice::from_ref(&vk.delta_g2))),
+        ("ic", Elements::G1(&vk.ic)),
+        ("h", Elements::G1(&params.h)),
+        ("l", Elements::G1(&params.l)),
+        ("a", Elements::G1(&params.a)),
+        ("b_g1", Elements::G1(&params.b_g1)),
+        ("b_g2", Elements::G2(&params.b_g2)),
+    ]
+}
+
+pub(crate) fn write_g1<W: Write>(writer: &mut W, p: &G1Affine, layout: RawLayout) -> Result<()> {
+    if p.is_zero() {
+        return writer.write_all(&[0; 64]);
+    }
+    let (x, y) = p.into_xy_unchecked();
+    layout.write(writer, &x)?;
+    layout.write(writer, &y)
+}
+
+pub(crate) fn write_g2<W: Write>(writer: &mut W, p: &G2Affine, layout: RawLayout) -> Result<()> {
+    if p.is_zero() {
+        return writer.write_all(&[0; 128]);
+    }
+    let (x, y) = p.into_xy_unchecked();
+    for c in &[x.c0, x.c1, y.c0, y.c1] {
+        layout.write(writer, c)?;
+    }
+    Ok(())
+}
+
+fn write_sections<W: Write>(sections: &[(&str, Elements)], flags: u32, layout: RawLayout, mut writer: W) -> Result<()> {
+    let header = FileHeader {
+        magic: RAW_PARAMS_MAGIC,
+        version: RAW_PARAMS_VERSION,
+        curve: CURVE_BN254,
+        flags: flags | FLAG_MONTGOMERY | layout.flags(),
+        metadata: None,
+    };
+    let mut head = vec![];
+    write_header(&mut head, &header)?;
+    let mut offset = layout.align(HEADER_LEN + 4 + sections.len() as u64 * SECTION_ENTRY_LEN);
+    head.write_u32::<LittleEndian>(sections.len() as u32)?;
+    for (name, elements) in sections {
+        let mut padded = [0u8; NAME_LEN];
+        padded[..name.len()].copy_from_slice(name.as_bytes());
+        head.write_all(&padded)?;
+        head.write_u32::<LittleEndian>(elements.kind())?;
+        head.write_u64::<LittleEndian>(elements.len() as u64)?;
+        head.write_u64::<LittleEndian>(offset)?;
+        offset = layout.align(offset + elements.len() as u64 * element_size(elements.kind()));
+    }
+    writer.write_all(&head)?;
+    let mut written = head.len() as u64;
+    for (_, elements) in sections {
+        let padding = layout.align(written) - written;
+        writer.write_all(&vec![0; padding as usize])?;
+        match elements {
+            Elements::G1(points) => points.iter().try_for_each(|p| write_g1(&mut writer, p, layout))?,
+            Elements::G2(points) => points.iter().try_for_each(|p| write_g2(&mut writer, p, layout))?,
+            Elements::Fr(scalars) => scalars.iter().try_for_each(|x| layout.write(&mut writer, x))?,
+        }
+        written += padding + elements.len() as u64 * element_size(elements.kind());
+    }
+    Ok(())
+}
+
+/// Writes parameters in raw Montgomery form, `flags` like `FLAG_INSECURE_DEV` are kept in the header
+pub fn write_raw_params<W: Write>(params: &Parameters<Bn256>, flags: u32, layout: RawLayout, writer: W) -> Result<()> {
+    write_sections(&params_sections(params), flags, layout, writer)
+}
+
+pub fn write_raw_params_file(params: &Parameters<Bn256>, flags: u32, layout: RawLayout, filename: &str) -> Result<()> {
+    write_file_atomic(filename, |w| write_raw_params(params, flags, layout, w))
+}
+
+/// Writes a witness in raw Montgomery form
+pub fn write_raw_witness<W: Write>(witness: &[Fr], layout: RawLayout, writer: W) -> Result<()> {
+    write_sections(&[("witness", Elements::Fr(witness))], 0, layout, writer)
+}
+
+pub fn write_raw_witness_file(witness: &[Fr], layout: RawLayout, filename: &str) -> Result<()> {
+    write_file_atomic(filename, |w| write_raw_witness(witness, layout, w))
+}
+
+fn invalid<T: Into<Box<dyn std::error::Error + Send + Sync>>>(msg: T) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+pub(crate) fn read_g1<R: Read>(reader: &mut R, layout: RawLayout) -> Result<G1Affine> {
+    let (x, y): (Fq, Fq) = (layout.read(reader)?, layout.read(reader)?);
+    if x.is_zero() && y.is_zero() {
+        return Ok(G1Affine::zero());
+    }
+    G1Affine::from_xy_checked(x, y).map_err(|e| invalid(format!("invalid G1 point: {}", e)))
+}
+
+pub(crate) fn read_g2<R: Read>(reader: &mut R, layout: RawLayout) -> Result<G2Affine> {
+    let x = Fq2 { c0: layout.read(reader)?, c1: layout.read(reader)? };
+    let y = Fq2 { c0: layout.read(reader)?, c1: layout.read(reader)? };
+    if x.is_zero() && y.is_zero() {
+        return Ok(G2Affine::zero());
+    }
+    G2Affine::from_xy_checked(x, y).map_err(|e| invalid(format!("invalid G2 point: {}", e)))
+}
+
+/// Sections of a raw file by element kind and name
+#[derive(Default)]
+struct Sections {
+    g1: HashMap<String, Vec<G1Affine>>,
+    g2: HashMap<String, Vec<G2Affine>>,
+    fr: HashMap<String, Vec<Fr>>,
+}
+
+fn take<T>(sections: &mut HashMap<String, Vec<T>>, name: &str) -> Result<Vec<T>> {
+    sections.remove(name).ok_or_else(|| invalid(format!("missing section {}", name)))
+}
+
+fn take_one<T>(sections: &mut HashMap<String, Vec<T>>, name: &str) -> Result<T> {
+    take(sections, name)?.into_iter().next().ok_or_else(|| invalid(format!("section {} is empty", name)))
+}
+
+fn read_sections<R: Read>(mut reader: R) -> Result<(FileHeader, Sections)> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != RAW_PARAMS_MAGIC {
+        return Err(invalid("not a raw Montgomery form file"));
+    }
+    let header = read_header_after_magic(&mut reader, magic, RAW_PARAMS_VERSION)?;
+    if header.flags & FLAG_MONTGOMERY == 0 {
+        return Err(invalid("raw file without the Montgomery form flag"));
+    }
+    let layout = RawLayout::from_flags(header.flags);
+    let num_sections = reader.read_u32::<LittleEndian>()?;
+    let mut table = vec![];
+    for _ in 0..num_sections {
+        let mut name = [0u8; NAME_LEN];
+        reader.read_exact(&mut name)?;
+        let name = String::from_utf8_lossy(&name).trim_end_matches('\0').to_string();
+        let kind = reader.read_u32::<LittleEndian>()?;
+        let count = reader.read_u64::<LittleEndian>()?;
+        let offset = reader.read_u64::<LittleEndian>()?;
+        table.push((name, kind, count, offset));
+    }
+    let mut position = HEADER_LEN + 4 + num_sections as u64 * SECTION_ENTRY_LEN;
+    let mut sections = Sections::default();
+    for (name, kind, count, offset) in table {
+        if offset < position {
+            return Err(invalid(format!("section {} overlaps the previous one", name)));
+        }
+        io::copy(&mut (&mut reader).take(offset - position), &mut io::sink())?;
+        match kind {
+            KIND_G1 => {
+                sections.g1.insert(name, (0..count).map(|_| read_g1(&mut reader, layout)).collect::<Result<Vec<_>>>()?);
+            }
+            KIND_G2 => {
+                sections.g2.insert(name, (0..count).map(|_| read_g2(&mut reader, layout)).collect::<Result<Vec<_>>>()?);
+            }
+            KIND_FR => {
+                sections.fr.insert(name, (0..count).map(|_| layout.read(&mut reader)).collect::<Result<Vec<_>>>()?);
+            }
+            kind => return Err(invalid(format!("unknown element kind {} of section {}", kind, name))),
+        }
+        position = offset + count * element_size(kind);
+    }
+    Ok((header, sections))
+}
+
+/// Reads parameters written by `write_raw_params`, points are checked to be on the curve and in
+/// the right subgroup
+pub fn read_raw_params<R: Read>(reader: R) -> Result<(FileHeader, Parameters<Bn256>)> {
+    let (header, mut sections) = read_sections(reader)?;
+    let (g1, g2) = (&mut sections.g1, &mut sections.g2);
+    let vk = VerifyingKey {
+        alpha_g1: take_one(g1, "alpha_g1")?,
+        beta_g1: take_one(g1, "beta_g1")?,
+        beta_g2: take_one(g2, "beta_g2")?,
+        gamma_g2: take_one(g2, "gamma_g2")?,
+        delta_g1: take_one(g1, "delta_g1")?,
+        delta_g2: take_one(g2, "delta_g2")?,
+        ic: take(g1, "ic")?,
+    };
+    let params = Parameters {
+        vk,
+        h: Arc::new(take(g1, "h")?),
+        l: Arc::new(take(g1, "l")?),
+        a: Arc::new(take(g1, "a")?),
+        b_g1: Arc::new(take(g1, "b_g1")?),
+        b_g2: Arc::new(take(g2, "b_g2")?),
+    };
+    Ok((header, params))
+}
+
+/// Reads a witness written by `write_raw_witness`
+pub fn read_raw_witness<R: Read>(reader: R) -> Result<Vec<Fr>> {
+    take(&mut read_sections(reader)?.1.fr, "witness")
+}
+
+#[test]
+fn raw_params_roundtrip() {
+    use bellman_ce::pairing::bn256::FqRepr;
+    use crate::file_header::FLAG_INSECURE_DEV;
+    use crate::test_utils::multiplier_params;
+    let one = Fr::one();
+    let params = multiplier_params();
+    for layout in &[RawLayout::default(), RawLayout { big_endian: true, packed: true }] {
+        let mut data = vec![];
+        write_raw_params(&params, FLAG_INSECURE_DEV, *layout, &mut data).unwrap();
+        let (header, read) = read_raw_params(&data[..]).unwrap();
+        assert!(header.is_insecure_dev());
+        assert_eq!(RawLayout::from_flags(header.flags), *layout);
+        assert!(read.vk == params.vk && read.h == params.h && read.l == params.l && read.a == params.a);
+        assert!(read.b_g1 == params.b_g1 && read.b_g2 == params.b_g2);
+
+        // alpha_g1 is the first section, aligned right after the table, and x is in Montgomery form
+        let offset = layout.align(HEADER_LEN + 4 + 12 * SECTION_ENTRY_LEN) as usize;
+        let mut x = FqRepr::default();
+        if layout.big_endian {
+            x.read_be(&data[offset..offset + 32]).unwrap();
+        } else {
+            x.read_le(&data[offset..offset + 32]).unwrap();
+        }
+        assert_eq!(x, params.vk.alpha_g1.into_xy_unchecked().0.into_raw_repr());
+        assert_ne!(x, params.vk.alpha_g1.into_xy_unchecked().0.into_repr());
+    }
+
+    let witness = vec![one, Fr::from_str("6").unwrap(), Fr::from_str("2").unwrap()];
+    let mut data = vec![];
+    write_raw_witness(&witness, RawLayout { big_endian: true, packed: false }, &mut data).unwrap();
+    assert_eq!(read_raw_witness(&data[..]).unwrap(), witness);
+}
@@ -0,0 +1,190 @@
+//! Circuit registry of `serve`: one server proves several circuits, each identified by name and
+//! version, with their params kept loaded. Circuits are registered and retired while the server
+//! runs, and every change is written back to the registry file, so a restarted server serves the
+//! same circuits.
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use bellman_ce::{groth16::Parameters, pairing::bn256::Bn256};
+use crate::circom_circuit::{check_params, R1CS};
+use crate::circuit::load_r1cs;
+use crate::jobs::ProofCache;
+use crate::keys;
+use crate::utils::write_file_atomic;
+
+/// Circuit of a registry file, paths are relative to the directory of the file
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CircuitEntry {
+    pub name: String,
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub circuit: Option<String>,
+    /// Bundle to take params and circuit from instead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bundle: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct RegistryFile {
+    pub circuits: Vec<CircuitEntry>,
+}
+
+pub struct RegisteredCircuit {
+    pub r1cs: R1CS<Bn256>,
+    pub params: Parameters<Bn256>,
+    pub cache: Mutex<ProofCache>,
+}
+
+/// Names and versions are single path segments
+fn valid_token(token: &str) -> bool {
+    !token.is_empty()
+        && token.len() <= 64
+        && !token.starts_with('.')
+        && token.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-')
+}
+
+pub struct Registry {
+    file: PathBuf,
+    cache_size: usize,
+    circuits: BTreeMap<(String, String), (CircuitEntry, Arc<RegisteredCircuit>)>,
+}
+
+impl Registry {
+    /// Loads the circuits of a JSON `RegistryFile`, an empty registry if the file doesn't exist yet.
+    /// Every circuit keeps the results of its last `cache_size` jobs, see `ProofCache`
+    pub fn load(file: &Path, cache_size: usize) -> Result<Self> {
+        let entries = match fs::read(file) {
+            Ok(data) => serde_json::from_slice::<RegistryFile>(&data)?.circuits,
+            Err(e) if e.kind() == ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+        let mut registry = Registry { file: file.to_path_buf(), cache_size, circuits: BTreeMap::new() };
+        for entry in entries {
+            let circuit = registry.load_circuit(&entry)?;
+            let key = (entry.name.clone(), entry.version.clone());
+            if registry.circuits.insert(key, (entry, Arc::new(circuit))).is_some() {
+                return Err(Error::new(ErrorKind::InvalidData, "circuits are registered twice"));
+            }
+        }
+        Ok(registry)
+    }
+
+    /// Path of a file of an entry. Entries come from the admin API, so they can't refer to files
+    /// outside of the directory of the registry file
+    fn resolve(&self, path: &str) -> Result<String> {
+        if !Path::new(path).components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("{} is not a relative path inside the registry directory", path)));
+        }
+        Ok(self.file.parent().unwrap_or_else(|| Path::new("")).join(path).to_string_lossy().to_string())
+    }
+
+    fn load_circuit(&self, entry: &CircuitEntry) -> Result<RegisteredCircuit> {
+        let invalid = |msg: String| Error::new(ErrorKind::InvalidInput, msg);
+        if !valid_token(&entry.name) || !valid_token(&entry.version) {
+            return Err(invalid(format!("{}/{}: names and versions are letters, digits, ., _ and -", entry.name, entry.version)));
+        }
+        let (params, r1cs) = match (&entry.bundle, &entry.params, &entry.circuit) {
+            (Some(bundle), None, None) => {
+                let bundle = keys::load_bundle(&self.resolve(bundle)?)?;
+                (bundle.params()?, bundle.r1cs()?)
+            }
+            (None, Some(params), Some(circuit)) => {
+                let (header, params) = keys::load_params(&self.resolve(params)?)?;
+                let r1cs = load_r1cs(&self.resolve(circuit)?)?;
+                keys::check_metadata(header.as_ref(), &r1cs).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+                (params, r1cs)
+            }
+            _ => return Err(invalid(format!("{}/{} needs either a bundle or params and a circuit", entry.name, entry.version))),
+        };
+        check_params(&r1cs, &params).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        let cache = Mutex::new(ProofCache::new(&r1cs, self.cache_size));
+        Ok(RegisteredCircuit { r1cs, params, cache })
+    }
+
+    fn save(&self, entries: Vec<CircuitEntry>) -> Result<()> {
+        write_file_atomic(&self.file.to_string_lossy(), |writer| {
+            serde_json::to_writer_pretty(writer, &RegistryFile { circuits: entries }).map_err(Error::from)
+        })
+    }
+
+    pub fn entries(&self) -> Vec<CircuitEntry> {
+        self.circuits.values().map(|(entry, _)| entry.clone()).collect()
+    }
+
+    /// A circuit stays loaded while it's proved, even if it's retired in the meantime
+    pub fn get(&self, name: &str, version: &str) -> Option<Arc<RegisteredCircuit>> {
+        self.circuits.get(&(name.to_string(), version.to_string())).map(|(_, circuit)| circuit.clone())
+    }
+
+    /// Loads a circuit and adds it to the registry file. A version can't be registered again
+    /// while it's served, proofs of a name and version always come from the same params
+    pub fn register(&mut self, entry: CircuitEntry) -> Result<()> {
+        let key = (entry.name.clone(), entry.version.clone());
+        if self.circuits.contains_key(&key) {
+            return Err(Error::new(ErrorKind::AlreadyExists, format!("{}/{} is already registered", key.0, key.1)));
+        }
+        let circuit = self.load_circuit(&entry)?;
+        let mut entries = self.entries();
+        entries.push(entry.clone());
+        self.save(entries)?;
+        self.circuits.insert(key, (entry, Arc::new(circuit)));
+        Ok(())
+    }
+
+    /// Stops serving a circuit and removes it from the registry file
+    pub fn retire(&mut self, name: &str, version: &str) -> Result<()> {
+        let key = (name.to_string(), version.to_string());
+        if !self.circuits.contains_key(&key) {
+            return Err(Error::new(ErrorKind::NotFound, format!("{}/{} is not registered", name, version)));
+        }
+        self.save(self.entries().into_iter().filter(|e| (&e.name, &e.version) != (&key.0, &key.1)).collect())?;
+        self.circuits.remove(&key);
+        Ok(())
+    }
+}
+
+#[test]
+fn registered_circuits() {
+    use crate::circom_circuit::{r1cs_to_json_file, write_params_file};
+    use crate::test_utils::{multiplier_circuit, multiplier_params};
+    let (r1cs, params) = (multiplier_circuit(), multiplier_params());
+    let dir = std::env::temp_dir().join(format!("zkutil-registry-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    r1cs_to_json_file(&r1cs, &dir.join("circuit.json").to_string_lossy()).unwrap();
+    write_params_file(&params, &dir.join("params.bin").to_string_lossy()).unwrap();
+    let entry = |version: &str| CircuitEntry {
+        name: String::from("multiplier"),
+        version: version.to_string(),
+        params: Some(String::from("params.bin")),
+        circuit: Some(String::from("circuit.json")),
+        bundle: None,
+    };
+    let file = dir.join("registry.json");
+    let mut registry = Registry::load(&file, 10).unwrap();
+    assert!(registry.entries().is_empty());
+    registry.register(entry("v1")).unwrap();
+    registry.register(entry("v2")).unwrap();
+    assert_eq!(registry.register(entry("v1")).unwrap_err().kind(), ErrorKind::AlreadyExists);
+    assert!(registry.register(entry("v/3")).is_err());
+    assert!(registry.register(entry("..")).is_err());
+    assert!(registry.register(CircuitEntry { params: None, ..entry("v3") }).is_err());
+    for path in ["../params.bin", "/etc/passwd", "a/../../params.bin"].iter() {
+        let error = registry.register(CircuitEntry { params: Some(path.to_string()), ..entry("v3") }).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput, "{}", path);
+    }
+    let proving = registry.get("multiplier", "v1").unwrap();
+    registry.retire("multiplier", "v1").unwrap();
+    assert!(registry.retire("multiplier", "v1").is_err());
+    assert_eq!(proving.r1cs.num_variables, 4);
+
+    // changes are persisted
+    let reloaded = Registry::load(&file, 10).unwrap();
+    assert_eq!(reloaded.entries(), vec![entry("v2")]);
+    assert!(reloaded.get("multiplier", "v2").is_some());
+    assert!(reloaded.get("multiplier", "v1").is_none());
+    fs::remove_dir_all(&dir).unwrap();
+}
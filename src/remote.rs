@@ -0,0 +1,219 @@
+//! Resolving remote artifact locations to local files.
+//!
+//! HTTP(S) URLs, `ipfs://` CIDs and, with the `object-storage` feature, `s3://` and `gs://`
+//! locations are downloaded into `downloads` of the per-user cache directory, or a private temporary
+//! directory of the process without one. A `#sha256=<hex>` suffix makes the download fail unless the
+//! content has the given SHA-256 hash.
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Error, ErrorKind, Read, Result, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use sha2::{Digest, Sha256};
+use crate::utils::{create_private_dir, user_cache_dir};
+
+pub fn is_remote(location: &str) -> bool {
+    location.starts_with("http://") || location.starts_with("https://") || location.starts_with("ipfs://")
+        || is_object_storage(location)
+}
+
+#[cfg(feature = "object-storage")]
+fn is_object_storage(location: &str) -> bool {
+    crate::object_storage::is_object_storage(location)
+}
+
+#[cfg(not(feature = "object-storage"))]
+fn is_object_storage(_location: &str) -> bool {
+    false
+}
+
+/// Whether outputs can be written to this location with `upload`
+pub fn is_writable_remote(location: &str) -> bool {
+    is_object_storage(location)
+}
+
+/// Splits `url#sha256=<hex>` into the url and expected hash
+fn split_checksum(location: &str) -> (&str, Option<String>) {
+    match location.rfind("#sha256=") {
+        Some(pos) => (&location[..pos], Some(location[pos + 8..].to_lowercase())),
+        None => (location, None),
+    }
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Writer that hashes everything written through it
+struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn check_hash(path: &Path, actual: &str, expected: Option<&str>) -> Result<()> {
+    match expected {
+        Some(expected) if actual != expected => {
+            fs::remove_file(path)?;
+            Err(Error::new(ErrorKind::InvalidData, format!(
+                "Checksum mismatch for {}: expected sha256 {}, got {}", path.display(), expected, actual,
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Streams `reader` into `path`, verifying SHA-256 of the content if `expected` is given.
+/// The file is removed if the hash doesn't match.
+pub fn download_to<R: Read>(mut reader: R, path: &Path, expected: Option<&str>) -> Result<()> {
+    let mut writer = HashingWriter { inner: BufWriter::new(File::create(path)?), hasher: Sha256::new() };
+    io::copy(&mut reader, &mut writer)?;
+    writer.flush()?;
+    check_hash(path, &to_hex(&writer.hasher.finalize()), expected)
+}
+
+/// Verifies SHA-256 of an already downloaded file, removing it on mismatch
+pub fn verify_file(path: &Path, expected: &str) -> Result<()> {
+    let mut writer = HashingWriter { inner: io::sink(), hasher: Sha256::new() };
+    io::copy(&mut File::open(path)?, &mut writer)?;
+    check_hash(path, &to_hex(&writer.hasher.finalize()), Some(expected))
+}
+
+fn local_name(url: &str) -> String {
+    let name = url.rsplit('/').next().filter(|n| !n.is_empty()).unwrap_or("artifact");
+    let url_hash = to_hex(&Sha256::digest(url.as_bytes())[..8]);
+    format!("{}-{}", url_hash, name)
+}
+
+/// Directory for downloads that other users can neither read nor plant files in. A shared
+/// `<temp dir>/zkutil` would let them substitute artifacts before an unchecked download is used
+fn download_dir() -> Result<PathBuf> {
+    static PROCESS_DIR: OnceLock<PathBuf> = OnceLock::new();
+    if let Some(dir) = user_cache_dir() {
+        let dir = dir.join("downloads");
+        create_private_dir(&dir)?;
+        return Ok(dir);
+    }
+    if let Some(dir) = PROCESS_DIR.get() {
+        return Ok(dir.clone());
+    }
+    let dir = tempfile::Builder::new().prefix("zkutil-").tempdir()?.keep();
+    Ok(PROCESS_DIR.get_or_init(|| dir).clone())
+}
+
+/// Local temporary file for a remote location
+pub fn temp_path(location: &str) -> Result<PathBuf> {
+    Ok(download_dir()?.join(local_name(location)))
+}
+
+#[cfg(not(feature = "net"))]
+fn no_network(url: &str) -> Error {
+    Error::new(ErrorKind::Other, format!("{}: zkutil is built without net feature", url))
+}
+
+/// Starts a GET request and returns the response body reader
+#[cfg(feature = "net")]
+pub fn http_get(url: &str, accept: Option<&str>) -> Result<Box<dyn Read + Send + Sync>> {
+    let mut request = ureq::get(url);
+    if let Some(accept) = accept {
+        request = request.set("Accept", accept);
+    }
+    let response = request.call().map_err(|e| Error::new(ErrorKind::Other, format!("{}: {}", url, e)))?;
+    Ok(response.into_reader())
+}
+
+#[cfg(not(feature = "net"))]
+pub fn http_get(url: &str, _accept: Option<&str>) -> Result<Box<dyn Read + Send + Sync>> {
+    Err(no_network(url))
+}
+
+/// Sends a JSON POST request and parses JSON response
+#[cfg(feature = "net")]
+pub fn http_post_json(url: &str, body: serde_json::Value) -> Result<serde_json::Value> {
+    ureq::post(url)
+        .send_json(body)
+        .map_err(|e| Error::new(ErrorKind::Other, format!("{}: {}", url, e)))?
+        .into_json()
+}
+
+#[cfg(not(feature = "net"))]
+pub fn http_post_json(url: &str, _body: serde_json::Value) -> Result<serde_json::Value> {
+    Err(no_network(url))
+}
+
+fn fetch_http(url: &str, expected: Option<&str>) -> Result<PathBuf> {
+    let path = temp_path(url)?;
+    download_to(http_get(url, None)?, &path, expected)?;
+    Ok(path)
+}
+
+/// Returns a local path for an artifact location, downloading remote artifacts first
+pub fn fetch(location: &str) -> Result<PathBuf> {
+    let (url, expected) = split_checksum(location);
+    if url.starts_with("http://") || url.starts_with("https://") {
+        fetch_http(url, expected.as_deref())
+    } else if url.starts_with("ipfs://") {
+        fetch_ipfs(url, expected.as_deref())
+    } else if is_object_storage(url) {
+        fetch_object(url, expected.as_deref())
+    } else {
+        Ok(PathBuf::from(location))
+    }
+}
+
+fn fetch_ipfs(location: &str, expected: Option<&str>) -> Result<PathBuf> {
+    let path = temp_path(location)?;
+    let mut writer = BufWriter::new(File::create(&path)?);
+    crate::ipfs::fetch(location, &mut writer)?;
+    writer.flush()?;
+    if let Some(expected) = expected {
+        verify_file(&path, expected)?;
+    }
+    Ok(path)
+}
+
+#[cfg(feature = "object-storage")]
+fn fetch_object(location: &str, expected: Option<&str>) -> Result<PathBuf> {
+    let path = temp_path(location)?;
+    crate::object_storage::download(location, &path)?;
+    if let Some(expected) = expected {
+        verify_file(&path, expected)?;
+    }
+    Ok(path)
+}
+
+#[cfg(not(feature = "object-storage"))]
+fn fetch_object(location: &str, _expected: Option<&str>) -> Result<PathBuf> {
+    Err(Error::new(ErrorKind::Other, format!("{}: zkutil is built without object-storage feature", location)))
+}
+
+/// Uploads a local file to a remote location
+#[cfg(feature = "object-storage")]
+pub fn upload(path: &Path, location: &str) -> Result<()> {
+    crate::object_storage::upload(path, location)
+}
+
+#[cfg(not(feature = "object-storage"))]
+pub fn upload(_path: &Path, location: &str) -> Result<()> {
+    Err(Error::new(ErrorKind::Other, format!("{}: zkutil is built without object-storage feature", location)))
+}
+
+#[test]
+fn checksum() {
+    let path = std::env::temp_dir().join("zkutil-remote-checksum-test");
+    let empty_hash = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+    download_to(&b""[..], &path, Some(empty_hash)).unwrap();
+    assert!(download_to(&b"x"[..], &path, Some(empty_hash)).is_err());
+    assert!(!path.exists());
+    assert_eq!(split_checksum("https://a/b.bin#sha256=ABC"), ("https://a/b.bin", Some(String::from("abc"))));
+}
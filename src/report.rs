@@ -0,0 +1,177 @@
+//! Audit dossier of a circuit and its trusted setup parameters.
+//!
+//! Collects constraint statistics, the public input layout, fingerprints of the files and the
+//! verifying key and the toolchain that produced the report, as JSON or markdown.
+use std::fmt::Write;
+use bellman_ce::{
+    groth16::Parameters,
+    pairing::{
+        ff::Field,
+        bn256::{Bn256, Fr},
+    },
+};
+use sha2::{Digest, Sha256};
+use crate::circom_circuit::{R1CS, check_params};
+use crate::file_header::FileHeader;
+use crate::keys::{check_metadata, simplify_for_params};
+use crate::remote::to_hex;
+use crate::signals::Signal;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ConstraintStats {
+    pub constraints: usize,
+    /// Constraints left after synthesis skips the trivial ones
+    pub enforced_constraints: usize,
+    pub public_inputs: usize,
+    pub private_variables: usize,
+    pub variables: usize,
+    /// Non-zero terms in A, B and C
+    pub terms: [usize; 3],
+    pub max_terms_per_constraint: usize,
+    pub domain_size: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PublicInput {
+    pub index: usize,
+    pub wire: usize,
+    pub name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ParamsInfo {
+    pub file: String,
+    pub sha256: String,
+    /// Params file format version, `None` for legacy headerless files
+    pub version: Option<u32>,
+    pub insecure_dev: bool,
+    /// SHA-256 of the verifying key in bellman binary encoding
+    pub vk_sha256: String,
+    /// Why params don't fit the circuit, if they don't
+    pub mismatch: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Toolchain {
+    pub zkutil: String,
+    pub target: String,
+    pub profile: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AuditReport {
+    pub circuit: String,
+    pub circuit_sha256: String,
+    pub stats: ConstraintStats,
+    pub public_inputs: Vec<PublicInput>,
+    pub params: ParamsInfo,
+    pub toolchain: Toolchain,
+}
+
+fn nonzero_terms(lc: &[(usize, Fr)]) -> usize {
+    lc.iter().filter(|(_, coeff)| !coeff.is_zero()).count()
+}
+
+pub fn constraint_stats(r1cs: &R1CS<Bn256>) -> ConstraintStats {
+    let mut terms = [0; 3];
+    let mut max_terms = 0;
+    for (a, b, c) in &r1cs.constraints {
+        let counts = [nonzero_terms(a), nonzero_terms(b), nonzero_terms(c)];
+        for (total, n) in terms.iter_mut().zip(counts.iter()) {
+            *total += n;
+        }
+        max_terms = max_terms.max(counts.iter().sum());
+    }
+    ConstraintStats {
+        constraints: r1cs.constraints.len(),
+        enforced_constraints: r1cs.num_enforced_constraints(),
+        public_inputs: r1cs.num_inputs - 1,
+        private_variables: r1cs.num_aux,
+        variables: r1cs.num_variables,
+        terms,
+        max_terms_per_constraint: max_terms,
+        domain_size: r1cs.domain_size(),
+    }
+}
+
+/// Public inputs in public.json order, named by `signals` if given
+pub fn public_inputs(r1cs: &R1CS<Bn256>, signals: Option<&[Signal]>) -> Vec<PublicInput> {
+    (1..r1cs.num_inputs).map(|wire| PublicInput {
+        index: wire - 1,
+        wire,
+        name: signals.and_then(|s| s.iter().find(|s| s.wire == wire)).map(|s| s.name.clone()),
+    }).collect()
+}
+
+pub fn vk_sha256(params: &Parameters<Bn256>) -> String {
+    let mut vk = vec![];
+    params.vk.write(&mut vk).expect("writing to memory doesn't fail");
+    to_hex(&Sha256::digest(&vk))
+}
+
+pub fn params_info(file: &str, sha256: String, header: Option<&FileHeader>, params: &Parameters<Bn256>, r1cs: &R1CS<Bn256>) -> ParamsInfo {
+    ParamsInfo {
+        file: file.to_string(),
+        sha256,
+        version: header.map(|h| h.version),
+        insecure_dev: header.map_or(false, |h| h.is_insecure_dev()),
+        vk_sha256: vk_sha256(params),
+        mismatch: if header.map_or(false, |h| h.is_simplified()) {
+            let mut simplified = r1cs.clone();
+            simplify_for_params(header, &mut simplified);
+            check_params(&simplified, params).and_then(|_| check_metadata(header, &simplified)).err()
+        } else {
+            check_params(r1cs, params).and_then(|_| check_metadata(header, r1cs)).err()
+        },
+    }
+}
+
+pub fn toolchain() -> Toolchain {
+    Toolchain {
+        zkutil: env!("CARGO_PKG_VERSION").to_string(),
+        target: format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS),
+        profile: String::from(if cfg!(debug_assertions) { "debug" } else { "release" }),
+    }
+}
+
+impl AuditReport {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut md = String::new();
+        let stats = &self.stats;
+        let params = &self.params;
+        // writing to a String doesn't fail
+        let _ = writeln!(md, "# Circuit audit report\n");
+        let _ = writeln!(md, "## Circuit\n");
+        let _ = writeln!(md, "| | |\n|---|---|");
+        let _ = writeln!(md, "| File | `{}` |", self.circuit);
+        let _ = writeln!(md, "| SHA-256 | `{}` |", self.circuit_sha256);
+        let _ = writeln!(md, "| Constraints | {} ({} enforced) |", stats.constraints, stats.enforced_constraints);
+        let _ = writeln!(md, "| Public inputs | {} |", stats.public_inputs);
+        let _ = writeln!(md, "| Private variables | {} |", stats.private_variables);
+        let _ = writeln!(md, "| Terms in A / B / C | {} / {} / {} |", stats.terms[0], stats.terms[1], stats.terms[2]);
+        let _ = writeln!(md, "| Max terms per constraint | {} |", stats.max_terms_per_constraint);
+        let _ = writeln!(md, "| FFT domain | {} |", stats.domain_size);
+        let _ = writeln!(md, "\n## Public inputs\n");
+        let _ = writeln!(md, "| Index | Wire | Signal |\n|---|---|---|");
+        for input in &self.public_inputs {
+            let _ = writeln!(md, "| {} | {} | {} |", input.index, input.wire, input.name.as_deref().unwrap_or("-"));
+        }
+        let _ = writeln!(md, "\n## Trusted setup parameters\n");
+        let _ = writeln!(md, "| | |\n|---|---|");
+        let _ = writeln!(md, "| File | `{}` |", params.file);
+        let _ = writeln!(md, "| SHA-256 | `{}` |", params.sha256);
+        let _ = writeln!(md, "| Format version | {} |", params.version.map_or(String::from("legacy"), |v| v.to_string()));
+        let _ = writeln!(md, "| Verifying key SHA-256 | `{}` |", params.vk_sha256);
+        let _ = writeln!(md, "| Matches circuit | {} |", params.mismatch.as_deref().map_or(String::from("yes"), |e| format!("**no**: {}", e)));
+        if params.insecure_dev {
+            let _ = writeln!(md, "\n**WARNING: parameters were generated by `setup --dev` and are INSECURE.**");
+        }
+        let _ = writeln!(md, "\n## Toolchain\n");
+        let _ = writeln!(md, "zkutil {} ({}, {} build)", self.toolchain.zkutil, self.toolchain.target, self.toolchain.profile);
+        md
+    }
+}
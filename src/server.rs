@@ -0,0 +1,764 @@
+//! HTTP prove server of `zkutil serve`. `POST /prove` takes a `ProveJob` with an inline witness
+//! and answers with its `ProveResult`, `200` with the proof and `422` with the error. Every
+//! connection is answered once on its own thread, proofs are generated one at a time in order of
+//! the `Priority` header, see `jobs::next_job`. With an `Auth` requests need a bearer token that
+//! may use the circuit, see `auth`. `GET /metrics` serves the Prometheus metrics of `metrics`.
+//!
+//! With `Uploads` large witnesses are streamed to `PUT /uploads/<name>` instead, with
+//! `Content-Length` or `Transfer-Encoding: chunked`. An interrupted upload continues at the
+//! `Upload-Offset` that `HEAD /uploads/<name>` returns, and the request with the `Upload-Sha256` of
+//! the whole file completes it. Jobs refer to completed uploads by name in `witness_path`.
+//!
+//! Every circuit answers jobs with a witness it proved before from a `ProofCache`, so requests
+//! retried by clients aren't proved again.
+//!
+//! A server of a `Registry` proves jobs sent to `POST /prove/<name>/<version>` with the params of
+//! that circuit. Admin keys and tokens of `Auth` list the circuits with `GET /circuits`, register
+//! a `CircuitEntry` with `POST /circuits` and retire one with `DELETE /circuits/<name>/<version>`.
+//! The admin API is only served with an `Auth`.
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use bellman_ce::{groth16::Parameters, pairing::bn256::Bn256};
+use crate::auth::{Auth, AuthError};
+use crate::circom_circuit::R1CS;
+use crate::jobs::{next_job, process_job_cached, ProofCache, ProveJob, QueuedJob, WitnessPaths};
+use crate::metrics;
+use crate::rate_limit::{Limit, RateLimiter};
+use crate::registry::{CircuitEntry, Registry, RegistryFile};
+use crate::upload::{UploadError, UploadStatus, Uploads};
+
+/// Connections that send nothing for this long are closed
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+/// Limit of the request line and of every header line in bytes
+const MAX_LINE: u64 = 8192;
+const MAX_HEADERS: usize = 100;
+
+pub struct Request {
+    pub method: String,
+    /// Path without the query string
+    pub path: String,
+    /// Names are lowercase
+    pub headers: Vec<(String, String)>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+    }
+}
+
+fn read_line<R: BufRead>(reader: &mut R, line: &mut String) -> io::Result<()> {
+    line.clear();
+    reader.by_ref().take(MAX_LINE).read_line(line)?;
+    if !line.ends_with('\n') {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "request line or header is too long or cut off"));
+    }
+    Ok(())
+}
+
+/// Reads the request line and the headers, the body is left in `reader`
+pub fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Request> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    let mut line = String::new();
+    read_line(reader, &mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version)) if version.starts_with("HTTP/1.") => {
+            (method.to_string(), target.split('?').next().unwrap_or("").to_string())
+        }
+        _ => return Err(invalid("malformed request line")),
+    };
+    let mut headers = vec![];
+    loop {
+        read_line(reader, &mut line)?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(invalid("too many headers"));
+        }
+        let (name, value) = header.split_once(':').ok_or_else(|| invalid("malformed header"))?;
+        headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+    }
+    Ok(Request { method, path, headers })
+}
+
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn json<T: serde::Serialize>(status: u16, value: &T) -> Self {
+        let body = serde_json::to_vec(value).expect("responses serialize to JSON");
+        Response { status, headers: vec![("Content-Type", String::from("application/json"))], body }
+    }
+
+    pub fn error(status: u16, error: &str) -> Self {
+        Response::json(status, &serde_json::json!({ "error": error }))
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            201 => "Created",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            409 => "Conflict",
+            411 => "Length Required",
+            413 => "Payload Too Large",
+            422 => "Unprocessable Entity",
+            429 => "Too Many Requests",
+            500 => "Internal Server Error",
+            _ => "",
+        }
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write!(writer, "HTTP/1.1 {} {}\r\n", self.status, self.reason())?;
+        for (name, value) in &self.headers {
+            write!(writer, "{}: {}\r\n", name, value)?;
+        }
+        write!(writer, "Content-Length: {}\r\nConnection: close\r\n\r\n", self.body.len())?;
+        writer.write_all(&self.body)?;
+        writer.flush()
+    }
+}
+
+/// Length of the body of `request`, bodies are only accepted with Content-Length
+fn content_length(request: &Request) -> Result<u64, Response> {
+    if request.header("transfer-encoding").is_some() {
+        return Err(Response::error(411, "send the body with Content-Length"));
+    }
+    match request.header("content-length") {
+        Some(length) => length.parse().map_err(|_| Response::error(400, "invalid Content-Length")),
+        None => Err(Response::error(411, "Content-Length is required")),
+    }
+}
+
+/// Reads a body of at most `limit` bytes
+fn read_body<R: Read>(reader: &mut R, request: &Request, limit: u64) -> Result<Vec<u8>, Response> {
+    let length = content_length(request)?;
+    if length > limit {
+        return Err(Response::error(413, &format!("bodies are limited to {} bytes", limit)));
+    }
+    let mut body = Vec::with_capacity(length as usize);
+    reader.by_ref().take(length).read_to_end(&mut body).map_err(|e| Response::error(400, &e.to_string()))?;
+    if body.len() as u64 != length {
+        return Err(Response::error(400, "body is shorter than Content-Length"));
+    }
+    Ok(body)
+}
+
+/// Body sent with `Transfer-Encoding: chunked`
+struct Chunked<'a, R> {
+    reader: &'a mut R,
+    /// Bytes left of the current chunk
+    remaining: u64,
+    done: bool,
+}
+
+impl<'a, R: BufRead> Chunked<'a, R> {
+    fn new(reader: &'a mut R) -> Self {
+        Chunked { reader, remaining: 0, done: false }
+    }
+}
+
+impl<R: BufRead> Read for Chunked<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+        let mut line = String::new();
+        if self.remaining == 0 {
+            read_line(self.reader, &mut line)?;
+            let size = line.split(';').next().unwrap_or("").trim();
+            self.remaining = u64::from_str_radix(size, 16).map_err(|_| invalid("malformed chunk size"))?;
+            if self.remaining == 0 {
+                // the last chunk is followed by trailers and an empty line
+                while { read_line(self.reader, &mut line)?; !line.trim_end().is_empty() } {}
+                self.done = true;
+                return Ok(0);
+            }
+        }
+        let len = buf.len().min(self.remaining as usize);
+        let n = self.reader.read(&mut buf[..len])?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "chunked body is cut off"));
+        }
+        self.remaining -= n as u64;
+        if self.remaining == 0 {
+            read_line(self.reader, &mut line)?;
+            if !line.trim_end().is_empty() {
+                return Err(invalid("chunk is longer than its size"));
+            }
+        }
+        Ok(n)
+    }
+}
+
+/// Reads and drops a body of at most `limit` bytes, a connection closed with unread data is reset
+/// before the client sees the response
+fn discard_body<R: Read>(reader: &mut R, request: &Request, limit: u64) {
+    let length = content_length(request).unwrap_or(0).min(limit);
+    let _ = io::copy(&mut reader.by_ref().take(length), &mut io::sink());
+}
+
+/// Requests waiting for the prover, the one that may prove next is `granted`
+#[derive(Default)]
+struct Scheduler {
+    queue: Vec<(u64, i64, Instant)>,
+    next_ticket: u64,
+    busy: bool,
+    granted: Option<u64>,
+}
+
+impl Scheduler {
+    /// Grants the prover to the request that goes first if it's free
+    fn dispatch(&mut self, aging: Duration) {
+        if self.busy || self.granted.is_some() {
+            return;
+        }
+        let now = Instant::now();
+        let queue = self.queue.iter()
+            .map(|&(ticket, priority, queued)| QueuedJob { job: ticket, priority, waiting: now.saturating_duration_since(queued) })
+            .collect::<Vec<_>>();
+        if let Some(i) = next_job(&queue, aging) {
+            self.granted = Some(self.queue.remove(i).0);
+        }
+        metrics::set_queue_depth(self.queue.len());
+    }
+}
+
+fn auth_error(e: AuthError) -> Response {
+    match e {
+        AuthError::Unauthorized(e) => {
+            let mut response = Response::error(401, &e);
+            response.headers.push(("WWW-Authenticate", String::from("Bearer")));
+            response
+        }
+        AuthError::Forbidden(e) => Response::error(403, &e),
+    }
+}
+
+/// Name and version of a `<name>/<version>` path
+fn circuit_id(path: &str) -> Option<(&str, &str)> {
+    path.split_once('/').filter(|(name, version)| !name.is_empty() && !version.is_empty() && !version.contains('/'))
+}
+
+fn upload_error_status(e: &UploadError) -> u16 {
+    match e {
+        UploadError::InvalidName | UploadError::InvalidHash | UploadError::Interrupted(_) => 400,
+        UploadError::Offset { .. } | UploadError::InProgress => 409,
+        UploadError::TooLarge(_) => 413,
+        UploadError::HashMismatch => 422,
+        UploadError::Io(_) => 500,
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct ServeOptions {
+    /// Size limit of request bodies in bytes
+    pub max_body: u64,
+    /// Limit of the requests of all clients
+    pub rate_limit: Option<Limit>,
+    /// Limit of the requests of every client
+    pub client_rate_limit: Option<Limit>,
+    /// Waiting time after which a request goes ahead of those with one more level of priority
+    pub aging: Duration,
+    /// Number of results every circuit keeps for jobs resubmitted with the same witness
+    pub cache_size: usize,
+}
+
+impl Default for ServeOptions {
+    fn default() -> Self {
+        ServeOptions { max_body: 64 << 20, rate_limit: None, client_rate_limit: None, aging: Duration::from_secs(60), cache_size: 1000 }
+    }
+}
+
+pub struct Server<'a> {
+    /// Circuit of `POST /prove`
+    circuit: Option<(&'a R1CS<Bn256>, &'a Parameters<Bn256>)>,
+    cache: Option<Mutex<ProofCache>>,
+    /// Circuits of `POST /prove/<name>/<version>`. Registering a circuit holds the lock while its
+    /// params are loaded
+    registry: Option<Mutex<Registry>>,
+    options: ServeOptions,
+    limiter: Mutex<RateLimiter>,
+    auth: Option<Auth>,
+    /// Name of `circuit` that `auth` allows or refuses
+    name: String,
+    uploads: Option<Uploads>,
+    // proving uses every core, concurrent proofs would only compete for them
+    scheduler: Mutex<Scheduler>,
+    turn: Condvar,
+}
+
+impl<'a> Server<'a> {
+    pub fn new(r1cs: &'a R1CS<Bn256>, params: &'a Parameters<Bn256>, options: ServeOptions) -> Self {
+        Server::build(Some((r1cs, params)), None, options)
+    }
+
+    /// Serves the circuits of `registry` instead of a single one
+    pub fn from_registry(registry: Registry, options: ServeOptions) -> Self {
+        Server::build(None, Some(Mutex::new(registry)), options)
+    }
+
+    fn build(circuit: Option<(&'a R1CS<Bn256>, &'a Parameters<Bn256>)>, registry: Option<Mutex<Registry>>, options: ServeOptions) -> Self {
+        let limiter = Mutex::new(RateLimiter::new(options.rate_limit, options.client_rate_limit));
+        Server {
+            circuit,
+            cache: circuit.map(|(r1cs, _)| Mutex::new(ProofCache::new(r1cs, options.cache_size))),
+            registry,
+            options,
+            limiter,
+            auth: None,
+            name: String::from("circuit"),
+            uploads: None,
+            scheduler: Mutex::default(),
+            turn: Condvar::new(),
+        }
+    }
+
+    /// Requires a token of `auth` that may use the circuit, `name` is the one of `POST /prove`
+    pub fn with_auth(self, auth: Auth, name: &str) -> Self {
+        Server { auth: Some(auth), name: name.to_string(), ..self }
+    }
+
+    /// Accepts witness uploads, see `upload`
+    pub fn with_uploads(self, uploads: Uploads) -> Self {
+        Server { uploads: Some(uploads), ..self }
+    }
+
+    /// Client of a request authorized to use `circuit`, the name of its key or `client` without
+    /// authentication
+    fn authorize(&self, client: &str, request: &Request, circuit: &str) -> Result<String, Response> {
+        match &self.auth {
+            Some(auth) => auth.authorize(request.header("authorization"), circuit, SystemTime::now()).map_err(auth_error),
+            None => Ok(client.to_string()),
+        }
+    }
+
+    /// Answers one request read from `reader`. `client` tells clients apart for rate limits of
+    /// requests without authentication
+    pub fn handle<R: BufRead>(&self, client: &str, reader: &mut R) -> Response {
+        let request = match read_request(reader) {
+            Ok(request) => request,
+            Err(e) => return Response::error(400, &e.to_string()),
+        };
+        match (request.method.as_str(), request.path.as_str()) {
+            ("POST", "/prove") => self.prove(client, &request, reader, None),
+            ("POST", path) if path.starts_with("/prove/") => match circuit_id(&path["/prove/".len()..]) {
+                Some(id) => self.prove(client, &request, reader, Some(id)),
+                None => Response::error(404, "not found, jobs are sent to /prove/<name>/<version>"),
+            },
+            (_, path) if path == "/prove" || path.starts_with("/prove/") => Response::error(405, "jobs are sent with POST"),
+            (_, path) if path == "/circuits" || path.starts_with("/circuits/") => self.admin(&request, reader),
+            ("PUT", path) | ("HEAD", path) if path.starts_with("/uploads/") => {
+                self.upload(&request, reader, &path["/uploads/".len()..])
+            }
+            ("GET", "/metrics") => Response {
+                status: 200,
+                headers: vec![("Content-Type", String::from("text/plain; version=0.0.4"))],
+                body: metrics::render().into_bytes(),
+            },
+            _ => Response::error(404, "not found, jobs are sent to /prove"),
+        }
+    }
+
+    /// Proves a job with the circuit of `POST /prove` or the registered circuit `id`
+    fn prove<R: Read>(&self, client: &str, request: &Request, reader: &mut R, id: Option<(&str, &str)>) -> Response {
+        let not_found = match (id, self.circuit, &self.registry) {
+            (None, None, _) => Some("not found, jobs are sent to /prove/<name>/<version>"),
+            (Some(_), _, None) => Some("not found, jobs are sent to /prove"),
+            _ => None,
+        };
+        if let Some(e) = not_found {
+            discard_body(reader, request, self.options.max_body);
+            return Response::error(404, e);
+        }
+        let client = match self.authorize(client, request, id.map_or(self.name.as_str(), |(name, _)| name)) {
+            Ok(client) => client,
+            Err(response) => {
+                discard_body(reader, request, self.options.max_body);
+                return response;
+            }
+        };
+        if let Err(wait) = self.limiter.lock().unwrap().check(&client, Instant::now()) {
+            discard_body(reader, request, self.options.max_body);
+            let mut response = Response::error(429, "rate limit exceeded");
+            // Retry-After is in whole seconds, rounded up so the retry isn't rejected again
+            response.headers.push(("Retry-After", (wait.as_secs_f64().ceil() as u64).max(1).to_string()));
+            return response;
+        }
+        let registered;
+        let (r1cs, params, cache) = match (id, self.circuit, &self.registry) {
+            (Some((name, version)), _, Some(registry)) => match registry.lock().unwrap().get(name, version) {
+                Some(circuit) => {
+                    registered = circuit;
+                    (&registered.r1cs, &registered.params, &registered.cache)
+                }
+                None => {
+                    discard_body(reader, request, self.options.max_body);
+                    return Response::error(404, &format!("circuit {}/{} is not registered", name, version));
+                }
+            },
+            (_, circuit, _) => {
+                let (r1cs, params) = circuit.expect("routes are checked above");
+                (r1cs, params, self.cache.as_ref().expect("a circuit has a cache"))
+            }
+        };
+        let body = match read_body(reader, request, self.options.max_body) {
+            Ok(body) => body,
+            Err(response) => return response,
+        };
+        let mut job = match serde_json::from_slice::<ProveJob>(&body) {
+            Ok(job) => job,
+            Err(e) => return Response::error(400, &format!("invalid job: {}", e)),
+        };
+        // the server must not read files on behalf of its clients, other than their uploads
+        if let Some(name) = &job.witness_path {
+            let path = match &self.uploads {
+                Some(uploads) => uploads.path(name),
+                None => return Response::error(400, "witness_path is not accepted, send the witness inline"),
+            };
+            match path {
+                Some(path) => job.witness_path = Some(path.to_string_lossy().to_string()),
+                None => return Response::error(400, &format!("witness_path {} is not a completed upload", name)),
+            }
+        }
+        let priority = match request.header("priority").map(str::parse::<i64>) {
+            Some(Ok(priority)) => priority,
+            Some(Err(_)) => return Response::error(400, "Priority is not an integer"),
+            None => 0,
+        };
+        // witness_path is a completed upload by now
+        let result = self.exclusive(priority, || process_job_cached(r1cs, params, &job, WitnessPaths::Any, &mut cache.lock().unwrap()));
+        Response::json(if result.error.is_none() { 200 } else { 422 }, &result)
+    }
+
+    fn upload<R: BufRead>(&self, request: &Request, reader: &mut R, name: &str) -> Response {
+        let uploads = match &self.uploads {
+            Some(uploads) => uploads,
+            None => {
+                discard_body(reader, request, self.options.max_body);
+                return Response::error(404, "uploads are not enabled");
+            }
+        };
+        // uploads don't belong to a circuit, any valid token may upload
+        if let Some(Err(e)) = self.auth.as_ref().map(|auth| auth.authenticate(request.header("authorization"), SystemTime::now())) {
+            discard_body(reader, request, self.options.max_body);
+            return auth_error(e);
+        }
+        let respond = |result: Result<UploadStatus, UploadError>| {
+            let mut response = match &result {
+                Ok(status) => Response::json(200, status),
+                Err(e) => Response::error(upload_error_status(e), &e.to_string()),
+            };
+            if let Ok(status) = result.or_else(|_| uploads.status(name)) {
+                response.headers.push(("Upload-Offset", status.received.to_string()));
+                response.headers.push(("Upload-Complete", status.complete.to_string()));
+            }
+            response
+        };
+        if request.method == "HEAD" {
+            return Response { body: vec![], ..respond(uploads.status(name)) };
+        }
+        let offset = match request.header("upload-offset").map(str::parse::<u64>) {
+            Some(Ok(offset)) => offset,
+            Some(Err(_)) => return Response::error(400, "Upload-Offset is not a number"),
+            None => 0,
+        };
+        let sha256 = request.header("upload-sha256");
+        let result = match request.header("transfer-encoding") {
+            Some(encoding) if encoding.eq_ignore_ascii_case("chunked") => uploads.append(name, offset, &mut Chunked::new(reader), sha256),
+            Some(_) => return Response::error(400, "only chunked transfer encoding is supported"),
+            None => match content_length(request) {
+                Ok(length) => uploads.append(name, offset, &mut reader.by_ref().take(length), sha256),
+                Err(response) => return response,
+            },
+        };
+        // these are refused before the body is read
+        if let Err(UploadError::InvalidName) | Err(UploadError::Offset { .. }) | Err(UploadError::InProgress) | Err(UploadError::InvalidHash) = result {
+            discard_body(reader, request, self.options.max_body);
+        }
+        respond(result)
+    }
+
+    /// Lists, registers and retires circuits of the registry for admin keys and tokens
+    fn admin<R: Read>(&self, request: &Request, reader: &mut R) -> Response {
+        let refuse = |reader: &mut R, response: Response| {
+            discard_body(reader, request, self.options.max_body);
+            response
+        };
+        let (registry, auth) = match (&self.registry, &self.auth) {
+            (Some(registry), Some(auth)) => (registry, auth),
+            (None, _) => return refuse(reader, Response::error(404, "not found, there is no circuit registry")),
+            (Some(_), None) => return refuse(reader, Response::error(403, "the admin API requires authentication")),
+        };
+        if let Err(e) = auth.authorize_admin(request.header("authorization"), SystemTime::now()) {
+            return refuse(reader, auth_error(e));
+        }
+        let circuits = |registry: &Registry| RegistryFile { circuits: registry.entries() };
+        let result = match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/circuits") => return Response::json(200, &circuits(&registry.lock().unwrap())),
+            ("POST", "/circuits") => {
+                let body = match read_body(reader, request, self.options.max_body) {
+                    Ok(body) => body,
+                    Err(response) => return response,
+                };
+                let entry = match serde_json::from_slice::<CircuitEntry>(&body) {
+                    Ok(entry) => entry,
+                    Err(e) => return Response::error(400, &format!("invalid circuit: {}", e)),
+                };
+                let mut registry = registry.lock().unwrap();
+                registry.register(entry).map(|_| Response::json(201, &circuits(&registry)))
+            }
+            ("DELETE", path) => match path.strip_prefix("/circuits/").and_then(circuit_id) {
+                Some((name, version)) => {
+                    let mut registry = registry.lock().unwrap();
+                    registry.retire(name, version).map(|_| Response::json(200, &circuits(&registry)))
+                }
+                None => return Response::error(404, "not found, circuits are retired at /circuits/<name>/<version>"),
+            },
+            _ => return refuse(reader, Response::error(405, "circuits are listed with GET, registered with POST and retired with DELETE")),
+        };
+        result.unwrap_or_else(|e| {
+            let status = match e.kind() {
+                ErrorKind::AlreadyExists => 409,
+                ErrorKind::NotFound => 404,
+                ErrorKind::InvalidInput => 400,
+                _ => 422,
+            };
+            Response::error(status, &e.to_string())
+        })
+    }
+
+    /// Waits until the request goes first and the prover is free, then runs `f` on it
+    fn exclusive<T, F: FnOnce() -> T>(&self, priority: i64, f: F) -> T {
+        let lock = || self.scheduler.lock().unwrap_or_else(|e| e.into_inner());
+        let mut scheduler = lock();
+        let ticket = scheduler.next_ticket;
+        scheduler.next_ticket += 1;
+        scheduler.queue.push((ticket, priority, Instant::now()));
+        scheduler.dispatch(self.options.aging);
+        while scheduler.granted != Some(ticket) {
+            scheduler = self.turn.wait(scheduler).unwrap_or_else(|e| e.into_inner());
+        }
+        scheduler.granted = None;
+        scheduler.busy = true;
+        drop(scheduler);
+        let result = f();
+        let mut scheduler = lock();
+        scheduler.busy = false;
+        scheduler.dispatch(self.options.aging);
+        self.turn.notify_all();
+        result
+    }
+
+    fn respond(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let client = stream.peer_addr()?.ip().to_string();
+        let response = self.handle(&client, &mut BufReader::new(&stream));
+        response.write_to(&stream)
+    }
+
+    /// Answers the connections of `listener`, each on its own thread
+    pub fn serve(&self, listener: TcpListener) {
+        std::thread::scope(|scope| {
+            for stream in listener.incoming().flatten() {
+                scope.spawn(move || {
+                    let _ = self.respond(stream);
+                });
+            }
+        });
+    }
+}
+
+#[test]
+fn prove_requests() {
+    use crate::test_utils::{multiplier_circuit, multiplier_params};
+    use crate::jobs::ProveResult;
+    let (r1cs, params) = (multiplier_circuit(), multiplier_params());
+    let options = ServeOptions { max_body: 100, client_rate_limit: Some("1/h".parse().unwrap()), ..ServeOptions::default() };
+    let server = Server::new(&r1cs, &params, options);
+    let post = |client: &str, body: &str| {
+        let request = format!("POST /prove HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}", body.len(), body);
+        server.handle(client, &mut request.as_bytes())
+    };
+
+    let response = post("10.0.0.1", r#"{"id": "1", "witness": ["1", "6", "2", "3"]}"#);
+    assert_eq!(response.status, 200);
+    let result: ProveResult = serde_json::from_slice(&response.body).unwrap();
+    assert_eq!((result.id.as_str(), result.public), ("1", Some(serde_json::json!(["6"]))));
+    let limited = post("10.0.0.1", r#"{"id": "2", "witness": ["1", "6", "2", "3"]}"#);
+    assert_eq!((limited.status, limited.headers.last().unwrap()), (429, &("Retry-After", String::from("3600"))));
+    // a retried job is answered from the cache
+    let retried: ProveResult = serde_json::from_slice(&post("10.0.0.7", r#"{"id": "2", "witness": ["1", "6", "2", "3"]}"#).body).unwrap();
+    assert_eq!((retried.id.as_str(), retried.proof), ("2", result.proof));
+
+    assert_eq!(post("10.0.0.2", r#"{"id": "3", "witness": ["1", "6"]}"#).status, 422);
+    assert_eq!(post("10.0.0.3", r#"{"id": "4", "witness_path": "/etc/passwd"}"#).status, 400);
+    assert_eq!(post("10.0.0.4", &format!(r#"{{"id": "{}"}}"#, "x".repeat(100))).status, 413);
+    let request = "POST /prove HTTP/1.1\r\nPriority: high\r\nContent-Length: 2\r\n\r\n{}";
+    assert_eq!(server.handle("10.0.0.6", &mut request.as_bytes()).status, 400);
+    let mut response = Vec::new();
+    server.handle("10.0.0.5", &mut &b"GET /prove HTTP/1.1\r\n\r\n"[..]).write_to(&mut response).unwrap();
+    assert!(response.starts_with(b"HTTP/1.1 405 Method Not Allowed\r\n"));
+    assert_eq!(server.handle("10.0.0.5", &mut &b"GET / HTTP/1.1\r\n\r\n"[..]).status, 404);
+    let metrics = server.handle("10.0.0.5", &mut &b"GET /metrics HTTP/1.1\r\n\r\n"[..]);
+    assert_eq!(metrics.status, 200);
+    assert!(String::from_utf8(metrics.body).unwrap().contains("zkutil_proofs_total{result=\"ok\"} "));
+    assert_eq!(server.handle("10.0.0.5", &mut &b"GET /\r\n\r\n"[..]).status, 400);
+}
+
+#[test]
+fn scheduled_by_priority() {
+    use crate::test_utils::{multiplier_circuit, multiplier_params};
+    let (r1cs, params) = (multiplier_circuit(), multiplier_params());
+    let server = Server::new(&r1cs, &params, ServeOptions::default());
+    let order = Mutex::new(vec![]);
+    let (started, release) = (std::sync::Barrier::new(2), std::sync::Barrier::new(2));
+    std::thread::scope(|scope| {
+        // the first request holds the prover until the others are queued
+        scope.spawn(|| server.exclusive(0, || {
+            started.wait();
+            release.wait();
+        }));
+        started.wait();
+        for priority in [1, 5, 3].iter() {
+            let (server, order) = (&server, &order);
+            scope.spawn(move || server.exclusive(*priority, || order.lock().unwrap().push(*priority)));
+            while server.scheduler.lock().unwrap().queue.iter().all(|&(_, p, _)| p != *priority) {
+                std::thread::yield_now();
+            }
+        }
+        release.wait();
+    });
+    assert_eq!(*order.lock().unwrap(), vec![5, 3, 1]);
+}
+
+#[test]
+fn uploaded_witness() {
+    use sha2::{Digest, Sha256};
+    use crate::test_utils::{multiplier_circuit, multiplier_params};
+    use crate::jobs::ProveResult;
+    let (r1cs, params) = (multiplier_circuit(), multiplier_params());
+    let dir = std::env::temp_dir().join(format!("zkutil-server-upload-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let server = Server::new(&r1cs, &params, ServeOptions::default()).with_uploads(Uploads::new(&dir, 1 << 20));
+    let request = |request: String| server.handle("10.0.0.1", &mut request.as_bytes());
+    let header = |response: &Response, name: &str| response.headers.iter().find(|(n, _)| *n == name).map(|(_, value)| value.clone());
+    let witness = r#"["1","6","2","3"]"#;
+
+    // the first part is streamed in chunks, the connection drops in the middle of the last one
+    let first = request(String::from("PUT /uploads/w.json HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n4\r\n[\"1\"\r\n5\r\n,\"6\",\r\n8\r\n\"2\""));
+    assert_eq!(first.status, 400);
+    let head = request(String::from("HEAD /uploads/w.json HTTP/1.1\r\n\r\n"));
+    assert_eq!((head.status, header(&head, "Upload-Offset"), head.body.len()), (200, Some(String::from("12")), 0));
+    let stale = request(String::from("PUT /uploads/w.json HTTP/1.1\r\nUpload-Offset: 4\r\nContent-Length: 1\r\n\r\nx"));
+    assert_eq!((stale.status, header(&stale, "Upload-Offset")), (409, Some(String::from("12"))));
+    let job = r#"{"id": "1", "witness_path": "w.json"}"#;
+    let prove = || request(format!("POST /prove HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}", job.len(), job));
+    let early = prove();
+    assert_eq!(early.status, 400);
+
+    let sha256 = Sha256::digest(witness.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    let rest = &witness[12..];
+    let last = request(format!("PUT /uploads/w.json HTTP/1.1\r\nUpload-Offset: 12\r\nUpload-Sha256: {}\r\nContent-Length: {}\r\n\r\n{}", sha256, rest.len(), rest));
+    assert_eq!((last.status, header(&last, "Upload-Complete")), (200, Some(String::from("true"))));
+    let proved = prove();
+    assert_eq!(proved.status, 200);
+    let result: ProveResult = serde_json::from_slice(&proved.body).unwrap();
+    assert_eq!(result.public, Some(serde_json::json!(["6"])));
+    let escape = request(String::from("PUT /uploads/..%2Fw.json HTTP/1.1\r\nContent-Length: 0\r\n\r\n"));
+    assert_eq!(escape.status, 400);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn registered_circuits() {
+    use sha2::{Digest, Sha256};
+    use crate::auth::{ApiKey, AuthConfig};
+    use crate::circom_circuit::{r1cs_to_json_file, write_params_file};
+    use crate::test_utils::{multiplier_circuit, multiplier_params};
+    let (r1cs, params) = (multiplier_circuit(), multiplier_params());
+    let dir = std::env::temp_dir().join(format!("zkutil-server-registry-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    r1cs_to_json_file(&r1cs, &dir.join("circuit.json").to_string_lossy()).unwrap();
+    write_params_file(&params, &dir.join("params.bin").to_string_lossy()).unwrap();
+    let key = |name: &str, key: &[u8], admin: bool| ApiKey {
+        name: name.to_string(),
+        sha256: Sha256::digest(key).iter().map(|b| format!("{:02x}", b)).collect(),
+        circuits: vec![String::from("multiplier")],
+        admin,
+    };
+    let config = AuthConfig { keys: vec![key("payments", b"key-1", false), key("ops", b"key-2", true)], jwt_secret_file: None };
+    let registry = Registry::load(&dir.join("registry.json"), 10).unwrap();
+    let server = Server::from_registry(registry, ServeOptions::default()).with_auth(Auth::new(config, None).unwrap(), "circuit");
+    let request = |method: &str, path: &str, key: &str, body: &str| {
+        let request = format!("{} {} HTTP/1.1\r\nAuthorization: Bearer {}\r\nContent-Length: {}\r\n\r\n{}", method, path, key, body.len(), body);
+        server.handle("10.0.0.1", &mut request.as_bytes())
+    };
+    let job = r#"{"id": "1", "witness": ["1", "6", "2", "3"]}"#;
+    let entry = r#"{"name": "multiplier", "version": "v1", "params": "params.bin", "circuit": "circuit.json"}"#;
+
+    assert_eq!(request("POST", "/prove/multiplier/v1", "key-1", job).status, 404);
+    assert_eq!(request("POST", "/circuits", "key-1", entry).status, 403);
+    assert_eq!(request("POST", "/circuits", "key-2", entry).status, 201);
+    assert_eq!(request("POST", "/circuits", "key-2", entry).status, 409);
+    let outside = r#"{"name": "multiplier", "version": "v2", "params": "../params.bin", "circuit": "circuit.json"}"#;
+    assert_eq!(request("POST", "/circuits", "key-2", outside).status, 400);
+    assert_eq!(request("POST", "/prove/multiplier/v1", "key-1", job).status, 200);
+    assert_eq!(request("POST", "/prove", "key-1", job).status, 404);
+    let listed = request("GET", "/circuits", "key-2", "");
+    let listed: RegistryFile = serde_json::from_slice(&listed.body).unwrap();
+    assert_eq!(listed.circuits.iter().map(|c| c.version.as_str()).collect::<Vec<_>>(), vec!["v1"]);
+    assert_eq!(request("DELETE", "/circuits/multiplier/v1", "key-1", "").status, 403);
+    assert_eq!(request("DELETE", "/circuits/multiplier/v1", "key-2", "").status, 200);
+    assert_eq!(request("DELETE", "/circuits/multiplier/v1", "key-2", "").status, 404);
+    assert_eq!(request("POST", "/prove/multiplier/v1", "key-1", job).status, 404);
+
+    // without authentication there is no admin API
+    let open = Server::from_registry(Registry::load(&dir.join("registry.json"), 10).unwrap(), ServeOptions::default());
+    assert_eq!(open.handle("10.0.0.1", &mut &b"GET /circuits HTTP/1.1\r\n\r\n"[..]).status, 403);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn authorized_requests() {
+    use sha2::{Digest, Sha256};
+    use crate::auth::{ApiKey, AuthConfig};
+    use crate::test_utils::{multiplier_circuit, multiplier_params};
+    let (r1cs, params) = (multiplier_circuit(), multiplier_params());
+    let key = |name: &str, key: &[u8], circuit: &str| ApiKey {
+        name: name.to_string(),
+        sha256: Sha256::digest(key).iter().map(|b| format!("{:02x}", b)).collect(),
+        circuits: vec![circuit.to_string()],
+        admin: false,
+    };
+    let config = AuthConfig { keys: vec![key("payments", b"key-1", "transfer"), key("risk", b"key-2", "liquidation")], jwt_secret_file: None };
+    let options = ServeOptions { client_rate_limit: Some("1/h".parse().unwrap()), ..ServeOptions::default() };
+    let server = Server::new(&r1cs, &params, options).with_auth(Auth::new(config, None).unwrap(), "transfer");
+    let post = |client: &str, authorization: &str| {
+        let body = r#"{"id": "1", "witness": ["1", "6", "2", "3"]}"#;
+        let request = format!("POST /prove HTTP/1.1\r\n{}Content-Length: {}\r\n\r\n{}", authorization, body.len(), body);
+        server.handle(client, &mut request.as_bytes())
+    };
+
+    let refused = post("10.0.0.1", "");
+    assert_eq!((refused.status, refused.headers.last().unwrap()), (401, &("WWW-Authenticate", String::from("Bearer"))));
+    assert_eq!(post("10.0.0.1", "Authorization: Bearer key-3\r\n").status, 401);
+    assert_eq!(post("10.0.0.1", "Authorization: Bearer key-2\r\n").status, 403);
+    assert_eq!(post("10.0.0.1", "Authorization: Bearer key-1\r\n").status, 200);
+    // rate limits count requests of the key, not of the address
+    assert_eq!(post("10.0.0.2", "Authorization: Bearer key-1\r\n").status, 429);
+}
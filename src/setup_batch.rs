@@ -0,0 +1,137 @@
+//! Trusted setup of many circuits in one run, e.g. regenerating all keys of a release.
+//!
+//! Every circuit gets its own RNG, so no toxic waste is shared between circuits: a fresh OS RNG
+//! normally, and the fixed seed of `setup --dev` with `dev`, which makes every params file identical
+//! to one from a separate `setup --dev` run. Circuits are set up on `jobs` threads in parallel, the
+//! params of `dir/name.r1cs` are written to `<output_dir>/name.params.bin` and hashes of all inputs
+//! and outputs to a JSON manifest.
+use std::collections::HashSet;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+use crate::circom_circuit::write_params_file_with_metadata;
+use crate::circuit::load_r1cs;
+use crate::file_header::FLAG_SIMPLIFIED;
+use crate::keys::{circuit_hash, params_metadata, setup};
+use crate::provenance::{file_sha256, setup_entropy, EntropySource};
+use crate::remote::to_hex;
+use crate::utils::write_file_atomic;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SetupManifest {
+    pub zkutil_version: String,
+    pub entropy: EntropySource,
+    pub circuits: Vec<SetupManifestEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SetupManifestEntry {
+    pub circuit: String,
+    pub circuit_sha256: String,
+    /// `keys::circuit_hash` of the constraints, also stored in the params metadata
+    pub constraints_hash: String,
+    pub params: String,
+    pub params_sha256: String,
+    pub seconds: f64,
+}
+
+/// Params file `setup_many` writes for a circuit
+pub fn params_file_for(circuit: &str, output_dir: &str) -> String {
+    let stem = Path::new(circuit).file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    Path::new(output_dir).join(format!("{}.params.bin", stem)).to_string_lossy().to_string()
+}
+
+fn setup_one(circuit: &str, params_file: &str, dev: bool, extra_flags: u32) -> Result<SetupManifestEntry> {
+    let start = Instant::now();
+    let mut r1cs = load_r1cs(circuit)?;
+    if extra_flags & FLAG_SIMPLIFIED != 0 {
+        r1cs.simplify();
+    }
+    let constraints_hash = to_hex(&circuit_hash(&r1cs));
+    let metadata = params_metadata(&r1cs);
+    let (params, flags) = setup(r1cs, dev).map_err(|e| Error::new(ErrorKind::InvalidData, format!("{:?}", e)))?;
+    write_params_file_with_metadata(&params, flags | extra_flags, &metadata, params_file)?;
+    Ok(SetupManifestEntry {
+        circuit: circuit.to_string(),
+        circuit_sha256: file_sha256(circuit)?,
+        constraints_hash,
+        params: params_file.to_string(),
+        params_sha256: file_sha256(params_file)?,
+        seconds: start.elapsed().as_secs_f64(),
+    })
+}
+
+/// Sets up `circuits` on `jobs` threads, with `extra_flags` like `FLAG_ZSTD` added to every params
+/// file and circuits simplified first for `FLAG_SIMPLIFIED`. Results are in the order of `circuits`,
+/// `on_done` is called as soon as a circuit is done
+pub fn setup_many<F: Fn(&str, &Result<SetupManifestEntry>) + Sync>(
+    circuits: &[String],
+    output_dir: &str,
+    dev: bool,
+    extra_flags: u32,
+    jobs: usize,
+    on_done: F,
+) -> Result<Vec<Result<SetupManifestEntry>>> {
+    let outputs = circuits.iter().map(|c| params_file_for(c, output_dir)).collect::<Vec<_>>();
+    let mut seen = HashSet::new();
+    if let Some(duplicate) = outputs.iter().find(|o| !seen.insert(o.as_str())) {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("several circuits would be written to {}, rename them", duplicate)));
+    }
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new((0..circuits.len()).map(|_| None).collect::<Vec<_>>());
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.max(1).min(circuits.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                if i >= circuits.len() {
+                    break;
+                }
+                let result = setup_one(&circuits[i], &outputs[i], dev, extra_flags);
+                on_done(&circuits[i], &result);
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+    Ok(results.into_inner().unwrap().into_iter().map(|r| r.expect("every circuit is set up")).collect())
+}
+
+pub fn write_setup_manifest_file(entries: Vec<SetupManifestEntry>, dev: bool, filename: &str) -> Result<()> {
+    let manifest = SetupManifest {
+        zkutil_version: env!("CARGO_PKG_VERSION").to_string(),
+        entropy: setup_entropy(dev),
+        circuits: entries,
+    };
+    write_file_atomic(filename, |w| serde_json::to_writer_pretty(w, &manifest).map_err(Error::from))
+}
+
+#[test]
+fn setup_many_circuits() {
+    use crate::keys::load_params;
+    let dir = std::env::temp_dir().join(format!("zkutil-setup-many-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+    let mul = r#"{"nPubInputs": 1, "nOutputs": 0, "nVars": 4, "constraints": [[{"2": "1"}, {"3": "1"}, {"1": "1"}]]}"#;
+    let square = r#"{"nPubInputs": 1, "nOutputs": 0, "nVars": 3, "constraints": [[{"2": "1"}, {"2": "1"}, {"1": "1"}]]}"#;
+    std::fs::write(path("mul.json"), mul).unwrap();
+    std::fs::write(path("square.json"), square).unwrap();
+    let circuits = vec![path("mul.json"), path("square.json"), path("missing.json")];
+    let output_dir = dir.to_string_lossy().to_string();
+    let done = AtomicUsize::new(0);
+    let results = setup_many(&circuits, &output_dir, true, 0, 2, |_, _| {
+        done.fetch_add(1, Ordering::SeqCst);
+    }).unwrap();
+    assert_eq!(done.into_inner(), 3);
+    assert!(results[2].is_err());
+    let entry = results[0].as_ref().unwrap();
+    assert_eq!(entry.params, path("mul.params.bin"));
+    let (header, params) = load_params(&entry.params).unwrap();
+    assert!(header.unwrap().is_insecure_dev());
+    assert_eq!(params.vk.ic.len(), 2);
+    assert_eq!(results[1].as_ref().unwrap().params_sha256, file_sha256(&path("square.params.bin")).unwrap());
+
+    let duplicate = vec![path("mul.json"), dir.join("sub").join("mul.r1cs").to_string_lossy().to_string()];
+    assert_eq!(setup_many(&duplicate, &output_dir, true, 0, 1, |_, _| {}).unwrap_err().kind(), ErrorKind::InvalidInput);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
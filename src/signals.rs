@@ -0,0 +1,194 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, Error, ErrorKind, Read, Result};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Visibility {
+    /// Wire 0, always equal to 1
+    One,
+    PublicOutput,
+    PublicInput,
+    PrivateInput,
+    Private,
+}
+
+impl Visibility {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Visibility::One => "constant",
+            Visibility::PublicOutput => "public output",
+            Visibility::PublicInput => "public input",
+            Visibility::PrivateInput => "private input",
+            Visibility::Private => "private",
+        }
+    }
+}
+
+pub struct Signal {
+    pub wire: usize,
+    pub name: String,
+    pub visibility: Visibility,
+}
+
+impl Signal {
+    /// Position of this signal in public.json, if it is public
+    pub fn public_index(&self) -> Option<usize> {
+        match self.visibility {
+            Visibility::PublicOutput | Visibility::PublicInput => Some(self.wire - 1),
+            _ => None,
+        }
+    }
+}
+
+/// Number of signals of each kind, as in the circom wire layout
+#[derive(Clone, Copy)]
+pub struct SignalCounts {
+    pub num_outputs: usize,
+    pub num_pub_inputs: usize,
+    pub num_prv_inputs: usize,
+}
+
+#[derive(Deserialize)]
+struct SignalJson {
+    names: Vec<String>,
+    id: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct CircuitSignalsJson {
+    signals: Vec<SignalJson>,
+    #[serde(rename = "nPubInputs")]
+    num_pub_inputs: usize,
+    #[serde(rename = "nOutputs")]
+    num_outputs: usize,
+    #[serde(rename = "nPrvInputs", default)]
+    num_prv_inputs: usize,
+}
+
+/// Reads circom `.sym` file lines `label,wire,component,name` and returns (wire, name) pairs,
+/// skipping signals that were optimized away
+pub fn read_sym<R: BufRead>(reader: R) -> Result<Vec<(usize, String)>> {
+    let mut result = vec![];
+    for (n, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.splitn(4, ',').collect();
+        if fields.len() != 4 {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Invalid sym file line {}: {}", n + 1, line)));
+        }
+        let wire: i64 = fields[1].parse()
+            .map_err(|_| Error::new(ErrorKind::InvalidData, format!("Invalid wire index on sym file line {}", n + 1)))?;
+        if wire >= 0 {
+            result.push((wire as usize, fields[3].to_string()));
+        }
+    }
+    Ok(result)
+}
+
+/// Reads signal names from legacy circuit.json, returns (wire, name) pairs and signal counts
+pub fn signals_from_json<R: Read>(reader: R) -> Result<(Vec<(usize, String)>, SignalCounts)> {
+    let circuit: CircuitSignalsJson = serde_json::from_reader(reader)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    let names = circuit.signals.into_iter()
+        .enumerate()
+        .flat_map(|(i, s)| {
+            let wire = s.id.unwrap_or(i);
+            s.names.into_iter().map(move |name| (wire, name))
+        })
+        .collect();
+    let counts = SignalCounts {
+        num_outputs: circuit.num_outputs,
+        num_pub_inputs: circuit.num_pub_inputs,
+        num_prv_inputs: circuit.num_prv_inputs,
+    };
+    Ok((names, counts))
+}
+
+/// Classifies named wires using circom layout:
+/// one, public outputs, public inputs, private inputs, other private signals
+pub fn classify(mut names: Vec<(usize, String)>, counts: SignalCounts) -> Vec<Signal> {
+    let SignalCounts { num_outputs, num_pub_inputs, num_prv_inputs } = counts;
+    names.sort_by_key(|(wire, _)| *wire);
+    names.into_iter().map(|(wire, name)| {
+        let visibility = if wire == 0 {
+            Visibility::One
+        } else if wire <= num_outputs {
+            Visibility::PublicOutput
+        } else if wire <= num_outputs + num_pub_inputs {
+            Visibility::PublicInput
+        } else if wire <= num_outputs + num_pub_inputs + num_prv_inputs {
+            Visibility::PrivateInput
+        } else {
+            Visibility::Private
+        };
+        Signal { wire, name, visibility }
+    }).collect()
+}
+
+/// Splits `main.a[1][2]` into `("a", [1, 2])`, returns None for signals of subcomponents
+fn parse_main_signal(name: &str) -> Option<(&str, Vec<usize>)> {
+    let name = name.strip_prefix("main.")?;
+    let base_end = name.find('[').unwrap_or_else(|| name.len());
+    let (base, rest) = name.split_at(base_end);
+    if base.contains('.') {
+        return None;
+    }
+    let indices = rest.split(|c| c == '[' || c == ']')
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse().ok())
+        .collect::<Option<Vec<usize>>>()?;
+    Some((base, indices))
+}
+
+fn placeholder_array(dims: &[usize]) -> serde_json::Value {
+    match dims.split_first() {
+        None => serde_json::Value::String(String::from("0")),
+        Some((len, rest)) => serde_json::Value::Array((0..*len).map(|_| placeholder_array(rest)).collect()),
+    }
+}
+
+/// Builds input.json skeleton with every input signal of the main component set to "0"
+pub fn input_template(signals: &[Signal]) -> serde_json::Map<String, serde_json::Value> {
+    let mut dims = BTreeMap::<&str, Vec<usize>>::new();
+    for signal in signals {
+        if signal.visibility != Visibility::PublicInput && signal.visibility != Visibility::PrivateInput {
+            continue;
+        }
+        if let Some((base, indices)) = parse_main_signal(&signal.name) {
+            let entry = dims.entry(base).or_insert_with(|| vec![0; indices.len()]);
+            for (dim, index) in entry.iter_mut().zip(indices) {
+                *dim = (*dim).max(index + 1);
+            }
+        }
+    }
+    dims.into_iter().map(|(base, dims)| (base.to_string(), placeholder_array(&dims))).collect()
+}
+
+#[test]
+fn template() {
+    let names = vec![
+        (1, String::from("main.out")),
+        (2, String::from("main.root")),
+        (3, String::from("main.path[0]")),
+        (4, String::from("main.path[1]")),
+        (4, String::from("main.hasher.in[1]")),
+        (5, String::from("main.hasher.out")),
+    ];
+    let counts = SignalCounts { num_outputs: 1, num_pub_inputs: 1, num_prv_inputs: 2 };
+    let template = input_template(&classify(names, counts));
+    assert_eq!(serde_json::Value::Object(template).to_string(), r#"{"path":["0","0"],"root":"0"}"#);
+}
+
+#[test]
+fn sym() {
+    let names = read_sym(&b"1,1,0,main.out\n2,-1,0,main.tmp\n3,2,0,main.a\n4,3,0,main.b\n"[..]).unwrap();
+    assert_eq!(names.len(), 3);
+    let signals = classify(names, SignalCounts { num_outputs: 1, num_pub_inputs: 1, num_prv_inputs: 0 });
+    assert_eq!(signals[0].visibility, Visibility::PublicOutput);
+    assert_eq!(signals[0].public_index(), Some(0));
+    assert_eq!(signals[1].name, "main.a");
+    assert_eq!(signals[1].visibility, Visibility::PublicInput);
+    assert_eq!(signals[2].visibility, Visibility::Private);
+    assert_eq!(signals[2].public_index(), None);
+}
@@ -0,0 +1,233 @@
+//! Ed25519 signatures of parameter files in minisign format.
+//!
+//! Signatures are detached `<file>.minisig` files that `minisign -Vm params.bin -p zkutil.pub`
+//! accepts, and public keys are minisign public keys. Files are prehashed with BLAKE2b-512, so
+//! signing and verification stream large parameter files. Secret keys are zkutil's own
+//! unencrypted format: the 2-byte algorithm, 8-byte key id and 32-byte seed in base64.
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{Error, ErrorKind, Read, Result};
+use std::path::Path;
+use blake2::{Blake2b, Digest};
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer, Verifier};
+use rand::Rng;
+use crate::provenance::unix_time;
+use crate::utils::write_file_atomic;
+
+const ALG_PURE: &[u8; 2] = b"Ed";
+const ALG_PREHASHED: &[u8; 2] = b"ED";
+
+fn invalid<T: Into<Box<dyn std::error::Error + Send + Sync>>>(msg: T) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// Default signature file of `filename`
+pub fn signature_file(filename: &str) -> String {
+    format!("{}.minisig", filename)
+}
+
+pub struct SigningKey {
+    pub key_id: [u8; 8],
+    keypair: Keypair,
+}
+
+pub struct VerifyingKey {
+    pub key_id: [u8; 8],
+    public: PublicKey,
+}
+
+/// Key id as minisign prints it
+pub fn key_id_hex(key_id: &[u8; 8]) -> String {
+    key_id.iter().rev().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// Base64 payload of a minisign file line after the comment lines
+fn decode_line(line: Option<&str>, what: &str) -> Result<Vec<u8>> {
+    let line = line.ok_or_else(|| invalid(format!("{} is truncated", what)))?;
+    base64::decode(line.trim()).map_err(|e| invalid(format!("invalid base64 in {}: {}", what, e)))
+}
+
+/// Payload of a file with an optional `untrusted comment:` line followed by base64
+fn read_keyfile(filename: &str) -> Result<Vec<u8>> {
+    let content = fs::read_to_string(filename)?;
+    let line = content.lines().find(|l| !l.trim().is_empty() && !l.starts_with("untrusted comment:"));
+    decode_line(line, filename)
+}
+
+impl SigningKey {
+    pub fn generate<R: Rng>(rng: &mut R) -> Self {
+        let mut seed = [0u8; 32];
+        rng.fill_bytes(&mut seed);
+        let mut key_id = [0u8; 8];
+        rng.fill_bytes(&mut key_id);
+        Self::from_seed(key_id, &seed).expect("any 32 bytes are a valid seed")
+    }
+
+    fn from_seed(key_id: [u8; 8], seed: &[u8]) -> Result<Self> {
+        let secret = SecretKey::from_bytes(seed).map_err(|e| invalid(e.to_string()))?;
+        let public = PublicKey::from(&secret);
+        Ok(SigningKey { key_id, keypair: Keypair { secret, public } })
+    }
+
+    pub fn load(filename: &str) -> Result<Self> {
+        let bytes = read_keyfile(filename)?;
+        if bytes.len() != 42 || &bytes[..2] != ALG_PURE {
+            return Err(invalid(format!("{} is not a zkutil secret key", filename)));
+        }
+        let mut key_id = [0u8; 8];
+        key_id.copy_from_slice(&bytes[2..10]);
+        Self::from_seed(key_id, &bytes[10..])
+    }
+
+    pub fn save(&self, filename: &str) -> Result<()> {
+        let mut bytes = ALG_PURE.to_vec();
+        bytes.extend_from_slice(&self.key_id);
+        bytes.extend_from_slice(self.keypair.secret.as_bytes());
+        let content = format!("untrusted comment: zkutil secret key {}, keep it private\n{}\n", key_id_hex(&self.key_id), base64::encode(&bytes));
+        write_file_atomic(filename, |w| std::io::Write::write_all(w, content.as_bytes()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(filename, fs::Permissions::from_mode(0o600))?;
+        }
+        Ok(())
+    }
+
+    pub fn verifying_key(&self) -> VerifyingKey {
+        VerifyingKey { key_id: self.key_id, public: self.keypair.public }
+    }
+
+    /// Signs `filename`, `comment` is covered by the signature and shown on verification
+    pub fn sign_file(&self, filename: &str, comment: &str) -> Result<String> {
+        let signature = self.keypair.sign(&blake2b_file(filename)?);
+        let mut payload = ALG_PREHASHED.to_vec();
+        payload.extend_from_slice(&self.key_id);
+        payload.extend_from_slice(&signature.to_bytes());
+        let trusted_comment = format!("timestamp:{}\tfile:{}\t{}", unix_time(), file_name(filename), comment);
+        let mut global = signature.to_bytes().to_vec();
+        global.extend_from_slice(trusted_comment.as_bytes());
+        let global_signature = self.keypair.sign(&global);
+        Ok(format!(
+            "untrusted comment: signature from zkutil secret key {}\n{}\ntrusted comment: {}\n{}\n",
+            key_id_hex(&self.key_id),
+            base64::encode(&payload),
+            trusted_comment,
+            base64::encode(&global_signature.to_bytes()[..]),
+        ))
+    }
+
+    pub fn sign_file_to(&self, filename: &str, comment: &str, signature_file: &str) -> Result<()> {
+        let signature = self.sign_file(filename, comment)?;
+        write_file_atomic(signature_file, |w| std::io::Write::write_all(w, signature.as_bytes()))
+    }
+}
+
+fn file_name(filename: &str) -> String {
+    Path::new(filename).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+fn blake2b_file(filename: &str) -> Result<Vec<u8>> {
+    let mut file = File::open(filename)?;
+    let mut hasher = Blake2b::new();
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize().to_vec())
+}
+
+fn parse_signature(bytes: &[u8]) -> Result<Signature> {
+    Signature::try_from(bytes).map_err(|e| invalid(format!("invalid signature: {}", e)))
+}
+
+impl VerifyingKey {
+    /// Reads a minisign public key file, or a bare base64 key as `minisign -P` takes it
+    pub fn load(filename: &str) -> Result<Self> {
+        let bytes = read_keyfile(filename)?;
+        if bytes.len() != 42 || &bytes[..2] != ALG_PURE {
+            return Err(invalid(format!("{} is not a minisign public key", filename)));
+        }
+        let mut key_id = [0u8; 8];
+        key_id.copy_from_slice(&bytes[2..10]);
+        let public = PublicKey::from_bytes(&bytes[10..]).map_err(|e| invalid(e.to_string()))?;
+        Ok(VerifyingKey { key_id, public })
+    }
+
+    pub fn save(&self, filename: &str) -> Result<()> {
+        let mut bytes = ALG_PURE.to_vec();
+        bytes.extend_from_slice(&self.key_id);
+        bytes.extend_from_slice(self.public.as_bytes());
+        let content = format!("untrusted comment: zkutil public key {}\n{}\n", key_id_hex(&self.key_id), base64::encode(&bytes));
+        write_file_atomic(filename, |w| std::io::Write::write_all(w, content.as_bytes()))
+    }
+
+    /// Verifies a minisign signature of `filename`, returns the trusted comment
+    pub fn verify_file(&self, filename: &str, signature_file: &str) -> Result<String> {
+        let content = fs::read_to_string(signature_file)?;
+        let mut lines = content.lines();
+        if !lines.next().map_or(false, |l| l.starts_with("untrusted comment:")) {
+            return Err(invalid(format!("{} is not a minisign signature", signature_file)));
+        }
+        let payload = decode_line(lines.next(), signature_file)?;
+        if payload.len() != 74 {
+            return Err(invalid(format!("{} is not a minisign signature", signature_file)));
+        }
+        let mut key_id = [0u8; 8];
+        key_id.copy_from_slice(&payload[2..10]);
+        if key_id != self.key_id {
+            return Err(invalid(format!(
+                "{} is signed with key {}, expected {}",
+                signature_file,
+                key_id_hex(&key_id),
+                key_id_hex(&self.key_id),
+            )));
+        }
+        let signature = parse_signature(&payload[10..])?;
+        let message = match &payload[..2] {
+            alg if alg == ALG_PREHASHED => blake2b_file(filename)?,
+            alg if alg == ALG_PURE => fs::read(filename)?,
+            _ => return Err(invalid(format!("unsupported signature algorithm in {}", signature_file))),
+        };
+        self.public.verify(&message, &signature)
+            .map_err(|_| invalid(format!("signature of {} is invalid, the file was modified or signed by another key", filename)))?;
+
+        let trusted_comment = lines.next()
+            .and_then(|l| l.strip_prefix("trusted comment: "))
+            .ok_or_else(|| invalid(format!("{} has no trusted comment", signature_file)))?;
+        let global_signature = parse_signature(&decode_line(lines.next(), signature_file)?)?;
+        let mut global = payload[10..].to_vec();
+        global.extend_from_slice(trusted_comment.as_bytes());
+        self.public.verify(&global, &global_signature)
+            .map_err(|_| invalid(format!("trusted comment of {} is invalid", signature_file)))?;
+        Ok(trusted_comment.to_string())
+    }
+}
+
+#[test]
+fn sign_and_verify() {
+    use rand::{SeedableRng, XorShiftRng};
+    let dir = std::env::temp_dir().join(format!("zkutil-signing-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+    fs::write(path("params.bin"), b"parameters").unwrap();
+
+    let key = SigningKey::generate(&mut XorShiftRng::from_seed([1, 2, 3, 4]));
+    key.save(&path("zkutil.key")).unwrap();
+    key.verifying_key().save(&path("zkutil.pub")).unwrap();
+    let key = SigningKey::load(&path("zkutil.key")).unwrap();
+    let public = VerifyingKey::load(&path("zkutil.pub")).unwrap();
+
+    key.sign_file_to(&path("params.bin"), "circuit:test", &path("params.bin.minisig")).unwrap();
+    let comment = public.verify_file(&path("params.bin"), &path("params.bin.minisig")).unwrap();
+    assert!(comment.ends_with("file:params.bin\tcircuit:test"));
+
+    fs::write(path("params.bin"), b"Parameters").unwrap();
+    assert!(public.verify_file(&path("params.bin"), &path("params.bin.minisig")).is_err());
+    let other = SigningKey::generate(&mut XorShiftRng::from_seed([5, 6, 7, 8])).verifying_key();
+    assert!(other.verify_file(&path("params.bin"), &path("params.bin.minisig")).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,96 @@
+//! Solidity verifier contract generation.
+use std::io::Write;
+use bellman_ce::{
+    groth16::{Parameters, VerifyingKey},
+    pairing::{
+        Engine,
+        CurveAffine,
+        ff::PrimeField,
+        bn256::Bn256,
+    },
+};
+use crate::utils::{repr_to_big, write_file_atomic};
+
+/// Options of the generated verifier contract
+#[derive(Clone, Copy, Default)]
+pub struct VerifierOptions {
+    /// Generate a Solidity 0.8 contract that reverts with custom errors identifying the failed check,
+    /// exposes non-reverting `tryVerify` returning a reason code and `verify` emitting `ProofVerified`
+    pub custom_errors: bool,
+}
+
+pub fn create_verifier_sol(params: &Parameters<Bn256>) -> String {
+    create_verifier_sol_with_options(params, VerifierOptions::default())
+}
+
+pub fn create_verifier_sol_with_options(params: &Parameters<Bn256>, options: VerifierOptions) -> String {
+    create_verifier_sol_for_key(&params.vk, options)
+}
+
+/// Generates the contract from just the verifying key, see `keys::load_verifying_key`
+pub fn create_verifier_sol_for_key(vk: &VerifyingKey<Bn256>, options: VerifierOptions) -> String {
+    // TODO: use a simple template engine
+    let bytes: &[u8] = if options.custom_errors {
+        include_bytes!("verifier_groth_errors.sol")
+    } else {
+        include_bytes!("verifier_groth.sol")
+    };
+    let template = String::from_utf8_lossy(bytes);
+
+    let p1_to_str = |p: &<Bn256 as Engine>::G1Affine| {
+        if p.is_zero() {
+            // todo: throw instead
+            return String::from("<POINT_AT_INFINITY>");
+        }
+        let xy = p.into_xy_unchecked();
+        let x = repr_to_big(xy.0.into_repr());
+        let y = repr_to_big(xy.1.into_repr());
+        format!("uint256({}), uint256({})", x, y)
+    };
+    let p2_to_str = |p: &<Bn256 as Engine>::G2Affine| {
+        if p.is_zero() {
+            // todo: throw instead
+            return String::from("<POINT_AT_INFINITY>");
+        }
+        let xy = p.into_xy_unchecked();
+        let x_c0 = repr_to_big(xy.0.c0.into_repr());
+        let x_c1 = repr_to_big(xy.0.c1.into_repr());
+        let y_c0 = repr_to_big(xy.1.c0.into_repr());
+        let y_c1 = repr_to_big(xy.1.c1.into_repr());
+        format!("[uint256({}), uint256({})], [uint256({}), uint256({})]", x_c1, x_c0, y_c1, y_c0)
+    };
+
+    let template = template.replace("<%vk_alfa1%>", &p1_to_str(&vk.alpha_g1));
+    let template = template.replace("<%vk_beta2%>", &p2_to_str(&vk.beta_g2));
+    let template = template.replace("<%vk_gamma2%>", &p2_to_str(&vk.gamma_g2));
+    let template = template.replace("<%vk_delta2%>", &p2_to_str(&vk.delta_g2));
+
+    let template = template.replace("<%vk_ic_length%>", &vk.ic.len().to_string());
+    let template = template.replace("<%vk_input_length%>", &(vk.ic.len() - 1).to_string());
+
+    let mut vi = String::from("");
+    for i in 0..vk.ic.len() {
+        vi = format!("{}{}vk.IC[{}] = Pairing.G1Point({});\n", vi, if vi.is_empty() { "" } else { "        " }, i, &*p1_to_str(&vk.ic[i]));
+    }
+    template.replace("<%vk_ic_pts%>", &vi)
+}
+
+pub fn write_verifier_sol<W: Write>(params: &Parameters<Bn256>, writer: W) -> std::io::Result<()> {
+    write_verifier_sol_with_options(params, VerifierOptions::default(), writer)
+}
+
+pub fn write_verifier_sol_with_options<W: Write>(params: &Parameters<Bn256>, options: VerifierOptions, mut writer: W) -> std::io::Result<()> {
+    writer.write_all(create_verifier_sol_with_options(params, options).as_bytes())
+}
+
+pub fn create_verifier_sol_file(params: &Parameters<Bn256>, filename: &str) -> std::io::Result<()> {
+    create_verifier_sol_file_with_options(params, VerifierOptions::default(), filename)
+}
+
+pub fn create_verifier_sol_file_with_options(params: &Parameters<Bn256>, options: VerifierOptions, filename: &str) -> std::io::Result<()> {
+    write_file_atomic(filename, |w| write_verifier_sol_with_options(params, options, w))
+}
+
+pub fn create_verifier_sol_file_for_key(vk: &VerifyingKey<Bn256>, options: VerifierOptions, filename: &str) -> std::io::Result<()> {
+    write_file_atomic(filename, |w| w.write_all(create_verifier_sol_for_key(vk, options).as_bytes()))
+}
@@ -0,0 +1,57 @@
+//! OTLP export of tracing spans, enabled with the `otlp` feature.
+//!
+//! The trace context is taken from the W3C `TRACEPARENT` and `TRACESTATE` variables, so spans of a
+//! zkutil run started by a traced service join its trace. Jobs consumed from NATS don't carry a trace
+//! context, their spans start new traces.
+use std::collections::HashMap;
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::sdk::propagation::TraceContextPropagator;
+use opentelemetry_otlp::WithExportConfig;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// Standard OpenTelemetry variable with the collector endpoint, e.g. `http://localhost:4318/v1/traces`
+pub const ENDPOINT_VAR: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+/// Variables with the W3C trace context of the parent span, as set by e.g. `otel-cli exec`
+pub const TRACEPARENT_VAR: &str = "TRACEPARENT";
+pub const TRACESTATE_VAR: &str = "TRACESTATE";
+
+/// Installs OTLP span exporter if the collector endpoint is configured, returns whether it was installed
+pub fn init() -> Result<bool, String> {
+    let endpoint = match std::env::var(ENDPOINT_VAR) {
+        Ok(endpoint) => endpoint,
+        Err(_) => return Ok(false),
+    };
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(opentelemetry_otlp::new_exporter().http().with_endpoint(endpoint))
+        .with_trace_config(opentelemetry::sdk::trace::config().with_resource(
+            opentelemetry::sdk::Resource::new(vec![opentelemetry::KeyValue::new("service.name", "zkutil")]),
+        ))
+        .install_simple()
+        .map_err(|e| e.to_string())?;
+    tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .try_init()
+        .map_err(|e| e.to_string())?;
+    Ok(true)
+}
+
+/// Span of the whole run, a child of the span in `TRACEPARENT` if it is set
+pub fn root_span() -> tracing::Span {
+    let span = tracing::info_span!("zkutil");
+    let carrier = [("traceparent", TRACEPARENT_VAR), ("tracestate", TRACESTATE_VAR)].iter()
+        .filter_map(|(key, var)| std::env::var(var).ok().map(|value| (key.to_string(), value)))
+        .collect::<HashMap<_, _>>();
+    if !carrier.is_empty() {
+        span.set_parent(TraceContextPropagator::new().extract(&carrier));
+    }
+    span
+}
+
+/// Flushes pending spans, must be called before process exit
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
@@ -0,0 +1,34 @@
+//! Fixtures shared by unit tests.
+use bellman_ce::{
+    groth16::Parameters,
+    pairing::{
+        bn256::{Bn256, Fr},
+        ff::PrimeField,
+    },
+};
+use crate::circom_circuit::{create_insecure_dev_rng, generate_random_parameters, CircomCircuit, R1CS};
+
+pub fn fr(s: &str) -> Fr {
+    Fr::from_str(s).unwrap()
+}
+
+/// Wires one, c (public), a, b and the constraint a * b = c
+pub fn multiplier_circuit() -> R1CS<Bn256> {
+    R1CS {
+        num_inputs: 2,
+        num_aux: 2,
+        num_variables: 4,
+        constraints: vec![(vec![(2, fr("1"))], vec![(3, fr("1"))], vec![(1, fr("1"))])],
+    }
+}
+
+/// Witness of `multiplier_circuit` for `a * b`
+pub fn multiplier_witness(a: u64, b: u64) -> Vec<Fr> {
+    vec![fr("1"), fr(&(a * b).to_string()), fr(&a.to_string()), fr(&b.to_string())]
+}
+
+/// Parameters of `multiplier_circuit` generated from the insecure dev seed
+pub fn multiplier_params() -> Parameters<Bn256> {
+    let circuit = CircomCircuit { r1cs: multiplier_circuit(), witness: None, wire_mapping: None };
+    generate_random_parameters(circuit, create_insecure_dev_rng()).unwrap()
+}
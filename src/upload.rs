@@ -0,0 +1,198 @@
+//! Resumable witness uploads of `serve`. A witness is sent with `PUT /uploads/<name>` as a streamed
+//! body that is appended to a file in the upload directory as it arrives, so neither side holds the
+//! whole witness in memory. An interrupted upload continues from the last stored byte, and the
+//! request with the SHA-256 of the whole file completes it. A completed upload is proved by a job
+//! with the upload name as `witness_path`.
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use sha2::{Digest, Sha256};
+use crate::utils::hex_to_bytes;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct UploadStatus {
+    pub upload: String,
+    /// Bytes stored so far, the offset to continue at
+    pub received: u64,
+    pub complete: bool,
+}
+
+#[derive(Debug)]
+pub enum UploadError {
+    InvalidName,
+    /// Data was sent at another offset than the number of bytes received
+    Offset { expected: u64 },
+    TooLarge(u64),
+    /// Another request is appending to the same upload
+    InProgress,
+    InvalidHash,
+    /// The upload is corrupted and starts over
+    HashMismatch,
+    /// Reading the data failed, what was read before is kept
+    Interrupted(io::Error),
+    Io(io::Error),
+}
+
+impl fmt::Display for UploadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UploadError::InvalidName => write!(f, "upload names are file names ending with .wtns or .json"),
+            UploadError::Offset { expected } => write!(f, "upload continues at offset {}", expected),
+            UploadError::TooLarge(max_size) => write!(f, "uploads are limited to {} bytes", max_size),
+            UploadError::InProgress => write!(f, "upload is in progress in another request"),
+            UploadError::InvalidHash => write!(f, "sha256 is not 32 hex bytes"),
+            UploadError::HashMismatch => write!(f, "SHA-256 of the upload doesn't match, upload it again"),
+            UploadError::Interrupted(e) => write!(f, "upload was interrupted: {}", e),
+            UploadError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<io::Error> for UploadError {
+    fn from(e: io::Error) -> Self {
+        UploadError::Io(e)
+    }
+}
+
+/// Upload names are plain file names, so an upload can't be written outside of the directory
+pub fn valid_name(name: &str) -> bool {
+    name.len() <= 128
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-')
+        && (name.ends_with(".wtns") || name.ends_with(".json"))
+}
+
+fn file_sha256(path: &Path) -> io::Result<Vec<u8>> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().to_vec())
+}
+
+pub struct Uploads {
+    dir: PathBuf,
+    max_size: u64,
+    /// Uploads being appended to
+    active: Mutex<HashSet<String>>,
+}
+
+impl Uploads {
+    /// Stores uploads of up to `max_size` bytes in `dir`
+    pub fn new(dir: &Path, max_size: u64) -> Self {
+        Uploads { dir: dir.to_path_buf(), max_size, active: Mutex::default() }
+    }
+
+    /// Incomplete uploads are dotfiles, which no upload can be named
+    fn part_file(&self, name: &str) -> PathBuf {
+        self.dir.join(format!(".{}.part", name))
+    }
+
+    /// Path of a completed upload
+    pub fn path(&self, name: &str) -> Option<PathBuf> {
+        Some(self.dir.join(name)).filter(|path| valid_name(name) && path.is_file())
+    }
+
+    pub fn status(&self, name: &str) -> Result<UploadStatus, UploadError> {
+        if !valid_name(name) {
+            return Err(UploadError::InvalidName);
+        }
+        let status = |received, complete| UploadStatus { upload: name.to_string(), received, complete };
+        match fs::metadata(self.dir.join(name)) {
+            Ok(metadata) => Ok(status(metadata.len(), true)),
+            Err(_) => Ok(status(fs::metadata(self.part_file(name)).map_or(0, |m| m.len()), false)),
+        }
+    }
+
+    /// Appends `data` to an upload at `offset`, which has to be the number of bytes received so
+    /// far, and completes the upload if `sha256` is the hash of the whole file. Data read before
+    /// `data` fails is kept, the status tells where to continue
+    pub fn append<R: Read>(&self, name: &str, offset: u64, data: &mut R, sha256: Option<&str>) -> Result<UploadStatus, UploadError> {
+        let status = self.status(name)?;
+        if status.complete {
+            return Ok(status);
+        }
+        if !self.active.lock().unwrap().insert(name.to_string()) {
+            return Err(UploadError::InProgress);
+        }
+        let result = self.store(name, status.received, offset, data, sha256);
+        self.active.lock().unwrap().remove(name);
+        result
+    }
+
+    fn store<R: Read>(&self, name: &str, received: u64, offset: u64, data: &mut R, sha256: Option<&str>) -> Result<UploadStatus, UploadError> {
+        if offset != received {
+            return Err(UploadError::Offset { expected: received });
+        }
+        let expected = match sha256 {
+            Some(hash) => Some(hex_to_bytes(hash).filter(|h| h.len() == 32).ok_or(UploadError::InvalidHash)?),
+            None => None,
+        };
+        let part = self.part_file(name);
+        let mut writer = OpenOptions::new().create(true).append(true).open(&part)?;
+        // one byte more than allowed tells a body that is too large from one that fits exactly
+        let mut data = data.take(self.max_size - received + 1);
+        let mut buf = vec![0; 64 << 10];
+        let interrupted = loop {
+            match data.read(&mut buf) {
+                Ok(0) => break None,
+                Ok(n) => writer.write_all(&buf[..n])?,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => break Some(e),
+            }
+        };
+        writer.sync_data()?;
+        let received = fs::metadata(&part)?.len();
+        if received > self.max_size {
+            writer.set_len(self.max_size)?;
+            return Err(UploadError::TooLarge(self.max_size));
+        }
+        if let Some(e) = interrupted {
+            return Err(UploadError::Interrupted(e));
+        }
+        let expected = match expected {
+            Some(expected) => expected,
+            None => return Ok(UploadStatus { upload: name.to_string(), received, complete: false }),
+        };
+        if file_sha256(&part)? != expected {
+            fs::remove_file(&part)?;
+            return Err(UploadError::HashMismatch);
+        }
+        fs::rename(&part, self.dir.join(name))?;
+        Ok(UploadStatus { upload: name.to_string(), received, complete: true })
+    }
+}
+
+#[test]
+fn resumed_upload() {
+    let dir = std::env::temp_dir().join(format!("zkutil-upload-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let uploads = Uploads::new(&dir, 17);
+    let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    let witness = br#"["1","6","2","3"]"#;
+    let hash = hex(&Sha256::digest(witness));
+    assert_eq!(uploads.append("w.json", 0, &mut &witness[..8], None).unwrap().received, 8);
+    // a repeated request is not appended again, the error tells where to continue
+    assert!(matches!(uploads.append("w.json", 0, &mut &witness[..8], None), Err(UploadError::Offset { expected: 8 })));
+    assert_eq!(uploads.status("w.json").unwrap(), UploadStatus { upload: String::from("w.json"), received: 8, complete: false });
+    assert!(matches!(uploads.append("w.json", 8, &mut &b"0123456789"[..], None), Err(UploadError::TooLarge(17))));
+    // the data over the limit is dropped
+    assert_eq!(uploads.status("w.json").unwrap().received, 17);
+    assert!(matches!(uploads.append("w.json", 17, &mut &b""[..], Some(&hash)), Err(UploadError::HashMismatch)), "wrong data starts over");
+    assert_eq!(uploads.status("w.json").unwrap().received, 0);
+
+    assert_eq!(uploads.append("w.json", 0, &mut &witness[..16], None).unwrap().received, 16);
+    assert!(uploads.path("w.json").is_none());
+    assert!(matches!(uploads.append("w.json", 16, &mut &witness[16..], Some("12")), Err(UploadError::InvalidHash)));
+    let done = uploads.append("w.json", 16, &mut &witness[16..], Some(&hash)).unwrap();
+    assert_eq!(done, UploadStatus { upload: String::from("w.json"), received: 17, complete: true });
+    assert_eq!(fs::read(uploads.path("w.json").unwrap()).unwrap(), witness.to_vec());
+    assert!(uploads.status("w.json").unwrap().complete);
+
+    for name in ["../w.json", ".w.json", "w.txt", "a/w.wtns"].iter() {
+        assert!(matches!(uploads.append(name, 0, &mut &b""[..], None), Err(UploadError::InvalidName)), "{}", name);
+        assert!(uploads.path(name).is_none());
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
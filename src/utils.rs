@@ -1,10 +1,12 @@
 extern crate bellman_ce;
-extern crate rand;
 extern crate byteorder;
 extern crate num_bigint;
 extern crate num_traits;
 
 use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::{BufWriter, Result, Write};
+use std::path::{Path, PathBuf};
 use itertools::Itertools;
 use num_bigint::BigUint;
 use num_traits::Num;
@@ -22,10 +24,215 @@ use bellman_ce::{
     },
 };
 
+/// Runs `a` and `b` concurrently, or one after the other on wasi, where threads can't be spawned
+pub fn join<A, B: Send>(a: impl FnOnce() -> A, b: impl FnOnce() -> B + Send) -> (A, B) {
+    #[cfg(not(target_os = "wasi"))]
+    {
+        std::thread::scope(|scope| {
+            let b = scope.spawn(b);
+            (a(), b.join().expect("thread panicked"))
+        })
+    }
+    #[cfg(target_os = "wasi")]
+    {
+        (a(), b())
+    }
+}
+
+/// Task started by `background`
+pub struct Background<T> {
+    #[cfg(not(target_os = "wasi"))]
+    handle: std::thread::JoinHandle<T>,
+    #[cfg(target_os = "wasi")]
+    task: Box<dyn FnOnce() -> T + Send>,
+}
+
+/// Runs `f` on a new thread. On wasi, where threads can't be spawned, it runs when joined
+pub fn background<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> Background<T> {
+    #[cfg(not(target_os = "wasi"))]
+    {
+        Background { handle: std::thread::spawn(f) }
+    }
+    #[cfg(target_os = "wasi")]
+    {
+        Background { task: Box::new(f) }
+    }
+}
+
+impl<T> Background<T> {
+    pub fn join(self) -> T {
+        #[cfg(not(target_os = "wasi"))]
+        {
+            self.handle.join().expect("background task panicked")
+        }
+        #[cfg(target_os = "wasi")]
+        {
+            (self.task)()
+        }
+    }
+}
+
+/// User and system CPU time of all threads of the process so far, `None` where it can't be read
+pub fn process_cpu_time() -> Option<std::time::Duration> {
+    #[cfg(unix)]
+    {
+        let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+        if unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) } != 0 {
+            return None;
+        }
+        let usage = unsafe { usage.assume_init() };
+        let time = |t: libc::timeval| std::time::Duration::new(t.tv_sec as u64, t.tv_usec as u32 * 1000);
+        Some(time(usage.ru_utime) + time(usage.ru_stime))
+    }
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+/// Per-user cache directory: `$XDG_CACHE_HOME/zkutil`, `~/.cache/zkutil` or `%LOCALAPPDATA%\zkutil`
+pub fn user_cache_dir() -> Option<PathBuf> {
+    match std::env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => Some(PathBuf::from(dir).join("zkutil")),
+        None => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache").join("zkutil"))
+            .or_else(|| std::env::var_os("LOCALAPPDATA").map(|dir| PathBuf::from(dir).join("zkutil"))),
+    }
+}
+
+/// Creates `dir` with missing parents, on unix accessible only to the current user
+pub fn create_private_dir(dir: &Path) -> Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(dir)
+}
+
+/// Temporary file `write_file_atomic` writes `filename` through
+pub fn atomic_temp_path(filename: &str) -> PathBuf {
+    let path = Path::new(filename);
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(format!(".{}.tmp{}", name, std::process::id()))
+}
+
+/// Writes a file through a temporary file in the same directory that is renamed into place,
+/// so readers never observe partially written content
+pub fn write_file_atomic<F: FnOnce(&mut BufWriter<File>) -> Result<()>>(filename: &str, write: F) -> Result<()> {
+    let tmp = atomic_temp_path(filename);
+    let result = File::create(&tmp).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        write(&mut writer)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()
+    });
+    match result {
+        Ok(()) => fs::rename(&tmp, filename),
+        Err(e) => {
+            let _ = fs::remove_file(&tmp);
+            Err(e)
+        }
+    }
+}
+
 pub fn repr_to_big<T: Display>(r: T) -> String {
     BigUint::from_str_radix(&format!("{}", r)[2..], 16).unwrap().to_str_radix(10)
 }
 
+pub fn repr_to_biguint<T: Display>(r: T) -> BigUint {
+    BigUint::from_str_radix(&format!("{}", r)[2..], 16).unwrap()
+}
+
+/// How field elements are written to proof, public input and key JSON files
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NumberFormat {
+    /// Decimal strings, as snarkjs writes them
+    Decimal,
+    /// `0x`-prefixed lowercase hex strings without leading zeros
+    Hex,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        NumberFormat::Decimal
+    }
+}
+
+impl NumberFormat {
+    pub fn biguint(&self, n: &BigUint) -> String {
+        match self {
+            NumberFormat::Decimal => n.to_str_radix(10),
+            NumberFormat::Hex => format!("0x{}", n.to_str_radix(16)),
+        }
+    }
+
+    pub fn repr<T: Display>(&self, r: T) -> String {
+        self.biguint(&repr_to_biguint(r))
+    }
+
+    /// Formats a small constant like the `1` of affine points
+    pub fn small(&self, n: u32) -> String {
+        self.biguint(&BigUint::from(n))
+    }
+}
+
+/// Parses a field element from decimal or `0x`-prefixed hex string, optionally negated with `-`
+pub fn fr_from_str<F: PrimeField>(s: &str) -> Option<F> {
+    let s = s.trim();
+    if let Some(abs) = s.strip_prefix('-') {
+        return fr_from_str::<F>(abs).map(|mut x| { x.negate(); x });
+    }
+    let (digits, radix) = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => (hex, 16),
+        None => (s, 10),
+    };
+    if let Some(x) = fr_from_digits(digits.as_bytes(), radix) {
+        return Some(x);
+    }
+    // from_str reduces values above the modulus
+    F::from_str(&BigUint::from_str_radix(digits, radix).ok()?.to_str_radix(10))
+}
+
+/// Accumulates digits straight into the field representation, which is much faster than
+/// `PrimeField::from_str`. `None` for invalid digits and values that are not below the modulus
+fn fr_from_digits<F: PrimeField>(digits: &[u8], radix: u32) -> Option<F> {
+    if digits.is_empty() {
+        return None;
+    }
+    let mut repr = F::Repr::default();
+    for digit in digits {
+        let mut carry = (*digit as char).to_digit(radix)? as u128;
+        for limb in repr.as_mut().iter_mut() {
+            let x = *limb as u128 * radix as u128 + carry;
+            *limb = x as u64;
+            carry = x >> 64;
+        }
+        if carry != 0 {
+            return None;
+        }
+    }
+    F::from_repr(repr).ok()
+}
+
+/// Parses a field element from a JSON string like `fr_from_str` or an integer. Integers that
+/// don't fit into 64 bits lose precision in JSON parsers and are rejected, they have to be strings
+pub fn fr_from_json<F: PrimeField>(value: &serde_json::Value) -> Option<F> {
+    match value {
+        serde_json::Value::String(s) => fr_from_str(s),
+        serde_json::Value::Number(n) if !n.is_f64() => fr_from_str(&n.to_string()),
+        _ => None,
+    }
+}
+
+/// Decodes a hex string with optional `0x` prefix
+pub fn hex_to_bytes(s: &str) -> Option<Vec<u8>> {
+    let s = s.trim();
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok()).collect()
+}
+
 pub fn repr_to_hex<T: Display>(r: T) -> String {
     format!("{}", r)[2..].to_string()
 }
@@ -41,62 +248,104 @@ pub fn proof_to_hex(proof: &Proof<Bn256>) -> String {
 }
 
 pub fn p1_to_vec(p: &G1Affine) -> Vec<String> {
+    p1_to_vec_with_format(p, NumberFormat::Decimal)
+}
+
+pub fn p1_to_vec_with_format(p: &G1Affine, format: NumberFormat) -> Vec<String> {
     let xy = p.into_xy_unchecked();
     vec![
-        repr_to_big(xy.0.into_repr()),
-        repr_to_big(xy.1.into_repr()),
-        if p.is_zero() { "0".to_string() } else { "1".to_string() }
+        format.repr(xy.0.into_repr()),
+        format.repr(xy.1.into_repr()),
+        format.small(if p.is_zero() { 0 } else { 1 }),
     ]
 }
 
 pub fn p2_to_vec(p: &G2Affine) -> Vec<Vec<String>> {
+    p2_to_vec_with_format(p, NumberFormat::Decimal)
+}
+
+pub fn p2_to_vec_with_format(p: &G2Affine, format: NumberFormat) -> Vec<Vec<String>> {
     let xy = p.into_xy_unchecked();
     vec![
         vec![
-            repr_to_big(xy.0.c0.into_repr()),
-            repr_to_big(xy.0.c1.into_repr()),
+            format.repr(xy.0.c0.into_repr()),
+            format.repr(xy.0.c1.into_repr()),
         ],
         vec![
-            repr_to_big(xy.1.c0.into_repr()),
-            repr_to_big(xy.1.c1.into_repr()),
+            format.repr(xy.1.c0.into_repr()),
+            format.repr(xy.1.c1.into_repr()),
         ],
-        if p.is_zero() {
-            vec!["0".to_string(), "0".to_string()]
-        } else {
-            vec!["1".to_string(), "0".to_string()]
-        },
+        vec![format.small(if p.is_zero() { 0 } else { 1 }), format.small(0)],
     ]
 }
 
 pub fn pairing_to_vec(p: &Fq12) -> Vec<Vec<Vec<String>>> {
-    vec![
-        vec![
-            vec![
-                repr_to_big(p.c0.c0.c0.into_repr()),
-                repr_to_big(p.c0.c0.c1.into_repr()),
-            ],
-            vec![
-                repr_to_big(p.c0.c1.c0.into_repr()),
-                repr_to_big(p.c0.c1.c1.into_repr()),
-            ],
-            vec![
-                repr_to_big(p.c0.c2.c0.into_repr()),
-                repr_to_big(p.c0.c2.c1.into_repr()),
-            ],
-        ],
-        vec![
-            vec![
-                repr_to_big(p.c1.c0.c0.into_repr()),
-                repr_to_big(p.c1.c0.c1.into_repr()),
-            ],
-            vec![
-                repr_to_big(p.c1.c1.c0.into_repr()),
-                repr_to_big(p.c1.c1.c1.into_repr()),
-            ],
-            vec![
-                repr_to_big(p.c1.c2.c0.into_repr()),
-                repr_to_big(p.c1.c2.c1.into_repr()),
-            ],
-        ],
-    ]
+    pairing_to_vec_with_format(p, NumberFormat::Decimal)
+}
+
+pub fn pairing_to_vec_with_format(p: &Fq12, format: NumberFormat) -> Vec<Vec<Vec<String>>> {
+    [p.c0, p.c1].iter()
+        .map(|c| [c.c0, c.c1, c.c2].iter()
+            .map(|fq2| vec![format.repr(fq2.c0.into_repr()), format.repr(fq2.c1.into_repr())])
+            .collect())
+        .collect()
+}
+
+#[test]
+fn atomic_write() {
+    let dir = std::env::temp_dir().join(format!("zkutil-atomic-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("out.json").to_string_lossy().to_string();
+    write_file_atomic(&file, |w| w.write_all(b"[]")).unwrap();
+    assert_eq!(fs::read(&file).unwrap(), b"[]");
+    let failed = write_file_atomic(&file, |w| {
+        w.write_all(b"[1,")?;
+        Err(std::io::Error::new(std::io::ErrorKind::Other, "interrupted"))
+    });
+    assert!(failed.is_err());
+    assert_eq!(fs::read(&file).unwrap(), b"[]");
+    assert!(!atomic_temp_path(&file).exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn json_field_elements() {
+    use bellman_ce::pairing::{bn256::Fr, ff::Field};
+    let values: Vec<serde_json::Value> = serde_json::from_str(r#"["255", "0xff", 255, -1, 1.5, 18446744073709551616, null]"#).unwrap();
+    let parsed = values.iter().map(fr_from_json::<Fr>).collect::<Vec<_>>();
+    let x = Fr::from_str("255");
+    assert_eq!(&parsed[..3], &[x, x, x]);
+    let mut minus_one = Fr::one();
+    minus_one.negate();
+    assert_eq!(parsed[3], Some(minus_one));
+    assert_eq!(&parsed[4..], &[None, None, None]);
+}
+
+#[test]
+fn number_formats() {
+    use bellman_ce::pairing::bn256::Fr;
+    let x = Fr::from_str("255").unwrap().into_repr();
+    assert_eq!(NumberFormat::Decimal.repr(x), "255");
+    assert_eq!(NumberFormat::Hex.repr(x), "0xff");
+    assert_eq!(fr_from_str::<Fr>(&NumberFormat::Hex.repr(x)), Some(Fr::from_str("255").unwrap()));
+    let p = p1_to_vec_with_format(&G1Affine::one(), NumberFormat::Hex);
+    assert_eq!(p, vec!["0x1", "0x2", "0x1"]);
+    assert_eq!(p1_to_vec(&G1Affine::one()), vec!["1", "2", "1"]);
+}
+
+#[test]
+fn concurrent_tasks() {
+    let borrowed = vec![1, 2, 3];
+    assert_eq!(join(|| borrowed.len(), || borrowed.iter().sum::<i32>()), (3, 6));
+    assert_eq!(background(|| 2 + 2).join(), 4);
+}
+
+#[test]
+fn cpu_time() {
+    let before = process_cpu_time();
+    let sum = (0..10_000_000u64).fold(0u64, |acc, i| acc.wrapping_add(i * i));
+    assert_ne!(sum, 1);
+    if cfg!(unix) {
+        assert!(process_cpu_time().unwrap() >= before.unwrap());
+    }
 }
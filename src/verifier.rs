@@ -0,0 +1,301 @@
+//! Groth16 verifier working on byte slices, available without the `prover` feature.
+//!
+//! Keys, proofs and inputs are decoded from byte slices instead of `std::io` readers and
+//! the pairing check is done directly, so this module doesn't depend on the prover, file
+//! formats or serde. It only uses `core` and `alloc`, and a build with the `verifier` feature
+//! alone is `no_std`. The curve arithmetic of bellman_ce and pairing_ce still links `std`, so
+//! targets without it need a `no_std` build of those crates as well. Binary layouts match `VerifyingKey::write` and `Proof::write` of bellman:
+//! verifying key is uncompressed alpha_g1, beta_g1, beta_g2, gamma_g2, delta_g1, delta_g2,
+//! big-endian u32 IC length and uncompressed IC points; proof is compressed a, b, c;
+//! public inputs are 32-byte big-endian field elements.
+//!
+//! Services verifying many proofs against one key can compute `PreparedKey` once with
+//! `prepare_key` and store it with `encode_prepared_key`, so neither startup nor each proof
+//! pays for the `e(alpha, beta)` pairing. Only that pairing and the negated G2 points are stored:
+//! bellman doesn't expose the miller loop coefficients of prepared points, so decoding prepares
+//! -gamma and -delta again, which costs a small fraction of a pairing.
+use alloc::vec::Vec;
+use core::fmt;
+use bellman_ce::{
+    groth16::{Proof, VerifyingKey},
+    pairing::{
+        Engine,
+        CurveAffine,
+        CurveProjective,
+        EncodedPoint,
+        ff::{Field, PrimeField},
+        bn256::{Bn256, Fq, Fq2, Fq6, Fq12},
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifierError {
+    /// Input ended before the structure was fully read
+    UnexpectedEof,
+    /// Bytes left after the structure
+    TrailingBytes,
+    /// Point is not on the curve or not in the right subgroup
+    InvalidPoint,
+    /// Field element is not below the field modulus
+    InvalidScalar,
+    /// Number of public inputs doesn't match the verifying key
+    InputCount,
+}
+
+impl fmt::Display for VerifierError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            VerifierError::UnexpectedEof => "unexpected end of input",
+            VerifierError::TrailingBytes => "unexpected trailing bytes",
+            VerifierError::InvalidPoint => "invalid curve point",
+            VerifierError::InvalidScalar => "field element is out of range",
+            VerifierError::InputCount => "wrong number of public inputs",
+        };
+        f.write_str(msg)
+    }
+}
+
+struct Bytes<'a>(&'a [u8]);
+
+impl<'a> Bytes<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], VerifierError> {
+        if self.0.len() < n {
+            return Err(VerifierError::UnexpectedEof);
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32, VerifierError> {
+        let b = self.take(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn point<P: EncodedPoint>(&mut self) -> Result<P::Affine, VerifierError> {
+        let mut encoded = P::empty();
+        let len = encoded.as_ref().len();
+        encoded.as_mut().copy_from_slice(self.take(len)?);
+        encoded.into_affine().map_err(|_| VerifierError::InvalidPoint)
+    }
+
+    fn field<F: PrimeField>(&mut self) -> Result<F, VerifierError> {
+        field_from_be(self.take(32)?)
+    }
+
+    fn finish(&self) -> Result<(), VerifierError> {
+        if self.0.is_empty() { Ok(()) } else { Err(VerifierError::TrailingBytes) }
+    }
+}
+
+fn field_from_be<F: PrimeField>(bytes: &[u8]) -> Result<F, VerifierError> {
+    let mut repr = F::Repr::default();
+    // repr limbs are little-endian u64 words
+    for (limb, word) in repr.as_mut().iter_mut().zip(bytes.rchunks(8)) {
+        let mut w = [0u8; 8];
+        w.copy_from_slice(word);
+        *limb = u64::from_be_bytes(w);
+    }
+    F::from_repr(repr).map_err(|_| VerifierError::InvalidScalar)
+}
+
+fn field_to_be<F: PrimeField>(value: &F, out: &mut Vec<u8>) {
+    for limb in value.into_repr().as_ref().iter().rev() {
+        out.extend_from_slice(&limb.to_be_bytes());
+    }
+}
+
+fn ic_points<E: Engine>(b: &mut Bytes) -> Result<Vec<E::G1Affine>, VerifierError> {
+    let ic_len = b.u32()? as usize;
+    let mut ic = Vec::with_capacity(ic_len.min(b.0.len() / 64));
+    for _ in 0..ic_len {
+        let point = b.point::<<E::G1Affine as CurveAffine>::Uncompressed>()?;
+        if point.is_zero() {
+            return Err(VerifierError::InvalidPoint);
+        }
+        ic.push(point);
+    }
+    Ok(ic)
+}
+
+pub fn decode_verifying_key<E: Engine>(bytes: &[u8]) -> Result<VerifyingKey<E>, VerifierError> {
+    let mut b = Bytes(bytes);
+    let alpha_g1 = b.point::<<E::G1Affine as CurveAffine>::Uncompressed>()?;
+    let beta_g1 = b.point::<<E::G1Affine as CurveAffine>::Uncompressed>()?;
+    let beta_g2 = b.point::<<E::G2Affine as CurveAffine>::Uncompressed>()?;
+    let gamma_g2 = b.point::<<E::G2Affine as CurveAffine>::Uncompressed>()?;
+    let delta_g1 = b.point::<<E::G1Affine as CurveAffine>::Uncompressed>()?;
+    let delta_g2 = b.point::<<E::G2Affine as CurveAffine>::Uncompressed>()?;
+    let ic = ic_points::<E>(&mut b)?;
+    b.finish()?;
+    Ok(VerifyingKey { alpha_g1, beta_g1, beta_g2, gamma_g2, delta_g1, delta_g2, ic })
+}
+
+pub fn decode_proof<E: Engine>(bytes: &[u8]) -> Result<Proof<E>, VerifierError> {
+    let mut b = Bytes(bytes);
+    let a = b.point::<<E::G1Affine as CurveAffine>::Compressed>()?;
+    let b_ = b.point::<<E::G2Affine as CurveAffine>::Compressed>()?;
+    let c = b.point::<<E::G1Affine as CurveAffine>::Compressed>()?;
+    b.finish()?;
+    if a.is_zero() || b_.is_zero() || c.is_zero() {
+        return Err(VerifierError::InvalidPoint);
+    }
+    Ok(Proof { a, b: b_, c })
+}
+
+pub fn decode_inputs<E: Engine>(bytes: &[u8]) -> Result<Vec<E::Fr>, VerifierError> {
+    if bytes.len() % 32 != 0 {
+        return Err(VerifierError::UnexpectedEof);
+    }
+    bytes.chunks(32).map(field_from_be).collect()
+}
+
+fn input_combination<E: Engine>(ic: &[E::G1Affine], inputs: &[E::Fr]) -> Result<E::G1Affine, VerifierError> {
+    if inputs.len() + 1 != ic.len() {
+        return Err(VerifierError::InputCount);
+    }
+    let mut acc = ic[0].into_projective();
+    for (input, point) in inputs.iter().zip(ic.iter().skip(1)) {
+        acc.add_assign(&point.mul(input.into_repr()));
+    }
+    Ok(acc.into_affine())
+}
+
+/// Checks `e(A, B) = e(alpha, beta) e(IC(inputs), gamma) e(C, delta)`
+pub fn verify<E: Engine>(vk: &VerifyingKey<E>, proof: &Proof<E>, inputs: &[E::Fr]) -> Result<bool, VerifierError> {
+    let mut ic_sum = input_combination::<E>(&vk.ic, inputs)?;
+    ic_sum.negate();
+    let mut c = proof.c;
+    c.negate();
+    let mut alpha = vk.alpha_g1;
+    alpha.negate();
+
+    let terms = [
+        (proof.a.prepare(), proof.b.prepare()),
+        (alpha.prepare(), vk.beta_g2.prepare()),
+        (ic_sum.prepare(), vk.gamma_g2.prepare()),
+        (c.prepare(), vk.delta_g2.prepare()),
+    ];
+    let refs = [(&terms[0].0, &terms[0].1), (&terms[1].0, &terms[1].1), (&terms[2].0, &terms[2].1), (&terms[3].0, &terms[3].1)];
+    Ok(E::final_exponentiation(&E::miller_loop(refs.iter())) == Some(E::Fqk::one()))
+}
+
+/// Verifying key with `e(alpha, beta)` computed and G2 points negated. `new` prepares the G2
+/// points for the miller loop, their coefficients aren't part of the encoded key
+pub struct PreparedKey<E: Engine> {
+    pub alpha_g1_beta_g2: E::Fqk,
+    pub neg_gamma_g2: E::G2Affine,
+    pub neg_delta_g2: E::G2Affine,
+    pub ic: Vec<E::G1Affine>,
+    neg_gamma_g2_prepared: <E::G2Affine as CurveAffine>::Prepared,
+    neg_delta_g2_prepared: <E::G2Affine as CurveAffine>::Prepared,
+}
+
+impl<E: Engine> PreparedKey<E> {
+    pub fn new(alpha_g1_beta_g2: E::Fqk, neg_gamma_g2: E::G2Affine, neg_delta_g2: E::G2Affine, ic: Vec<E::G1Affine>) -> Self {
+        PreparedKey {
+            neg_gamma_g2_prepared: neg_gamma_g2.prepare(),
+            neg_delta_g2_prepared: neg_delta_g2.prepare(),
+            alpha_g1_beta_g2,
+            neg_gamma_g2,
+            neg_delta_g2,
+            ic,
+        }
+    }
+}
+
+pub fn prepare_key<E: Engine>(vk: &VerifyingKey<E>) -> PreparedKey<E> {
+    let mut neg_gamma_g2 = vk.gamma_g2;
+    neg_gamma_g2.negate();
+    let mut neg_delta_g2 = vk.delta_g2;
+    neg_delta_g2.negate();
+    PreparedKey::new(E::pairing(vk.alpha_g1, vk.beta_g2), neg_gamma_g2, neg_delta_g2, vk.ic.clone())
+}
+
+/// Checks `e(A, B) e(IC(inputs), -gamma) e(C, -delta) = e(alpha, beta)` with a single miller loop
+pub fn verify_prepared<E: Engine>(pvk: &PreparedKey<E>, proof: &Proof<E>, inputs: &[E::Fr]) -> Result<bool, VerifierError> {
+    let ic_sum = input_combination::<E>(&pvk.ic, inputs)?;
+    let (a, ic_sum, c) = (proof.a.prepare(), ic_sum.prepare(), proof.c.prepare());
+    let b = proof.b.prepare();
+    let terms = [(&a, &b), (&ic_sum, &pvk.neg_gamma_g2_prepared), (&c, &pvk.neg_delta_g2_prepared)];
+    Ok(E::final_exponentiation(&E::miller_loop(terms.iter())) == Some(pvk.alpha_g1_beta_g2))
+}
+
+fn fq12_coefficients(value: &Fq12) -> [Fq; 12] {
+    let fq2 = |v: &Fq2| [v.c0, v.c1];
+    let fq6 = |v: &Fq6| [fq2(&v.c0), fq2(&v.c1), fq2(&v.c2)];
+    let [a, b] = [fq6(&value.c0), fq6(&value.c1)];
+    [a[0][0], a[0][1], a[1][0], a[1][1], a[2][0], a[2][1], b[0][0], b[0][1], b[1][0], b[1][1], b[2][0], b[2][1]]
+}
+
+/// Encodes a prepared BN254 key: `e(alpha, beta)` as 12 big-endian base field coefficients
+/// (c0.c0.c0, c0.c0.c1, c0.c1.c0, ...), uncompressed -gamma and -delta, big-endian u32 IC length
+/// and uncompressed IC points
+pub fn encode_prepared_key(pvk: &PreparedKey<Bn256>) -> Vec<u8> {
+    let mut out = Vec::with_capacity(12 * 32 + 2 * 128 + 4 + pvk.ic.len() * 64);
+    for coeff in fq12_coefficients(&pvk.alpha_g1_beta_g2).iter() {
+        field_to_be(coeff, &mut out);
+    }
+    out.extend_from_slice(pvk.neg_gamma_g2.into_uncompressed().as_ref());
+    out.extend_from_slice(pvk.neg_delta_g2.into_uncompressed().as_ref());
+    out.extend_from_slice(&(pvk.ic.len() as u32).to_be_bytes());
+    for point in &pvk.ic {
+        out.extend_from_slice(point.into_uncompressed().as_ref());
+    }
+    out
+}
+
+/// Decodes a key written by `encode_prepared_key`. `e(alpha, beta)` can't be checked
+/// against anything, so the encoded key must come from a trusted source.
+pub fn decode_prepared_key(bytes: &[u8]) -> Result<PreparedKey<Bn256>, VerifierError> {
+    let mut b = Bytes(bytes);
+    let mut c = [Fq::zero(); 12];
+    for coeff in c.iter_mut() {
+        *coeff = b.field()?;
+    }
+    let fq6 = |c: &[Fq]| Fq6 {
+        c0: Fq2 { c0: c[0], c1: c[1] },
+        c1: Fq2 { c0: c[2], c1: c[3] },
+        c2: Fq2 { c0: c[4], c1: c[5] },
+    };
+    let alpha_g1_beta_g2 = Fq12 { c0: fq6(&c[..6]), c1: fq6(&c[6..]) };
+    let neg_gamma_g2 = b.point::<<<Bn256 as Engine>::G2Affine as CurveAffine>::Uncompressed>()?;
+    let neg_delta_g2 = b.point::<<<Bn256 as Engine>::G2Affine as CurveAffine>::Uncompressed>()?;
+    let ic = ic_points::<Bn256>(&mut b)?;
+    b.finish()?;
+    Ok(PreparedKey::new(alpha_g1_beta_g2, neg_gamma_g2, neg_delta_g2, ic))
+}
+
+/// Decodes all parts and verifies the proof
+pub fn verify_bytes<E: Engine>(vk: &[u8], proof: &[u8], inputs: &[u8]) -> Result<bool, VerifierError> {
+    verify(&decode_verifying_key::<E>(vk)?, &decode_proof::<E>(proof)?, &decode_inputs::<E>(inputs)?)
+}
+
+#[cfg(feature = "prover")]
+#[test]
+fn verify_encoded() {
+    use bellman_ce::pairing::ff::PrimeFieldRepr;
+    use crate::circom_circuit::{CircomCircuit, create_insecure_dev_rng, prove};
+    use crate::test_utils::{fr, multiplier_circuit, multiplier_params, multiplier_witness};
+    let params = multiplier_params();
+    let circuit = CircomCircuit { r1cs: multiplier_circuit(), witness: Some(multiplier_witness(2, 3)), wire_mapping: None };
+    let proof = prove(circuit, &params, create_insecure_dev_rng()).unwrap();
+
+    let mut vk = vec![];
+    params.vk.write(&mut vk).unwrap();
+    let mut proof_bytes = vec![];
+    proof.write(&mut proof_bytes).unwrap();
+    let mut inputs = vec![];
+    fr("6").into_repr().write_be(&mut inputs).unwrap();
+    assert_eq!(verify_bytes::<Bn256>(&vk, &proof_bytes, &inputs), Ok(true));
+
+    let mut wrong = vec![];
+    fr("7").into_repr().write_be(&mut wrong).unwrap();
+    assert_eq!(verify_bytes::<Bn256>(&vk, &proof_bytes, &wrong), Ok(false));
+    assert_eq!(verify_bytes::<Bn256>(&vk, &proof_bytes[..proof_bytes.len() - 1], &inputs), Err(VerifierError::UnexpectedEof));
+    assert_eq!(decode_inputs::<Bn256>(&[0xff; 32]), Err(VerifierError::InvalidScalar));
+
+    let pvk = decode_prepared_key(&encode_prepared_key(&prepare_key(&params.vk))).unwrap();
+    assert_eq!(verify_prepared(&pvk, &proof, &[fr("6")]), Ok(true));
+    assert_eq!(verify_prepared(&pvk, &proof, &[fr("7")]), Ok(false));
+}
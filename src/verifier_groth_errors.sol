@@ -0,0 +1,234 @@
+// SPDX-License-Identifier: MIT
+
+pragma solidity ^0.8.4;
+
+library Pairing {
+    uint256 constant PRIME_Q = 21888242871839275222246405745257275088696311157297823662689037894645226208583;
+
+    struct G1Point {
+        uint256 X;
+        uint256 Y;
+    }
+
+    // Encoding of field elements is: X[0] * z + X[1]
+    struct G2Point {
+        uint256[2] X;
+        uint256[2] Y;
+    }
+
+    /*
+     * @return The negation of p, i.e. p.plus(p.negate()) should be zero
+     */
+    function negate(G1Point memory p) internal pure returns (G1Point memory) {
+        // The prime q in the base field F_q for G1
+        if (p.X == 0 && p.Y == 0) {
+            return G1Point(0, 0);
+        } else {
+            return G1Point(p.X, PRIME_Q - (p.Y % PRIME_Q));
+        }
+    }
+
+    /*
+     * @return success Whether the precompile call succeeded
+     * @return r the sum of two points of G1
+     */
+    function plus(
+        G1Point memory p1,
+        G1Point memory p2
+    ) internal view returns (bool success, G1Point memory r) {
+        uint256[4] memory input = [
+            p1.X, p1.Y,
+            p2.X, p2.Y
+        ];
+
+        // solium-disable-next-line security/no-inline-assembly
+        assembly {
+            success := staticcall(sub(gas(), 2000), 6, input, 0x80, r, 0x40)
+        }
+    }
+
+    /*
+     * @return success Whether the precompile call succeeded
+     * @return r the product of a point on G1 and a scalar
+     */
+    function scalarMul(G1Point memory p, uint256 s) internal view returns (bool success, G1Point memory r) {
+        uint256[3] memory input = [p.X, p.Y, s];
+
+        // solium-disable-next-line security/no-inline-assembly
+        assembly {
+            success := staticcall(sub(gas(), 2000), 7, input, 0x60, r, 0x40)
+        }
+    }
+
+    /* @return success Whether the precompile call succeeded
+     * @return valid The result of the pairing check
+     *         e(p1[0], p2[0]) *  .... * e(p1[n], p2[n]) == 1
+     */
+    function pairing(
+        G1Point memory a1,
+        G2Point memory a2,
+        G1Point memory b1,
+        G2Point memory b2,
+        G1Point memory c1,
+        G2Point memory c2,
+        G1Point memory d1,
+        G2Point memory d2
+    ) internal view returns (bool success, bool valid) {
+        uint256[24] memory input = [
+            a1.X, a1.Y, a2.X[0], a2.X[1], a2.Y[0], a2.Y[1],
+            b1.X, b1.Y, b2.X[0], b2.X[1], b2.Y[0], b2.Y[1],
+            c1.X, c1.Y, c2.X[0], c2.X[1], c2.Y[0], c2.Y[1],
+            d1.X, d1.Y, d2.X[0], d2.X[1], d2.Y[0], d2.Y[1]
+        ];
+        uint256[1] memory out;
+
+        // solium-disable-next-line security/no-inline-assembly
+        assembly {
+            success := staticcall(sub(gas(), 2000), 8, input, mul(24, 0x20), out, 0x20)
+        }
+        valid = success && out[0] != 0;
+    }
+}
+
+contract Verifier {
+    uint256 constant SNARK_SCALAR_FIELD = 21888242871839275222246405745257275088548364400416034343698204186575808495617;
+    uint256 constant PRIME_Q = 21888242871839275222246405745257275088696311157297823662689037894645226208583;
+
+    // Reason codes returned by tryVerify
+    uint8 public constant OK = 0;
+    uint8 public constant MALFORMED_PROOF = 1;
+    uint8 public constant PROOF_ELEMENT_OUT_OF_RANGE = 2;
+    uint8 public constant INPUT_OUT_OF_RANGE = 3;
+    uint8 public constant PRECOMPILE_FAILED = 4;
+    uint8 public constant INVALID_PROOF = 5;
+
+    error MalformedProof(uint256 length);
+    error ProofElementOutOfRange(uint256 index);
+    error InputOutOfRange(uint256 index);
+    error PrecompileFailed();
+    error InvalidProof();
+
+    event ProofVerified(address indexed sender, bytes32 indexed inputsHash);
+
+    struct VerifyingKey {
+        Pairing.G1Point alfa1;
+        Pairing.G2Point beta2;
+        Pairing.G2Point gamma2;
+        Pairing.G2Point delta2;
+        Pairing.G1Point[<%vk_ic_length%>] IC;
+    }
+
+    function verifyingKey() internal pure returns (VerifyingKey memory vk) {
+        vk.alfa1 = Pairing.G1Point(<%vk_alfa1%>);
+        vk.beta2 = Pairing.G2Point(<%vk_beta2%>);
+        vk.gamma2 = Pairing.G2Point(<%vk_gamma2%>);
+        vk.delta2 = Pairing.G2Point(<%vk_delta2%>);
+        <%vk_ic_pts%>
+    }
+
+    /*
+     * @return code OK or the reason code of the failed check
+     * @return index Index of the offending input
+     * @return vkX The linear combination of IC points and public inputs
+     */
+    function linearCombination(
+        VerifyingKey memory vk,
+        uint256[<%vk_input_length%>] memory input
+    ) internal view returns (uint8 code, uint256 index, Pairing.G1Point memory vkX) {
+        vkX = vk.IC[0];
+        for (uint256 i = 0; i < input.length; i++) {
+            // Make sure that every input is less than the snark scalar field
+            if (input[i] >= SNARK_SCALAR_FIELD) {
+                return (INPUT_OUT_OF_RANGE, i, vkX);
+            }
+            (bool mulSuccess, Pairing.G1Point memory term) = Pairing.scalarMul(vk.IC[i + 1], input[i]);
+            bool addSuccess;
+            (addSuccess, vkX) = Pairing.plus(vkX, term);
+            if (!mulSuccess || !addSuccess) {
+                return (PRECOMPILE_FAILED, 0, vkX);
+            }
+        }
+        return (OK, 0, vkX);
+    }
+
+    /*
+     * @return code OK if the proof is valid, otherwise the reason code of the failed check
+     * @return index Proof length, index of the offending proof element or input
+     */
+    function check(
+        bytes memory proof,
+        uint256[<%vk_input_length%>] memory input
+    ) internal view returns (uint8 code, uint256 index) {
+        if (proof.length != 256) {
+            return (MALFORMED_PROOF, proof.length);
+        }
+        uint256[8] memory p = abi.decode(proof, (uint256[8]));
+        for (uint256 i = 0; i < p.length; i++) {
+            // Make sure that each element in the proof is less than the prime q
+            if (p[i] >= PRIME_Q) {
+                return (PROOF_ELEMENT_OUT_OF_RANGE, i);
+            }
+        }
+
+        VerifyingKey memory vk = verifyingKey();
+        Pairing.G1Point memory vkX;
+        (code, index, vkX) = linearCombination(vk, input);
+        if (code != OK) {
+            return (code, index);
+        }
+
+        (bool success, bool valid) = Pairing.pairing(
+            Pairing.negate(Pairing.G1Point(p[0], p[1])),
+            Pairing.G2Point([p[2], p[3]], [p[4], p[5]]),
+            vk.alfa1,
+            vk.beta2,
+            vkX,
+            vk.gamma2,
+            Pairing.G1Point(p[6], p[7]),
+            vk.delta2
+        );
+        if (!success) {
+            return (PRECOMPILE_FAILED, 0);
+        }
+        return (valid ? OK : INVALID_PROOF, 0);
+    }
+
+    /*
+     * @returns OK (0) if the proof is valid, otherwise the reason code of the failed check.
+     *          Never reverts.
+     */
+    function tryVerify(
+        bytes memory proof,
+        uint256[<%vk_input_length%>] memory input
+    ) public view returns (uint8 code) {
+        (code, ) = check(proof, input);
+    }
+
+    /*
+     * @returns Whether the proof is valid given the hardcoded verifying key
+     *          above and the public inputs. Reverts with a custom error if the
+     *          proof or inputs are malformed.
+     */
+    function verifyProof(
+        bytes memory proof,
+        uint256[<%vk_input_length%>] memory input
+    ) public view returns (bool) {
+        (uint8 code, uint256 index) = check(proof, input);
+        if (code == MALFORMED_PROOF) revert MalformedProof(index);
+        if (code == PROOF_ELEMENT_OUT_OF_RANGE) revert ProofElementOutOfRange(index);
+        if (code == INPUT_OUT_OF_RANGE) revert InputOutOfRange(index);
+        if (code == PRECOMPILE_FAILED) revert PrecompileFailed();
+        return code == OK;
+    }
+
+    /*
+     * Reverts with InvalidProof unless the proof is valid, emits ProofVerified otherwise
+     */
+    function verify(
+        bytes memory proof,
+        uint256[<%vk_input_length%>] memory input
+    ) public {
+        if (!verifyProof(proof, input)) revert InvalidProof();
+        emit ProofVerified(msg.sender, keccak256(abi.encodePacked(input)));
+    }
+}
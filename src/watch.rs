@@ -0,0 +1,155 @@
+//! Directory watcher of `zkutil watch`: witness files moved into the input directory are proved
+//! in priority order, see `jobs::next_job`, and moved to the done or failed directory afterwards.
+use std::collections::HashSet;
+use std::fs;
+use std::io::{Error, Result};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use rand::Rng;
+use bellman_ce::{groth16::Parameters, pairing::bn256::Bn256};
+use crate::circom_circuit::{create_rng, R1CS};
+use crate::jobs;
+use crate::metrics;
+use crate::proof::prove_witness_file;
+
+pub struct WatchDirs {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub done: PathBuf,
+    pub failed: PathBuf,
+}
+
+pub enum WatchEvent<'a> {
+    Proving(&'a Path),
+    Proved { witness: &'a Path, proof: &'a Path, public: &'a Path },
+    Failed { witness: &'a Path, error: &'a str },
+    /// The witness couldn't be moved out of the input directory and is skipped from now on
+    Stuck { witness: &'a Path, target: &'a Path, error: &'a Error },
+}
+
+pub struct Watcher<'a> {
+    r1cs: &'a R1CS<Bn256>,
+    params: &'a Parameters<Bn256>,
+    dirs: WatchDirs,
+    aging: Duration,
+    // files that couldn't be moved out of the input directory, they would be proved over and over
+    stuck: HashSet<PathBuf>,
+}
+
+impl<'a> Watcher<'a> {
+    /// Creates the watched directories if they don't exist
+    pub fn new(r1cs: &'a R1CS<Bn256>, params: &'a Parameters<Bn256>, dirs: WatchDirs, aging: Duration) -> Result<Self> {
+        for dir in [&dirs.input, &dirs.output, &dirs.done, &dirs.failed].iter() {
+            fs::create_dir_all(dir)?;
+        }
+        Ok(Watcher { r1cs, params, dirs, aging, stuck: HashSet::new() })
+    }
+
+    pub fn dirs(&self) -> &WatchDirs {
+        &self.dirs
+    }
+
+    fn queue(&self) -> Result<Vec<jobs::QueuedJob<PathBuf>>> {
+        // dotfiles and .tmp files are still being written, writers should move finished files in
+        let mut files = fs::read_dir(&self.dirs.input)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.is_file() && !self.stuck.contains(path))
+            .filter(|path| !path.file_name().map_or(true, |n| n.to_string_lossy().starts_with('.')))
+            .filter(|path| matches!(path.extension().and_then(|e| e.to_str()), Some("wtns") | Some("json")))
+            .collect::<Vec<_>>();
+        files.sort();
+        Ok(files.into_iter()
+            .map(|file| {
+                let stem = file.file_stem().unwrap().to_string_lossy().to_string();
+                let priority = jobs::job_priority(&stem).1;
+                let waiting = fs::metadata(&file).and_then(|m| m.modified()).ok()
+                    .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                    .unwrap_or_default();
+                jobs::QueuedJob { job: file, priority, waiting }
+            })
+            .collect())
+    }
+
+    /// Proves the most urgent witness in the input directory, `false` if there is none
+    pub fn poll<R: Rng, F: FnMut(WatchEvent)>(&mut self, rng: R, mut on_event: F) -> Result<bool> {
+        let queue = self.queue()?;
+        let file = match jobs::next_job(&queue, self.aging) {
+            Some(i) => &queue[i].job,
+            None => {
+                metrics::set_queue_depth(0);
+                return Ok(false);
+            }
+        };
+        metrics::set_queue_depth(queue.len() - 1);
+        metrics::job_started();
+        let stem = file.file_stem().unwrap().to_string_lossy().to_string();
+        let name = jobs::job_priority(&stem).0;
+        let proof_file = self.dirs.output.join(format!("{}.proof.json", name));
+        let public_file = self.dirs.output.join(format!("{}.public.json", name));
+        on_event(WatchEvent::Proving(file));
+        // loaders panic on malformed input, a single bad file must not stop the queue
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| prove_witness_file(
+            self.r1cs,
+            self.params,
+            &file.to_string_lossy(),
+            &proof_file.to_string_lossy(),
+            &public_file.to_string_lossy(),
+            rng,
+        ).map_err(|e| e.to_string()))).unwrap_or_else(|_| Err(String::from("prover panicked")));
+        metrics::job_finished();
+        metrics::proof_done(result.is_ok());
+        let target_dir = match &result {
+            Ok(()) => {
+                on_event(WatchEvent::Proved { witness: file, proof: &proof_file, public: &public_file });
+                &self.dirs.done
+            }
+            Err(e) => {
+                on_event(WatchEvent::Failed { witness: file, error: e });
+                &self.dirs.failed
+            }
+        };
+        let target = target_dir.join(file.file_name().unwrap());
+        if let Err(e) = fs::rename(file, &target) {
+            on_event(WatchEvent::Stuck { witness: file, target: &target, error: &e });
+            self.stuck.insert(file.clone());
+        }
+        Ok(true)
+    }
+
+    /// Polls the input directory forever, sleeping `interval` whenever it's empty
+    pub fn run<F: FnMut(WatchEvent)>(&mut self, interval: Duration, mut on_event: F) -> Result<()> {
+        loop {
+            if !self.poll(create_rng(), &mut on_event)? {
+                std::thread::sleep(interval);
+            }
+        }
+    }
+}
+
+#[test]
+fn watch_dir() {
+    use crate::test_utils::{multiplier_circuit, multiplier_params};
+    let dir = std::env::temp_dir().join(format!("zkutil-watch-{}", std::process::id()));
+    let dirs = WatchDirs { input: dir.join("in"), output: dir.join("out"), done: dir.join("done"), failed: dir.join("failed") };
+    let (r1cs, params) = (multiplier_circuit(), multiplier_params());
+    let mut watcher = Watcher::new(&r1cs, &params, dirs, Duration::from_secs(60)).unwrap();
+    fs::write(dir.join("in/a.json"), r#"["1","6","2","3"]"#).unwrap();
+    fs::write(dir.join("in/b.p5.json"), "not a witness").unwrap();
+    fs::write(dir.join("in/.c.json"), r#"["1","6","2","3"]"#).unwrap();
+
+    let mut events = vec![];
+    let mut record = |event: WatchEvent| events.push(match event {
+        WatchEvent::Proving(file) => format!("proving {}", file.file_name().unwrap().to_string_lossy()),
+        WatchEvent::Proved { proof, .. } => format!("proved {}", proof.file_name().unwrap().to_string_lossy()),
+        WatchEvent::Failed { witness, .. } => format!("failed {}", witness.file_name().unwrap().to_string_lossy()),
+        WatchEvent::Stuck { witness, .. } => format!("stuck {}", witness.file_name().unwrap().to_string_lossy()),
+    });
+    while watcher.poll(create_rng(), &mut record).unwrap() {}
+    // the higher priority file is picked first, dotfiles are left alone
+    assert_eq!(events, ["proving b.p5.json", "failed b.p5.json", "proving a.json", "proved a.proof.json"]);
+    assert!(dir.join("failed/b.p5.json").exists());
+    assert!(dir.join("done/a.json").exists());
+    assert!(dir.join("out/a.public.json").exists());
+    assert!(dir.join("in/.c.json").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
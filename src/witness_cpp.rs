@@ -0,0 +1,77 @@
+//! Witness calculation with the C++ calculator circom generates with `--c`, much faster than the
+//! WASM one for large circuits. `circom circuit.circom --c` writes the sources to `circuit_cpp/`,
+//! `make` there builds `circuit_cpp/circuit`, which is run as `circuit input.json witness.wtns`.
+use std::fs::{self, File};
+use std::io::{BufReader, Error, ErrorKind, Result};
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use bellman_ce::pairing::bn256::{Bn256, Fr};
+use crate::wtns_reader;
+
+static NEXT_OUTPUT: AtomicUsize = AtomicUsize::new(0);
+
+/// Binary circom generates for `dir/name.r1cs`: `dir/name_cpp/name`
+pub fn default_binary_for(circuit: &str) -> String {
+    let path = Path::new(circuit);
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    dir.join(format!("{}_cpp", stem)).join(&stem).to_string_lossy().to_string()
+}
+
+/// Runs the witness calculator `binary` on the circuit inputs JSON file `input` and reads the wtns
+/// file it writes to a temporary file
+pub fn calculate_witness(binary: &str, input: &str) -> Result<Vec<Fr>> {
+    if !Path::new(binary).is_file() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("witness calculator {} not found, build it with make in the directory circom --c created", binary),
+        ));
+    }
+    let output = std::env::temp_dir().join(format!(
+        "zkutil-witness-{}-{}.wtns",
+        std::process::id(),
+        NEXT_OUTPUT.fetch_add(1, Ordering::SeqCst),
+    ));
+    let result = Command::new(binary).arg(input).arg(&output).output();
+    let witness = result.and_then(|run| {
+        if !run.status.success() {
+            let status = run.status;
+            let message = [run.stderr, run.stdout].iter()
+                .map(|out| String::from_utf8_lossy(out).trim().to_string())
+                .find(|out| !out.is_empty())
+                .unwrap_or_else(|| status.to_string());
+            return Err(Error::new(ErrorKind::InvalidData, format!("{} failed: {}", binary, message)));
+        }
+        Ok(wtns_reader::read::<Bn256, _>(BufReader::new(File::open(&output)?))?.witness)
+    });
+    let _ = fs::remove_file(&output);
+    witness
+}
+
+#[cfg(unix)]
+#[test]
+fn cpp_witness_calculator() {
+    use std::os::unix::fs::PermissionsExt;
+    use bellman_ce::pairing::ff::PrimeField;
+    let dir = std::env::temp_dir().join(format!("zkutil-witness-cpp-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+    let witness = vec![Fr::from_str("1").unwrap(), Fr::from_str("6").unwrap()];
+    wtns_reader::write::<Bn256, _>(File::create(path("prepared.wtns")).unwrap(), &witness).unwrap();
+    let script = |name: &str, body: String| {
+        fs::write(path(name), format!("#!/bin/sh\n{}\n", body)).unwrap();
+        fs::set_permissions(path(name), fs::Permissions::from_mode(0o755)).unwrap();
+    };
+    script("circuit", format!("test -f \"$1\" && cp '{}' \"$2\"", path("prepared.wtns")));
+    script("failing", String::from("echo 'Assert Failed.' >&2; exit 1"));
+    fs::write(path("input.json"), "{}").unwrap();
+
+    assert!(calculate_witness(&path("circuit"), &path("input.json")).unwrap() == witness);
+    let error = calculate_witness(&path("failing"), &path("input.json")).unwrap_err();
+    assert!(error.to_string().ends_with("failed: Assert Failed."));
+    assert_eq!(calculate_witness(&path("missing"), &path("input.json")).unwrap_err().kind(), ErrorKind::NotFound);
+    assert_eq!(default_binary_for("build/circuit.r1cs"), "build/circuit_cpp/circuit");
+    assert_eq!(default_binary_for("circuit.r1cs"), "circuit_cpp/circuit");
+    fs::remove_dir_all(&dir).unwrap();
+}
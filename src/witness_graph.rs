@@ -0,0 +1,560 @@
+//! Native witness calculator that executes the circuit evaluation graphs of
+//! [circom-witnesscalc](https://github.com/iden3/circom-witnesscalc), as built by its
+//! `build-circuit circuit.circom graph.bin`.
+//!
+//! A graph file is the magic `wtns.graph.001`, the node count as a little-endian u64, the nodes in
+//! topological order and the graph metadata, each a varint length-delimited protobuf message of
+//! `messages.proto`, and finally the offset of the metadata as a little-endian u64:
+//!
+//! ```proto
+//! message Node {
+//!   oneof node {
+//!     InputNode input = 1;       // uint32 idx = 1
+//!     ConstantNode constant = 2; // BigUInt value = 1, which is bytes value_le = 1
+//!     UnoOpNode uno_op = 3;      // UnoOp op = 1; uint32 a_idx = 2
+//!     DuoOpNode duo_op = 4;      // DuoOp op = 1; uint32 a_idx = 2; uint32 b_idx = 3
+//!     TresOpNode tres_op = 5;    // TresOp op = 1; uint32 a_idx = 2; uint32 b_idx = 3; uint32 c_idx = 4
+//!   }
+//! }
+//! message GraphMetadata {
+//!   repeated uint32 witness_signals = 1;
+//!   map<string, SignalDescription> inputs = 2; // uint32 offset = 1; uint32 len = 2
+//! }
+//! ```
+//!
+//! Input nodes index a buffer of all circuit inputs, whose element 0 is the constant 1.
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use num_bigint::{BigInt, BigUint, Sign};
+use num_traits::ToPrimitive;
+use bellman_ce::pairing::{
+    Engine,
+    ff::{Field, PrimeField},
+};
+use crate::utils::repr_to_biguint;
+
+pub const GRAPH_MAGIC: &[u8] = b"wtns.graph.001";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UnoOp {
+    Neg,
+    Id,
+    Lnot,
+    Bnot,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DuoOp {
+    Mul,
+    Div,
+    Add,
+    Sub,
+    Pow,
+    Idiv,
+    Mod,
+    Eq,
+    Neq,
+    Lt,
+    Gt,
+    Leq,
+    Geq,
+    Land,
+    Lor,
+    Shl,
+    Shr,
+    Bor,
+    Band,
+    Bxor,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TresOp {
+    TernCond,
+}
+
+const UNO_OPS: [UnoOp; 4] = [UnoOp::Neg, UnoOp::Id, UnoOp::Lnot, UnoOp::Bnot];
+const DUO_OPS: [DuoOp; 20] = [
+    DuoOp::Mul, DuoOp::Div, DuoOp::Add, DuoOp::Sub, DuoOp::Pow, DuoOp::Idiv, DuoOp::Mod, DuoOp::Eq, DuoOp::Neq, DuoOp::Lt,
+    DuoOp::Gt, DuoOp::Leq, DuoOp::Geq, DuoOp::Land, DuoOp::Lor, DuoOp::Shl, DuoOp::Shr, DuoOp::Bor, DuoOp::Band, DuoOp::Bxor,
+];
+const TRES_OPS: [TresOp; 1] = [TresOp::TernCond];
+
+/// A graph node, operands are indices of preceding nodes
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Node {
+    /// Index into the inputs buffer
+    Input(usize),
+    Constant(BigUint),
+    Uno(UnoOp, usize),
+    Duo(DuoOp, usize, usize),
+    Tres(TresOp, usize, usize, usize),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct InputSignal {
+    pub offset: usize,
+    pub len: usize,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    /// Node index for every witness wire, wire 0 must evaluate to 1
+    pub signals: Vec<usize>,
+    pub inputs: BTreeMap<String, InputSignal>,
+}
+
+fn invalid<T: Into<Box<dyn std::error::Error + Send + Sync>>>(msg: T) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+fn to_big<E: Engine>(fr: &E::Fr) -> BigUint {
+    repr_to_biguint(fr.into_repr())
+}
+
+fn from_big<E: Engine>(big: &BigUint) -> E::Fr {
+    // from_str reduces the value modulo the field characteristic
+    E::Fr::from_str(&big.to_str_radix(10)).unwrap()
+}
+
+fn from_bool<E: Engine>(b: bool) -> E::Fr {
+    if b { E::Fr::one() } else { E::Fr::zero() }
+}
+
+/// Values above p/2 are treated as negative, the same way circom compares field elements
+fn to_signed<E: Engine>(fr: &E::Fr) -> BigInt {
+    let modulus = repr_to_biguint(E::Fr::char());
+    let value = to_big::<E>(fr);
+    if value > &modulus >> 1 {
+        BigInt::from_biguint(Sign::Plus, value) - BigInt::from_biguint(Sign::Plus, modulus)
+    } else {
+        BigInt::from_biguint(Sign::Plus, value)
+    }
+}
+
+/// All bits of field elements, circom masks results of bitwise operations with it
+fn mask<E: Engine>() -> BigUint {
+    (BigUint::from(1u32) << E::Fr::NUM_BITS as usize) - BigUint::from(1u32)
+}
+
+/// Shifts `a` left by `amount`, or right by p - `amount` for amounts above p/2, like circom.
+/// Shifts past the field size are capped, they leave nothing of `a` either way
+fn shift_left<E: Engine>(a: &E::Fr, amount: &E::Fr) -> E::Fr {
+    let modulus = repr_to_biguint(E::Fr::char());
+    let amount = to_big::<E>(amount);
+    let (right, bits) = if amount > &modulus >> 1 { (true, modulus - amount) } else { (false, amount) };
+    let bits = bits.min(BigUint::from(E::Fr::NUM_BITS)).to_usize().unwrap();
+    if right {
+        from_big::<E>(&(to_big::<E>(a) >> bits))
+    } else {
+        from_big::<E>(&((to_big::<E>(a) << bits) & mask::<E>()))
+    }
+}
+
+fn shift_right<E: Engine>(a: &E::Fr, amount: &E::Fr) -> E::Fr {
+    let mut amount = *amount;
+    amount.negate();
+    shift_left::<E>(a, &amount)
+}
+
+fn eval_uno<E: Engine>(op: UnoOp, a: E::Fr) -> E::Fr {
+    match op {
+        UnoOp::Neg => { let mut a = a; a.negate(); a }
+        UnoOp::Id => a,
+        UnoOp::Lnot => from_bool::<E>(a.is_zero()),
+        UnoOp::Bnot => from_big::<E>(&(to_big::<E>(&a) ^ mask::<E>())),
+    }
+}
+
+/// The graph evaluates both branches of conditionals, so like circom-witnesscalc division by zero
+/// gives zero instead of failing, a taken branch can't divide by zero in a satisfiable circuit
+fn eval_duo<E: Engine>(op: DuoOp, a: E::Fr, b: E::Fr) -> E::Fr {
+    let big = |f: fn(BigUint, BigUint) -> BigUint| from_big::<E>(&f(to_big::<E>(&a), to_big::<E>(&b)));
+    match op {
+        DuoOp::Mul => { let mut a = a; a.mul_assign(&b); a }
+        DuoOp::Add => { let mut a = a; a.add_assign(&b); a }
+        DuoOp::Sub => { let mut a = a; a.sub_assign(&b); a }
+        DuoOp::Div => match b.inverse() {
+            Some(inv) => { let mut a = a; a.mul_assign(&inv); a }
+            None => E::Fr::zero(),
+        },
+        DuoOp::Pow => a.pow(b.into_repr()),
+        DuoOp::Idiv | DuoOp::Mod if b.is_zero() => E::Fr::zero(),
+        DuoOp::Idiv => big(|a, b| a / b),
+        DuoOp::Mod => big(|a, b| a % b),
+        DuoOp::Eq => from_bool::<E>(a == b),
+        DuoOp::Neq => from_bool::<E>(a != b),
+        DuoOp::Lt => from_bool::<E>(to_signed::<E>(&a) < to_signed::<E>(&b)),
+        DuoOp::Gt => from_bool::<E>(to_signed::<E>(&a) > to_signed::<E>(&b)),
+        DuoOp::Leq => from_bool::<E>(to_signed::<E>(&a) <= to_signed::<E>(&b)),
+        DuoOp::Geq => from_bool::<E>(to_signed::<E>(&a) >= to_signed::<E>(&b)),
+        DuoOp::Land => from_bool::<E>(!a.is_zero() && !b.is_zero()),
+        DuoOp::Lor => from_bool::<E>(!a.is_zero() || !b.is_zero()),
+        DuoOp::Shl => shift_left::<E>(&a, &b),
+        DuoOp::Shr => shift_right::<E>(&a, &b),
+        DuoOp::Bor => big(|a, b| a | b),
+        DuoOp::Band => big(|a, b| a & b),
+        DuoOp::Bxor => big(|a, b| a ^ b),
+    }
+}
+
+impl Graph {
+    /// Size of the inputs buffer, including the constant 1 at index 0
+    pub fn num_inputs(&self) -> usize {
+        let nodes = self.nodes.iter().filter_map(|n| match n { Node::Input(i) => Some(i + 1), _ => None });
+        let signals = self.inputs.values().map(|i| i.offset + i.len);
+        nodes.chain(signals).max().unwrap_or(0).max(1)
+    }
+
+    /// Evaluates all nodes on the inputs buffer and returns the witness in wire order
+    pub fn calculate_witness<E: Engine>(&self, inputs: &[E::Fr]) -> Result<Vec<E::Fr>> {
+        if inputs.len() != self.num_inputs() {
+            return Err(invalid(format!("Graph expects {} input values, got {}", self.num_inputs(), inputs.len())));
+        }
+        let mut values: Vec<E::Fr> = Vec::with_capacity(self.nodes.len());
+        for (i, node) in self.nodes.iter().enumerate() {
+            let arg = |a: usize| -> Result<E::Fr> {
+                values.get(a).copied().ok_or_else(|| invalid(format!("Node {} refers to node {} that is not evaluated yet", i, a)))
+            };
+            let value = match node {
+                Node::Input(index) => inputs[*index],
+                Node::Constant(value) => from_big::<E>(value),
+                Node::Uno(op, a) => eval_uno::<E>(*op, arg(*a)?),
+                Node::Duo(op, a, b) => eval_duo::<E>(*op, arg(*a)?, arg(*b)?),
+                Node::Tres(TresOp::TernCond, cond, a, b) => if arg(*cond)?.is_zero() { arg(*b)? } else { arg(*a)? },
+            };
+            values.push(value);
+        }
+        let witness = self.signals.iter()
+            .map(|n| values.get(*n).copied().ok_or_else(|| invalid(format!("Signal refers to missing node {}", n))))
+            .collect::<Result<Vec<_>>>()?;
+        if witness.first() != Some(&E::Fr::one()) {
+            return Err(invalid("Witness wire 0 must be equal to 1"));
+        }
+        Ok(witness)
+    }
+
+    /// Flattens circom-style input JSON (`{"a": "1", "b": [["2", 3]]}`) into the inputs buffer
+    pub fn inputs_from_json<E: Engine, R: Read>(&self, reader: R) -> Result<Vec<E::Fr>> {
+        let json: BTreeMap<String, serde_json::Value> = serde_json::from_reader(reader).map_err(invalid)?;
+        let mut inputs = vec![E::Fr::zero(); self.num_inputs()];
+        inputs[0] = E::Fr::one();
+        for (name, signal) in self.inputs.iter() {
+            let value = json.get(name).ok_or_else(|| invalid(format!("Input signal {} is missing", name)))?;
+            let mut flat = vec![];
+            flatten_json::<E>(name, value, &mut flat)?;
+            if flat.len() != signal.len {
+                return Err(invalid(format!("Input signal {} expects {} values, got {}", name, signal.len, flat.len())));
+            }
+            inputs[signal.offset..signal.offset + signal.len].copy_from_slice(&flat);
+        }
+        if let Some(name) = json.keys().find(|k| !self.inputs.contains_key(*k)) {
+            return Err(invalid(format!("Unknown input signal {}", name)));
+        }
+        Ok(inputs)
+    }
+}
+
+fn flatten_json<E: Engine>(name: &str, value: &serde_json::Value, out: &mut Vec<E::Fr>) -> Result<()> {
+    match value {
+        serde_json::Value::Array(items) => {
+            for item in items {
+                flatten_json::<E>(name, item, out)?;
+            }
+        }
+        serde_json::Value::String(s) => out.push(parse_input::<E>(name, s)?),
+        serde_json::Value::Number(n) => out.push(parse_input::<E>(name, &n.to_string())?),
+        _ => return Err(invalid(format!("Input signal {} has non-numeric value", name))),
+    }
+    Ok(())
+}
+
+fn parse_input<E: Engine>(name: &str, s: &str) -> Result<E::Fr> {
+    let value = match s.strip_prefix('-') {
+        Some(abs) => E::Fr::from_str(abs).map(|mut v| { v.negate(); v }),
+        None => E::Fr::from_str(s),
+    };
+    value.ok_or_else(|| invalid(format!("Input signal {} has invalid value {}", name, s)))
+}
+
+fn read_varint<R: Read>(mut reader: R) -> Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = reader.read_u8()?;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid("Varint is too long"))
+}
+
+fn write_varint(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+enum WireValue<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+}
+
+/// Fields of a protobuf message by number, graphs use only varint and length-delimited ones
+struct Message<'a>(Vec<(u64, WireValue<'a>)>);
+
+impl<'a> Message<'a> {
+    fn decode(mut data: &'a [u8]) -> Result<Self> {
+        let mut fields = vec![];
+        while !data.is_empty() {
+            let key = read_varint(&mut data)?;
+            let field = match key & 7 {
+                0 => WireValue::Varint(read_varint(&mut data)?),
+                2 => {
+                    let len = read_varint(&mut data)? as usize;
+                    if len > data.len() {
+                        return Err(invalid("Truncated protobuf field"));
+                    }
+                    let (bytes, rest) = data.split_at(len);
+                    data = rest;
+                    WireValue::Bytes(bytes)
+                }
+                wire_type => return Err(invalid(format!("Unexpected protobuf wire type {}", wire_type))),
+            };
+            fields.push((key >> 3, field));
+        }
+        Ok(Message(fields))
+    }
+
+    /// Scalar field, 0 if absent as in proto3
+    fn uint(&self, number: u64) -> Result<u64> {
+        match self.0.iter().rev().find(|f| f.0 == number) {
+            None => Ok(0),
+            Some((_, WireValue::Varint(value))) => Ok(*value),
+            Some(_) => Err(invalid(format!("Protobuf field {} must be a varint", number))),
+        }
+    }
+
+    fn index(&self, number: u64) -> Result<usize> {
+        self.uint(number).map(|v| v as usize)
+    }
+
+    /// Bytes, string or message field, empty if absent
+    fn bytes(&self, number: u64) -> Result<&'a [u8]> {
+        match self.0.iter().rev().find(|f| f.0 == number) {
+            None => Ok(&[]),
+            Some((_, WireValue::Bytes(bytes))) => Ok(bytes),
+            Some(_) => Err(invalid(format!("Protobuf field {} must be length-delimited", number))),
+        }
+    }
+
+    fn repeated_bytes(&self, number: u64) -> impl Iterator<Item = Result<&'a [u8]>> + '_ {
+        self.0.iter().filter(move |f| f.0 == number).map(move |f| match f.1 {
+            WireValue::Bytes(bytes) => Ok(bytes),
+            _ => Err(invalid(format!("Protobuf field {} must be length-delimited", number))),
+        })
+    }
+
+    /// Packed or unpacked repeated scalar field
+    fn repeated_uint(&self, number: u64) -> Result<Vec<u64>> {
+        let mut values = vec![];
+        for (_, field) in self.0.iter().filter(|f| f.0 == number) {
+            match field {
+                WireValue::Varint(value) => values.push(*value),
+                WireValue::Bytes(packed) => {
+                    let mut packed = *packed;
+                    while !packed.is_empty() {
+                        values.push(read_varint(&mut packed)?);
+                    }
+                }
+            }
+        }
+        Ok(values)
+    }
+}
+
+fn decode_op<T: Copy>(ops: &[T], value: u64, kind: &str) -> Result<T> {
+    ops.get(value as usize).copied().ok_or_else(|| invalid(format!("Unknown {} operation {}", kind, value)))
+}
+
+fn decode_node(data: &[u8]) -> Result<Node> {
+    let node = Message::decode(data)?;
+    let (kind, body) = match node.0.as_slice() {
+        [(kind, WireValue::Bytes(body))] => (*kind, Message::decode(body)?),
+        _ => return Err(invalid("Graph node must have exactly one kind")),
+    };
+    Ok(match kind {
+        1 => Node::Input(body.index(1)?),
+        2 => Node::Constant(BigUint::from_bytes_le(Message::decode(body.bytes(1)?)?.bytes(1)?)),
+        3 => Node::Uno(decode_op(&UNO_OPS, body.uint(1)?, "unary")?, body.index(2)?),
+        4 => Node::Duo(decode_op(&DUO_OPS, body.uint(1)?, "binary")?, body.index(2)?, body.index(3)?),
+        5 => Node::Tres(decode_op(&TRES_OPS, body.uint(1)?, "ternary")?, body.index(2)?, body.index(3)?, body.index(4)?),
+        _ => return Err(invalid(format!("Unknown graph node kind {}", kind))),
+    })
+}
+
+fn field_uint(number: u64, value: u64, out: &mut Vec<u8>) {
+    if value != 0 {
+        write_varint(number << 3, out);
+        write_varint(value, out);
+    }
+}
+
+fn field_bytes(number: u64, bytes: &[u8], out: &mut Vec<u8>) {
+    write_varint(number << 3 | 2, out);
+    write_varint(bytes.len() as u64, out);
+    out.extend_from_slice(bytes);
+}
+
+fn encode_op<T: PartialEq>(ops: &[T], op: &T) -> u64 {
+    ops.iter().position(|o| o == op).unwrap() as u64
+}
+
+fn encode_node(node: &Node) -> Vec<u8> {
+    let mut body = vec![];
+    let kind = match node {
+        Node::Input(index) => {
+            field_uint(1, *index as u64, &mut body);
+            1
+        }
+        Node::Constant(value) => {
+            let mut big = vec![];
+            field_bytes(1, &value.to_bytes_le(), &mut big);
+            field_bytes(1, &big, &mut body);
+            2
+        }
+        Node::Uno(op, a) => {
+            field_uint(1, encode_op(&UNO_OPS, op), &mut body);
+            field_uint(2, *a as u64, &mut body);
+            3
+        }
+        Node::Duo(op, a, b) => {
+            field_uint(1, encode_op(&DUO_OPS, op), &mut body);
+            field_uint(2, *a as u64, &mut body);
+            field_uint(3, *b as u64, &mut body);
+            4
+        }
+        Node::Tres(op, cond, a, b) => {
+            field_uint(1, encode_op(&TRES_OPS, op), &mut body);
+            field_uint(2, *cond as u64, &mut body);
+            field_uint(3, *a as u64, &mut body);
+            field_uint(4, *b as u64, &mut body);
+            5
+        }
+    };
+    let mut node = vec![];
+    field_bytes(kind, &body, &mut node);
+    node
+}
+
+fn read_delimited<R: Read>(mut reader: R, buf: &mut Vec<u8>) -> Result<()> {
+    let len = read_varint(&mut reader)?;
+    buf.clear();
+    reader.take(len).read_to_end(buf)?;
+    if buf.len() as u64 != len {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "Truncated graph message"));
+    }
+    Ok(())
+}
+
+fn write_delimited<W: Write>(mut writer: W, message: &[u8]) -> Result<u64> {
+    let mut len = vec![];
+    write_varint(message.len() as u64, &mut len);
+    writer.write_all(&len)?;
+    writer.write_all(message)?;
+    Ok((len.len() + message.len()) as u64)
+}
+
+pub fn read<R: Read>(mut reader: R) -> Result<Graph> {
+    let mut magic = [0u8; 14];
+    reader.read_exact(&mut magic)?;
+    if magic[..] != *GRAPH_MAGIC {
+        return Err(invalid("Not a circom-witnesscalc graph file"));
+    }
+    let count = reader.read_u64::<LittleEndian>()? as usize;
+    let mut buf = vec![];
+    let mut nodes = Vec::with_capacity(count.min(1 << 24));
+    for i in 0..count {
+        read_delimited(&mut reader, &mut buf)?;
+        nodes.push(decode_node(&buf).map_err(|e| invalid(format!("Invalid node {}: {}", i, e)))?);
+    }
+    read_delimited(&mut reader, &mut buf)?;
+    let metadata = Message::decode(&buf)?;
+    let signals = metadata.repeated_uint(1)?.into_iter().map(|s| s as usize).collect();
+    let mut inputs = BTreeMap::new();
+    for entry in metadata.repeated_bytes(2) {
+        let entry = Message::decode(entry?)?;
+        let name = String::from_utf8(entry.bytes(1)?.to_vec()).map_err(invalid)?;
+        let signal = Message::decode(entry.bytes(2)?)?;
+        inputs.insert(name, InputSignal { offset: signal.index(1)?, len: signal.index(2)? });
+    }
+    // the trailing metadata offset is only needed to read the metadata first
+    Ok(Graph { nodes, signals, inputs })
+}
+
+pub fn write<W: Write>(graph: &Graph, mut writer: W) -> Result<()> {
+    writer.write_all(GRAPH_MAGIC)?;
+    writer.write_u64::<LittleEndian>(graph.nodes.len() as u64)?;
+    let mut offset = GRAPH_MAGIC.len() as u64 + 8;
+    for node in &graph.nodes {
+        offset += write_delimited(&mut writer, &encode_node(node))?;
+    }
+    let mut metadata = vec![];
+    let mut signals = vec![];
+    graph.signals.iter().for_each(|s| write_varint(*s as u64, &mut signals));
+    field_bytes(1, &signals, &mut metadata);
+    for (name, signal) in &graph.inputs {
+        let (mut entry, mut description) = (vec![], vec![]);
+        field_uint(1, signal.offset as u64, &mut description);
+        field_uint(2, signal.len as u64, &mut description);
+        field_bytes(1, name.as_bytes(), &mut entry);
+        field_bytes(2, &description, &mut entry);
+        field_bytes(2, &entry, &mut metadata);
+    }
+    write_delimited(&mut writer, &metadata)?;
+    writer.write_u64::<LittleEndian>(offset)?;
+    writer.flush()
+}
+
+#[test]
+fn evaluate() {
+    use bellman_ce::pairing::bn256::{Bn256, Fr};
+    let fr = |s: &str| Fr::from_str(s).unwrap();
+    let graph = Graph {
+        nodes: vec![
+            Node::Constant(BigUint::from(1u32)),
+            Node::Input(1),
+            Node::Input(2),
+            Node::Duo(DuoOp::Mul, 1, 2),
+            Node::Duo(DuoOp::Lt, 1, 2),
+            Node::Duo(DuoOp::Shr, 3, 0),
+            Node::Tres(TresOp::TernCond, 4, 1, 2),
+            Node::Uno(UnoOp::Neg, 0),
+            Node::Duo(DuoOp::Shl, 3, 7),
+        ],
+        signals: vec![0, 3, 1, 2, 4, 5, 6, 8],
+        inputs: vec![
+            ("a".to_string(), InputSignal { offset: 1, len: 1 }),
+            ("b".to_string(), InputSignal { offset: 2, len: 1 }),
+        ].into_iter().collect(),
+    };
+    let mut data = vec![];
+    write(&graph, &mut data).unwrap();
+    assert_eq!(&data[..14], GRAPH_MAGIC);
+    let graph = read(&data[..]).unwrap();
+    assert_eq!(graph.num_inputs(), 3);
+
+    let inputs = graph.inputs_from_json::<Bn256, _>(&br#"{"a": 3, "b": "11"}"#[..]).unwrap();
+    let witness = graph.calculate_witness::<Bn256>(&inputs).unwrap();
+    // a shift by -1 is a shift the other way
+    assert_eq!(witness, vec![fr("1"), fr("33"), fr("3"), fr("11"), fr("1"), fr("16"), fr("3"), fr("16")]);
+
+    let inputs = graph.inputs_from_json::<Bn256, _>(&br#"{"a": "-1", "b": "2"}"#[..]).unwrap();
+    let witness = graph.calculate_witness::<Bn256>(&inputs).unwrap();
+    assert_eq!(witness[4], fr("1"));
+    assert!(graph.inputs_from_json::<Bn256, _>(&br#"{"a": "1"}"#[..]).is_err());
+    assert!(read(&data[..data.len() - 20]).is_err());
+}
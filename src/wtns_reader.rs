@@ -1,5 +1,5 @@
-use byteorder::{ReadBytesExt, LittleEndian};
-use std::io::{Read, Result, ErrorKind, Error};
+use byteorder::{ReadBytesExt, WriteBytesExt, LittleEndian};
+use std::io::{Read, Write, Result, ErrorKind, Error};
 use bellman_ce::pairing::{
     Engine,
     ff::{
@@ -7,6 +7,9 @@ use bellman_ce::pairing::{
     }
 };
 
+const MAGIC: [u8; 4] = [119, 116, 110, 115]; // magic = "wtns"
+const BN256_PRIME: [u8; 32] = hex!("010000f093f5e1439170b97948e833285d588181b64550b829a031e1724e6430");
+
 pub struct Header {
     pub field_size: u32,
     pub prime_size: Vec<u8>,
@@ -19,7 +22,7 @@ pub struct WTNSFile<E: Engine> {
     pub witness: Vec<E::Fr>,
 }
 
-fn read_field<R: Read, E: Engine>(mut reader: R) -> Result<E::Fr> {
+pub(crate) fn read_field<R: Read, E: Engine>(mut reader: R) -> Result<E::Fr> {
     let mut repr = E::Fr::zero().into_repr();
     repr.read_le(&mut reader)?;
     let fr = E::Fr::from_repr(repr)
@@ -57,7 +60,7 @@ fn read_witness<R: Read, E:Engine>(mut reader: R, size: u64, header: &Header) ->
 pub fn read<E: Engine, R: Read>(mut reader: R) -> Result<WTNSFile<E>> {
     let mut magic = [0u8; 4];
     reader.read_exact(&mut magic)?;
-    if magic != [119, 116, 110, 115] { // magic = "wtns"
+    if magic != MAGIC {
         return Err(Error::new(ErrorKind::InvalidData, "Invalid magic number"))
     }
 
@@ -79,8 +82,8 @@ pub fn read<E: Engine, R: Read>(mut reader: R) -> Result<WTNSFile<E>> {
     if header.field_size != 32 {
         return Err(Error::new(ErrorKind::InvalidData, "This parser only supports 32-byte fields"))
     }
-    if header.prime_size != hex!("010000f093f5e1439170b97948e833285d588181b64550b829a031e1724e6430") {
-        return Err(Error::new(ErrorKind::InvalidData, "This parser only supports bn256"))
+    if header.prime_size != BN256_PRIME {
+        return Err(Error::new(ErrorKind::InvalidData, "Witness is for a field other than BN254 (bn128), only BN254 is supported"))
     }
 
     let sec_type = reader.read_u32::<LittleEndian>()?;
@@ -92,3 +95,35 @@ pub fn read<E: Engine, R: Read>(mut reader: R) -> Result<WTNSFile<E>> {
 
     Ok(WTNSFile { version, header, witness })
 }
+
+/// Writes witness in version 2 wtns format, as produced by snarkjs and circom witness generators
+pub fn write<E: Engine, W: Write>(mut writer: W, witness: &[E::Fr]) -> Result<()> {
+    writer.write_all(&MAGIC)?;
+    writer.write_u32::<LittleEndian>(2)?;
+    writer.write_u32::<LittleEndian>(2)?;
+
+    writer.write_u32::<LittleEndian>(1)?;
+    writer.write_u64::<LittleEndian>(4 + 32 + 4)?;
+    writer.write_u32::<LittleEndian>(32)?;
+    writer.write_all(&BN256_PRIME)?;
+    writer.write_u32::<LittleEndian>(witness.len() as u32)?;
+
+    writer.write_u32::<LittleEndian>(2)?;
+    writer.write_u64::<LittleEndian>(witness.len() as u64 * 32)?;
+    for fr in witness {
+        fr.into_repr().write_le(&mut writer)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn roundtrip() {
+    use bellman_ce::pairing::bn256::{Bn256, Fr};
+    let witness = vec![Fr::one(), Fr::from_str("3").unwrap(), Fr::from_str("12345678901234567890").unwrap()];
+    let mut data = vec![];
+    write::<Bn256, _>(&mut data, &witness).unwrap();
+    let file = read::<Bn256, _>(&data[..]).unwrap();
+    assert_eq!(file.version, 2);
+    assert_eq!(file.header.witness_len, 3);
+    assert_eq!(file.witness, witness);
+}
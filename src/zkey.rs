@@ -0,0 +1,292 @@
+//! Import of snarkjs Groth16 `.zkey` proving keys, e.g. the output of a phase 2 ceremony.
+//!
+//! A zkey starts with magic `zkey`, u32 version 1 and u32 number of sections, every section is a
+//! u32 type, u64 size and its content. Points are affine with coordinates in little-endian
+//! Montgomery form like `raw_params` files, the point at infinity is all zeros.
+//!
+//! The verifying key and the A, B, L (`C` in snarkjs) and IC queries are the same as bellman's.
+//! The H query is not: snarkjs stores `L_{2j+1}(tau) / delta`, Lagrange polynomials at the odd
+//! points of a domain of twice the size for its coset FFT prover, bellman stores
+//! `tau^i * Z(tau) / delta`. As `tau^i * Z(tau) = -2 w^i * sum_j w^(2ij) * L_{2j+1}(tau)` for the
+//! 2n-th root of unity `w`, the bellman query is a DFT of the snarkjs one, computed without tau.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Read, Result, Seek, SeekFrom};
+use std::sync::Arc;
+use byteorder::{LittleEndian, ReadBytesExt};
+use bellman_ce::{
+    groth16::{Parameters, VerifyingKey},
+    pairing::{
+        CurveAffine,
+        CurveProjective,
+        ff::{Field, PrimeField, PrimeFieldRepr},
+        bn256::{Bn256, Fq, Fr, G1, G1Affine},
+    },
+};
+use crate::raw_params::{read_g1, read_g2, RawLayout};
+
+pub const ZKEY_MAGIC: [u8; 4] = *b"zkey";
+
+const SECTION_HEADER: u32 = 1;
+const SECTION_GROTH16_HEADER: u32 = 2;
+const SECTION_IC: u32 = 3;
+const SECTION_A: u32 = 5;
+const SECTION_B1: u32 = 6;
+const SECTION_B2: u32 = 7;
+const SECTION_C: u32 = 8;
+const SECTION_H: u32 = 9;
+const PROTOCOL_GROTH16: u32 = 1;
+
+fn invalid<T: Into<Box<dyn std::error::Error + Send + Sync>>>(msg: T) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// Seeks to the content of a section, sections can be in any order
+fn seek_section<R: Read + Seek>(reader: &mut R, sections: &HashMap<u32, u64>, id: u32) -> Result<()> {
+    let offset = sections.get(&id).ok_or_else(|| invalid(format!("zkey has no section {}", id)))?;
+    reader.seek(SeekFrom::Start(*offset))?;
+    Ok(())
+}
+
+fn read_modulus<R: Read, F: PrimeField>(reader: &mut R, name: &str) -> Result<()> {
+    let size = reader.read_u32::<LittleEndian>()?;
+    let mut modulus = vec![0u8; size as usize];
+    reader.read_exact(&mut modulus)?;
+    let mut expected = vec![];
+    F::char().write_le(&mut expected)?;
+    if modulus != expected {
+        return Err(invalid(format!("zkey {} field is not the one of BN254", name)));
+    }
+    Ok(())
+}
+
+fn read_g1s<R: Read>(reader: &mut R, count: usize) -> Result<Vec<G1Affine>> {
+    (0..count).map(|_| read_g1(reader, RawLayout::default())).collect()
+}
+
+/// Reads Groth16 parameters from a zkey, points are checked to be on the curve and in the right subgroup
+pub fn read_zkey<R: Read + Seek>(mut reader: R) -> Result<Parameters<Bn256>> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != ZKEY_MAGIC {
+        return Err(invalid("not a snarkjs zkey file"));
+    }
+    let version = reader.read_u32::<LittleEndian>()?;
+    if version != 1 {
+        return Err(invalid(format!("unsupported zkey version {}", version)));
+    }
+    let num_sections = reader.read_u32::<LittleEndian>()?;
+    let mut sections = HashMap::new();
+    for _ in 0..num_sections {
+        let id = reader.read_u32::<LittleEndian>()?;
+        let size = reader.read_u64::<LittleEndian>()?;
+        let offset = reader.stream_position()?;
+        if sections.insert(id, offset).is_some() {
+            return Err(invalid(format!("zkey has section {} twice", id)));
+        }
+        reader.seek(SeekFrom::Current(size as i64))?;
+    }
+
+    seek_section(&mut reader, &sections, SECTION_HEADER)?;
+    let protocol = reader.read_u32::<LittleEndian>()?;
+    if protocol != PROTOCOL_GROTH16 {
+        return Err(invalid(format!("only Groth16 zkeys can be imported, got protocol {}", protocol)));
+    }
+    seek_section(&mut reader, &sections, SECTION_GROTH16_HEADER)?;
+    read_modulus::<_, Fq>(&mut reader, "base")?;
+    read_modulus::<_, Fr>(&mut reader, "scalar")?;
+    let num_vars = reader.read_u32::<LittleEndian>()? as usize;
+    let num_public = reader.read_u32::<LittleEndian>()? as usize;
+    let domain_size = reader.read_u32::<LittleEndian>()? as usize;
+    if num_public >= num_vars {
+        return Err(invalid(format!("zkey has {} public inputs and only {} variables", num_public, num_vars)));
+    }
+    let layout = RawLayout::default();
+    let alpha_g1 = read_g1(&mut reader, layout)?;
+    let beta_g1 = read_g1(&mut reader, layout)?;
+    let beta_g2 = read_g2(&mut reader, layout)?;
+    let gamma_g2 = read_g2(&mut reader, layout)?;
+    let delta_g1 = read_g1(&mut reader, layout)?;
+    let delta_g2 = read_g2(&mut reader, layout)?;
+
+    seek_section(&mut reader, &sections, SECTION_IC)?;
+    let ic = read_g1s(&mut reader, num_public + 1)?;
+    // like bellman's generator, A and B queries skip the points at infinity of unused variables
+    seek_section(&mut reader, &sections, SECTION_A)?;
+    let a = read_g1s(&mut reader, num_vars)?.into_iter().filter(|p| !p.is_zero()).collect();
+    seek_section(&mut reader, &sections, SECTION_B1)?;
+    let b_g1 = read_g1s(&mut reader, num_vars)?.into_iter().filter(|p| !p.is_zero()).collect();
+    seek_section(&mut reader, &sections, SECTION_B2)?;
+    let b_g2 = (0..num_vars).map(|_| read_g2(&mut reader, layout)).collect::<Result<Vec<_>>>()?
+        .into_iter().filter(|p| !p.is_zero()).collect();
+    seek_section(&mut reader, &sections, SECTION_C)?;
+    let l = read_g1s(&mut reader, num_vars - num_public - 1)?;
+    seek_section(&mut reader, &sections, SECTION_H)?;
+    let h = h_from_lagrange(&read_g1s(&mut reader, domain_size)?)?;
+
+    Ok(Parameters {
+        vk: VerifyingKey { alpha_g1, beta_g1, beta_g2, gamma_g2, delta_g1, delta_g2, ic },
+        h: Arc::new(h),
+        l: Arc::new(l),
+        a: Arc::new(a),
+        b_g1: Arc::new(b_g1),
+        b_g2: Arc::new(b_g2),
+    })
+}
+
+pub fn load_zkey(filename: &str) -> Result<Parameters<Bn256>> {
+    read_zkey(BufReader::new(File::open(filename)?))
+}
+
+/// In-place radix-2 DFT of points: `a_i = sum_j w^(ij) a_j`
+fn fft(a: &mut [G1], omega: Fr) {
+    let n = a.len();
+    let log_n = n.trailing_zeros();
+    for k in 0..n {
+        let rk = k.reverse_bits() >> (usize::BITS - log_n);
+        if k < rk {
+            a.swap(k, rk);
+        }
+    }
+    let mut m = 1;
+    while m < n {
+        let w_m = omega.pow(&[(n / (2 * m)) as u64]);
+        for k in (0..n).step_by(2 * m) {
+            let mut w = Fr::one();
+            for j in 0..m {
+                let mut t = a[k + j + m];
+                t.mul_assign(w.into_repr());
+                let mut u = a[k + j];
+                u.sub_assign(&t);
+                a[k + j + m] = u;
+                a[k + j].add_assign(&t);
+                w.mul_assign(&w_m);
+            }
+        }
+        m *= 2;
+    }
+}
+
+/// Bellman's `tau^i * Z(tau) / delta` H query of size `n - 1` from snarkjs
+/// `L_{2j+1}(tau) / delta` points, `n` is the domain size
+fn h_from_lagrange(points: &[G1Affine]) -> Result<Vec<G1Affine>> {
+    let n = points.len();
+    if n < 2 || !n.is_power_of_two() || n.trailing_zeros() + 1 > Fr::S {
+        return Err(invalid(format!("invalid zkey domain size {}", n)));
+    }
+    let mut w = Fr::root_of_unity();
+    for _ in n.trailing_zeros() + 1..Fr::S {
+        w.square();
+    }
+    let mut w_n = w;
+    w_n.square();
+    let mut h = points.iter().map(|p| p.into_projective()).collect::<Vec<_>>();
+    fft(&mut h, w_n);
+    h.truncate(n - 1);
+    let mut factor = Fr::one();
+    factor.double();
+    factor.negate();
+    for point in h.iter_mut() {
+        point.mul_assign(factor.into_repr());
+        factor.mul_assign(&w);
+    }
+    G1::batch_normalization(&mut h);
+    Ok(h.into_iter().map(|p| p.into_affine()).collect())
+}
+
+#[test]
+fn zkey_h_query() {
+    let fr = |s: &str| Fr::from_str(s).unwrap();
+    let (n, tau) = (4usize, fr("5"));
+    let mut w = Fr::root_of_unity();
+    for _ in 3..Fr::S {
+        w.square();
+    }
+    // L_k(tau) = w^k (tau^2n - 1) / (2n (tau - w^k)) on the domain of 2n = 8 points
+    let lagrange = |k: u64| {
+        let w_k = w.pow(&[k]);
+        let mut numerator = tau.pow(&[2 * n as u64]);
+        numerator.sub_assign(&Fr::one());
+        numerator.mul_assign(&w_k);
+        let mut denominator = tau;
+        denominator.sub_assign(&w_k);
+        denominator.mul_assign(&fr("8"));
+        numerator.mul_assign(&denominator.inverse().unwrap());
+        numerator
+    };
+    let points = (0..n).map(|j| G1Affine::one().mul(lagrange(2 * j as u64 + 1).into_repr()).into_affine()).collect::<Vec<_>>();
+    let h = h_from_lagrange(&points).unwrap();
+    assert_eq!(h.len(), n - 1);
+    for (i, point) in h.iter().enumerate() {
+        let mut expected = tau.pow(&[n as u64]);
+        expected.sub_assign(&Fr::one());
+        expected.mul_assign(&tau.pow(&[i as u64]));
+        assert!(*point == G1Affine::one().mul(expected.into_repr()).into_affine());
+    }
+    assert!(h_from_lagrange(&points[..3]).is_err());
+}
+
+#[test]
+fn zkey_sections() {
+    use std::io::Cursor;
+    use byteorder::WriteBytesExt;
+    use bellman_ce::pairing::bn256::G2Affine;
+    use crate::raw_params::{write_g1, write_g2};
+    let layout = RawLayout::default();
+    let g1 = |k: u64| if k == 0 { G1Affine::zero() } else { G1Affine::one().mul(Fr::from_str(&k.to_string()).unwrap().into_repr()).into_affine() };
+    let g1s = |points: &[u64]| {
+        let mut out = vec![];
+        points.iter().for_each(|p| write_g1(&mut out, &g1(*p), layout).unwrap());
+        out
+    };
+    let g2s = |count: usize| {
+        let mut out = vec![];
+        (0..count).for_each(|_| write_g2(&mut out, &G2Affine::one(), layout).unwrap());
+        out
+    };
+    let mut groth16 = vec![];
+    for modulus in [Fq::char().as_ref().to_vec(), Fr::char().as_ref().to_vec()].iter() {
+        groth16.write_u32::<LittleEndian>(32).unwrap();
+        modulus.iter().for_each(|limb| groth16.write_u64::<LittleEndian>(*limb).unwrap());
+    }
+    // 3 variables with 1 public input and a domain of 2
+    [3u32, 1, 2].iter().for_each(|n| groth16.write_u32::<LittleEndian>(*n).unwrap());
+    groth16.extend(g1s(&[1, 2]));
+    groth16.extend(g2s(2));
+    groth16.extend(g1s(&[4]));
+    groth16.extend(g2s(1));
+    let sections = vec![
+        (SECTION_GROTH16_HEADER, groth16),
+        (SECTION_HEADER, PROTOCOL_GROTH16.to_le_bytes().to_vec()),
+        (SECTION_IC, g1s(&[5, 6])),
+        (SECTION_A, g1s(&[7, 0, 8])),
+        (SECTION_B1, g1s(&[9, 10, 11])),
+        (SECTION_B2, g2s(3)),
+        (SECTION_C, g1s(&[12])),
+        (SECTION_H, g1s(&[13, 14])),
+    ];
+    let mut zkey = ZKEY_MAGIC.to_vec();
+    zkey.write_u32::<LittleEndian>(1).unwrap();
+    zkey.write_u32::<LittleEndian>(sections.len() as u32).unwrap();
+    for (id, content) in &sections {
+        zkey.write_u32::<LittleEndian>(*id).unwrap();
+        zkey.write_u64::<LittleEndian>(content.len() as u64).unwrap();
+        zkey.extend_from_slice(content);
+    }
+    let params = read_zkey(Cursor::new(&zkey)).unwrap();
+    assert!(params.vk.alpha_g1 == g1(1) && params.vk.beta_g1 == g1(2) && params.vk.delta_g1 == g1(4));
+    assert!(params.vk.ic == vec![g1(5), g1(6)]);
+    assert!(*params.a == vec![g1(7), g1(8)]);
+    assert_eq!((params.b_g1.len(), params.b_g2.len(), params.l.len(), params.h.len()), (3, 3, 1, 1));
+    assert!(params.l[0] == g1(12));
+    // for a domain of 2, h_0 = -2 (L_1 + L_3) / delta
+    let mut h = g1(13).into_projective();
+    h.add_assign_mixed(&g1(14));
+    let mut minus_two = Fr::one();
+    minus_two.double();
+    minus_two.negate();
+    h.mul_assign(minus_two.into_repr());
+    assert!(params.h[0] == h.into_affine());
+
+    assert!(read_zkey(Cursor::new(b"r1cs\x01\0\0\0\0\0\0\0".to_vec())).is_err());
+}
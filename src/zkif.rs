@@ -0,0 +1,271 @@
+//! Export and import of circuits and witnesses in the zkinterface format.
+//!
+//! A `.zkif` file is a sequence of size-prefixed FlatBuffers messages: a circuit header with
+//! public inputs, the constraint system and optionally the private part of the witness. Variable 0
+//! is the constant one in both circom and zkinterface, so exported wire indices are kept as is.
+//! Field elements are encoded as little-endian numbers.
+//!
+//! On import public inputs become wires 1.. in the order of the header, followed by all other
+//! variable ids below `free_variable_id` in increasing order. The mapping only depends on the
+//! header, so circuits and witnesses from separate files line up.
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Result, Write};
+use std::path::PathBuf;
+use bellman_ce::pairing::{
+    Engine,
+    bn256::{Bn256, Fr},
+    ff::{Field, PrimeField, PrimeFieldRepr},
+};
+use ::zkinterface::{BilinearConstraint, CircuitHeader, ConstraintSystem, KeyValue, Message, Variables, Witness, Workspace};
+use crate::circom_circuit::R1CS;
+use crate::utils::write_file_atomic;
+
+fn to_le_bytes(values: &[Fr], out: &mut Vec<u8>) {
+    for value in values {
+        value.into_repr().write_le(&mut *out).expect("writing to memory doesn't fail");
+    }
+}
+
+fn variables(ids: impl Iterator<Item = usize>, values: Option<&[Fr]>) -> Variables {
+    Variables {
+        variable_ids: ids.map(|id| id as u64).collect(),
+        values: values.map(|values| {
+            let mut bytes = Vec::with_capacity(values.len() * 32);
+            to_le_bytes(values, &mut bytes);
+            bytes
+        }),
+    }
+}
+
+fn linear_combination(lc: &[(usize, Fr)]) -> Variables {
+    let coeffs = lc.iter().map(|(_, coeff)| *coeff).collect::<Vec<_>>();
+    variables(lc.iter().map(|(wire, _)| *wire), Some(&coeffs))
+}
+
+fn zkif_error(e: Box<dyn std::error::Error>) -> Error {
+    Error::new(ErrorKind::Other, format!("zkinterface: {}", e))
+}
+
+/// Circuit header with public inputs taken from `witness` if given
+pub fn circuit_header(r1cs: &R1CS<Bn256>, witness: Option<&[Fr]>) -> CircuitHeader {
+    let mut field_maximum = vec![];
+    let mut max = Fr::zero();
+    max.sub_assign(&Fr::one());
+    to_le_bytes(&[max], &mut field_maximum);
+    CircuitHeader {
+        instance_variables: variables(1..r1cs.num_inputs, witness.map(|w| &w[1..r1cs.num_inputs])),
+        free_variable_id: r1cs.num_variables as u64,
+        field_maximum: Some(field_maximum),
+        configuration: Some(vec![KeyValue {
+            key: String::from("producer"),
+            data: None,
+            text: Some(format!("zkutil {}", env!("CARGO_PKG_VERSION"))),
+            number: 0,
+        }]),
+    }
+}
+
+pub fn constraint_system(r1cs: &R1CS<Bn256>) -> ConstraintSystem {
+    ConstraintSystem {
+        constraints: r1cs.constraints.iter().map(|(a, b, c)| BilinearConstraint {
+            linear_combination_a: linear_combination(a),
+            linear_combination_b: linear_combination(b),
+            linear_combination_c: linear_combination(c),
+        }).collect(),
+    }
+}
+
+/// Private part of the witness
+pub fn witness(r1cs: &R1CS<Bn256>, witness: &[Fr]) -> Witness {
+    Witness {
+        assigned_variables: variables(r1cs.num_inputs..r1cs.num_variables, Some(&witness[r1cs.num_inputs..])),
+    }
+}
+
+/// Writes header, constraints and, if `witness` is given, the witness messages
+pub fn write<W: Write>(r1cs: &R1CS<Bn256>, witness_values: Option<&[Fr]>, mut writer: W) -> Result<()> {
+    if let Some(values) = witness_values {
+        if values.len() != r1cs.num_variables {
+            return Err(Error::new(ErrorKind::InvalidData, format!(
+                "witness has {} values, circuit expects {}", values.len(), r1cs.num_variables,
+            )));
+        }
+    }
+    circuit_header(r1cs, witness_values).write_into(&mut writer).map_err(zkif_error)?;
+    constraint_system(r1cs).write_into(&mut writer).map_err(zkif_error)?;
+    if let Some(values) = witness_values {
+        witness(r1cs, values).write_into(&mut writer).map_err(zkif_error)?;
+    }
+    Ok(())
+}
+
+pub fn write_file(r1cs: &R1CS<Bn256>, witness_values: Option<&[Fr]>, filename: &str) -> Result<()> {
+    write_file_atomic(filename, |w| write(r1cs, witness_values, w))
+}
+
+/// Messages of a zkinterface file
+pub struct Messages {
+    pub header: CircuitHeader,
+    pub constraint_systems: Vec<ConstraintSystem>,
+    pub witnesses: Vec<Witness>,
+}
+
+pub fn read_messages(filename: &str) -> Result<Messages> {
+    let workspace = Workspace::from_dirs_and_files(&[PathBuf::from(filename)]).map_err(zkif_error)?;
+    let mut header = None;
+    let mut constraint_systems = vec![];
+    let mut witnesses = vec![];
+    for message in workspace.iter_messages() {
+        match message {
+            Message::Header(h) => header = Some(h),
+            Message::ConstraintSystem(cs) => constraint_systems.push(cs),
+            Message::Witness(w) => witnesses.push(w),
+            _ => {}
+        }
+    }
+    let header = header.ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("{} has no circuit header", filename)))?;
+    Ok(Messages { header, constraint_systems, witnesses })
+}
+
+fn field_element<F: PrimeField>(bytes: &[u8]) -> Result<F> {
+    let mut repr = F::Repr::default();
+    let size = repr.as_ref().len() * 8;
+    if bytes[size.min(bytes.len())..].iter().any(|b| *b != 0) {
+        return Err(Error::new(ErrorKind::InvalidData, "field element is out of range"));
+    }
+    let mut padded = vec![0u8; size];
+    let len = size.min(bytes.len());
+    padded[..len].copy_from_slice(&bytes[..len]);
+    repr.read_le(&padded[..])?;
+    F::from_repr(repr).map_err(|e| Error::new(ErrorKind::InvalidData, format!("{}", e)))
+}
+
+/// Values of `vars`, `None` if they have no values
+fn values<F: PrimeField>(vars: &Variables) -> Result<Option<Vec<F>>> {
+    let bytes = match &vars.values {
+        Some(bytes) if !bytes.is_empty() => bytes,
+        _ => return Ok(None),
+    };
+    if vars.variable_ids.is_empty() || bytes.len() % vars.variable_ids.len() != 0 {
+        return Err(Error::new(ErrorKind::InvalidData, "values don't match variable ids"));
+    }
+    let size = bytes.len() / vars.variable_ids.len();
+    bytes.chunks(size).map(field_element).collect::<Result<Vec<_>>>().map(Some)
+}
+
+fn check_field<F: PrimeField>(header: &CircuitHeader) -> Result<()> {
+    if let Some(max) = &header.field_maximum {
+        let mut expected = F::zero();
+        expected.sub_assign(&F::one());
+        if field_element::<F>(max).ok() != Some(expected) {
+            return Err(Error::new(ErrorKind::InvalidData, "circuit is defined over a different field, only BN254 is supported"));
+        }
+    }
+    Ok(())
+}
+
+/// Wire index of every zkinterface variable id
+struct WireMap {
+    wires: HashMap<u64, usize>,
+    num_inputs: usize,
+    num_variables: usize,
+}
+
+impl WireMap {
+    fn new(header: &CircuitHeader) -> Result<Self> {
+        let mut wires = HashMap::new();
+        wires.insert(0, 0);
+        for &id in &header.instance_variables.variable_ids {
+            if id == 0 || wires.insert(id, wires.len()).is_some() {
+                return Err(Error::new(ErrorKind::InvalidData, format!("invalid public variable id {}", id)));
+            }
+        }
+        let num_inputs = wires.len();
+        for id in 1..header.free_variable_id {
+            if !wires.contains_key(&id) {
+                wires.insert(id, wires.len());
+            }
+        }
+        let num_variables = wires.len();
+        Ok(WireMap { wires, num_inputs, num_variables })
+    }
+
+    fn wire(&self, id: u64) -> Result<usize> {
+        self.wires.get(&id).copied().ok_or_else(|| Error::new(
+            ErrorKind::InvalidData,
+            format!("variable id {} is not below free_variable_id", id),
+        ))
+    }
+
+    fn linear_combination<F: PrimeField>(&self, vars: &Variables) -> Result<Vec<(usize, F)>> {
+        let coeffs = values::<F>(vars)?.ok_or_else(|| Error::new(ErrorKind::InvalidData, "linear combination has no coefficients"))?;
+        vars.variable_ids.iter().zip(coeffs).map(|(id, coeff)| Ok((self.wire(*id)?, coeff))).collect()
+    }
+}
+
+/// Builds a circuit from zkinterface header and constraint system messages
+pub fn to_r1cs<E: Engine>(messages: &Messages) -> Result<R1CS<E>> {
+    check_field::<E::Fr>(&messages.header)?;
+    let map = WireMap::new(&messages.header)?;
+    let mut constraints = vec![];
+    for cs in &messages.constraint_systems {
+        for c in &cs.constraints {
+            constraints.push((
+                map.linear_combination(&c.linear_combination_a)?,
+                map.linear_combination(&c.linear_combination_b)?,
+                map.linear_combination(&c.linear_combination_c)?,
+            ));
+        }
+    }
+    let mut r1cs = R1CS {
+        num_inputs: map.num_inputs,
+        num_aux: map.num_variables - map.num_inputs,
+        num_variables: map.num_variables,
+        constraints,
+    };
+    r1cs.sort_terms();
+    Ok(r1cs)
+}
+
+/// Builds a full witness from public values in the header and private values in witness messages,
+/// variables without a value are set to zero
+pub fn to_witness<E: Engine>(messages: &Messages) -> Result<Vec<E::Fr>> {
+    check_field::<E::Fr>(&messages.header)?;
+    let map = WireMap::new(&messages.header)?;
+    let mut witness = vec![E::Fr::zero(); map.num_variables];
+    witness[0] = E::Fr::one();
+    let instance = &messages.header.instance_variables;
+    if values::<E::Fr>(instance)?.is_none() && !instance.variable_ids.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidData, "circuit header has no public input values"));
+    }
+    let private = messages.witnesses.iter().map(|w| &w.assigned_variables);
+    for vars in std::iter::once(instance).chain(private) {
+        for (id, value) in vars.variable_ids.iter().zip(values::<E::Fr>(vars)?.unwrap_or_default()) {
+            witness[map.wire(*id)?] = value;
+        }
+    }
+    Ok(witness)
+}
+
+pub fn load_r1cs<E: Engine>(filename: &str) -> Result<R1CS<E>> {
+    to_r1cs(&read_messages(filename)?)
+}
+
+pub fn load_witness<E: Engine>(filename: &str) -> Result<Vec<E::Fr>> {
+    to_witness::<E>(&read_messages(filename)?)
+}
+
+#[test]
+fn roundtrip() {
+    use crate::test_utils::{multiplier_circuit, multiplier_witness};
+    let (r1cs, witness) = (multiplier_circuit(), multiplier_witness(2, 3));
+    let messages = Messages {
+        header: circuit_header(&r1cs, Some(&witness)),
+        constraint_systems: vec![constraint_system(&r1cs)],
+        witnesses: vec![self::witness(&r1cs, &witness)],
+    };
+    let imported = to_r1cs::<Bn256>(&messages).unwrap();
+    assert_eq!((imported.num_inputs, imported.num_variables), (2, 4));
+    assert!(imported.constraints == r1cs.constraints);
+    assert!(to_witness::<Bn256>(&messages).unwrap() == witness);
+}